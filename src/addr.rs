@@ -0,0 +1,195 @@
+//! Node addressing shared by subcommands: `--path A/B/C`, `--rec N`, `--id X`.
+//!
+//! Heading text is neither unique nor stable, so every command that takes a
+//! path also takes a record index (as shown by `--dump`) or a stable id (see
+//! `ids.rs`; any unique prefix of at least 4 hex digits is accepted).
+
+use crate::heading_paths_capped;
+use crate::{rec_levels_capped, Rec};
+use std::io;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Addr {
+    Path(String),
+    Rec(usize),
+    Id(String),
+}
+
+pub const ADDR_USAGE: &str = "--path <A/B/C> | --rec <N> | --id <hex>";
+
+impl Addr {
+    /// Try to consume an addressing flag `a` (and its value from `rest`).
+    /// Returns None if `a` is not an addressing flag.
+    pub fn from_flag<'a, I>(a: &str, rest: &mut I) -> Option<io::Result<Addr>>
+    where
+        I: Iterator<Item = &'a String>,
+    {
+        let flag = match a {
+            "--path" | "--rec" | "--id" => a,
+            _ => return None,
+        };
+        let Some(v) = rest.next() else {
+            return Some(Err(bad(format!("{flag} needs a value"))));
+        };
+        Some(Addr::from_value(flag, v))
+    }
+
+    /// An address written as one word (edit operations, scripts):
+    /// `rec:N`, `id:HEX`, or a heading path (optionally `path:A/B`).
+    pub fn parse(s: &str) -> io::Result<Addr> {
+        if let Some(v) = s.strip_prefix("rec:") {
+            Addr::from_value("--rec", v)
+        } else if let Some(v) = s.strip_prefix("id:") {
+            Addr::from_value("--id", v)
+        } else {
+            Ok(Addr::Path(s.strip_prefix("path:").unwrap_or(s).to_string()))
+        }
+    }
+
+    fn from_value(flag: &str, v: &str) -> io::Result<Addr> {
+        match flag {
+            "--path" => Ok(Addr::Path(v.to_string())),
+            "--rec" => v
+                .parse::<usize>()
+                .map(Addr::Rec)
+                .map_err(|_| bad(format!("--rec: not a record index: {v}"))),
+            _ => {
+                let id = v.to_ascii_lowercase();
+                if id.len() < 4 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+                    Err(bad(format!("--id: need at least 4 hex digits: {v}")))
+                } else {
+                    Ok(Addr::Id(id))
+                }
+            }
+        }
+    }
+
+    /// Record indexes addressed by `self`, in record order. Paths may match
+    /// several records; an id prefix must be unique.
+    pub fn resolve(&self, recs: &[Rec]) -> io::Result<Vec<usize>> {
+        let hits: Vec<usize> = match self {
+            Addr::Rec(n) => {
+                if *n >= recs.len() {
+                    return Err(bad(format!(
+                        "--rec {n} out of range ({} records)",
+                        recs.len()
+                    )));
+                }
+                vec![*n]
+            }
+            Addr::Path(p) => heading_paths_capped(recs)
+                .iter()
+                .enumerate()
+                .filter(|(_, q)| *q == p)
+                .map(|(i, _)| i)
+                .collect(),
+            Addr::Id(id) => {
+                let hits: Vec<usize> = recs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, r)| r.id.starts_with(id.as_str()))
+                    .map(|(i, _)| i)
+                    .collect();
+                if hits.len() > 1 {
+                    return Err(bad(format!("--id {id} is ambiguous")));
+                }
+                hits
+            }
+        };
+        if hits.is_empty() {
+            return Err(bad(format!("no record matches {self}")));
+        }
+        Ok(hits)
+    }
+}
+
+impl std::fmt::Display for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Addr::Path(p) => write!(f, "--path {p}"),
+            Addr::Rec(n) => write!(f, "--rec {n}"),
+            Addr::Id(id) => write!(f, "--id {id}"),
+        }
+    }
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Index one past the last descendant of record `i`.
+pub fn subtree_end(recs: &[Rec], i: usize) -> usize {
+    subtree_end_at(&rec_levels_capped(recs), i)
+}
+
+/// `subtree_end` from precomputed `levels` (see rec_levels_capped), for loops
+/// that would otherwise recompute them per record.
+pub fn subtree_end_at(levels: &[usize], i: usize) -> usize {
+    let mut j = i + 1;
+    while j < levels.len() && levels[j] > levels[i] {
+        j += 1;
+    }
+    j
+}
+
+/// Records of the subtree rooted at `i`, re-based so the root sits at level 0.
+pub fn subtree_recs(recs: &[Rec], i: usize) -> Vec<Rec> {
+    let mut sub = recs[i..subtree_end(recs, i)].to_vec();
+    sub[0].delta = 0;
+    sub
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, M_EXPANDED};
+
+    fn sample() -> Vec<Rec> {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("x", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("x", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("y", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("B", 0x00, M_EXPANDED, -2, None),
+        ]);
+        parse_otl(&buf, "latin1").unwrap()
+    }
+
+    #[test]
+    fn resolve_path_rec_and_id() {
+        let recs = sample();
+        assert_eq!(Addr::Path("A/x".into()).resolve(&recs).unwrap(), [1, 2]);
+        assert_eq!(Addr::Rec(3).resolve(&recs).unwrap(), [3]);
+        assert!(Addr::Rec(9).resolve(&recs).is_err());
+
+        assert_ne!(
+            recs[1].id, recs[2].id,
+            "same-named siblings get distinct ids"
+        );
+        let id = Addr::Id(recs[2].id[..8].to_string());
+        assert_eq!(id.resolve(&recs).unwrap(), [2]);
+    }
+
+    #[test]
+    fn one_word_addresses() {
+        assert_eq!(Addr::parse("rec:3").unwrap(), Addr::Rec(3));
+        assert_eq!(Addr::parse("id:ABCD").unwrap(), Addr::Id("abcd".into()));
+        assert_eq!(Addr::parse("A/x").unwrap(), Addr::Path("A/x".into()));
+        assert_eq!(
+            Addr::parse("path:rec:1").unwrap(),
+            Addr::Path("rec:1".into())
+        );
+        assert!(Addr::parse("rec:x").is_err());
+    }
+
+    #[test]
+    fn subtree_is_rebased() {
+        let recs = sample();
+        assert_eq!(subtree_end(&recs, 2), 4);
+        let sub = subtree_recs(&recs, 2);
+        assert_eq!(sub.len(), 2);
+        assert_eq!(sub[0].delta, 0);
+        assert_eq!(&*sub[1].text, "y");
+    }
+}
@@ -0,0 +1,89 @@
+//! `--anki-tsv` export: flashcards for Anki's text importer.
+//!
+//! Every leaf heading with a note is a card: the heading is the front, the
+//! note the back. The headings above it become one hierarchical tag
+//! (`Biology::Cells`), so the deck can be browsed by outline section.
+//! Fields are HTML (line breaks as `<br>`), which keeps tabs and newlines out
+//! of the tab-separated rows.
+
+use crate::markup::html_escape;
+use crate::Node;
+
+const HEADER: &str = "#separator:tab\n#html:true\n#tags column:3\n";
+
+fn field(text: &str) -> String {
+    html_escape(&text.replace("\r\n", "\n"))
+        .replace('\t', "&#9;")
+        .replace('\n', "<br>")
+}
+
+// Anki tags are space-separated; `::` nests them.
+fn tag(path: &[String]) -> String {
+    path.iter()
+        .map(|t| t.split_whitespace().collect::<Vec<_>>().join("_"))
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn cards(nodes: &[Node], path: &mut Vec<String>, out: &mut String) {
+    for n in nodes {
+        if n.synthetic {
+            cards(&n.children, path, out);
+            continue;
+        }
+        if n.children.is_empty() {
+            if let Some(note) = n.note.as_deref().filter(|s| !s.trim().is_empty()) {
+                if !n.text.trim().is_empty() {
+                    out.push_str(&format!(
+                        "{}\t{}\t{}\n",
+                        field(n.text.trim()),
+                        field(note.trim_end()),
+                        tag(path)
+                    ));
+                }
+            }
+            continue;
+        }
+        path.push(n.text.trim().to_string());
+        cards(&n.children, path, out);
+        path.pop();
+    }
+}
+
+pub fn render_anki_tsv(nodes: &[Node]) -> String {
+    let mut out = HEADER.to_string();
+    cards(nodes, &mut Vec::new(), &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn leaves_with_notes_become_tagged_cards() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Cell biology", A_NOTE, M_EXPANDED, 0, Some(b"not a card")),
+                rec_bytes(
+                    "Mitochondria",
+                    A_NOTE | A_SIBFOLLOWS,
+                    M_EXPANDED,
+                    1,
+                    Some(b"ATP\r\n<site>\tx"),
+                ),
+                rec_bytes("Ribosome", 0x00, M_EXPANDED, 0, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let out = render_anki_tsv(&build_tree_capped(&recs));
+        assert_eq!(
+            out,
+            format!("{HEADER}Mitochondria\tATP<br>&lt;site&gt;&#9;x\tCell_biology\n")
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! `--annotations`: per-node comments kept in a sidecar, `file.OTL.ann.toml`,
+//! so headings can be explained without touching the original file.
+//!
+//! The sidecar is the config file's TOML subset (see config.rs): one
+//! `id = "comment"` line per node, ids as printed by `--json`
+//! (a unique prefix will do). Comments show up as `annotation` in `--json`,
+//! a `<div class="annotation">` in `--html`, and `# ` lines before the
+//! record in `--canon` (which `parse_canon` skips).
+//!
+//! ```toml
+//! # Plans.OTL
+//! 3f2a9c01 = "Q3 = the 1993 budget cycle"
+//! "b7e0" = "Bob: Robert Lee, facilities"
+//! ```
+
+use crate::config::{parse_value, strip_comment, Value};
+use crate::{render_canon, Node, Rec};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+fn bad(path: &str, line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{path}:{line_no}: {msg}"),
+    )
+}
+
+/// Comments by full node id.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    by_id: HashMap<String, String>,
+}
+
+/// The sidecar next to `file`.
+pub fn sidecar(file: &str) -> String {
+    format!("{file}.ann.toml")
+}
+
+/// (line, id or prefix, comment) for each entry of a sidecar.
+pub fn parse(text: &str, path: &str) -> io::Result<Vec<(usize, String, String)>> {
+    let mut out = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((k, v)) = line.split_once('=') else {
+            return Err(bad(path, i + 1, "expected id = \"comment\""));
+        };
+        let id = k.trim().trim_matches('"').to_ascii_lowercase();
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(bad(path, i + 1, &format!("{id:?} is not a node id")));
+        }
+        match parse_value(v.trim()) {
+            Some(Value::Str(c)) => out.push((i + 1, id, c)),
+            _ => return Err(bad(path, i + 1, "comment must be a string")),
+        }
+    }
+    Ok(out)
+}
+
+impl Annotations {
+    /// Match sidecar entries to `recs`. Entries that match no record (the
+    /// node was renamed or moved) or several come back as warnings.
+    pub fn resolve(
+        entries: Vec<(usize, String, String)>,
+        recs: &[Rec],
+        path: &str,
+    ) -> (Annotations, Vec<String>) {
+        let mut ann = Annotations::default();
+        let mut warnings = Vec::new();
+        for (line, key, comment) in entries {
+            let hits: Vec<&Rec> = recs.iter().filter(|r| r.id.starts_with(&key)).collect();
+            match hits[..] {
+                [r] => {
+                    ann.by_id.insert(r.id.clone(), comment);
+                }
+                [] => warnings.push(format!("{path}:{line}: id {key} matches no record")),
+                _ => warnings.push(format!(
+                    "{path}:{line}: id {key} matches {} records",
+                    hits.len()
+                )),
+            }
+        }
+        (ann, warnings)
+    }
+
+    /// Read and resolve the sidecar of `file`.
+    pub fn load(file: &str, recs: &[Rec]) -> io::Result<(Annotations, Vec<String>)> {
+        let path = sidecar(file);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{path}: {e}")))?;
+        Ok(Annotations::resolve(parse(&text, &path)?, recs, &path))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.by_id.get(id).map(String::as_str)
+    }
+
+    /// Set `annotation` on every annotated node.
+    pub fn apply(&self, nodes: &mut [Node]) {
+        for n in nodes {
+            if let Some(id) = &n.id {
+                n.annotation = self.get(id).map(str::to_string);
+            }
+            self.apply(&mut n.children);
+        }
+    }
+
+    /// `render_canon` with each comment as `# ` lines before its record.
+    pub fn render_canon(&self, recs: &[Rec], show_cursor: bool) -> String {
+        let mut out = String::new();
+        for r in recs {
+            if let Some(c) = self.get(&r.id) {
+                for line in c.lines() {
+                    out.push_str(format!("# {line}").trim_end());
+                    out.push('\n');
+                }
+            }
+            out.push_str(&render_canon(std::slice::from_ref(r), show_cursor));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canon::parse_canon;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn comments_attach_by_id_prefix() {
+        let buf = otl_file(vec![
+            rec_bytes("Q3", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("BLR", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let text = format!(
+            "# ids from --json\n{} = \"the 1993 cycle\"\n\"{}\" = \"Bob\\nfacilities\" # short\n\
+             0000000000000000 = \"gone\"\n",
+            recs[0].id,
+            &recs[1].id[..6]
+        );
+        let (ann, warnings) = Annotations::resolve(parse(&text, "t").unwrap(), &recs, "t");
+        assert_eq!(warnings, ["t:4: id 0000000000000000 matches no record"]);
+
+        let mut tree = build_tree_capped(&recs);
+        ann.apply(&mut tree);
+        assert_eq!(tree[1].annotation.as_deref(), Some("Bob\nfacilities"));
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(json.contains("\"annotation\":\"the 1993 cycle\""));
+
+        let canon = ann.render_canon(&recs, false);
+        assert!(canon.starts_with("# the 1993 cycle\n"));
+        assert!(canon.contains("# Bob\n# facilities\n"));
+        let back = parse_canon(&canon).unwrap();
+        assert_eq!(back.len(), 2);
+
+        assert!(parse("xyz = \"a\"\n", "t").is_err());
+        assert!(parse("abc = 3\n", "t").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! `assert` subcommand: structural limits for outlines produced by scripts,
+//! checked the way a CI step wants them (one line per failure, exit 1).
+//!
+//! Depth counts top-level headings as 1. `--require-note` takes a heading
+//! path, `rec:N` or `id:HEX` (as in edit operations) and fails unless every
+//! matching heading has a non-empty note. `--forbid-unknown-bits` fails on
+//! attr bits other than note/cursor/sibling/child and on markers other than
+//! expanded/collapsed.
+
+use crate::addr::Addr;
+use crate::heading_paths_capped;
+use crate::{
+    parse_otl, read_input, rec_levels_capped, usage, Rec, A_CURSOR, A_HASKIDS, A_NOTE,
+    A_SIBFOLLOWS, EXIT_FINDINGS,
+};
+use std::io;
+
+const KNOWN_ATTR: u8 = A_NOTE | A_CURSOR | A_SIBFOLLOWS | A_HASKIDS;
+
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_depth: Option<usize>,
+    pub max_records: Option<usize>,
+    pub require_note: Vec<Addr>,
+    pub forbid_unknown_bits: bool,
+}
+
+/// One line per failed constraint; empty when all hold.
+pub fn failures(recs: &[Rec], limits: &Limits) -> Vec<String> {
+    let mut out = Vec::new();
+    let paths = heading_paths_capped(recs);
+    if let Some(max) = limits.max_depth {
+        let levels = rec_levels_capped(recs);
+        if let Some((i, &lvl)) = levels
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, &l)| (l, usize::MAX - i))
+        {
+            if lvl + 1 > max {
+                out.push(format!(
+                    "max-depth: depth {} > {max} (first at rec #{i:03} {:?})",
+                    lvl + 1,
+                    paths[i]
+                ));
+            }
+        }
+    }
+    if let Some(max) = limits.max_records {
+        if recs.len() > max {
+            out.push(format!("max-records: {} records > {max}", recs.len()));
+        }
+    }
+    for addr in &limits.require_note {
+        match addr.resolve(recs) {
+            Err(e) => out.push(format!("require-note: {e}")),
+            Ok(hits) => {
+                for i in hits {
+                    if recs[i].note.as_deref().is_none_or(|n| n.trim().is_empty()) {
+                        out.push(format!(
+                            "require-note: rec #{i:03} {:?} has no note",
+                            paths[i]
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    if limits.forbid_unknown_bits {
+        for (i, r) in recs.iter().enumerate() {
+            if r.attr & !KNOWN_ATTR != 0 {
+                out.push(format!(
+                    "unknown-bits: rec #{i:03} {:?} attr {:02x} (unknown {:02x})",
+                    paths[i],
+                    r.attr,
+                    r.attr & !KNOWN_ATTR
+                ));
+            }
+            if r.marker_u16 != 0xFFFF && r.marker_u16 != 0xFFFE {
+                out.push(format!(
+                    "unknown-bits: rec #{i:03} {:?} marker {:04x}",
+                    paths[i], r.marker_u16
+                ));
+            }
+        }
+    }
+    out
+}
+
+fn count(flag: &str, v: Option<&String>) -> io::Result<usize> {
+    let v = v.map(|s| s.as_str()).unwrap_or("");
+    v.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{flag}: expected a number, got {v:?}"),
+        )
+    })
+}
+
+pub fn cmd_assert(prog: &str, args: &[String]) -> io::Result<u8> {
+    let mut file: Option<&str> = None;
+    let mut enc = "latin1";
+    let mut limits = Limits::default();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--max-depth" => limits.max_depth = Some(count(a, it.next())?),
+            "--max-records" => limits.max_records = Some(count(a, it.next())?),
+            "--require-note" => limits
+                .require_note
+                .push(Addr::parse(it.next().unwrap_or_else(|| usage(prog)))?),
+            "--forbid-unknown-bits" => limits.forbid_unknown_bits = true,
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let Some(file) = file else { usage(prog) };
+    let buf = read_input(file)?;
+    let recs = parse_otl(&buf, enc)?;
+    let failed = failures(&recs, &limits);
+    for f in &failed {
+        println!("{file}: FAIL {f}");
+    }
+    Ok(if failed.is_empty() { 0 } else { EXIT_FINDINGS })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_SIBFOLLOWS, M_EXPANDED};
+
+    fn recs() -> Vec<Rec> {
+        parse_otl(
+            &otl_file(vec![
+                rec_bytes("Intro", A_NOTE | A_SIBFOLLOWS, M_EXPANDED, 0, Some(b"hi")),
+                rec_bytes("Body", 0x01, M_EXPANDED, 0, None),
+                rec_bytes("deep", 0x00, M_EXPANDED, 1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn passing_limits_report_nothing() {
+        let limits = Limits {
+            max_depth: Some(2),
+            max_records: Some(3),
+            require_note: vec![Addr::parse("Intro").unwrap()],
+            forbid_unknown_bits: false,
+        };
+        assert!(failures(&recs(), &limits).is_empty());
+    }
+
+    #[test]
+    fn each_constraint_can_fail() {
+        let limits = Limits {
+            max_depth: Some(1),
+            max_records: Some(2),
+            require_note: vec![Addr::parse("Body").unwrap(), Addr::parse("Gone").unwrap()],
+            forbid_unknown_bits: true,
+        };
+        let f = failures(&recs(), &limits);
+        let kinds: Vec<&str> = f.iter().map(|l| l.split(':').next().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "max-depth",
+                "max-records",
+                "require-note",
+                "require-note",
+                "unknown-bits"
+            ]
+        );
+        assert!(f[0].contains("rec #002 \"Body/deep\""), "{}", f[0]);
+        assert!(f[4].contains("unknown 01"), "{}", f[4]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Parse cache for long-running and batch modes (`serve`, `--serve-stdio`,
+//! `check`): documents keyed by content hash, so bytes seen before (an
+//! unchanged file re-read, or the same file copied around an archive) are
+//! not parsed, nor their tree built, twice.
+//!
+//! Keys are (FNV-1a hash, length, encoding). Entries are evicted oldest
+//! first beyond `capacity`. With `lazy_notes` (set before first use) notes
+//! are left undecoded, see parse_doc_lazy.
+
+use crate::ids::fnv1a64;
+use crate::intern;
+use crate::{build_tree_capped, parse_doc, parse_doc_lazy, Doc, Node};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::rc::Rc;
+
+pub const DEFAULT_CAPACITY: usize = 256;
+
+type Key = (u64, usize, String);
+
+struct Entry {
+    doc: Rc<Doc>,
+    tree: Option<Rc<Vec<Node>>>,
+}
+
+pub struct ParseCache {
+    capacity: usize,
+    entries: HashMap<Key, Entry>,
+    order: VecDeque<Key>,
+    pub hits: usize,
+    pub misses: usize,
+    pub lazy_notes: bool,
+}
+
+impl Default for ParseCache {
+    fn default() -> Self {
+        ParseCache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ParseCache {
+    pub fn new(capacity: usize) -> ParseCache {
+        ParseCache {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+            lazy_notes: false,
+        }
+    }
+
+    fn entry(&mut self, buf: &[u8], enc: &str) -> io::Result<&mut Entry> {
+        let key: Key = (fnv1a64(buf), buf.len(), enc.to_string());
+        if self.entries.contains_key(&key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let doc = Rc::new(if self.lazy_notes {
+                parse_doc_lazy(buf, enc)?
+            } else {
+                parse_doc(buf, enc)?
+            });
+            if self.order.len() == self.capacity {
+                if let Some(old) = self.order.pop_front() {
+                    self.entries.remove(&old);
+                    intern::sweep();
+                }
+            }
+            self.order.push_back(key.clone());
+            self.entries.insert(key.clone(), Entry { doc, tree: None });
+        }
+        Ok(self.entries.get_mut(&key).expect("just inserted"))
+    }
+
+    /// The parsed document for `buf`.
+    pub fn doc(&mut self, buf: &[u8], enc: &str) -> io::Result<Rc<Doc>> {
+        Ok(self.entry(buf, enc)?.doc.clone())
+    }
+
+    /// The parsed document and its (unshaped) tree.
+    pub fn tree(&mut self, buf: &[u8], enc: &str) -> io::Result<(Rc<Doc>, Rc<Vec<Node>>)> {
+        let e = self.entry(buf, enc)?;
+        let tree = e
+            .tree
+            .get_or_insert_with(|| Rc::new(build_tree_capped(&e.doc.recs)))
+            .clone();
+        Ok((e.doc.clone(), tree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::M_EXPANDED;
+
+    #[test]
+    fn same_bytes_parse_once_and_old_entries_go() {
+        let a = otl_file(vec![rec_bytes("A", 0x00, M_EXPANDED, 0, None)]);
+        let b = otl_file(vec![rec_bytes("B", 0x00, M_EXPANDED, 0, None)]);
+        let mut c = ParseCache::new(1);
+        let (d1, t1) = c.tree(&a, "latin1").unwrap();
+        let (d2, t2) = c.tree(&a.clone(), "latin1").unwrap();
+        assert!(Rc::ptr_eq(&d1, &d2) && Rc::ptr_eq(&t1, &t2));
+        assert_eq!((c.hits, c.misses), (1, 1));
+        c.doc(&a, "utf8").unwrap(); // another encoding is another entry
+        assert_eq!(&*c.doc(&b, "latin1").unwrap().recs[0].text, "B");
+        c.doc(&a, "latin1").unwrap(); // evicted by capacity 1
+        assert_eq!((c.hits, c.misses), (1, 4));
+        assert!(c.doc(b"junk", "latin1").is_err());
+    }
+}
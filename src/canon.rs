@@ -0,0 +1,343 @@
+//! Reverse of `render_canon`: parse the canonical text dump back to records.
+//!
+//! Lengths in the dump drive reconstruction of what the text form loses:
+//! `textLen` picks how many heading spaces are folded into high bits (see
+//! write::encode_heading), and `noteLen` picks CRLF vs LF line ends and
+//! whether the note ended with a newline. When a length no longer fits
+//! (hand-edited text), headings are left uncompressed and notes use CRLF.
+//! Binary notes (see enc::is_binary) are dumped as `note hex` blocks and
+//! come back byte for byte.
+
+use crate::write::encode_heading;
+use crate::{enc, fmt_attr_bits, ids, Rec, A_CURSOR, A_HASKIDS, A_NOTE, A_SIBFOLLOWS};
+use std::io;
+
+/// Note bytes per line of a `note hex` block.
+pub const HEX_PER_LINE: usize = 32;
+
+fn bad(line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("canon line {line_no}: {msg}"),
+    )
+}
+
+/// Parse attr letters as printed by `fmt_attr_bits`. Each '1' is an unknown
+/// bit; it takes the highest unknown bit below the previous letter, which is
+/// right except when a single low bit is set (then `attr=0xnn` follows).
+pub fn parse_attr_bits(s: &str) -> Option<u8> {
+    let mut attr = 0u8;
+    let mut next: i32 = 7; // highest bit still available
+    for ch in s.chars() {
+        let (mask, set) = match ch {
+            'N' | 'n' => (A_NOTE, ch == 'N'),
+            'C' | 'c' => (A_CURSOR, ch == 'C'),
+            'S' | 's' => (A_SIBFOLLOWS, ch == 'S'),
+            'K' | 'k' => (A_HASKIDS, ch == 'K'),
+            '1' => {
+                let known = A_NOTE | A_CURSOR | A_SIBFOLLOWS | A_HASKIDS;
+                let bit = (0..=next).rev().find(|&b| known & (1 << b) == 0)?;
+                (1u8 << bit, true)
+            }
+            _ => return None,
+        };
+        let bit = mask.trailing_zeros() as i32;
+        if bit > next {
+            return None; // out of order
+        }
+        if set {
+            attr |= mask;
+        }
+        next = bit - 1;
+    }
+    Some(attr)
+}
+
+/// ` attr=0xnn` when `fmt_attr_bits` output would not parse back to `attr`.
+pub fn attr_hex_field(attr: u8, show_cursor: bool) -> String {
+    let shown = if show_cursor { attr } else { attr & !A_CURSOR };
+    if parse_attr_bits(&fmt_attr_bits(attr, show_cursor)) == Some(shown) {
+        String::new()
+    } else {
+        format!(" attr=0x{shown:02x}")
+    }
+}
+
+fn parse_mark(s: &str) -> Option<u16> {
+    match s {
+        "-1:+" => Some(0xFFFF),
+        "-2:-" => Some(0xFFFE),
+        _ => u16::from_str_radix(s.strip_prefix("0x")?, 16).ok(),
+    }
+}
+
+fn parse_delta(s: &str) -> Option<i16> {
+    match s.strip_prefix("0x") {
+        Some(h) => u16::from_str_radix(h, 16).ok().map(|u| u as i16),
+        None => s.parse::<i16>().ok(),
+    }
+}
+
+fn unescape_headline(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut it = s.chars();
+    while let Some(ch) = it.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match it.next()? {
+            'x' => {
+                let hex: String = it.by_ref().take(2).collect();
+                out.push(char::from(u8::from_str_radix(&hex, 16).ok()?));
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+// Rebuild raw note text from its LF-normalized body (final newline removed)
+// so that its latin1 length equals `note_len`, if any line-end choice does.
+fn restore_note(body: &str, note_len: usize) -> String {
+    let base = body.chars().count();
+    let nl = body.matches('\n').count();
+    // (trailing newline?, number of leading newlines written as CRLF)
+    let mut choices = vec![(false, nl), (true, nl + 1), (false, 0), (true, 0)];
+    for t in [false, true] {
+        choices.extend((1..nl + t as usize).map(|k| (t, k)));
+    }
+    let (trailing, crs) = choices
+        .into_iter()
+        .find(|&(t, k)| base + t as usize + k == note_len)
+        .unwrap_or((false, nl));
+    let mut out = String::with_capacity(note_len);
+    let mut left = crs;
+    let mut push_nl = |out: &mut String| {
+        if left > 0 {
+            out.push('\r');
+            left -= 1;
+        }
+        out.push('\n');
+    };
+    for (i, line) in body.split('\n').enumerate() {
+        if i > 0 {
+            push_nl(&mut out);
+        }
+        out.push_str(line);
+    }
+    if trailing {
+        push_nl(&mut out);
+    }
+    out
+}
+
+/// 0-based line number of each record in `render_canon` output, plus one
+/// final entry for the line count.
+pub fn canon_line_starts(recs: &[Rec]) -> Vec<usize> {
+    let mut out = Vec::with_capacity(recs.len() + 1);
+    let mut line = 0;
+    for r in recs {
+        out.push(line);
+        line += 1;
+        if let Some(bytes) = &r.note_bin {
+            line += 3 + bytes.len().div_ceil(HEX_PER_LINE);
+        } else if r.flags.has_note {
+            let note = r.note.as_deref().unwrap_or("").replace("\r\n", "\n");
+            // noteLen=, note, body (newline-terminated), /note
+            line += 3 + note.matches('\n').count() + usize::from(!note.ends_with('\n'));
+        }
+    }
+    out.push(line);
+    out
+}
+
+/// Parse a canonical dump (as printed by `--canon`) into records.
+pub fn parse_canon(text: &str) -> io::Result<Vec<Rec>> {
+    let mut out = Vec::new();
+    // Split on '\n' only: a '\r' left in a note body is note text (see
+    // restore_note); the dump's own lines may still end in CRLF.
+    let mut lines = text
+        .split('\n')
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .peekable();
+    fn bare(l: &str) -> &str {
+        l.strip_suffix('\r').unwrap_or(l)
+    }
+    while let Some((no, line)) = lines.next() {
+        let line = bare(line);
+        if line.is_empty() || line.starts_with('#') {
+            continue; // blank or comment (see annotate.rs)
+        }
+        let q = line
+            .find('"')
+            .ok_or_else(|| bad(no, "missing heading text"))?;
+        if !line.ends_with('"') || line.len() < q + 2 {
+            return Err(bad(no, "unterminated heading text"));
+        }
+        let heading = unescape_headline(&line[q + 1..line.len() - 1])
+            .ok_or_else(|| bad(no, "bad escape in heading"))?;
+
+        let mut fields = line[..q].split_whitespace();
+        let bits = fields.next().ok_or_else(|| bad(no, "missing attr bits"))?;
+        let mut attr = parse_attr_bits(bits).ok_or_else(|| bad(no, "bad attr bits"))?;
+        let (mut mark, mut delta, mut text_len) = (None, None, None);
+        for f in fields {
+            let (k, v) = f.split_once('=').ok_or_else(|| bad(no, "bad field"))?;
+            match k {
+                "attr" => {
+                    attr = v
+                        .strip_prefix("0x")
+                        .and_then(|h| u8::from_str_radix(h, 16).ok())
+                        .ok_or_else(|| bad(no, "bad attr"))?
+                }
+                "mark" => mark = Some(parse_mark(v).ok_or_else(|| bad(no, "bad mark"))?),
+                "delta" => delta = Some(parse_delta(v).ok_or_else(|| bad(no, "bad delta"))?),
+                "textLen" => {
+                    text_len =
+                        Some(usize::from_str_radix(v, 16).map_err(|_| bad(no, "bad textLen"))?)
+                }
+                _ => return Err(bad(no, "unknown field")),
+            }
+        }
+        let (Some(mark), Some(delta)) = (mark, delta) else {
+            return Err(bad(no, "missing mark= or delta="));
+        };
+
+        let mut note = None;
+        let mut note_bin = None;
+        if let Some((no, l)) = lines.next_if(|(_, l)| l.starts_with("noteLen=")) {
+            let n = usize::from_str_radix(&bare(l)["noteLen=".len()..], 16)
+                .map_err(|_| bad(no, "bad noteLen"))?;
+            let hex = match lines.next().map(|(_, l)| bare(l)) {
+                Some("note") => false,
+                Some("note hex") => true,
+                _ => return Err(bad(no, "expected 'note' after noteLen")),
+            };
+            let mut body: Vec<&str> = Vec::new();
+            loop {
+                match lines.next() {
+                    Some((_, l)) if bare(l) == "/note" => break,
+                    Some((_, l)) => body.push(l),
+                    None => return Err(bad(no, "unterminated note")),
+                }
+            }
+            if hex {
+                let bytes =
+                    enc::from_hex(&body.join("\n")).ok_or_else(|| bad(no, "bad hex note"))?;
+                note = Some(enc::to_hex(&bytes));
+                note_bin = Some(bytes);
+            } else {
+                note = Some(restore_note(&body.join("\n"), n));
+            }
+        }
+
+        let mut r = Rec::new(heading, attr, mark, delta, note);
+        if let Some(bytes) = note_bin {
+            r.note_len = bytes.len();
+            r.flags.note_binary = true;
+            r.note_bin = Some(bytes);
+        }
+        if let Some(n) = text_len {
+            // clamp to what the writer can produce for this text
+            let shortest = encode_heading(&r.text, 0).len();
+            r.len_text = n.clamp(shortest, r.len_text);
+        }
+        out.push(r);
+    }
+    ids::assign_ids(&mut out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::write::serialize_recs;
+    use crate::{parse_otl, render_canon, M_COLLAPSED, M_EXPANDED};
+
+    #[test]
+    fn attr_bits_roundtrip() {
+        for attr in 0..=255u8 {
+            let s = fmt_attr_bits(attr, true);
+            let hex = attr_hex_field(attr, true);
+            let back = match hex.strip_prefix(" attr=0x") {
+                Some(h) => u8::from_str_radix(h, 16).unwrap(),
+                None => parse_attr_bits(&s).unwrap(),
+            };
+            assert_eq!(back, attr, "attr {attr:#04x} -> {s}{hex}");
+        }
+    }
+
+    #[test]
+    fn canon_to_bytes_is_byte_stable() {
+        let mut compressed = b"Two".to_vec();
+        compressed[2] |= 0x80; // "Tw" + "o " ...
+        compressed.extend(b"words");
+        let mut recs = vec![
+            rec_bytes("Top \"q\"", A_SIBFOLLOWS | A_HASKIDS, M_EXPANDED, 0, None),
+            rec_bytes("", A_NOTE | 0x01, M_COLLAPSED, 1, Some(b"l1\r\nl2\r\n")),
+            rec_bytes("lf", A_NOTE | A_CURSOR, M_EXPANDED, 0, Some(b"a\nb")),
+            rec_bytes("x", 0x00, M_EXPANDED, -1, None),
+        ];
+        let mut raw = compressed.clone();
+        raw.extend(&recs[3][1..]);
+        recs[3] = raw;
+        let mut buf = otl_file(recs);
+        buf.push(0x1a);
+
+        let parsed = parse_otl(&buf, "latin1").unwrap();
+        assert_eq!(&*parsed[3].text, "Two words");
+        let canon = render_canon(&parsed, true);
+        let back = parse_canon(&canon).unwrap();
+        assert_eq!(serialize_recs(&back, "latin1").unwrap(), buf);
+        assert_eq!(render_canon(&back, true), canon);
+    }
+
+    #[test]
+    fn line_starts_match_render() {
+        let buf = otl_file(vec![
+            rec_bytes("a", A_NOTE, M_EXPANDED, 0, Some(b"x\r\ny")),
+            rec_bytes("b", A_NOTE, M_EXPANDED, 0, Some(b"z\r\n")),
+            rec_bytes("c", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let canon = render_canon(&recs, false);
+        let starts = canon_line_starts(&recs);
+        let lines: Vec<&str> = canon.lines().collect();
+        assert_eq!(starts, [0, 6, 11, 12]);
+        assert_eq!(lines.len(), 12);
+        assert!(lines[starts[2]].ends_with("\"c\""));
+    }
+
+    #[test]
+    fn edited_canon_still_parses() {
+        let canon = "nsk mark=-1:+ delta=+0 textLen=0001 \"Longer now\"\n\
+                     Nsk mark=-2:- delta=+1 textLen=0000 \"n\"\n\
+                     noteLen=0001\nnote\nline one\nline two\n/note\n";
+        let recs = parse_canon(canon).unwrap();
+        assert_eq!(&*recs[0].text, "Longer now");
+        assert_eq!(
+            recs[0].len_text, 9,
+            "textLen clamped to what the writer can fold"
+        );
+        assert_eq!(recs[1].note.as_deref(), Some("line one\r\nline two"));
+        let buf = serialize_recs(&recs, "latin1").unwrap();
+        let again = parse_otl(&buf, "latin1").unwrap();
+        assert_eq!(&*again[0].text, "Longer now");
+        assert!(again[1].collapsed);
+
+        // a lone CR in a note is text, not part of a line end
+        let buf = otl_file(vec![rec_bytes(
+            "cr",
+            A_NOTE,
+            M_EXPANDED,
+            0,
+            Some(b"a\r\r\nb\r"),
+        )]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let back = parse_canon(&render_canon(&recs, true)).unwrap();
+        assert_eq!(back[0].note.as_deref(), Some("a\r\r\nb\r"));
+    }
+}
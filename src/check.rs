@@ -0,0 +1,346 @@
+//! `check` subcommand: parse and validate files for scripts.
+//!
+//! Silent on success. Each problem is one `path: ...` line on stderr; the
+//! exit status is the most severe one seen (findings 1 < parse error 3 <
+//! I/O error 4). Directories are searched for .OTL files.
+//!
+//! `--report json` prints one document instead: per-file status, findings,
+//! stats and timing plus a summary, for tracking an archive's health.
+//!
+//! `--hypothesis <bit>=<predicate>` (repeatable, see hypo.rs) adds
+//! corpus-wide agreement counts: per file in JSON, totals on stdout.
+//!
+//! Notes are decoded with `--enc`, or per file from an `encodings.toml`
+//! manifest (see encmap.rs).
+//!
+//! `--unique` adds duplicate-sibling and slug-collision findings (see
+//! unique.rs), with slugs as `--slugs` would make them and siblings
+//! compared under `--collate <locale>` when given.
+//!
+//! `--lazy-notes` leaves notes undecoded (check never shows them), which
+//! keeps memory flat on archives of files full of maximal notes. `--stats`
+//! prints the heading interner counters (see intern.rs) on stderr.
+
+use crate::cache::ParseCache;
+use crate::collate::Collator;
+use crate::corpus::Stats;
+use crate::encmap;
+use crate::hypo::{evaluate, Hypothesis, Tally};
+use crate::intern;
+use crate::progress::Progress;
+use crate::slug::SlugStyle;
+use crate::unique::{collision_findings, Unique};
+use crate::{
+    collect_otl_files, exit_code, max_level, read_input, usage, validate_findings, Doc,
+    EXIT_FINDINGS, EXIT_PARSE,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub status: u8, // exit status this file alone would produce
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>, // tolerated oddities (parse-report notes)
+    pub warnings: usize,
+    pub by_kind: BTreeMap<&'static str, usize>,
+    pub findings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hypotheses: Vec<Tally>,
+    pub millis: f64,
+}
+
+impl FileReport {
+    fn failed(path: &Path, e: &io::Error, start: Instant) -> FileReport {
+        FileReport {
+            path: path.display().to_string(),
+            status: exit_code(e),
+            error: Some(e.to_string()),
+            notes: Vec::new(),
+            warnings: 0,
+            by_kind: BTreeMap::new(),
+            findings: Vec::new(),
+            stats: None,
+            hypotheses: Vec::new(),
+            millis: start.elapsed().as_secs_f64() * 1000.0,
+        }
+    }
+
+    /// The text-mode stderr lines for this file.
+    pub fn lines(&self) -> Vec<String> {
+        match &self.error {
+            Some(e) => vec![format!("{}: {e}", self.path)],
+            None => self
+                .findings
+                .iter()
+                .map(|f| format!("{}: {f}", self.path))
+                .collect(),
+        }
+    }
+}
+
+pub fn check_file(
+    path: &Path,
+    enc: &str,
+    assume_child_bit: bool,
+    unique: Option<&Unique>,
+    hyps: &[Hypothesis],
+    cache: &mut ParseCache,
+) -> FileReport {
+    let start = Instant::now();
+    let buf = match read_input(&path.to_string_lossy()) {
+        Ok(buf) => buf,
+        Err(e) => return FileReport::failed(path, &e, start),
+    };
+    let doc = match cache.doc(&buf, enc) {
+        Ok(doc) => doc,
+        Err(e) => return FileReport::failed(path, &e, start),
+    };
+    let Doc { recs, framing } = &*doc;
+    let mut findings = validate_findings(recs, assume_child_bit);
+    if let Some(u) = unique {
+        findings.extend(collision_findings(recs, u));
+    }
+    let mut by_kind = BTreeMap::new();
+    for f in &findings {
+        *by_kind.entry(f.kind).or_insert(0) += 1;
+    }
+    let stats = Stats::of(buf.len(), &doc, max_level());
+    FileReport {
+        path: path.display().to_string(),
+        status: if findings.is_empty() {
+            0
+        } else {
+            EXIT_FINDINGS
+        },
+        error: None,
+        notes: framing.notes.clone(),
+        warnings: findings.len(),
+        by_kind,
+        findings: findings
+            .iter()
+            .map(|f| format!("rec #{:03} {}", f.rec, f.detail))
+            .collect(),
+        stats: Some(stats),
+        hypotheses: evaluate(recs, hyps),
+        millis: start.elapsed().as_secs_f64() * 1000.0,
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Summary {
+    files: usize,
+    ok: usize,
+    findings: usize,
+    parse_errors: usize,
+    io_errors: usize,
+    warnings: usize,
+    status: u8,
+    millis: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hypotheses: Vec<Tally>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    version: u32,
+    generated_unix: u64,
+    summary: Summary,
+    files: &'a [FileReport],
+}
+
+fn summarize(reports: &[FileReport], millis: f64) -> Summary {
+    let mut s = Summary {
+        files: reports.len(),
+        millis,
+        ..Summary::default()
+    };
+    for r in reports {
+        match r.status {
+            0 => s.ok += 1,
+            EXIT_FINDINGS => s.findings += 1,
+            EXIT_PARSE => s.parse_errors += 1,
+            _ => s.io_errors += 1,
+        }
+        s.warnings += r.warnings;
+        s.status = s.status.max(r.status);
+        if s.hypotheses.is_empty() {
+            s.hypotheses = r.hypotheses.clone();
+        } else {
+            for (t, o) in s.hypotheses.iter_mut().zip(&r.hypotheses) {
+                t.add(o);
+            }
+        }
+    }
+    s
+}
+
+/// Expand directories to the .OTL files below them.
+pub fn expand_paths(paths: Vec<PathBuf>) -> Vec<Result<PathBuf, (PathBuf, io::Error)>> {
+    let mut out = Vec::new();
+    for p in paths {
+        if p.is_dir() {
+            match collect_otl_files(&p) {
+                Ok(files) => out.extend(files.into_iter().map(Ok)),
+                Err(e) => out.push(Err((p, e))),
+            }
+        } else {
+            out.push(Ok(p));
+        }
+    }
+    out
+}
+
+pub fn cmd_check(prog: &str, args: &[String]) -> io::Result<u8> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut enc = "latin1";
+    let mut enc_map: Option<&str> = None;
+    let mut assume_child_bit = false;
+    let mut unique = false;
+    let mut slugs = SlugStyle::default();
+    let mut collate: Option<Collator> = None;
+    let mut json = false;
+    let mut quiet = false;
+    let mut lazy_notes = false;
+    let mut stats = false;
+    let mut hyps = Vec::new();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "--enc-map" => enc_map = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--assume-child-bit" => assume_child_bit = true,
+            "--unique" => unique = true,
+            "--slugs" => slugs = SlugStyle::parse(it.next().unwrap_or_else(|| usage(prog)))?,
+            "--collate" => collate = Some(Collator::new(it.next().unwrap_or_else(|| usage(prog)))?),
+            "--quiet" => quiet = true,
+            "--lazy-notes" => lazy_notes = true,
+            "--stats" => stats = true,
+            "--hypothesis" => {
+                hyps.push(Hypothesis::parse(it.next().unwrap_or_else(|| usage(prog)))?)
+            }
+            "--report" => match it.next().map(|s| s.as_str()) {
+                Some("json") => json = true,
+                Some("text") => json = false,
+                _ => usage(prog),
+            },
+            _ => paths.push(PathBuf::from(a)),
+        }
+    }
+    if paths.is_empty() || (collate.is_some() && !unique) {
+        usage(prog);
+    }
+    let unique = unique.then_some(Unique { slugs, collate });
+    let start = Instant::now();
+    let mut reports = Vec::new();
+    let maps = encmap::manifests(enc_map, &paths)?;
+    let paths = expand_paths(paths);
+    let mut progress = Progress::new(paths.len(), quiet);
+    let mut cache = ParseCache::default();
+    cache.lazy_notes = lazy_notes;
+    for p in paths {
+        let r = match p {
+            Ok(f) => {
+                progress.tick(&f.to_string_lossy());
+                let enc = encmap::enc_for(&maps, &f, enc);
+                check_file(
+                    &f,
+                    enc,
+                    assume_child_bit,
+                    unique.as_ref(),
+                    &hyps,
+                    &mut cache,
+                )
+            }
+            Err((dir, e)) => FileReport::failed(&dir, &e, Instant::now()),
+        };
+        if !json {
+            for line in r.lines() {
+                progress.eprintln(&line);
+            }
+        }
+        reports.push(r);
+    }
+    drop(progress);
+    if stats {
+        eprintln!("{}", intern::stats());
+    }
+    let summary = summarize(&reports, start.elapsed().as_secs_f64() * 1000.0);
+    let status = summary.status;
+    if json {
+        let report = Report {
+            version: 1,
+            generated_unix: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            summary,
+            files: &reports,
+        };
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        for t in &summary.hypotheses {
+            println!("{}", t.line());
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_SIBFOLLOWS, EXIT_IO, M_EXPANDED};
+
+    #[test]
+    fn statuses_by_severity() {
+        let dir = std::env::temp_dir().join(format!("otl-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.OTL");
+        let warn = dir.join("warn.OTL");
+        let bad = dir.join("bad.OTL");
+        std::fs::write(
+            &good,
+            otl_file(vec![rec_bytes("a", 0, M_EXPANDED, 0, None)]),
+        )
+        .unwrap();
+        let w = otl_file(vec![rec_bytes("a", A_SIBFOLLOWS, M_EXPANDED, 0, None)]);
+        std::fs::write(&warn, w).unwrap();
+        std::fs::write(&bad, b"\x1a\x93\x1aabc").unwrap();
+
+        let status =
+            |p: &Path| check_file(p, "latin1", false, None, &[], &mut ParseCache::default()).status;
+        assert_eq!(status(&good), 0);
+        assert_eq!(status(&warn), EXIT_FINDINGS);
+        assert_eq!(status(&bad), EXIT_PARSE);
+        assert_eq!(status(&dir.join("missing.OTL")), EXIT_IO);
+        let all = cmd_check("otl", &[dir.to_string_lossy().into_owned()]).unwrap();
+        assert_eq!(all, EXIT_PARSE);
+
+        let w = check_file(
+            &warn,
+            "latin1",
+            false,
+            None,
+            &[],
+            &mut ParseCache::default(),
+        );
+        assert_eq!(w.by_kind.get("sibling-bit"), Some(&1));
+        assert_eq!(w.stats.as_ref().unwrap().records, 1);
+        let reports: Vec<FileReport> = [&good, &warn, &bad]
+            .iter()
+            .map(|p| check_file(p, "latin1", false, None, &[], &mut ParseCache::default()))
+            .collect();
+        let s = summarize(&reports, 0.0);
+        assert_eq!((s.files, s.ok, s.findings, s.parse_errors), (3, 1, 1, 1));
+        assert_eq!(s.status, EXIT_PARSE);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
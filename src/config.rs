@@ -0,0 +1,310 @@
+//! Defaults from `~/.config/otl/config.toml` (or `--config <path>`).
+//!
+//! Only a TOML subset is read: `key = value` lines with string, integer or
+//! boolean values, `[opt]`/`[opt.<format>]` tables, and `#` comments. Each
+//! top-level key stands for the command-line flag of the same name and is
+//! put in front of the real arguments of every command taking that flag
+//! (see `keys_for`), so flags given on the command line win; `--no-<key>`
+//! drops the setting, turning a boolean off or a value back to its
+//! default. `[opt]` keys become `--opt key=value` (see opts.rs).
+//!
+//! ```toml
+//! enc = "cp437"
+//! indent-char = "tab"
+//! glyphs = "unicode"
+//! color = "never"
+//! max-level = 40
+//! [opt.json]
+//! pretty = false
+//! ```
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Keys accepted by the viewing commands (normal mode, `select`).
+pub const VIEW_KEYS: &[&str] = &[
+    "enc",
+    "indent",
+    "indent-char",
+    "glyphs",
+    "fillers",
+    "notes",
+    "prune-empty",
+    "show-cursor",
+    "opt",
+];
+
+/// Keys accepted by `check`.
+pub const CHECK_KEYS: &[&str] = &["enc", "assume-child-bit", "unique"];
+
+/// Keys accepted by `diff-dir` (`--diff` takes all but `enc`).
+pub const DIFF_KEYS: &[&str] = &["enc", "show-cursor", "color"];
+
+/// Keys accepted by the other commands that read or write notes.
+pub const ENC_KEYS: &[&str] = &["enc"];
+
+/// Keys for every command (guardrails taken before the subcommand).
+pub const GLOBAL_KEYS: &[&str] = &["max-level"];
+
+/// The keys subcommand `cmd` accepts; normal mode (a file rather than a
+/// subcommand) takes VIEW_KEYS.
+pub fn keys_for(cmd: &str) -> &'static [&'static str] {
+    match cmd {
+        "check" => CHECK_KEYS,
+        "select" => VIEW_KEYS,
+        "diff-dir" => DIFF_KEYS,
+        "--diff" => &["show-cursor", "color"],
+        "edit" | "sort" | "transform" | "normalize" | "flatten" | "unflatten" | "clean"
+        | "smudge" | "new" | "assert" | "notes" | "wc" | "grep" | "serve" => ENC_KEYS,
+        _ => &[],
+    }
+}
+
+fn known(k: &str) -> bool {
+    [VIEW_KEYS, CHECK_KEYS, DIFF_KEYS, GLOBAL_KEYS]
+        .iter()
+        .any(|keys| keys.contains(&k))
+}
+
+fn bad(path: &str, line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{path}:{line_no}: {msg}"),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::Int(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+/// A TOML string (basic escapes only), integer or boolean.
+pub fn parse_value(v: &str) -> Option<Value> {
+    if let Some(body) = v.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return chars.as_str().trim().is_empty().then_some(Value::Str(out)),
+                '\\' => match chars.next()? {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    c @ ('"' | '\\') => out.push(c),
+                    _ => return None,
+                },
+                c => out.push(c),
+            }
+        }
+        return None;
+    }
+    match v {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => v.parse().ok().map(Value::Int),
+    }
+}
+
+/// `line` up to a '#' outside a string (a comment).
+pub fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    settings: Vec<(String, Value)>, // flag name (or "opt") with its value
+    opts: Vec<String>,              // key=value for --opt
+}
+
+impl Config {
+    pub fn parse(text: &str, path: &str) -> io::Result<Config> {
+        let mut cfg = Config::default();
+        let mut table: Option<String> = None; // None: top level; Some(prefix) in [opt*]
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                table = match name.trim() {
+                    "opt" => Some(String::new()),
+                    n => match n.strip_prefix("opt.") {
+                        Some(fmt) => Some(format!("{fmt}.")),
+                        None => return Err(bad(path, i + 1, &format!("unknown table [{n}]"))),
+                    },
+                };
+                continue;
+            }
+            let Some((k, v)) = line.split_once('=') else {
+                return Err(bad(path, i + 1, "expected key = value"));
+            };
+            let k = k.trim().trim_matches('"');
+            let v = parse_value(v.trim()).ok_or_else(|| bad(path, i + 1, "bad value"))?;
+            match &table {
+                Some(prefix) => cfg.opts.push(format!("{prefix}{k}={}", v.text())),
+                None if known(k) => cfg.settings.push((k.to_string(), v)),
+                None => return Err(bad(path, i + 1, &format!("unknown key {k:?}"))),
+            }
+        }
+        Ok(cfg)
+    }
+
+    /// The default file, `$XDG_CONFIG_HOME/otl/config.toml` or
+    /// `~/.config/otl/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(d) if !d.is_empty() => PathBuf::from(d),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("otl").join("config.toml"))
+    }
+
+    /// Read `explicit` (which must exist) or else the default file, if any.
+    pub fn load(explicit: Option<&str>) -> io::Result<Config> {
+        let path = match explicit {
+            Some(p) => PathBuf::from(p),
+            None => match Config::default_path() {
+                Some(p) if p.is_file() => p,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        Config::parse(&text, &path.to_string_lossy())
+    }
+
+    /// A command's arguments: flags for the settings it accepts (`keys`),
+    /// then `cli`. `--no-<key>` in `cli` drops that setting and is itself
+    /// dropped, so it works with no config file too.
+    pub fn args_for(&self, keys: &[&str], cli: &[String]) -> Vec<String> {
+        let off = |k: &str| keys.contains(&k) && cli.iter().any(|a| *a == format!("--no-{k}"));
+        let mut out = Vec::new();
+        for (k, v) in &self.settings {
+            if !keys.contains(&k.as_str()) || off(k) {
+                continue;
+            }
+            match v {
+                Value::Bool(true) => out.push(format!("--{k}")),
+                Value::Bool(false) => {}
+                v => out.extend([format!("--{k}"), v.text()]),
+            }
+        }
+        if keys.contains(&"opt") && !off("opt") {
+            for kv in &self.opts {
+                out.extend(["--opt".to_string(), kv.clone()]);
+            }
+        }
+        out.extend(
+            cli.iter()
+                .filter(|a| !a.strip_prefix("--no-").is_some_and(|k| keys.contains(&k)))
+                .cloned(),
+        );
+        out
+    }
+}
+
+/// Split `--config <path>` (anywhere) off the arguments.
+pub fn take_config_flag(args: &[String]) -> io::Result<(Option<String>, Vec<String>)> {
+    let mut path = None;
+    let mut rest = Vec::new();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if a == "--config" {
+            let p = it.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "--config needs a path")
+            })?;
+            path = Some(p.clone());
+        } else {
+            rest.push(a.clone());
+        }
+    }
+    Ok((path, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_becomes_leading_flags() {
+        let cfg = Config::parse(
+            "# defaults\nenc = \"cp437\"  # DOS files\nindent = 2\nprune-empty = true\n\
+             show-cursor = false\nassume-child-bit = true\n\n[opt.json]\npretty = false\n\
+             [opt]\nctags.kind = \"s\"\n",
+            "t.toml",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.args_for(VIEW_KEYS, &[]),
+            [
+                "--enc",
+                "cp437",
+                "--indent",
+                "2",
+                "--prune-empty",
+                "--opt",
+                "json.pretty=false",
+                "--opt",
+                "ctags.kind=s"
+            ]
+        );
+        assert_eq!(
+            cfg.args_for(CHECK_KEYS, &[]),
+            ["--enc", "cp437", "--assume-child-bit"]
+        );
+        let cli = ["--no-assume-child-bit".to_string(), "f.OTL".to_string()];
+        assert_eq!(cfg.args_for(CHECK_KEYS, &cli), ["--enc", "cp437", "f.OTL"]);
+        assert_eq!(
+            cfg.args_for(keys_for("edit"), &cli[1..]),
+            ["--enc", "cp437", "f.OTL"]
+        );
+        assert_eq!(cfg.args_for(keys_for("history"), &cli[1..]), ["f.OTL"]);
+        let guard = Config::parse("max-level = 40\ncolor = \"never\"\n", "t.toml").unwrap();
+        assert_eq!(guard.args_for(GLOBAL_KEYS, &[]), ["--max-level", "40"]);
+        assert_eq!(
+            guard.args_for(keys_for("--diff"), &[]),
+            ["--color", "never"]
+        );
+        assert!(Config::parse("colour = \"auto\"\n", "t.toml").is_err());
+        assert!(Config::parse("[paths]\n", "t.toml").is_err());
+        assert!(Config::parse("enc = \"utf8\n", "t.toml").is_err());
+    }
+
+    #[test]
+    fn config_flag_is_taken_anywhere() {
+        let args: Vec<String> = ["f.OTL", "--config", "c.toml", "--text"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (p, rest) = take_config_flag(&args).unwrap();
+        assert_eq!(p.as_deref(), Some("c.toml"));
+        assert_eq!(rest, ["f.OTL", "--text"]);
+    }
+}
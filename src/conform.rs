@@ -0,0 +1,201 @@
+//! `conformance` subcommand: check our writer against files SideKick saved.
+//!
+//! A reference directory holds pairs: `X.OTL` as saved by SideKick and the
+//! canon dump it was made from, `X.canon` (or `X.canon.txt`). Each canon is
+//! serialized with our writer and compared byte for byte with the
+//! reference; where they differ, the divergence is classified by framing
+//! detail (preamble, EOF sentinel, attr bits, heading folding, note line
+//! ends, ...) so each remaining unknown of the format shows up by name.
+
+use crate::canon::parse_canon;
+use crate::write::serialize_recs;
+use crate::{
+    collect_otl_files, parse_doc, usage, Doc, Rec, A_CURSOR, A_HASKIDS, A_NOTE, A_SIBFOLLOWS,
+    EXIT_FINDINGS, MAGIC, PREAMBLE,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Divergence kind -> (count, first offset in the reference).
+pub type Divergences = BTreeMap<&'static str, (usize, usize)>;
+
+fn note_bytes<'a>(buf: &'a [u8], r: &Rec) -> &'a [u8] {
+    r.off_note.map_or(&[], |o| &buf[o..o + r.note_len])
+}
+
+fn crlf_to_lf(b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(b.len());
+    for (i, &c) in b.iter().enumerate() {
+        if !(c == b'\r' && b.get(i + 1) == Some(&b'\n')) {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// How the records end: "1a", "ff ff 1a", or "none" (end of file).
+fn sentinel(buf: &[u8], doc: &Doc) -> &'static str {
+    let end = doc.framing.end;
+    let last = doc.recs.last().map_or(0, |r| match r.off_note {
+        Some(o) => o + r.note_len,
+        None => r.off_delta + 2,
+    });
+    match &buf[last.max(end.saturating_sub(3))..end] {
+        [0xff, 0xff, 0x1a] => "ff ff 1a",
+        [.., 0x1a] => "1a",
+        _ => "none",
+    }
+}
+
+fn add(out: &mut Divergences, kind: &'static str, off: usize) {
+    out.entry(kind).or_insert((0, off)).0 += 1;
+}
+
+/// Classify how `ours` differs from `reference` (empty if identical).
+pub fn compare(ours: &[u8], reference: &[u8]) -> io::Result<Divergences> {
+    let mut out = Divergences::new();
+    if ours == reference {
+        return Ok(out);
+    }
+    let a = parse_doc(ours, "latin1")?;
+    let b = parse_doc(reference, "latin1")?;
+    let pre = b.framing.prefix.len() + MAGIC.len();
+    if reference.get(pre..pre + PREAMBLE.len()) != Some(&PREAMBLE[..]) {
+        add(&mut out, "preamble", pre);
+    }
+    if !b.framing.prefix.is_empty() {
+        add(&mut out, "prefix", 0);
+    }
+    if sentinel(ours, &a) != sentinel(reference, &b) {
+        add(&mut out, "eof-sentinel", b.framing.end.saturating_sub(1));
+    }
+    if a.framing.trailer != b.framing.trailer {
+        add(&mut out, "trailer", b.framing.end);
+    }
+    if a.recs.len() != b.recs.len() {
+        add(
+            &mut out,
+            "record-count",
+            b.recs.last().map_or(0, |r| r.off_text),
+        );
+    }
+    for (x, y) in a.recs.iter().zip(&b.recs) {
+        let diff_bits = x.attr ^ y.attr;
+        if diff_bits & A_HASKIDS != 0 {
+            add(&mut out, "child-bit", y.off_attr);
+        }
+        if diff_bits & A_SIBFOLLOWS != 0 {
+            add(&mut out, "sibling-bit", y.off_attr);
+        }
+        if diff_bits & A_CURSOR != 0 {
+            add(&mut out, "cursor-bit", y.off_attr);
+        }
+        if diff_bits & A_NOTE != 0 {
+            add(&mut out, "note-bit", y.off_attr);
+        }
+        if diff_bits & !(A_NOTE | A_HASKIDS | A_SIBFOLLOWS | A_CURSOR) != 0 {
+            add(&mut out, "unknown-attr-bits", y.off_attr);
+        }
+        if x.marker_u16 != y.marker_u16 {
+            add(&mut out, "marker", y.off_marker);
+        }
+        if x.delta != y.delta {
+            add(&mut out, "delta", y.off_delta);
+        }
+        if x.text != y.text {
+            add(&mut out, "heading-text", y.off_text);
+        } else if ours[x.off_text..x.off_terminator] != reference[y.off_text..y.off_terminator] {
+            add(&mut out, "heading-folding", y.off_text);
+        }
+        let (nx, ny) = (note_bytes(ours, x), note_bytes(reference, y));
+        if nx != ny {
+            let off = y.off_note.or(y.off_note_len).unwrap_or(y.off_attr);
+            if crlf_to_lf(nx) == crlf_to_lf(ny) {
+                add(&mut out, "note-line-ends", off);
+            } else {
+                add(&mut out, "note-bytes", off);
+            }
+        }
+    }
+    if out.is_empty() {
+        let first = ours.iter().zip(reference).position(|(p, q)| p != q);
+        add(
+            &mut out,
+            "other",
+            first.unwrap_or(ours.len().min(reference.len())),
+        );
+    }
+    Ok(out)
+}
+
+// The canon dump paired with a reference .OTL, if any.
+fn canon_for(otl: &Path) -> Option<PathBuf> {
+    ["canon", "canon.txt"]
+        .iter()
+        .map(|ext| otl.with_extension(ext))
+        .find(|p| p.is_file())
+}
+
+pub fn cmd_conformance(prog: &str, args: &[String]) -> io::Result<u8> {
+    let [dir] = args else { usage(prog) };
+    let (mut pairs, mut diverging) = (0, 0);
+    let mut totals: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for otl in collect_otl_files(Path::new(dir))? {
+        let Some(canon) = canon_for(&otl) else {
+            continue;
+        };
+        pairs += 1;
+        let text = String::from_utf8_lossy(&fs::read(&canon)?).into_owned();
+        let ours = serialize_recs(&parse_canon(&text)?, "latin1")?;
+        let divergences = compare(&ours, &fs::read(&otl)?)?;
+        if divergences.is_empty() {
+            println!("ok   {}", otl.display());
+            continue;
+        }
+        diverging += 1;
+        println!("DIFF {}", otl.display());
+        for (kind, (n, off)) in &divergences {
+            println!("     {kind}: {n} (first at {off:#06x})");
+            *totals.entry(kind).or_insert(0) += 1;
+        }
+    }
+    println!("{pairs} pair(s), {diverging} diverging");
+    for (kind, n) in &totals {
+        println!("  {kind}: {n} file(s)");
+    }
+    Ok(if diverging > 0 { EXIT_FINDINGS } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::M_EXPANDED;
+
+    #[test]
+    fn divergences_are_classified() {
+        let ours = [
+            otl_file(vec![
+                rec_bytes("A", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("B", A_NOTE, M_EXPANDED, 0, Some(b"x\r\ny")),
+            ]),
+            vec![0x1a],
+        ]
+        .concat();
+        assert!(compare(&ours, &ours).unwrap().is_empty());
+        let reference = [
+            otl_file(vec![
+                rec_bytes("A", A_SIBFOLLOWS | A_HASKIDS, M_EXPANDED, 0, None),
+                rec_bytes("B", A_NOTE, M_EXPANDED, 0, Some(b"x\ny")),
+            ]),
+            vec![0xff, 0xff, 0x1a],
+        ]
+        .concat();
+        let d = compare(&ours, &reference).unwrap();
+        let kinds: Vec<&str> = d.keys().copied().collect();
+        assert_eq!(kinds, ["child-bit", "eof-sentinel", "note-line-ends"]);
+        assert_eq!(d["child-bit"], (1, 11)); // attr byte of "A"
+    }
+}
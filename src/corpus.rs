@@ -0,0 +1,267 @@
+//! A directory of outlines loaded once and queried together: the batch side
+//! of the CLI (`grep`, `check` stats, finding copies of the same outline)
+//! as an API for programs embedding the library.
+//!
+//! `Corpus` is Send + Sync, so it can be loaded on a worker thread and then
+//! shared (in an `Arc`) by the threads of a GUI. `load` with `threads > 1`
+//! parses on that many scoped threads; the result is the same as loading
+//! one file after another. A file that fails to read or parse is kept in
+//! `errors` rather than failing the load, as `check` reports it and goes on.
+
+use crate::search::{search, Hit};
+use crate::{collect_otl_files, parse_doc, rec_levels_with, Doc, DEFAULT_MAX_LEVEL};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Size and shape of one outline (`check --stats`), or summed over a corpus
+/// (`max_depth` is then the deepest).
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stats {
+    pub bytes: usize,
+    pub records: usize,
+    pub notes: usize,
+    pub note_bytes: usize,
+    pub max_depth: usize,
+    pub collapsed: usize,
+    pub trailer_bytes: usize,
+}
+
+impl Stats {
+    /// Stats of `doc`, parsed from `bytes` bytes, levels clamped at
+    /// `max_level`.
+    pub fn of(bytes: usize, doc: &Doc, max_level: usize) -> Stats {
+        let recs = &doc.recs;
+        Stats {
+            bytes,
+            records: recs.len(),
+            notes: recs.iter().filter(|r| r.flags.has_note).count(),
+            note_bytes: recs.iter().map(|r| r.note_len).sum(),
+            max_depth: rec_levels_with(recs, max_level)
+                .into_iter()
+                .max()
+                .unwrap_or(0),
+            collapsed: recs.iter().filter(|r| r.collapsed).count(),
+            trailer_bytes: doc.framing.trailer.len(),
+        }
+    }
+}
+
+/// One loaded file.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub doc: Doc,
+    pub bytes: usize, // file size
+}
+
+#[derive(Debug)]
+pub struct Corpus {
+    outlines: BTreeMap<PathBuf, Outline>,
+    errors: Vec<(PathBuf, io::Error)>,
+    max_level: usize, // level clamp for stats and dupes
+}
+
+impl Default for Corpus {
+    fn default() -> Self {
+        Corpus {
+            outlines: BTreeMap::new(),
+            errors: Vec::new(),
+            max_level: DEFAULT_MAX_LEVEL,
+        }
+    }
+}
+
+fn load_file(path: &Path, enc: &str) -> io::Result<Outline> {
+    let buf = fs::read(path)?;
+    Ok(Outline {
+        doc: parse_doc(&buf, enc)?,
+        bytes: buf.len(),
+    })
+}
+
+impl Corpus {
+    /// Every .OTL file under `root` (see collect_otl_files), notes decoded
+    /// as `enc`, parsed on up to `threads` threads. Only failing to list
+    /// `root` is an error.
+    pub fn load(root: &Path, enc: &str, threads: usize) -> io::Result<Corpus> {
+        let files = collect_otl_files(root)?;
+        let load_all = |files: &[PathBuf]| -> Vec<io::Result<Outline>> {
+            files.iter().map(|p| load_file(p, enc)).collect()
+        };
+        let loaded = if threads <= 1 || files.len() <= 1 {
+            load_all(&files)
+        } else {
+            let chunk = files.len().div_ceil(threads);
+            let load_all = &load_all;
+            thread::scope(|s| {
+                let workers: Vec<_> = files
+                    .chunks(chunk)
+                    .map(|c| s.spawn(move || load_all(c)))
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|w| w.join().expect("corpus loader panicked"))
+                    .collect()
+            })
+        };
+        let mut corpus = Corpus::default();
+        for (path, r) in files.into_iter().zip(loaded) {
+            match r {
+                Ok(o) => {
+                    corpus.outlines.insert(path, o);
+                }
+                Err(e) => corpus.errors.push((path, e)),
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// Add (or replace) one file, as `load` would have read it.
+    pub fn insert(&mut self, path: &Path, enc: &str) -> io::Result<()> {
+        let o = load_file(path, enc)?;
+        self.errors.retain(|(p, _)| p != path);
+        self.outlines.insert(path.to_path_buf(), o);
+        Ok(())
+    }
+
+    /// Clamp levels at `n` rather than DEFAULT_MAX_LEVEL (`--max-level`).
+    pub fn with_max_level(mut self, n: usize) -> Corpus {
+        self.max_level = n;
+        self
+    }
+
+    /// Stats of one outline.
+    pub fn stats_of(&self, o: &Outline) -> Stats {
+        Stats::of(o.bytes, &o.doc, self.max_level)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Outline> {
+        self.outlines.get(path)
+    }
+
+    /// The loaded outlines, in path order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Outline)> {
+        self.outlines.iter().map(|(p, o)| (p.as_path(), o))
+    }
+
+    pub fn len(&self) -> usize {
+        self.outlines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outlines.is_empty()
+    }
+
+    /// Files that could not be read or parsed, in path order.
+    pub fn errors(&self) -> &[(PathBuf, io::Error)] {
+        &self.errors
+    }
+
+    /// `search` over every outline: hits best first, then by path and
+    /// record.
+    pub fn grep(&self, query: &str, notes: bool, fuzzy: bool) -> Vec<(&Path, Hit)> {
+        let mut hits: Vec<(&Path, Hit)> = self
+            .iter()
+            .flat_map(|(p, o)| {
+                search(&o.doc.recs, query, notes, fuzzy)
+                    .into_iter()
+                    .map(move |h| (p, h))
+            })
+            .collect();
+        hits.sort_by(|(pa, a), (pb, b)| {
+            b.score
+                .total_cmp(&a.score)
+                .then(pa.cmp(pb))
+                .then(a.rec.cmp(&b.rec))
+        });
+        hits
+    }
+
+    /// Stats summed over every outline.
+    pub fn stats(&self) -> Stats {
+        let mut t = Stats::default();
+        for (_, o) in self.iter() {
+            let s = self.stats_of(o);
+            t.bytes += s.bytes;
+            t.records += s.records;
+            t.notes += s.notes;
+            t.note_bytes += s.note_bytes;
+            t.max_depth = t.max_depth.max(s.max_depth);
+            t.collapsed += s.collapsed;
+            t.trailer_bytes += s.trailer_bytes;
+        }
+        t
+    }
+
+    /// Groups of files holding the same outline: headings, levels and
+    /// notes equal, whatever their fold state, cursor or framing. Each
+    /// group is in path order, groups by their first path.
+    pub fn dupes(&self) -> Vec<Vec<&Path>> {
+        type Shape<'a> = Vec<(usize, &'a str, Option<&'a str>)>;
+        let mut groups: HashMap<Shape, Vec<&Path>> = HashMap::new();
+        for (p, o) in self.iter() {
+            let recs = &o.doc.recs;
+            let shape: Shape = rec_levels_with(recs, self.max_level)
+                .into_iter()
+                .zip(recs)
+                .map(|(lvl, r)| (lvl, &*r.text, r.note.as_deref()))
+                .collect();
+            groups.entry(shape).or_default().push(p);
+        }
+        let mut out: Vec<Vec<&Path>> = groups.into_values().filter(|g| g.len() > 1).collect();
+        out.sort();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write::serialize_recs;
+    use crate::{Rec, A_NOTE, A_SIBFOLLOWS};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn loads_in_parallel_and_queries_across_files() {
+        assert_send_sync::<Corpus>();
+        let dir = std::env::temp_dir().join(format!("otl-corpus-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let plan = |marker: u16| {
+            serialize_recs(
+                &[
+                    Rec::new("Kitchen".into(), A_SIBFOLLOWS, 0xFFFF, 0, None),
+                    Rec::new("tiles".into(), A_NOTE, marker, 1, Some("order".into())),
+                    Rec::new("Garden".into(), 0x00, 0xFFFF, -1, None),
+                ],
+                "latin1",
+            )
+            .unwrap()
+        };
+        fs::write(dir.join("a.OTL"), plan(0xFFFF)).unwrap();
+        fs::write(dir.join("sub/b.otl"), plan(0xFFFE)).unwrap();
+        let other = [Rec::new("Kitchn list".into(), 0x00, 0xFFFF, 0, None)];
+        fs::write(dir.join("c.OTL"), serialize_recs(&other, "latin1").unwrap()).unwrap();
+        fs::write(dir.join("d.OTL"), b"\x1a\x93\x1aabc").unwrap();
+
+        let seq = Corpus::load(&dir, "latin1", 1).unwrap();
+        let par = Corpus::load(&dir, "latin1", 3).unwrap();
+        assert_eq!((par.len(), par.errors().len()), (3, 1));
+        assert_eq!(seq.stats(), par.stats());
+        assert_eq!(par.stats().records, 7);
+        assert_eq!(par.stats().max_depth, 1);
+        let flat = Corpus::load(&dir, "latin1", 2).unwrap().with_max_level(0);
+        assert_eq!(flat.stats().max_depth, 0);
+
+        let hits = par.grep("kitchen", false, true);
+        let got: Vec<(&Path, usize)> = hits.iter().map(|(p, h)| (*p, h.rec)).collect();
+        let (a, b, c) = (dir.join("a.OTL"), dir.join("sub/b.otl"), dir.join("c.OTL"));
+        assert_eq!(got, [(a.as_path(), 0), (b.as_path(), 0), (c.as_path(), 0)]);
+        assert!(hits[2].1.score < 1.0);
+        assert_eq!(par.dupes(), [[a.as_path(), b.as_path()]]);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,449 @@
+//! `--diff` mode: structural comparison of two parsed outlines.
+//!
+//! Records are paired by stable id, then by identical heading text, then by
+//! similarity (heading edit distance plus child-heading overlap). A pair whose
+//! text differs is reported as a rename; a pair whose parent is not paired with
+//! the other side's parent is reported as a move. Similarity only compares an
+//! unpaired record with the MAX_CANDIDATES unpaired records nearest it in
+//! document order, so outlines with thousands of renames stay fast.
+//!
+//! `--color` (`--diff`, `diff-dir`) paints the listing for a terminal:
+//! additions green, removals red, field changes yellow, renames and moves
+//! cyan. `auto`, the default, colors only a terminal and honours NO_COLOR.
+
+use crate::heading_paths_capped;
+use crate::{delta_field, fmt_attr_bits, mark_field, rec_parents_capped, Rec};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::io::{self, IsTerminal};
+
+// Minimum similarity score (0..1) for pairing leftover added/removed records.
+const MIN_SIMILARITY: f64 = 0.6;
+
+// Unpaired prev records scored against each unpaired curr record.
+const MAX_CANDIDATES: usize = 128;
+
+fn render_mark_for_diff(u: u16) -> String {
+    mark_field(u)
+}
+fn render_delta_for_diff(d: i16) -> String {
+    delta_field(d)
+}
+
+fn diff_two_recs(prev: &Rec, curr: &Rec, show_cursor: bool) -> Vec<String> {
+    let mut changes = Vec::new();
+    if prev.attr != curr.attr {
+        changes.push(format!(
+            "  attr: {} -> {}",
+            fmt_attr_bits(prev.attr, show_cursor),
+            fmt_attr_bits(curr.attr, show_cursor)
+        ));
+    }
+    if prev.marker_u16 != curr.marker_u16 {
+        changes.push(format!(
+            "  mark: {} -> {}",
+            render_mark_for_diff(prev.marker_u16),
+            render_mark_for_diff(curr.marker_u16)
+        ));
+    }
+    if prev.delta != curr.delta {
+        changes.push(format!(
+            "  delta: {} -> {}",
+            render_delta_for_diff(prev.delta),
+            render_delta_for_diff(curr.delta)
+        ));
+    }
+    if prev.len_text != curr.len_text {
+        changes.push(format!(
+            "  textLen: {:04x} -> {:04x}",
+            prev.len_text as u16, curr.len_text as u16
+        ));
+    }
+    if prev.note_len != curr.note_len {
+        changes.push(format!(
+            "  noteLen: {:04x} -> {:04x}",
+            prev.note_len as u16, curr.note_len as u16
+        ));
+    }
+    let prev_note = prev.note.as_deref().unwrap_or("");
+    let curr_note = curr.note.as_deref().unwrap_or("");
+    if prev_note != curr_note {
+        if prev.note_len == curr.note_len {
+            changes.push("  note: (text changed)".to_string());
+        } else {
+            changes.push("  note: (length and text changed)".to_string());
+        }
+    }
+    changes
+}
+
+/// Levenshtein distance over chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut diag = row[0];
+        row[0] = i;
+        for j in 1..=b.len() {
+            let up = row[j];
+            row[j] = if a[i - 1] == b[j - 1] {
+                diag
+            } else {
+                1 + diag.min(up).min(row[j - 1])
+            };
+            diag = up;
+        }
+    }
+    row[b.len()]
+}
+
+// 1.0 for identical headings, 0.0 for nothing in common.
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let (la, lb) = (a.chars().count(), b.chars().count());
+    let longest = la.max(lb);
+    if longest == 0 {
+        return 1.0;
+    }
+    // cheap bound: the length difference alone may rule out a match
+    if (la.abs_diff(lb) as f64) / (longest as f64) > 1.0 - MIN_SIMILARITY {
+        return 0.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longest as f64
+}
+
+// Headings of the direct children of each record.
+fn child_texts<'a>(recs: &'a [Rec], parents: &[Option<usize>]) -> Vec<HashSet<&'a str>> {
+    let mut out = vec![HashSet::new(); recs.len()];
+    for (i, p) in parents.iter().enumerate() {
+        if let Some(p) = p {
+            out[*p].insert(&*recs[i].text);
+        }
+    }
+    out
+}
+
+fn similarity(p: &Rec, c: &Rec, pk: &HashSet<&str>, ck: &HashSet<&str>) -> f64 {
+    let text = text_similarity(&p.text, &c.text);
+    if pk.is_empty() && ck.is_empty() {
+        return text;
+    }
+    let inter = pk.intersection(ck).count() as f64;
+    let union = pk.union(ck).count() as f64;
+    0.5 * text + 0.5 * inter / union
+}
+
+/// For each curr record, the index of its paired prev record.
+fn pair_records(prev: &[Rec], curr: &[Rec]) -> Vec<Option<usize>> {
+    let mut used_prev = vec![false; prev.len()];
+    let mut pairs: Vec<Option<usize>> = vec![None; curr.len()];
+
+    // Pair records by stable id first, then greedily by heading text
+    // (first unmatched occurrence) for nodes whose path changed.
+    let mut by_id: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (j, p) in prev.iter().enumerate() {
+        by_id.entry(&p.id).or_default().push_back(j);
+    }
+    for (i, c) in curr.iter().enumerate() {
+        if let Some(j) = by_id.get_mut(&*c.id).and_then(VecDeque::pop_front) {
+            used_prev[j] = true;
+            pairs[i] = Some(j);
+        }
+    }
+    let mut by_text: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (j, p) in prev.iter().enumerate().filter(|&(j, _)| !used_prev[j]) {
+        by_text.entry(&p.text).or_default().push_back(j);
+    }
+    for (i, c) in curr.iter().enumerate() {
+        if pairs[i].is_some() {
+            continue;
+        }
+        if let Some(j) = by_text.get_mut(&*c.text).and_then(VecDeque::pop_front) {
+            used_prev[j] = true;
+            pairs[i] = Some(j);
+        }
+    }
+
+    // Leftovers: best-scoring candidates first, each side used at most once.
+    // Each curr leftover meets the prev leftovers around its own rank.
+    let prev_kids = child_texts(prev, &rec_parents_capped(prev));
+    let curr_kids = child_texts(curr, &rec_parents_capped(curr));
+    let left_prev: Vec<usize> = (0..prev.len()).filter(|&j| !used_prev[j]).collect();
+    let left_curr: Vec<usize> = (0..curr.len()).filter(|&i| pairs[i].is_none()).collect();
+    let mut cands: Vec<(f64, usize, usize)> = Vec::new();
+    for (k, &i) in left_curr.iter().enumerate() {
+        let center = k * left_prev.len() / left_curr.len();
+        let from = center
+            .saturating_sub(MAX_CANDIDATES / 2)
+            .min(left_prev.len().saturating_sub(MAX_CANDIDATES));
+        let to = (from + MAX_CANDIDATES).min(left_prev.len());
+        for &j in &left_prev[from..to] {
+            let score = similarity(&prev[j], &curr[i], &prev_kids[j], &curr_kids[i]);
+            if score >= MIN_SIMILARITY {
+                cands.push((score, i, j));
+            }
+        }
+    }
+    cands.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    for (_, i, j) in cands {
+        if pairs[i].is_none() && !used_prev[j] {
+            used_prev[j] = true;
+            pairs[i] = Some(j);
+        }
+    }
+    pairs
+}
+
+fn parent_label(paths: &[String], parent: Option<usize>) -> String {
+    match parent {
+        Some(p) => format!("\"{}\"", paths[p]),
+        None => "(top)".to_string(),
+    }
+}
+
+/// Per-category counts of one diff.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize, // paired records with field changes
+    pub renamed: usize,
+    pub moved: usize,
+    pub notes: usize, // paired records whose note text changed
+}
+
+impl DiffCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == DiffCounts::default()
+    }
+}
+
+impl std::fmt::Display for DiffCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "+{} -{} ~{} renamed {} moved {} notes {}",
+            self.added, self.removed, self.changed, self.renamed, self.moved, self.notes
+        )
+    }
+}
+
+/// `--color auto|always|never`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Color {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    pub fn parse(v: &str) -> io::Result<Color> {
+        match v {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--color: expected auto, always or never, got {v:?}"),
+            )),
+        }
+    }
+
+    /// Whether to paint what goes to stdout.
+    pub fn enabled(self) -> bool {
+        match self {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+        }
+    }
+}
+
+// ANSI color for one listing line (as `diff_report` or `diff-dir` print
+// them), if any; indented lines are field changes.
+fn line_color(line: &str) -> Option<&'static str> {
+    if line.starts_with(' ') {
+        return Some("33");
+    }
+    match line.split(' ').next() {
+        Some("+" | "A") => Some("32"),
+        Some("-" | "D") => Some("31"),
+        Some("~" | "M") => Some("33"),
+        Some("renamed" | "moved") => Some("36"),
+        _ => None,
+    }
+}
+
+/// `report` with each line painted by kind (see the module docs).
+pub fn paint(report: &str) -> String {
+    let mut out = String::with_capacity(report.len());
+    for line in report.lines() {
+        match line_color(line) {
+            Some(code) => out.push_str(&format!("\x1b[{code}m{line}\x1b[0m\n")),
+            None => out.push_str(&format!("{line}\n")),
+        }
+    }
+    out
+}
+
+pub fn diff_mode(prev: &[Rec], curr: &[Rec], show_cursor: bool) -> String {
+    diff_report(prev, curr, show_cursor).0
+}
+
+/// Full change listing plus its counts.
+pub fn diff_report(prev: &[Rec], curr: &[Rec], show_cursor: bool) -> (String, DiffCounts) {
+    let mut out = String::new();
+    let mut counts = DiffCounts::default();
+    let pairs = pair_records(prev, curr);
+    let mut used_prev = vec![false; prev.len()];
+    for j in pairs.iter().flatten() {
+        used_prev[*j] = true;
+    }
+    let (prev_par, curr_par) = (rec_parents_capped(prev), rec_parents_capped(curr));
+    let (prev_paths, curr_paths) = (heading_paths_capped(prev), heading_paths_capped(curr));
+
+    for (i, c) in curr.iter().enumerate() {
+        let Some(j) = pairs[i] else {
+            out.push_str(&format!("+ \"{}\"\n", c.text));
+            counts.added += 1;
+            continue;
+        };
+        let p = &prev[j];
+        let moved = match (prev_par[j], curr_par[i]) {
+            (None, None) => false,
+            (Some(pp), Some(cp)) => pairs[cp] != Some(pp),
+            _ => true,
+        };
+        let changes = diff_two_recs(p, c, show_cursor);
+        if p.note != c.note || p.note_bin != c.note_bin {
+            counts.notes += 1;
+        }
+        if p.text != c.text {
+            out.push_str(&format!("renamed \"{}\" -> \"{}\"\n", p.text, c.text));
+            counts.renamed += 1;
+        }
+        if moved {
+            counts.moved += 1;
+            out.push_str(&format!(
+                "moved \"{}\" from {} to {}\n",
+                c.text,
+                parent_label(&prev_paths, prev_par[j]),
+                parent_label(&curr_paths, curr_par[i])
+            ));
+        }
+        if !changes.is_empty() {
+            counts.changed += 1;
+            if p.text == c.text && !moved {
+                out.push_str(&format!("~ \"{}\"\n", c.text));
+            }
+            for line in changes {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+    }
+    for (j, p) in prev.iter().enumerate() {
+        if !used_prev[j] {
+            out.push_str(&format!("- \"{}\"\n", p.text));
+            counts.removed += 1;
+        }
+    }
+    (out, counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    fn recs(v: Vec<Vec<u8>>) -> Vec<Rec> {
+        parse_otl(&otl_file(v), "latin1").unwrap()
+    }
+
+    #[test]
+    fn diff_pairs_by_id_before_text() {
+        // "x" under P is deleted; the "x" under Q is unchanged and must not be
+        // paired with (and reported as a change to) the first "x".
+        let prev = recs(vec![
+            rec_bytes("P", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("x", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("Q", 0x00, M_EXPANDED, -1, None),
+            rec_bytes("x", A_NOTE, M_EXPANDED, 1, Some(b"n")),
+        ]);
+        let curr = recs(vec![
+            rec_bytes("P", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("Q", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("x", A_NOTE, M_EXPANDED, 1, Some(b"n")),
+        ]);
+        let report = diff_mode(&prev, &curr, false);
+        assert_eq!(report, "~ \"Q\"\n  delta: -1 -> +0\n- \"x\"\n");
+    }
+
+    #[test]
+    fn diff_reports_renames_and_moves() {
+        let prev = recs(vec![
+            rec_bytes("Inbox", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("Buy milk", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("Projects", 0x00, M_EXPANDED, -1, None),
+            rec_bytes("Kitchen remodel", 0x00, M_EXPANDED, 1, None),
+        ]);
+        let curr = recs(vec![
+            rec_bytes("Inbox", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("Projects", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("Kitchen remodels", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+            rec_bytes("Buy milk", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let report = diff_mode(&prev, &curr, false);
+        assert!(report.contains("renamed \"Kitchen remodel\" -> \"Kitchen remodels\"\n"));
+        assert!(report.contains("moved \"Buy milk\" from \"Inbox\" to \"Projects\"\n"));
+        assert!(!report.contains("\n+ ") && !report.starts_with('+'));
+        assert!(!report.contains("- \""));
+        let painted = paint(&report);
+        assert!(painted.contains("\x1b[36mmoved \"Buy milk\""));
+        assert_eq!(
+            paint("+ \"a\"\n  attr: s -> S\n"),
+            "\x1b[32m+ \"a\"\x1b[0m\n\x1b[33m  attr: s -> S\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn large_diffs_pair_renames_near_their_position() {
+        let outline = |task: &str| {
+            recs(
+                (0..3000)
+                    .map(|n| rec_bytes(&format!("{task} {n}"), 0x08, M_EXPANDED, 0, None))
+                    .collect(),
+            )
+        };
+        let (prev, curr) = (outline("task"), outline("Task"));
+        let pairs = pair_records(&prev, &curr);
+        assert!(pairs.iter().enumerate().all(|(i, p)| *p == Some(i)));
+        assert!(pair_records(&prev, &prev)
+            .iter()
+            .enumerate()
+            .all(|(i, p)| *p == Some(i)));
+    }
+
+    #[test]
+    fn counts_cover_notes() {
+        let prev = recs(vec![
+            rec_bytes("A", A_NOTE | A_SIBFOLLOWS, M_EXPANDED, 0, Some(b"old")),
+            rec_bytes("B", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let curr = recs(vec![
+            rec_bytes("A", A_NOTE | A_SIBFOLLOWS, M_EXPANDED, 0, Some(b"new")),
+            rec_bytes("C", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let (_, counts) = diff_report(&prev, &curr, false);
+        assert_eq!(counts.to_string(), "+1 -1 ~1 renamed 0 moved 0 notes 1");
+    }
+
+    #[test]
+    fn edit_distance_basics() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+}
@@ -0,0 +1,170 @@
+//! `diff-dir` subcommand: compare two directory trees of .OTL files.
+//!
+//! Files are paired by relative path. Added/removed files are listed; files
+//! whose bytes differ get the per-file structural diff (or just its counts
+//! with `--summary`). Exits 1 when any file was added, removed or changed.
+//! Notes are decoded with `--enc` (default latin1) or per file from either
+//! tree's `encodings.toml` or `--enc-map` (see encmap.rs).
+
+use crate::diff::{diff_report, paint, Color};
+use crate::encmap;
+use crate::progress::Progress;
+use crate::{collect_otl_files, parse_otl, usage, EXIT_FINDINGS};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Relative paths (with '/' separators) of all .OTL files under `root`.
+fn rel_paths(root: &Path) -> io::Result<BTreeSet<String>> {
+    let mut out = BTreeSet::new();
+    for p in collect_otl_files(root)? {
+        let rel = p.strip_prefix(root).unwrap_or(&p);
+        let parts: Vec<String> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        out.insert(parts.join("/"));
+    }
+    Ok(out)
+}
+
+pub fn cmd_diff_dir(prog: &str, args: &[String]) -> io::Result<u8> {
+    let mut dirs: Vec<&str> = Vec::new();
+    let mut summary = false;
+    let mut show_cursor = false;
+    let mut quiet = false;
+    let mut enc = "latin1";
+    let mut enc_map: Option<&str> = None;
+    let mut color = Color::default();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "--enc-map" => enc_map = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--summary" => summary = true,
+            "--quiet" => quiet = true,
+            "--show-cursor" => show_cursor = true,
+            "--color" => color = Color::parse(it.next().unwrap_or_else(|| usage(prog)))?,
+            _ => dirs.push(a),
+        }
+    }
+    if dirs.len() != 2 {
+        usage(prog);
+    }
+    let (old_root, new_root) = (Path::new(dirs[0]), Path::new(dirs[1]));
+    let old = rel_paths(old_root)?;
+    let new = rel_paths(new_root)?;
+    let maps = encmap::manifests(enc_map, &[old_root.to_path_buf(), new_root.to_path_buf()])?;
+    let color = color.enabled();
+    // one listing line, painted before it is indented
+    let say = |indent: &str, text: &str| {
+        if color {
+            print!("{indent}{}", paint(text));
+        } else {
+            println!("{indent}{text}");
+        }
+    };
+
+    let (mut added, mut removed, mut changed, mut same) = (0, 0, 0, 0);
+    let mut progress = Progress::new(old.union(&new).count(), quiet);
+    for rel in old.union(&new) {
+        progress.tick(rel);
+        match (old.contains(rel), new.contains(rel)) {
+            (true, false) => {
+                progress.clear();
+                say("", &format!("D {rel}"));
+                removed += 1;
+            }
+            (false, true) => {
+                progress.clear();
+                say("", &format!("A {rel}"));
+                added += 1;
+            }
+            _ => {
+                let (fa, fb) = (old_root.join(rel), new_root.join(rel));
+                let (ea, eb) = (
+                    encmap::enc_for(&maps, &fa, enc),
+                    encmap::enc_for(&maps, &fb, enc),
+                );
+                let a = fs::read(&fa)?;
+                let b = fs::read(&fb)?;
+                if a == b {
+                    same += 1;
+                    continue;
+                }
+                changed += 1;
+                progress.clear();
+                let (pa, pb) = match (parse_otl(&a, ea), parse_otl(&b, eb)) {
+                    (Ok(pa), Ok(pb)) => (pa, pb),
+                    (Err(e), _) | (_, Err(e)) => {
+                        say("", &format!("M {rel}: parse error: {e}"));
+                        continue;
+                    }
+                };
+                let (report, counts) = diff_report(&pa, &pb, show_cursor);
+                if summary {
+                    say("", &format!("M {rel}: {counts}"));
+                } else {
+                    say("", &format!("M {rel}"));
+                    if counts.is_empty() {
+                        println!("  (bytes differ; no structural changes)");
+                    }
+                    for line in report.lines() {
+                        say("  ", line);
+                    }
+                }
+            }
+        }
+    }
+    drop(progress);
+    if summary {
+        println!("files: {added} added, {removed} removed, {changed} changed, {same} unchanged");
+    }
+    Ok(if added + removed + changed > 0 {
+        EXIT_FINDINGS
+    } else {
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::M_EXPANDED;
+
+    #[test]
+    fn pairs_files_by_relative_path() {
+        let dir = std::env::temp_dir().join(format!("otl-dirdiff-{}", std::process::id()));
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        for d in [&a, &b] {
+            fs::create_dir_all(d.join("sub")).unwrap();
+        }
+        let one = |text: &str| otl_file(vec![rec_bytes(text, 0x00, M_EXPANDED, 0, None)]);
+        fs::write(a.join("sub/x.OTL"), one("Plan")).unwrap();
+        fs::write(b.join("sub/x.OTL"), one("Plan")).unwrap();
+        let args = |extra: &[&str]| -> Vec<String> {
+            [a.to_str().unwrap(), b.to_str().unwrap(), "--quiet"]
+                .iter()
+                .chain(extra)
+                .map(|s| s.to_string())
+                .collect()
+        };
+        assert_eq!(
+            rel_paths(&a).unwrap(),
+            BTreeSet::from(["sub/x.OTL".to_string()])
+        );
+        assert_eq!(cmd_diff_dir("otl", &args(&[])).unwrap(), 0);
+
+        fs::write(b.join("sub/x.OTL"), one("Plans")).unwrap();
+        assert_eq!(
+            cmd_diff_dir("otl", &args(&["--summary"])).unwrap(),
+            EXIT_FINDINGS
+        );
+        fs::write(b.join("sub/x.OTL"), one("Plan")).unwrap();
+        fs::write(b.join("new.otl"), one("Extra")).unwrap();
+        assert_eq!(cmd_diff_dir("otl", &args(&[])).unwrap(), EXIT_FINDINGS);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
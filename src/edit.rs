@@ -0,0 +1,587 @@
+//! `edit` subcommand: add, delete, move and rename headings and set notes,
+//! addressed by one-word addresses (see `Addr::parse`; `/` as a destination
+//! is the top level).
+//!
+//! Heading text collides constantly, so a path matching several records is
+//! an error by default. With `--interactive` the candidates are listed for
+//! picking instead, and destructive steps (deleting, replacing or removing a
+//! note) ask before going ahead.
+//!
+//! `--script FILE` runs one operation per line (words split like a shell:
+//! double quotes group, `\"`, `\\` and `\n` escape inside them, `#` starts a
+//! comment) as a transaction: every line is parsed first, each step is
+//! validated, and nothing is written unless all of them succeed.
+
+use crate::addr::{subtree_end, Addr};
+use crate::heading_paths_capped;
+use crate::journal::write_in_place;
+use crate::ops::{prepare_write, restructure};
+use crate::write::{compress_headings, Preserve};
+use crate::{
+    ids, parse_doc, read_input, rec_levels_capped, usage, validate_findings, write_output, Doc,
+    Finding, Rec, A_NOTE,
+};
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+pub const EDIT_OPS: &str = "add <parent|/> <text> | delete <addr> | move <addr> <parent|/> | \
+                            rename <addr> <text> | note <addr> <text>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// New last child of `parent` (top level for None).
+    Add {
+        parent: Option<Addr>,
+        text: String,
+    },
+    /// Remove a record with its subtree.
+    Delete(Addr),
+    /// Make a subtree the last child of `to` (top level for None).
+    Move {
+        from: Addr,
+        to: Option<Addr>,
+    },
+    Rename {
+        at: Addr,
+        text: String,
+    },
+    /// Set a note; empty text removes it.
+    Note {
+        at: Addr,
+        text: String,
+    },
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn dest(s: &str) -> io::Result<Option<Addr>> {
+    if s == "/" {
+        Ok(None)
+    } else {
+        Addr::parse(s).map(Some)
+    }
+}
+
+impl Op {
+    /// An operation from its words, as in `EDIT_OPS`.
+    pub fn parse(words: &[&str]) -> io::Result<Op> {
+        Ok(match words {
+            ["add", p, t] => Op::Add {
+                parent: dest(p)?,
+                text: t.to_string(),
+            },
+            ["delete", a] => Op::Delete(Addr::parse(a)?),
+            ["move", a, p] => Op::Move {
+                from: Addr::parse(a)?,
+                to: dest(p)?,
+            },
+            ["rename", a, t] => Op::Rename {
+                at: Addr::parse(a)?,
+                text: t.to_string(),
+            },
+            ["note", a, t] => Op::Note {
+                at: Addr::parse(a)?,
+                text: t.to_string(),
+            },
+            _ => return Err(bad(format!("bad edit operation: {}", words.join(" ")))),
+        })
+    }
+}
+
+/// Settles ambiguous addresses and confirms destructive steps.
+pub trait Chooser {
+    fn pick(&mut self, addr: &Addr, hits: &[usize], recs: &[Rec]) -> io::Result<usize>;
+    fn confirm(&mut self, question: &str) -> io::Result<bool>;
+}
+
+/// Non-interactive: ambiguity is an error; nothing needs confirming.
+pub struct Strict;
+
+impl Chooser for Strict {
+    fn pick(&mut self, addr: &Addr, hits: &[usize], _recs: &[Rec]) -> io::Result<usize> {
+        match hits {
+            [i] => Ok(*i),
+            _ => Err(bad(format!(
+                "{addr} matches {} records; use rec:N or id:HEX, or --interactive",
+                hits.len()
+            ))),
+        }
+    }
+
+    fn confirm(&mut self, _question: &str) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Asks on `out`, reads answers from `input`.
+pub struct Prompt<R, W> {
+    pub input: R,
+    pub out: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        write!(self.out, "{question}")?;
+        self.out.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no answer (end of input)",
+            ));
+        }
+        Ok(line.trim().to_string())
+    }
+}
+
+impl<R: BufRead, W: Write> Chooser for Prompt<R, W> {
+    fn pick(&mut self, addr: &Addr, hits: &[usize], recs: &[Rec]) -> io::Result<usize> {
+        if let [i] = hits {
+            return Ok(*i);
+        }
+        let paths = heading_paths_capped(recs);
+        writeln!(self.out, "{addr} matches {} records:", hits.len())?;
+        for (n, &i) in hits.iter().enumerate() {
+            writeln!(
+                self.out,
+                "  {}) rec #{i:03} {} {}",
+                n + 1,
+                recs[i].id,
+                paths[i]
+            )?;
+        }
+        loop {
+            let a = self.ask(&format!("which one [1-{}, q to quit]? ", hits.len()))?;
+            if a == "q" {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "edit cancelled"));
+            }
+            if let Some(n) = a
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=hits.len()).contains(n))
+            {
+                return Ok(hits[n - 1]);
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        let a = self.ask(&format!("{question} [y/N] "))?;
+        Ok(matches!(a.as_str(), "y" | "Y" | "yes"))
+    }
+}
+
+fn resolve(addr: &Addr, recs: &[Rec], ch: &mut dyn Chooser) -> io::Result<usize> {
+    let hits = addr.resolve(recs)?;
+    ch.pick(addr, &hits, recs)
+}
+
+fn set_note(r: &mut Rec, text: &str) {
+    r.note_bin = None;
+    r.flags.note_binary = false;
+    if text.is_empty() {
+        r.note = None;
+        r.note_len = 0;
+        r.attr &= !A_NOTE;
+        r.flags.has_note = false;
+    } else {
+        // SideKick notes use CRLF line ends
+        let note = text.replace("\r\n", "\n").replace('\n', "\r\n");
+        r.note_len = note.chars().count();
+        r.note = Some(note);
+        r.attr |= A_NOTE;
+        r.flags.has_note = true;
+    }
+}
+
+/// Apply one operation; Ok(false) if a confirmation was declined (and
+/// nothing changed).
+pub fn apply(recs: &mut Vec<Rec>, op: &Op, ch: &mut dyn Chooser) -> io::Result<bool> {
+    let mut levels = rec_levels_capped(recs);
+    match op {
+        Op::Rename { at, text } => {
+            let i = resolve(at, recs, ch)?;
+            recs[i].text = text.as_str().into();
+            recs[i].len_text = text.chars().count();
+        }
+        Op::Note { at, text } => {
+            let i = resolve(at, recs, ch)?;
+            if recs[i].note.is_some() {
+                let verb = if text.is_empty() { "remove" } else { "replace" };
+                if !ch.confirm(&format!("{verb} the note of {:?}?", recs[i].text))? {
+                    return Ok(false);
+                }
+            }
+            set_note(&mut recs[i], text);
+        }
+        Op::Delete(at) => {
+            let i = resolve(at, recs, ch)?;
+            let end = subtree_end(recs, i);
+            let q = format!(
+                "delete {:?} and {} record(s) below it?",
+                recs[i].text,
+                end - i - 1
+            );
+            if !ch.confirm(&q)? {
+                return Ok(false);
+            }
+            recs.drain(i..end);
+            levels.drain(i..end);
+        }
+        Op::Add { parent, text } => {
+            let (at, lvl) = match parent {
+                None => (recs.len(), 0),
+                Some(p) => {
+                    let i = resolve(p, recs, ch)?;
+                    (subtree_end(recs, i), levels[i] + 1)
+                }
+            };
+            recs.insert(at, Rec::new(text.clone(), 0x00, 0xFFFF, 0, None));
+            levels.insert(at, lvl);
+        }
+        Op::Move { from, to } => {
+            let i = resolve(from, recs, ch)?;
+            let end = subtree_end(recs, i);
+            let (dest_end, lvl) = match to {
+                None => (recs.len(), 0),
+                Some(p) => {
+                    let j = resolve(p, recs, ch)?;
+                    if (i..end).contains(&j) {
+                        return Err(bad(format!("cannot move {from} under itself")));
+                    }
+                    (subtree_end(recs, j), levels[j] + 1)
+                }
+            };
+            let root = levels[i];
+            let block: Vec<Rec> = recs.drain(i..end).collect();
+            let block_levels: Vec<usize> = levels.drain(i..end).map(|l| l - root + lvl).collect();
+            // the destination's end moves up if it lay after the block
+            let at = if dest_end > i {
+                dest_end - block.len()
+            } else {
+                dest_end
+            };
+            recs.splice(at..at, block);
+            levels.splice(at..at, block_levels);
+        }
+    }
+    restructure(recs, &levels);
+    ids::assign_ids(recs);
+    Ok(true)
+}
+
+// Split a script line into words (see the module docs); `#` starts a
+// comment only if `comments`.
+fn split_words(line: &str, comments: bool) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            None => return Ok(words),
+            Some('#') if comments => return Ok(words),
+            Some('"') => {
+                chars.next();
+                let mut w = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unterminated quote".into()),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => w.push('\n'),
+                            Some(c @ ('"' | '\\')) => w.push(c),
+                            _ => return Err("bad escape".into()),
+                        },
+                        Some(c) => w.push(c),
+                    }
+                }
+                words.push(w);
+            }
+            Some(_) => {
+                let mut w = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    w.push(c);
+                }
+                words.push(w);
+            }
+        }
+    }
+}
+
+/// Operations of a script with their line numbers.
+pub fn parse_script(text: &str, name: &str) -> io::Result<Vec<(usize, Op)>> {
+    let mut ops = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let words = split_words(line, true).map_err(|e| bad(format!("{name}:{}: {e}", i + 1)))?;
+        if words.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        let op = Op::parse(&words).map_err(|e| bad(format!("{name}:{}: {e}", i + 1)))?;
+        ops.push((i + 1, op));
+    }
+    Ok(ops)
+}
+
+/// One operation given as a single string (an `apply_edits` op): quoting
+/// as in a script line, but `#` is literal and line breaks are refused.
+pub fn parse_op(text: &str) -> io::Result<Op> {
+    if text.contains(['\n', '\r']) {
+        return Err(bad(
+            "line break in operation (use \"\\n\" inside quotes)".into()
+        ));
+    }
+    let words = split_words(text, false).map_err(bad)?;
+    let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+    Op::parse(&words)
+}
+
+/// Apply `ops` in order to a copy of `recs`; the result only if every step
+/// succeeded (and none was declined) without adding validation findings.
+/// A finding counts as already there if the step before had one of the
+/// same kind at the same index or on a heading with the same text (edits
+/// shift indexes and renames change text, but not both at once).
+pub fn apply_all(
+    recs: &[Rec],
+    ops: &[(usize, Op)],
+    name: &str,
+    ch: &mut dyn Chooser,
+) -> io::Result<Option<Vec<Rec>>> {
+    let mut work = recs.to_vec();
+    let keys = |recs: &[Rec], found: Vec<Finding>| -> Vec<(&'static str, usize, Arc<str>)> {
+        found
+            .into_iter()
+            .map(|f| (f.kind, f.rec, recs[f.rec].text.clone()))
+            .collect()
+    };
+    let mut before = keys(&work, validate_findings(&work, false));
+    for (line, op) in ops {
+        let at = |e: io::Error| io::Error::new(e.kind(), format!("{name}:{line}: {e}"));
+        if !apply(&mut work, op, ch).map_err(at)? {
+            return Ok(None);
+        }
+        let now = validate_findings(&work, false);
+        let new = now.iter().find(|f| {
+            !before.iter().any(|(kind, rec, text)| {
+                *kind == f.kind && (*rec == f.rec || **text == *work[f.rec].text)
+            })
+        });
+        if let Some(f) = new {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{name}:{line}: leaves rec #{:03} {}", f.rec, f.detail),
+            ));
+        }
+        before = keys(&work, now);
+    }
+    Ok(Some(work))
+}
+
+pub fn cmd_edit(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut words: Vec<&str> = Vec::new();
+    let mut out: Option<&str> = None;
+    let mut in_place = false;
+    let mut journal = false;
+    let mut compress = false;
+    let mut keep = Preserve::default();
+    let mut interactive = false;
+    let mut script: Option<&str> = None;
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = keep.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        match a.as_str() {
+            "--interactive" => interactive = true,
+            "--in-place" => in_place = true,
+            "--journal" => journal = true,
+            "--compress-headings" => compress = true,
+            "--script" => script = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            _ => words.push(a),
+        }
+    }
+    let Some((&file, op_words)) = words.split_first() else {
+        usage(prog)
+    };
+    let out = match (out, in_place) {
+        (Some(o), false) => o,
+        (None, true) if file != "-" => file,
+        _ => usage(prog),
+    };
+    if journal && !in_place {
+        usage(prog);
+    }
+    if interactive && file == "-" {
+        return Err(bad(
+            "--interactive reads answers from stdin; give a file".into()
+        ));
+    }
+    let (ops, name) = match (script, op_words) {
+        (Some(path), []) => {
+            let text = String::from_utf8_lossy(&read_input(path)?).into_owned();
+            (parse_script(&text, path)?, path)
+        }
+        (None, words) if !words.is_empty() => (vec![(1, Op::parse(words)?)], "edit"),
+        _ => usage(prog),
+    };
+
+    let buf = read_input(file)?;
+    let Doc { recs, framing } = parse_doc(&buf, enc)?;
+    let result = if interactive {
+        let mut prompt = Prompt {
+            input: io::stdin().lock(),
+            out: io::stderr(),
+        };
+        apply_all(&recs, &ops, name, &mut prompt)?
+    } else {
+        apply_all(&recs, &ops, name, &mut Strict)?
+    };
+    let Some(recs) = result else {
+        eprintln!("nothing changed");
+        return Ok(());
+    };
+    let mut recs = prepare_write(recs, None);
+    if compress {
+        compress_headings(&mut recs);
+    }
+    keep.apply(&mut recs);
+    let bytes = keep.serialize(&recs, &framing, enc)?;
+    if in_place {
+        let command = format!("edit {}", args.join(" "));
+        return write_in_place(file, &buf, &bytes, journal, &command);
+    }
+    write_output(out, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    fn sample() -> Vec<Rec> {
+        parse_otl(
+            &otl_file(vec![
+                rec_bytes("Inbox", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Call", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Work", 0x00, M_EXPANDED, -1, None),
+                rec_bytes("Call", A_NOTE, M_EXPANDED, 1, Some(b"old")),
+            ]),
+            "latin1",
+        )
+        .unwrap()
+    }
+
+    fn outline(recs: &[Rec]) -> Vec<String> {
+        heading_paths_capped(recs)
+    }
+
+    fn run(recs: &mut Vec<Rec>, words: &[&str]) -> io::Result<bool> {
+        apply(recs, &Op::parse(words)?, &mut Strict)
+    }
+
+    #[test]
+    fn edit_operations() {
+        let mut recs = sample();
+        run(&mut recs, &["add", "Work", "Plan"]).unwrap();
+        run(&mut recs, &["move", "Inbox", "Work"]).unwrap();
+        run(&mut recs, &["rename", "rec:0", "Job"]).unwrap();
+        assert_eq!(
+            outline(&recs),
+            ["Job", "Job/Call", "Job/Plan", "Job/Inbox", "Job/Inbox/Call"]
+        );
+        assert!(recs[1].flags.has_next_sibling && !recs[3].flags.has_next_sibling);
+        run(&mut recs, &["note", "Job/Plan", "a\nb"]).unwrap();
+        assert_eq!(recs[2].note.as_deref(), Some("a\r\nb"));
+        run(&mut recs, &["delete", "Job/Inbox"]).unwrap();
+        run(&mut recs, &["move", "Job/Plan", "/"]).unwrap();
+        assert_eq!(outline(&recs), ["Job", "Job/Call", "Plan"]);
+        assert!(run(&mut recs, &["move", "Job", "Job/Call"]).is_err());
+        assert!(run(&mut recs, &["frob", "Job"]).is_err());
+    }
+
+    #[test]
+    fn scripts_are_all_or_nothing() {
+        let recs = sample();
+        let ops = parse_script(
+            "# tidy up\nadd / \"Done \\\"old\\\"\"\n\nmove rec:1 rec:4  # from Inbox\n",
+            "s.txt",
+        )
+        .unwrap();
+        assert_eq!(ops[0].0, 2);
+        assert_eq!(ops[1].0, 4);
+        let out = apply_all(&recs, &ops, "s.txt", &mut Strict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            outline(&out),
+            [
+                "Inbox",
+                "Work",
+                "Work/Call",
+                "Done \"old\"",
+                "Done \"old\"/Call"
+            ]
+        );
+
+        let failing = parse_script("delete Inbox\nrename Inbox X\n", "s.txt").unwrap();
+        let e = apply_all(&recs, &failing, "s.txt", &mut Strict).unwrap_err();
+        assert!(
+            e.to_string().starts_with("s.txt:2: no record matches"),
+            "{e}"
+        );
+        assert!(parse_script("note \"x\n", "s.txt")
+            .unwrap_err()
+            .to_string()
+            .starts_with("s.txt:1:"));
+        assert!(parse_script("frob x\n", "s.txt").is_err());
+
+        // a finding the file already had may move or be renamed
+        let mut odd = recs.clone();
+        odd[2].attr |= 0x40;
+        let ops =
+            parse_script("add / First\nmove rec:0 rec:4\nrename Work Job\n", "s.txt").unwrap();
+        let out = apply_all(&odd, &ops, "s.txt", &mut Strict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(outline(&out)[..2], ["Job", "Job/Call"]);
+    }
+
+    #[test]
+    fn ambiguous_paths_need_a_pick() {
+        let mut recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("x", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+                rec_bytes("x", A_NOTE, M_EXPANDED, 0, Some(b"keep?")),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let op = Op::parse(&["note", "A/x", ""]).unwrap();
+        assert!(apply(&mut recs.clone(), &op, &mut Strict).is_err());
+
+        // pick the second candidate (after an invalid answer), then decline
+        let mut p = Prompt {
+            input: &b"7\n2\nn\n"[..],
+            out: Vec::new(),
+        };
+        assert!(!apply(&mut recs, &op, &mut p).unwrap());
+        assert!(recs[2].note.is_some());
+        let shown = String::from_utf8(p.out).unwrap();
+        assert!(shown.contains("  2) rec #002 "), "{shown}");
+
+        let mut p = Prompt {
+            input: &b"2\ny\n"[..],
+            out: Vec::new(),
+        };
+        assert!(apply(&mut recs, &op, &mut p).unwrap());
+        assert!(recs[2].note.is_none() && !recs[2].flags.has_note);
+    }
+}
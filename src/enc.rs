@@ -0,0 +1,217 @@
+//! Note encodings beyond UTF-8/Latin-1: Windows-1252, DOS code pages 437 (US)
+//! and 850 (Western Europe), and `mixed`, a per-note guess for corpora
+//! edited with different tools.
+//!
+//! `mixed` decodes a note as UTF-8 when it is valid UTF-8; otherwise it
+//! weighs cp1252 evidence (smart quotes/dashes 0x91-0x97, 0x85 ellipsis,
+//! accented lowercase 0xE0-0xFF next to ASCII letters) against cp437
+//! evidence (accented letters 0x80-0xA5 next to ASCII letters, box drawing
+//! 0xB0-0xDF) and picks the stronger, cp437 on a tie (SideKick is a DOS
+//! program). On write each note goes back in the encoding it was read as,
+//! unless an edit added characters that encoding lacks.
+//!
+//! Independently of the encoding, a note holding control bytes other than
+//! tab, CR, LF and form feed (pasted spreadsheet ranges and the like) is
+//! treated as binary: shown as hex and written back byte for byte.
+
+/// cp1252 0x80..=0x9F; the five undefined bytes map to the C1 controls.
+const CP1252_80: &str = "€\u{81}‚ƒ„…†‡ˆ‰Š‹Œ\u{8d}Ž\u{8f}\u{90}‘’“”•–—˜™š›œ\u{9d}žŸ";
+
+/// cp437 0x80..=0xFF.
+const CP437_80: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»\
+                        ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+                        αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// cp850 0x80..=0xFF.
+const CP850_80: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜø£Ø×ƒáíóúñÑªº¿®¬½¼¡«»\
+                        ░▒▓│┤ÁÂÀ©╣║╗╝¢¥┐└┴┬├─┼ãÃ╚╔╩╦╠═╬¤ðÐÊËÈıÍÎÏ┘┌█▄¦Ì▀\
+                        ÓßÔÒõÕµþÞÚÛÙýÝ¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}";
+
+fn high(table: &str, b: u8) -> char {
+    table
+        .chars()
+        .nth(usize::from(b - 0x80))
+        .expect("table covers the byte")
+}
+
+pub fn decode_cp1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9f => high(CP1252_80, b),
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn decode_dos(table: &str, bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| if b < 0x80 { b as char } else { high(table, b) })
+        .collect()
+}
+
+pub fn decode_cp437(bytes: &[u8]) -> String {
+    decode_dos(CP437_80, bytes)
+}
+
+pub fn decode_cp850(bytes: &[u8]) -> String {
+    decode_dos(CP850_80, bytes)
+}
+
+fn encode_with(note: &str, byte: impl Fn(char) -> Option<u8>) -> Vec<u8> {
+    note.chars().map(|c| byte(c).unwrap_or(b'?')).collect()
+}
+
+fn cp1252_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        0..=0x7f | 0xa0..=0xff => Some(c as u8),
+        _ => CP1252_80
+            .chars()
+            .position(|t| t == c)
+            .map(|i| 0x80 + i as u8),
+    }
+}
+
+fn dos_byte(table: &str, c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+    table.chars().position(|t| t == c).map(|i| 0x80 + i as u8)
+}
+
+pub fn encode_cp1252(note: &str) -> Vec<u8> {
+    encode_with(note, cp1252_byte)
+}
+
+pub fn encode_cp437(note: &str) -> Vec<u8> {
+    encode_with(note, |c| dos_byte(CP437_80, c))
+}
+
+pub fn encode_cp850(note: &str) -> Vec<u8> {
+    encode_with(note, |c| dos_byte(CP850_80, c))
+}
+
+/// Which encoding `mixed` uses for these note bytes.
+pub fn guess(bytes: &[u8]) -> &'static str {
+    if std::str::from_utf8(bytes).is_ok() {
+        return "utf8";
+    }
+    let letter = |i: Option<usize>| {
+        i.and_then(|i| bytes.get(i))
+            .is_some_and(u8::is_ascii_alphabetic)
+    };
+    let (mut win, mut dos) = (0, 0);
+    for (i, &b) in bytes.iter().enumerate() {
+        let in_word = letter(i.checked_sub(1)) || letter(Some(i + 1));
+        match b {
+            0x91..=0x94 | 0x96 | 0x97 | 0x85 => win += 2,
+            0xe0..=0xff if in_word => win += 1,
+            0x80..=0xa5 if in_word => dos += 1,
+            0xb0..=0xdf => dos += 1,
+            _ => {}
+        }
+    }
+    if win > dos {
+        "cp1252"
+    } else {
+        "cp437"
+    }
+}
+
+/// `mixed` on write: the encoding the note was read as (`guessed`, see
+/// `Rec::note_guess`) when every char fits it, so unchanged notes keep
+/// their bytes; else cp1252 when every char fits, UTF-8 otherwise.
+pub fn encode_mixed(note: &str, guessed: Option<&str>) -> Vec<u8> {
+    let fits = |enc: &str| match enc {
+        "utf8" => true,
+        "cp1252" => note.chars().all(|c| cp1252_byte(c).is_some()),
+        "cp437" => note.chars().all(|c| dos_byte(CP437_80, c).is_some()),
+        _ => false,
+    };
+    match guessed
+        .filter(|g| fits(g))
+        .unwrap_or(if fits("cp1252") { "cp1252" } else { "utf8" })
+    {
+        "cp1252" => encode_cp1252(note),
+        "cp437" => encode_cp437(note),
+        _ => note.as_bytes().to_vec(),
+    }
+}
+
+/// True when a note is not text under any of our encodings.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Inverse of `to_hex`; whitespace between digit pairs is ignored.
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|p| u8::from_str_radix(std::str::from_utf8(p).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_complete_and_invertible() {
+        assert_eq!(CP1252_80.chars().count(), 32);
+        assert_eq!(CP437_80.chars().count(), 128);
+        assert_eq!(CP850_80.chars().count(), 128);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(encode_cp437(&decode_cp437(&all)), all);
+        assert_eq!(encode_cp850(&decode_cp850(&all)), all);
+        assert_eq!(decode_cp850(b"Gr\x94\xe1e \x9b"), "Größe ø");
+        assert_eq!(encode_cp1252(&decode_cp1252(&all)), all);
+    }
+
+    #[test]
+    fn mixed_guesses_per_note() {
+        assert_eq!(guess("café".as_bytes()), "utf8");
+        assert_eq!(guess(b"don\x92t \x93quoted\x94"), "cp1252");
+        assert_eq!(guess(b"caf\xe9"), "cp1252");
+        assert_eq!(guess(b"caf\x82 \xc4\xc4\xc4"), "cp437");
+        assert_eq!(decode_cp1252(b"don\x92t"), "don’t");
+        assert_eq!(decode_cp437(b"caf\x82"), "café");
+    }
+
+    #[test]
+    fn mixed_writes_each_note_as_it_was_read() {
+        for raw in ["café ─".as_bytes(), b"caf\x82 \xc4\xc4", b"don\x92t"] {
+            let g = guess(raw);
+            let text = crate::decode_note(raw, g);
+            assert_eq!(encode_mixed(&text, Some(g)), raw, "{g}");
+        }
+        // an edit that no longer fits the guess falls back
+        assert_eq!(encode_mixed("caf\u{e9}", Some("cp437")), b"caf\x82");
+        assert_eq!(
+            encode_mixed("\u{2192}", Some("cp437")),
+            "\u{2192}".as_bytes()
+        );
+        assert_eq!(encode_mixed("caf\u{e9}", None), b"caf\xe9");
+    }
+
+    #[test]
+    fn binary_notes_and_hex() {
+        assert!(!is_binary(b"line one\r\n\tline two\x0c"));
+        assert!(is_binary(b"A1\x00\x00\x07B2"));
+        let bytes = b"\x00\x1a\xffok";
+        assert_eq!(to_hex(bytes), "001aff6f6b");
+        assert_eq!(from_hex("001a ff\n6f6b").unwrap(), bytes);
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
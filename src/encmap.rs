@@ -0,0 +1,198 @@
+//! Per-file note encodings for batch runs: an `encodings.toml` manifest
+//! mapping globs to `--enc` values, for archives that mix US cp437, German
+//! cp850 and later cp1252 files.
+//!
+//! ```toml
+//! # glob = encoding; the last matching line wins
+//! "*" = "cp437"
+//! "de/**" = "cp850"
+//! "1998/*.otl" = "cp1252"
+//! ```
+//!
+//! Globs match the path relative to the manifest's directory, with `/`
+//! separators and ignoring case (DOS file names): `*` and `?` stay within
+//! one path segment, `**` spans segments, and a glob without `/` matches the
+//! file name in any directory. `check` and `diff-dir` read `encodings.toml`
+//! from each directory they are given (the git filters `clean`/`smudge`
+//! from the current directory), or the file named by `--enc-map`; files no
+//! line matches use `--enc`.
+
+use crate::config::{parse_value, strip_comment, Value};
+use crate::encscan::CANDIDATES;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST: &str = "encodings.toml";
+
+#[derive(Debug, Clone)]
+pub struct EncodingMap {
+    dir: PathBuf,                 // globs are relative to this
+    scoped: bool,                 // only for files below `dir`
+    rules: Vec<(String, String)>, // (lowercased glob, encoding)
+}
+
+fn bad(path: &str, line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{path}:{line_no}: {msg}"),
+    )
+}
+
+/// `*`, `?` and `**` glob match of `pat` against `text`, both split on '/'.
+fn glob(pat: &[&str], text: &[&str]) -> bool {
+    match (pat.first(), text.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => glob(&pat[1..], text) || (!text.is_empty() && glob(pat, &text[1..])),
+        (Some(p), Some(t)) => segment(p.as_bytes(), t.as_bytes()) && glob(&pat[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+fn segment(p: &[u8], t: &[u8]) -> bool {
+    match (p.first(), t.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => segment(&p[1..], t) || (!t.is_empty() && segment(p, &t[1..])),
+        (Some(b'?'), Some(_)) => segment(&p[1..], &t[1..]),
+        (Some(a), Some(b)) => a == b && segment(&p[1..], &t[1..]),
+        _ => false,
+    }
+}
+
+impl EncodingMap {
+    pub fn parse(text: &str, path: &str, dir: &Path) -> io::Result<EncodingMap> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            // a quoted glob may itself hold '='
+            let kv = match line.strip_prefix('"') {
+                Some(rest) => rest
+                    .split_once('"')
+                    .and_then(|(k, v)| Some((k, v.trim_start().strip_prefix('=')?))),
+                None => line.split_once('=').map(|(k, v)| (k.trim(), v)),
+            };
+            let Some((glob, v)) = kv else {
+                return Err(bad(path, i + 1, "expected \"glob\" = \"encoding\""));
+            };
+            let Some(Value::Str(enc)) = parse_value(v.trim()) else {
+                return Err(bad(path, i + 1, "expected a quoted encoding"));
+            };
+            if !CANDIDATES.contains(&enc.as_str()) {
+                return Err(bad(
+                    path,
+                    i + 1,
+                    &format!(
+                        "unknown encoding {enc:?} (one of {})",
+                        CANDIDATES.join(", ")
+                    ),
+                ));
+            }
+            rules.push((glob.to_lowercase(), enc));
+        }
+        Ok(EncodingMap {
+            dir: dir.to_path_buf(),
+            scoped: false,
+            rules,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<EncodingMap> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        EncodingMap::parse(&text, &path.to_string_lossy(), dir)
+    }
+
+    /// The manifest in directory `dir`, if it has one.
+    pub fn in_dir(dir: &Path) -> io::Result<Option<EncodingMap>> {
+        let path = dir.join(MANIFEST);
+        if !dir.is_dir() || !path.is_file() {
+            return Ok(None);
+        }
+        let mut m = EncodingMap::load(&path)?;
+        m.scoped = true;
+        Ok(Some(m))
+    }
+
+    /// The encoding for `file` (its path below the manifest's directory, or
+    /// as given when outside), if a line matches it.
+    pub fn enc_for(&self, file: &Path) -> Option<&str> {
+        let rel = file.strip_prefix(&self.dir).unwrap_or(file);
+        let rel = rel.to_string_lossy().replace('\\', "/").to_lowercase();
+        let parts: Vec<&str> = rel
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect();
+        self.rules
+            .iter()
+            .rev()
+            .find(|(g, _)| {
+                let pat: Vec<&str> = g.split('/').collect();
+                if pat.len() == 1 {
+                    parts.last().is_some_and(|name| glob(&pat, &[name]))
+                } else {
+                    glob(&pat, &parts)
+                }
+            })
+            .map(|(_, e)| e.as_str())
+    }
+}
+
+/// Manifests for a batch run: the `--enc-map` file (for every file), else
+/// each directory argument's own `encodings.toml` (for the files below it).
+pub fn manifests(explicit: Option<&str>, roots: &[PathBuf]) -> io::Result<Vec<EncodingMap>> {
+    if let Some(p) = explicit {
+        return Ok(vec![EncodingMap::load(Path::new(p))?]);
+    }
+    let mut out = Vec::new();
+    for r in roots {
+        out.extend(EncodingMap::in_dir(r)?);
+    }
+    Ok(out)
+}
+
+/// The encoding for `file`: the first manifest with a matching line, else
+/// `default`.
+pub fn enc_for<'a>(maps: &'a [EncodingMap], file: &Path, default: &'a str) -> &'a str {
+    maps.iter()
+        .filter(|m| !m.scoped || file.starts_with(&m.dir))
+        .find_map(|m| m.enc_for(file))
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_matching_glob_wins() {
+        let m = EncodingMap::parse(
+            "# corpus\n\"*\" = \"cp437\"\n\"de/**\" = \"cp850\"  # German\n\
+             \"1998/*.OTL\" = \"cp1252\"\n\"de/old/?.otl\" = \"cp437\"\n",
+            "encodings.toml",
+            Path::new("arch"),
+        )
+        .unwrap();
+        let enc = |p: &str| m.enc_for(Path::new(p));
+        assert_eq!(enc("arch/US/PLANS.OTL"), Some("cp437"));
+        assert_eq!(enc("arch/de/x/y/BRIEF.OTL"), Some("cp850"));
+        assert_eq!(enc("arch/DE/old/A.otl"), Some("cp437"));
+        assert_eq!(enc("arch/1998/todo.otl"), Some("cp1252"));
+        assert_eq!(enc("arch/1998/sub/todo.otl"), Some("cp437"));
+        let mut maps = [m];
+        assert_eq!(
+            enc_for(&maps, Path::new("other/de/a.otl"), "latin1"),
+            "cp437"
+        );
+        maps[0].scoped = true;
+        assert_eq!(
+            enc_for(&maps, Path::new("other/de/a.otl"), "latin1"),
+            "latin1"
+        );
+        assert!(EncodingMap::parse("\"*\" = \"cp999\"\n", "e.toml", Path::new("")).is_err());
+        assert!(EncodingMap::parse("\"*\" cp437\n", "e.toml", Path::new("")).is_err());
+    }
+}
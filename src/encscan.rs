@@ -0,0 +1,178 @@
+//! `encscan` subcommand: which 8-bit bytes an archive's notes use, what each
+//! `--enc` candidate makes of them, and which candidates round-trip.
+//!
+//! Only notes carry encoded text: heading bytes are 7-bit, their high bit
+//! folds a following space (see `decode_heading`). Binary notes (see enc.rs)
+//! are written back byte for byte under any encoding and are left out.
+//!
+//! A candidate is lossless for a note when reading it with `--enc E` keeps
+//! every byte (E re-encodes the text to the same bytes) and writing the text
+//! with `--write-enc W` (W = E unless given) gives bytes that decode under W
+//! to the same text.
+
+use crate::write::encode_note;
+use crate::{decode_note, enc, parse_otl, read_input, usage, Rec};
+use std::collections::BTreeMap;
+use std::io;
+
+pub const CANDIDATES: &[&str] = &[
+    "utf8", "latin1", "cp1252", "cp437", "cp850", "mixed", "ascii",
+];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Scan {
+    pub notes: usize,
+    pub binary: usize,
+    pub eight_bit: usize,  // text notes with a byte >= 0x80
+    pub valid_utf8: usize, // ... of which are valid UTF-8
+    pub bytes: BTreeMap<u8, usize>,
+    /// candidate -> notes that would not survive the round trip
+    pub lossy: BTreeMap<&'static str, usize>,
+}
+
+fn show(c: char) -> String {
+    if c.is_control() {
+        format!("U+{:04X}", c as u32)
+    } else {
+        c.to_string()
+    }
+}
+
+pub fn scan(buf: &[u8], recs: &[Rec], write_enc: Option<&str>) -> Scan {
+    let mut s = Scan::default();
+    for &c in CANDIDATES {
+        s.lossy.insert(c, 0);
+    }
+    for r in recs {
+        let Some(off) = r.off_note else { continue };
+        let raw = &buf[off..off + r.note_len];
+        s.notes += 1;
+        if enc::is_binary(raw) {
+            s.binary += 1;
+            continue;
+        }
+        if !raw.iter().any(|&b| b >= 0x80) {
+            continue;
+        }
+        s.eight_bit += 1;
+        if std::str::from_utf8(raw).is_ok() {
+            s.valid_utf8 += 1;
+        }
+        for &b in raw.iter().filter(|&&b| b >= 0x80) {
+            *s.bytes.entry(b).or_default() += 1;
+        }
+        // `mixed` writes a note back in the encoding it guessed for it
+        let encode = |text: &str, e: &str| match e {
+            "mixed" => enc::encode_mixed(text, Some(enc::guess(raw))),
+            _ => encode_note(text, e),
+        };
+        for &c in CANDIDATES {
+            let w = write_enc.unwrap_or(c);
+            let text = decode_note(raw, c);
+            let read_ok = encode(&text, c) == *raw;
+            if !read_ok || decode_note(&encode(&text, w), w) != text {
+                *s.lossy.entry(c).or_default() += 1;
+            }
+        }
+    }
+    s
+}
+
+pub fn report(s: &Scan, write_enc: Option<&str>) -> String {
+    let mut out = format!(
+        "notes: {} ({} with bytes >= 0x80, {} of those valid UTF-8; {} binary, skipped)\n",
+        s.notes, s.eight_bit, s.valid_utf8, s.binary
+    );
+    if s.bytes.is_empty() {
+        out.push_str("no 8-bit bytes: every --enc reads these notes the same\n");
+        return out;
+    }
+    out.push_str("byte  count  latin1  cp1252  cp437\n");
+    for (&b, &n) in &s.bytes {
+        out.push_str(&format!(
+            "0x{b:02x} {n:>6}  {:<6}  {:<6}  {}\n",
+            show(b as char),
+            show(enc::decode_cp1252(&[b]).chars().next().unwrap_or('?')),
+            show(enc::decode_cp437(&[b]).chars().next().unwrap_or('?')),
+        ));
+    }
+    match write_enc {
+        Some(w) => out.push_str(&format!("round trip (--enc E, --write-enc {w}):\n")),
+        None => out.push_str("round trip (--enc E, written back with E):\n"),
+    }
+    for (c, n) in &s.lossy {
+        let verdict = if *n == 0 {
+            "lossless".to_string()
+        } else {
+            format!("{n} note(s) lossy")
+        };
+        out.push_str(&format!("  {c:<7} {verdict}\n"));
+    }
+    out
+}
+
+pub fn cmd_encscan(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut write_enc: Option<&str> = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--write-enc" => {
+                let w = it.next().unwrap_or_else(|| usage(prog));
+                if !CANDIDATES.contains(&w.as_str()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "--write-enc: expected one of {}, got {w:?}",
+                            CANDIDATES.join(", ")
+                        ),
+                    ));
+                }
+                write_enc = Some(w);
+            }
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let Some(file) = file else { usage(prog) };
+    let buf = read_input(file)?;
+    let recs = parse_otl(&buf, "latin1")?;
+    print!("{}", report(&scan(&buf, &recs, write_enc), write_enc));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn scans_note_bytes_and_round_trips() {
+        let buf = otl_file(vec![
+            rec_bytes(
+                "a",
+                A_NOTE | A_SIBFOLLOWS,
+                M_EXPANDED,
+                0,
+                Some(b"caf\x82 \x93q\x94"),
+            ),
+            rec_bytes("b", A_NOTE | A_SIBFOLLOWS, M_EXPANDED, 0, Some(b"plain")),
+            rec_bytes("c", A_NOTE, M_EXPANDED, 0, Some(b"\x01\x02\xff")),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let s = scan(&buf, &recs, None);
+        assert_eq!((s.notes, s.eight_bit, s.binary, s.valid_utf8), (3, 1, 1, 0));
+        assert_eq!(
+            s.bytes.keys().copied().collect::<Vec<_>>(),
+            [0x82, 0x93, 0x94]
+        );
+        assert_eq!(s.lossy["latin1"], 0);
+        assert_eq!(s.lossy["cp437"], 0);
+        assert_eq!(s.lossy["ascii"], 1);
+        // cp1252 text written as cp437: the curly quotes have no cp437 byte
+        assert_eq!(scan(&buf, &recs, Some("cp437")).lossy["cp1252"], 1);
+        let text = report(&s, None);
+        assert!(text.contains("0x82      1  U+0082  ‚       é\n"), "{text}");
+    }
+}
@@ -0,0 +1,117 @@
+//! `--enex` export: an Evernote export file (also imported by Joplin and
+//! Notion).
+//!
+//! Each heading that has a note becomes one ENEX note titled by the heading;
+//! the headings above it become its tags, so the outline structure survives
+//! as a tag hierarchy in the importing app. Headings without notes only
+//! contribute tags.
+
+use crate::gen::civil_from_days;
+use crate::markup::html_escape;
+use crate::Node;
+
+// ENEX/ENML timestamp: 20261016T113000Z
+fn enex_time(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    let t = secs % 86_400;
+    format!(
+        "{y:04}{m:02}{d:02}T{:02}{:02}{:02}Z",
+        t / 3600,
+        t / 60 % 60,
+        t % 60
+    )
+}
+
+// Evernote rejects commas in tag names and trims surrounding space.
+fn tag_name(text: &str) -> String {
+    text.replace(',', " ").trim().to_string()
+}
+
+fn enml(note: &str) -> String {
+    let mut body = String::new();
+    for line in note.replace("\r\n", "\n").split('\n') {
+        if line.is_empty() {
+            body.push_str("<div><br/></div>");
+        } else {
+            body.push_str(&format!("<div>{}</div>", html_escape(line)));
+        }
+    }
+    // escaping `>` also keeps `]]>` out of the CDATA section
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <!DOCTYPE en-note SYSTEM \"http://xml.evernote.com/pub/enml2.dtd\">\
+         <en-note>{body}</en-note>"
+    )
+}
+
+fn notes(nodes: &[Node], path: &mut Vec<String>, stamp: &str, out: &mut String) {
+    for n in nodes {
+        if n.synthetic {
+            notes(&n.children, path, stamp, out);
+            continue;
+        }
+        if let Some(note) = &n.note {
+            let title = if n.text.trim().is_empty() {
+                "(untitled)"
+            } else {
+                n.text.trim()
+            };
+            out.push_str("  <note>\n");
+            out.push_str(&format!("    <title>{}</title>\n", html_escape(title)));
+            out.push_str(&format!(
+                "    <content><![CDATA[{}]]></content>\n",
+                enml(note)
+            ));
+            out.push_str(&format!("    <created>{stamp}</created>\n"));
+            out.push_str(&format!("    <updated>{stamp}</updated>\n"));
+            for t in path.iter().filter(|t| !t.is_empty()) {
+                out.push_str(&format!("    <tag>{}</tag>\n", html_escape(t)));
+            }
+            out.push_str("  </note>\n");
+        }
+        path.push(tag_name(&n.text));
+        notes(&n.children, path, stamp, out);
+        path.pop();
+    }
+}
+
+/// The whole export file; `now` (seconds since 1970) dates the export and
+/// every note.
+pub fn render_enex(nodes: &[Node], now: u64) -> String {
+    let stamp = enex_time(now);
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE en-export SYSTEM \"http://xml.evernote.com/pub/evernote-export4.dtd\">\n\
+         <en-export export-date=\"{stamp}\" application=\"otl\" version=\"{}\">\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    notes(nodes, &mut Vec::new(), &stamp, &mut out);
+    out.push_str("</en-export>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn one_note_per_noted_heading_tagged_by_path() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Work, 2026", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("Plan <A>", A_NOTE, M_EXPANDED, 1, Some(b"x & y\r\n\r\n]]>")),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let out = render_enex(&build_tree_capped(&recs), 1_792_150_200);
+        assert_eq!(out.matches("<note>").count(), 1);
+        assert!(out.contains("export-date=\"20261016T113000Z\""), "{out}");
+        assert!(out.contains("<title>Plan &lt;A&gt;</title>"));
+        assert!(out.contains("<tag>Work  2026</tag>"));
+        assert!(out
+            .contains("<en-note><div>x &amp; y</div><div><br/></div><div>]]&gt;</div></en-note>"));
+    }
+}
@@ -0,0 +1,298 @@
+//! `flatten` / `unflatten` subcommands.
+//!
+//! `flatten` turns every heading into a top-level one named by its
+//! breadcrumb ("A > B > C"); `unflatten` rebuilds the hierarchy from such
+//! names, creating any missing parents. Input is .OTL (sniffed by MAGIC) or
+//! text; output is .OTL when `-o` names a .OTL file (or `--to otl`), text
+//! otherwise. Flat text lines are `breadcrumb[<TAB>note]`, with note line
+//! breaks, tabs and backslashes escaped as \n, \t, \\. Within a breadcrumb a
+//! heading's own backslashes and separators are escaped as \\ and \<sep>,
+//! so "1 > 2" under "Math" flattens to "Math > 1\ > 2" and comes back whole.
+
+use crate::import::parse_indented;
+use crate::ops::restructure;
+use crate::write::serialize_recs;
+use crate::{
+    ids, parse_otl, read_input, rec_levels_capped, usage, write_output, Rec, A_NOTE, MAGIC,
+};
+use std::io;
+
+pub const DEFAULT_SEP: &str = " > ";
+
+fn escape_note(note: &str) -> String {
+    let mut out = String::new();
+    let mut it = note.chars().peekable();
+    while let Some(ch) = it.next() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\r' if it.peek() == Some(&'\n') => {}
+            '\n' => out.push_str("\\n"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn unescape_note(s: &str) -> String {
+    let mut out = String::new();
+    let mut it = s.chars();
+    while let Some(ch) = it.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match it.next() {
+            Some('n') => out.push_str("\r\n"),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn escape_crumb(heading: &str, sep: &str) -> String {
+    heading
+        .replace('\\', "\\\\")
+        .replace(sep, &format!("\\{sep}"))
+}
+
+// The headings of a breadcrumb: split at unescaped `sep`, escapes undone.
+fn split_crumbs(text: &str, sep: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        let part = parts.last_mut().expect("starts with one part");
+        if let Some(r) = rest.strip_prefix(sep) {
+            parts.push(String::new());
+            rest = r;
+        } else if let Some((lit, r)) = rest.strip_prefix('\\').and_then(|r| {
+            r.strip_prefix(sep)
+                .map(|r| (sep, r))
+                .or(r.strip_prefix('\\').map(|r| ("\\", r)))
+        }) {
+            part.push_str(lit);
+            rest = r;
+        } else {
+            part.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    parts
+}
+
+/// Records with breadcrumb headings, all at level 0.
+pub fn flatten(recs: &[Rec], sep: &str) -> Vec<Rec> {
+    let levels = rec_levels_capped(recs);
+    let mut stack: Vec<String> = Vec::new();
+    let mut out: Vec<Rec> = Vec::with_capacity(recs.len());
+    for (r, &lvl) in recs.iter().zip(&levels) {
+        stack.truncate(lvl);
+        while stack.len() < lvl {
+            stack.push(String::new());
+        }
+        stack.push(escape_crumb(&r.text, sep));
+        let mut f = r.clone();
+        f.text = stack.join(sep).into();
+        f.len_text = f.text.chars().count();
+        out.push(f);
+    }
+    let flat = vec![0; out.len()];
+    restructure(&mut out, &flat);
+    ids::assign_ids(&mut out);
+    out
+}
+
+/// Rebuild nesting from breadcrumb headings. Consecutive records share
+/// parents as long as their breadcrumbs share a prefix; missing parents are
+/// created as plain headings.
+pub fn unflatten(recs: &[Rec], sep: &str) -> Vec<Rec> {
+    let mut out: Vec<Rec> = Vec::new();
+    let mut levels: Vec<usize> = Vec::new();
+    let mut stack: Vec<String> = Vec::new();
+    for r in recs {
+        let parts = split_crumbs(&r.text, sep);
+        let (leaf, parents) = parts.split_last().expect("split yields one part");
+        let common = stack
+            .iter()
+            .zip(parents.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        stack.truncate(common);
+        for p in &parents[common..] {
+            out.push(Rec::new(p.clone(), 0x00, 0xFFFF, 0, None));
+            levels.push(stack.len());
+            stack.push(p.clone());
+        }
+        let mut n = r.clone();
+        n.text = leaf.as_str().into();
+        n.len_text = n.text.chars().count();
+        out.push(n);
+        levels.push(stack.len());
+        stack.push(leaf.to_string());
+    }
+    restructure(&mut out, &levels);
+    ids::assign_ids(&mut out);
+    out
+}
+
+fn flat_text(recs: &[Rec]) -> String {
+    let mut out = String::new();
+    for r in recs {
+        out.push_str(&r.text);
+        if let Some(n) = &r.note {
+            out.push('\t');
+            out.push_str(&escape_note(n));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_flat_text(text: &str) -> Vec<Rec> {
+    let mut out: Vec<Rec> = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| match l.split_once('\t') {
+            Some((h, n)) => Rec::new(h.to_string(), A_NOTE, 0xFFFF, 0, Some(unescape_note(n))),
+            None => Rec::new(l.to_string(), 0x00, 0xFFFF, 0, None),
+        })
+        .collect();
+    let flat = vec![0; out.len()];
+    restructure(&mut out, &flat);
+    out
+}
+
+fn indented_text(recs: &[Rec]) -> String {
+    let mut out = String::new();
+    for (r, lvl) in recs.iter().zip(rec_levels_capped(recs)) {
+        out.push_str(&format!("{}{}\n", "  ".repeat(lvl), r.text));
+    }
+    out
+}
+
+struct Opts<'a> {
+    file: &'a str,
+    out: &'a str,
+    sep: String,
+    to_otl: bool,
+    enc: &'a str,
+}
+
+fn parse_opts<'a>(prog: &str, args: &'a [String]) -> Opts<'a> {
+    let mut file: Option<&str> = None;
+    let mut out = "-";
+    let mut sep = DEFAULT_SEP.to_string();
+    let mut to: Option<&str> = None;
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--sep" => sep = it.next().unwrap_or_else(|| usage(prog)).clone(),
+            "--to" => to = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "-o" => out = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let to_otl = match to {
+        Some("otl") => true,
+        Some("text") => false,
+        Some(_) => usage(prog),
+        None => out.to_ascii_lowercase().ends_with(".otl"),
+    };
+    if sep.is_empty() {
+        usage(prog);
+    }
+    Opts {
+        file: file.unwrap_or_else(|| usage(prog)),
+        out,
+        sep,
+        to_otl,
+        enc,
+    }
+}
+
+pub fn cmd_flatten(prog: &str, args: &[String]) -> io::Result<()> {
+    let o = parse_opts(prog, args);
+    let buf = read_input(o.file)?;
+    let recs = if buf.starts_with(&MAGIC) {
+        parse_otl(&buf, o.enc)?
+    } else {
+        parse_indented(&String::from_utf8_lossy(&buf))
+    };
+    let flat = flatten(&recs, &o.sep);
+    if o.to_otl {
+        write_output(o.out, &serialize_recs(&flat, o.enc)?)
+    } else {
+        write_output(o.out, flat_text(&flat).as_bytes())
+    }
+}
+
+pub fn cmd_unflatten(prog: &str, args: &[String]) -> io::Result<()> {
+    let o = parse_opts(prog, args);
+    let buf = read_input(o.file)?;
+    let flat = if buf.starts_with(&MAGIC) {
+        parse_otl(&buf, o.enc)?
+    } else {
+        parse_flat_text(&String::from_utf8_lossy(&buf))
+    };
+    let recs = unflatten(&flat, &o.sep);
+    if o.to_otl {
+        write_output(o.out, &serialize_recs(&recs, o.enc)?)
+    } else {
+        write_output(o.out, indented_text(&recs).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::M_EXPANDED;
+
+    #[test]
+    fn flatten_then_unflatten_restores_structure() {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", A_NOTE, M_EXPANDED, 1, Some(b"n1\r\n\tn2")),
+            rec_bytes("C", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("D", 0x00, M_EXPANDED, -2, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let flat = flatten(&recs, DEFAULT_SEP);
+        let text = flat_text(&flat);
+        assert_eq!(text, "A\nA > B\tn1\\n\\tn2\nA > B > C\nD\n");
+
+        let back = unflatten(&parse_flat_text(&text), DEFAULT_SEP);
+        assert_eq!(indented_text(&back), "A\n  B\n    C\nD\n");
+        assert_eq!(back[1].note.as_deref(), Some("n1\r\n\tn2"));
+        assert_eq!(rec_levels_capped(&back), rec_levels_capped(&recs));
+    }
+
+    #[test]
+    fn unflatten_creates_missing_parents() {
+        let flat = parse_flat_text("X > Y > Z\nX > W\n");
+        let recs = unflatten(&flat, DEFAULT_SEP);
+        assert_eq!(indented_text(&recs), "X\n  Y\n    Z\n  W\n");
+    }
+
+    #[test]
+    fn separators_inside_headings_survive() {
+        let buf = otl_file(vec![
+            rec_bytes("Math", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("x", 0x00, M_EXPANDED, 1, None),
+        ]);
+        let mut recs = parse_otl(&buf, "latin1").unwrap();
+        recs[1].text = "2 > 1 \\ \u{e9}t\u{e9}".into();
+        let flat = flatten(&recs, DEFAULT_SEP);
+        assert_eq!(&*flat[1].text, "Math > 2\\ > 1 \\\\ \u{e9}t\u{e9}");
+        assert_eq!(flat[1].len_text, 20);
+        let back = unflatten(&flat, DEFAULT_SEP);
+        assert_eq!(back.len(), 2);
+        assert_eq!(back[1].text, recs[1].text);
+        assert_eq!(rec_levels_capped(&back), [0, 1]);
+    }
+}
@@ -0,0 +1,232 @@
+//! `gen` subcommand: random outlines (benchmarks, fuzz seeds) and starter
+//! templates (fresh files for SideKick under DOSBox).
+
+use crate::import::parse_indented;
+use crate::ops::restructure;
+use crate::write::serialize_recs;
+use crate::{ids, usage, write_output, Rec, A_CURSOR, A_NOTE};
+use std::io;
+
+const WORDS: &[&str] = &[
+    "alpha", "budget", "call", "draft", "email", "follow", "up", "meeting", "notes", "plan",
+    "review", "schedule", "team", "update", "vendor", "ideas", "list", "agenda", "report",
+    "status", "phone", "order", "backup", "disk", "print", "memo", "travel", "lunch", "fix",
+    "release",
+];
+
+/// splitmix64: small, seedable, good enough for test data.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in 0..n (n > 0).
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True with probability p.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+fn words(rng: &mut Rng, min: usize, max: usize) -> String {
+    let n = min + rng.below(max - min + 1);
+    (0..n)
+        .map(|_| WORDS[rng.below(WORDS.len())])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `nodes` records, at most `max_depth` levels deep, no level jumps; the
+/// first record carries the cursor bit.
+pub fn random_outline(nodes: usize, max_depth: usize, note_prob: f64, rng: &mut Rng) -> Vec<Rec> {
+    let mut recs = Vec::with_capacity(nodes);
+    let mut levels = Vec::with_capacity(nodes);
+    let mut prev: Option<usize> = None;
+    for _ in 0..nodes {
+        let lvl = match prev {
+            None => 0,
+            Some(p) => rng.below((p + 2).min(max_depth.max(1))),
+        };
+        let text = words(rng, 1, 5);
+        let r = if rng.chance(note_prob) {
+            let lines = (0..1 + rng.below(3))
+                .map(|_| words(rng, 2, 8))
+                .collect::<Vec<_>>();
+            Rec::new(text, A_NOTE, 0xFFFF, 0, Some(lines.join("\r\n")))
+        } else {
+            Rec::new(text, 0x00, 0xFFFF, 0, None)
+        };
+        recs.push(r);
+        levels.push(lvl);
+        prev = Some(lvl);
+    }
+    // collapse some of the headings that have children
+    for i in 0..recs.len().saturating_sub(1) {
+        if levels[i + 1] > levels[i] && rng.chance(0.1) {
+            recs[i].marker_u16 = 0xFFFE;
+            recs[i].collapsed = true;
+        }
+    }
+    if let Some(r) = recs.first_mut() {
+        r.attr |= A_CURSOR;
+        r.flags.selected = true;
+    }
+    restructure(&mut recs, &levels);
+    ids::assign_ids(&mut recs);
+    recs
+}
+
+// (year, month, day) of a day count since 1970-01-01 (proleptic Gregorian).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+// Day count since 1970-01-01 of (year, month, day); inverse of civil_from_days.
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = i64::from((m + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn today() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+pub const TEMPLATES: &[&str] = &["meeting", "project", "journal"];
+
+/// Indented-text source of a starter template.
+pub fn template(name: &str) -> Option<String> {
+    let text = match name {
+        "meeting" => {
+            "Meeting: TITLE\n  Date / time\n  Attendees\n  Agenda\n    Item 1\n    Item 2\n  \
+                      Decisions\n  Action items\n    Who / what / when\n  Next meeting\n"
+                .to_string()
+        }
+        "project" => {
+            "Project: NAME\n  Goal\n  Milestones\n    M1\n    M2\n  Tasks\n    To do\n    \
+                      In progress\n    Done\n  Risks\n  Contacts\n  Log\n"
+                .to_string()
+        }
+        "journal" => format!(
+            "Journal\n  {}\n    Today\n    Done\n    Tomorrow\n  Ideas\n",
+            today()
+        ),
+        _ => return None,
+    };
+    Some(text)
+}
+
+pub fn cmd_gen(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut nodes = 100usize;
+    let mut max_depth = 4usize;
+    let mut note_prob = 0.2f64;
+    let mut seed: Option<u64> = None;
+    let mut tmpl: Option<&str> = None;
+    let mut out = "-";
+    let mut it = args.iter();
+    fn num<T: std::str::FromStr>(prog: &str, v: Option<&String>) -> T {
+        v.and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| usage(prog))
+    }
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--nodes" => nodes = num(prog, it.next()),
+            "--max-depth" => max_depth = num(prog, it.next()),
+            "--note-prob" => note_prob = num(prog, it.next()),
+            "--seed" => seed = Some(num(prog, it.next())),
+            "--template" => tmpl = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "-o" => out = it.next().unwrap_or_else(|| usage(prog)),
+            _ => usage(prog),
+        }
+    }
+    let recs = match tmpl {
+        Some(name) => {
+            let text = template(name).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "unknown template {name:?} (one of {})",
+                        TEMPLATES.join(", ")
+                    ),
+                )
+            })?;
+            let mut recs = parse_indented(&text);
+            recs[0].attr |= A_CURSOR;
+            recs[0].flags.selected = true;
+            recs
+        }
+        None => {
+            let seed = seed.unwrap_or_else(|| {
+                let s = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64);
+                eprintln!("seed: {s}");
+                s
+            });
+            random_outline(nodes, max_depth, note_prob, &mut Rng::new(seed))
+        }
+    };
+    write_output(out, &serialize_recs(&recs, "latin1")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::level_jumps;
+    use crate::{parse_otl, rec_levels_capped};
+
+    #[test]
+    fn random_outline_is_valid_and_reproducible() {
+        let a = random_outline(200, 3, 0.5, &mut Rng::new(7));
+        let b = random_outline(200, 3, 0.5, &mut Rng::new(7));
+        let bytes = serialize_recs(&a, "latin1").unwrap();
+        assert_eq!(bytes, serialize_recs(&b, "latin1").unwrap());
+        let back = parse_otl(&bytes, "latin1").unwrap();
+        assert_eq!(back.len(), 200);
+        assert!(rec_levels_capped(&back).iter().all(|&l| l < 3));
+        assert!(level_jumps(&back).is_empty());
+        assert!(back.iter().any(|r| r.note.is_some()));
+        assert!(back[0].flags.selected);
+    }
+
+    #[test]
+    fn templates_parse() {
+        for name in TEMPLATES {
+            let recs = parse_indented(&template(name).unwrap());
+            assert!(recs.len() > 3, "{name}");
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20_742), (2026, 10, 16));
+        assert_eq!(days_from_civil(2026, 10, 16), 20_742);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+}
@@ -0,0 +1,305 @@
+//! Git integration entry points.
+//!
+//! `otl textconv <file>` prints the canonical dump (no offsets, cursor bit
+//! hidden) for `diff.otl.textconv`. `otl --git-diff` takes the seven
+//! arguments git passes to an external diff driver (`diff.otl.command`):
+//!   path old-file old-hex old-mode new-file new-hex new-mode
+//! and prints the structural diff.
+//!
+//! `otl clean` / `otl smudge` are stdin->stdout filters (`filter.otl.*`)
+//! storing outlines as canon text (cursor bit included) in the repository.
+//! Both pass through input that is already in the target form. Notes are
+//! decoded and re-encoded per `--enc` or the encodings manifest (see
+//! encmap.rs), so a cp437 outline comes back as cp437. Framing the canon
+//! text has no place for (junk before MAGIC, nonstandard preamble words, an
+//! EOF sentinel other than a single 0x1a, bytes after it) goes in leading
+//! `# otl-framing <field> <hex>` comment lines, so smudge gives back the
+//! file clean was given.
+
+use crate::canon::{parse_canon, HEX_PER_LINE};
+use crate::diff::diff_mode;
+use crate::enc::{from_hex, to_hex};
+use crate::ops::{prepare_write, JumpFix};
+use crate::write::serialize_framed;
+use crate::{
+    encmap, parse_doc, parse_otl, read_input, render_canon, usage, Doc, Framing, Rec, MAGIC,
+    PREAMBLE,
+};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub fn cmd_textconv(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut show_cursor = false;
+    for a in args {
+        match a.as_str() {
+            "--show-cursor" => show_cursor = true,
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let buf = read_input(file.unwrap_or_else(|| usage(prog)))?;
+    let recs = parse_otl(&buf, "latin1")?;
+    print!("{}", render_canon(&recs, show_cursor));
+    Ok(())
+}
+
+pub fn cmd_git_diff(prog: &str, args: &[String]) -> io::Result<()> {
+    // git passes /dev/null for the missing side of an added/deleted file
+    let [path, old_file, old_hex, _old_mode, new_file, new_hex, _new_mode] = args else {
+        usage(prog)
+    };
+    let prev = parse_otl(&read_input(old_file)?, "latin1")?;
+    let curr = parse_otl(&read_input(new_file)?, "latin1")?;
+    print!("{}", git_diff(path, [old_hex, new_hex], &prev, &curr));
+    Ok(())
+}
+
+// The driver's output: a git-style header, then the structural diff.
+fn git_diff(path: &str, hexes: [&str; 2], prev: &[Rec], curr: &[Rec]) -> String {
+    let short = |h: &str| h.chars().take(7).collect::<String>();
+    format!(
+        "diff --otl a/{path} b/{path}\nindex {}..{}\n{}",
+        short(hexes[0]),
+        short(hexes[1]),
+        diff_mode(prev, curr, false)
+    )
+}
+
+// Filter arguments: `[--enc E] [--enc-map FILE] [--path P] [file]`, plus
+// `--fix-level-jumps` for smudge.
+#[derive(Default)]
+struct Filter<'a> {
+    file: Option<&'a str>, // stdin if None
+    path: Option<&'a str>, // work-tree path (git's %f) for the manifest
+    enc: Option<&'a str>,
+    enc_map: Option<&'a str>,
+    fix: Option<JumpFix>,
+}
+
+impl<'a> Filter<'a> {
+    fn parse(prog: &str, args: &'a [String], smudge: bool) -> io::Result<Filter<'a>> {
+        let mut f = Filter::default();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            let mut value = || it.next().map(|s| s.as_str()).unwrap_or_else(|| usage(prog));
+            match a.as_str() {
+                "--enc" => f.enc = Some(value()),
+                "--enc-map" => f.enc_map = Some(value()),
+                "--path" => f.path = Some(value()),
+                "--fix-level-jumps" if smudge => f.fix = Some(JumpFix::parse(value())?),
+                _ if f.file.is_none() => f.file = Some(a),
+                _ => usage(prog),
+            }
+        }
+        Ok(f)
+    }
+
+    fn input(&self) -> io::Result<Vec<u8>> {
+        read_input(self.file.unwrap_or("-"))
+    }
+
+    // Note encoding: the manifest's line for the file (`--path`, else the
+    // file argument), from `--enc-map` or else encodings.toml in the current
+    // directory (git runs filters at the top of the work tree); `--enc`
+    // (default latin1) for files no line matches.
+    fn enc(&self) -> io::Result<String> {
+        let enc = self.enc.unwrap_or("latin1");
+        let Some(p) = self.path.or(self.file) else {
+            return Ok(enc.to_string());
+        };
+        let maps = encmap::manifests(self.enc_map, &[PathBuf::from(".")])?;
+        Ok(encmap::enc_for(&maps, &Path::new(".").join(p), enc).to_string())
+    }
+}
+
+pub fn cmd_clean(prog: &str, args: &[String]) -> io::Result<()> {
+    let f = Filter::parse(prog, args, false)?;
+    let buf = f.input()?;
+    let mut stdout = io::stdout().lock();
+    if !buf.starts_with(&MAGIC) {
+        if let Ok(text) = std::str::from_utf8(&buf) {
+            if parse_canon(text).is_ok() {
+                return stdout.write_all(&buf);
+            }
+        }
+    }
+    stdout.write_all(clean(&buf, &f.enc()?)?.as_bytes())
+}
+
+pub fn cmd_smudge(prog: &str, args: &[String]) -> io::Result<()> {
+    let f = Filter::parse(prog, args, true)?;
+    let buf = f.input()?;
+    let mut stdout = io::stdout().lock();
+    if buf.starts_with(&MAGIC) {
+        return stdout.write_all(&buf);
+    }
+    stdout.write_all(&smudge(
+        &String::from_utf8_lossy(&buf),
+        &f.enc()?,
+        f.fix.as_ref(),
+    )?)
+}
+
+const FRAMING: &str = "# otl-framing ";
+
+fn clean(buf: &[u8], enc: &str) -> io::Result<String> {
+    let doc = parse_doc(buf, enc)?;
+    Ok(render_framing(buf, &doc) + &render_canon(&doc.recs, true))
+}
+
+fn smudge(text: &str, enc: &str, fix: Option<&JumpFix>) -> io::Result<Vec<u8>> {
+    let (framing, sentinel) = parse_framing(text)?;
+    let recs = prepare_write(parse_canon(text)?, fix);
+    let mut out = serialize_framed(&recs, &framing, enc, false)?;
+    // serialize_framed ends the records with a single 0x1a
+    let at = out.len() - framing.trailer.len() - 1;
+    out.splice(at..at + 1, sentinel);
+    Ok(out)
+}
+
+// The EOF sentinel as read: 1a, ff ff 1a, or nothing (the input ran out).
+fn sentinel<'b>(buf: &'b [u8], doc: &Doc) -> &'b [u8] {
+    let f = &doc.framing;
+    let body = match doc.recs.last() {
+        Some(r) => r.off_note.map_or(r.off_delta + 2, |o| o + r.note_len),
+        None => f.start + f.prefix.len() + MAGIC.len() + PREAMBLE.len(),
+    };
+    &buf[body.min(f.end)..f.end]
+}
+
+// Framing header lines for `doc`; everything after the sentinel (a trailer,
+// or further documents of a stream) is kept as trailer.
+fn render_framing(buf: &[u8], doc: &Doc) -> String {
+    let f = &doc.framing;
+    let mut out = String::new();
+    let mut line = |field: &str, hex: String| out.push_str(&format!("{FRAMING}{field} {hex}\n"));
+    if !f.prefix.is_empty() {
+        line("prefix", to_hex(&f.prefix));
+    }
+    if let Some(words) = f.preamble {
+        line("preamble", to_hex(&words));
+    }
+    match sentinel(buf, doc) {
+        [0x1a] => {}
+        [] => line("sentinel", "none".to_string()),
+        s => line("sentinel", to_hex(s)),
+    }
+    for chunk in buf[f.end..].chunks(HEX_PER_LINE) {
+        line("trailer", to_hex(chunk));
+    }
+    out
+}
+
+// Inverse of `render_framing`: the framing and EOF sentinel from the header
+// lines (comment lines before the first record).
+fn parse_framing(text: &str) -> io::Result<(Framing, Vec<u8>)> {
+    let mut framing = Framing::default();
+    let mut sentinel = vec![0x1a];
+    for (no, line) in text.lines().enumerate() {
+        let Some(rest) = line.strip_prefix(FRAMING) else {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            break;
+        };
+        let bad = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("canon line {}: bad framing line", no + 1),
+            )
+        };
+        let (field, value) = rest.split_once(' ').ok_or_else(bad)?;
+        let bytes = match value {
+            "none" if field == "sentinel" => Vec::new(),
+            _ => from_hex(value).ok_or_else(bad)?,
+        };
+        match field {
+            "prefix" => framing.prefix = bytes,
+            "preamble" => framing.preamble = Some(bytes.try_into().map_err(|_| bad())?),
+            "sentinel" => sentinel = bytes,
+            "trailer" => framing.trailer.extend(bytes),
+            _ => return Err(bad()),
+        }
+    }
+    Ok((framing, sentinel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, M_EXPANDED};
+    use std::fs;
+
+    #[test]
+    fn filters_keep_the_manifest_encoding() {
+        let dir = std::env::temp_dir().join(format!("otl-git-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let map = dir.join("encodings.toml");
+        fs::write(&map, "\"*\" = \"cp1252\"\n\"dos/**\" = \"cp437\"\n").unwrap();
+        let args: Vec<String> = ["--enc-map", map.to_str().unwrap(), "--path", "dos/a.OTL"]
+            .map(String::from)
+            .into();
+        let f = Filter::parse("otl", &args, false).unwrap();
+        assert_eq!(f.enc().unwrap(), "cp437");
+
+        // clean then smudge: the cp437 note comes back as the same bytes
+        let buf = otl_file(vec![rec_bytes(
+            "Cafe",
+            A_NOTE,
+            M_EXPANDED,
+            0,
+            Some(b"\x82t\x82 \xb0"),
+        )]);
+        let recs = parse_otl(&buf, "cp437").unwrap();
+        assert_eq!(recs[0].note.as_deref(), Some("été ░"));
+        assert_eq!(
+            smudge(&clean(&buf, "cp437").unwrap(), "cp437", None).unwrap(),
+            buf
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn filters_keep_the_framing() {
+        let body = otl_file(vec![rec_bytes("Plan", A_NOTE, M_EXPANDED, 0, Some(b"x"))]);
+        let framed = |prefix: &[u8], sentinel: &[u8], trailer: &[u8]| {
+            [prefix, &body[..], sentinel, trailer].concat()
+        };
+        let mut odd_preamble = framed(b"", &[0x1a], b"");
+        odd_preamble[3..9].copy_from_slice(&[0xff, 1, 2, 0xff, 3, 4]);
+        for buf in [
+            framed(b"\xef\xbb\xbf\r\n", &[0x1a], b"SETTINGS\x00\x01"),
+            framed(b"", &[0xff, 0xff, 0x1a], &[0x1a; 40]),
+            framed(b"\r\n", b"", b""),
+            odd_preamble,
+        ] {
+            let text = clean(&buf, "latin1").unwrap();
+            assert!(parse_canon(&text).is_ok());
+            assert_eq!(smudge(&text, "latin1", None).unwrap(), buf, "{text}");
+        }
+        // plain files get no header
+        let plain = framed(b"", &[0x1a], b"");
+        assert!(!clean(&plain, "latin1").unwrap().contains(FRAMING));
+    }
+
+    #[test]
+    fn diff_driver_handles_added_files() {
+        let buf = otl_file(vec![rec_bytes("Plan", 0x00, M_EXPANDED, 0, None)]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        // git passes /dev/null (no bytes) for the missing side
+        let none = parse_otl(b"", "latin1").unwrap();
+        let out = git_diff("a.OTL", ["0000000000", "1234567abc"], &none, &recs);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[..2],
+            ["diff --otl a/a.OTL b/a.OTL", "index 0000000..1234567"]
+        );
+        assert_eq!(lines[2..], ["+ \"Plan\""]);
+        assert_eq!(
+            git_diff("a.OTL", ["1", "1"], &recs, &recs).lines().count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! `annotate-hex` subcommand: a hex editor overlay for one .OTL file.
+//!
+//! Emits a 010 Editor binary template (`--format 010-template`, .bt) or an
+//! ImHex pattern (`--format imhex`, .hexpat) that places every part of the
+//! file at the offset the parser found it: junk prefix, MAGIC, PREAMBLE,
+//! each record (heading bytes, 0xFF terminator, attr, marker, delta, note
+//! length and note), the EOF sentinel and the trailer. Records are labelled
+//! `#NNN heading` as in `--dump`, so byte surgery starts from a map rather
+//! than a guess. The overlay is only valid for the file it was made from.
+
+use crate::{
+    escape_headline, parse_doc, read_input, usage, write_output, Doc, Rec, MAGIC, PREAMBLE,
+};
+use std::io;
+
+/// A span of the file outside the records: (name, offset, length, what).
+type Region = (&'static str, usize, usize, &'static str);
+
+// Framing regions in file order, then the EOF sentinel and trailer.
+fn regions(buf: &[u8], doc: &Doc) -> (Vec<Region>, Vec<Region>) {
+    let f = &doc.framing;
+    let mut head = Vec::new();
+    let mut at = f.start;
+    if !f.prefix.is_empty() {
+        head.push(("prefix", at, f.prefix.len(), "junk before MAGIC"));
+        at += f.prefix.len();
+    }
+    if buf[at..].starts_with(&MAGIC) {
+        head.push(("magic", at, MAGIC.len(), "MAGIC"));
+        at += MAGIC.len();
+        if buf[at..].starts_with(&PREAMBLE) {
+            head.push(("preamble", at, PREAMBLE.len(), "PREAMBLE"));
+        } else if f.preamble.is_some() {
+            head.push(("preamble", at, PREAMBLE.len(), "nonstandard PREAMBLE"));
+        }
+    }
+    let mut tail = Vec::new();
+    if f.end > 0 && f.end <= buf.len() && buf[f.end - 1] == 0x1a {
+        let sentinel = if f.end >= 3 && buf[f.end - 3..f.end] == [0xff, 0xff, 0x1a] {
+            3
+        } else {
+            1
+        };
+        tail.push(("eof", f.end - sentinel, sentinel, "EOF sentinel"));
+    }
+    if !f.trailer.is_empty() {
+        tail.push(("trailer", f.end, f.trailer.len(), "after the EOF sentinel"));
+    }
+    (head, tail)
+}
+
+fn label(i: usize, r: &Rec) -> String {
+    format!("#{i:03} {}", escape_headline(&r.text))
+}
+
+pub fn render_010(buf: &[u8], doc: &Doc, name: &str) -> String {
+    let mut out = format!(
+        "// {name}: record layout from `otl annotate-hex` (offsets fixed to this file)\n\
+         LittleEndian();\n\n\
+         typedef struct (int tlen, int has_note) {{\n    \
+             if (tlen > 0) char text[tlen];\n    \
+             uchar terminator <format=hex>;\n    \
+             uchar attr <format=hex, comment=\"N=0x80 C=0x20 S=0x08 K=0x04\">;\n    \
+             ushort marker <format=hex, comment=\"FFFF expanded, FFFE collapsed\">;\n    \
+             short delta <comment=\"level change from the previous record\">;\n    \
+             if (has_note) {{\n        \
+                 ushort noteLen;\n        \
+                 if (noteLen > 0) uchar note[noteLen];\n    \
+             }}\n\
+         }} REC;\n\n",
+        name = escape_headline(name)
+    );
+    let (head, tail) = regions(buf, doc);
+    let region = |(n, at, len, what): &Region| {
+        format!("FSeek({at:#x}); uchar {n}[{len}] <bgcolor=cLtGray, comment=\"{what}\">;\n")
+    };
+    head.iter().for_each(|r| out.push_str(&region(r)));
+    for (i, r) in doc.recs.iter().enumerate() {
+        out.push_str(&format!(
+            "FSeek({:#x}); REC rec_{i:03}({}, {}) <comment=\"{}\">;\n",
+            r.off_text,
+            r.len_text,
+            u8::from(r.off_note_len.is_some()),
+            label(i, r)
+        ));
+    }
+    tail.iter().for_each(|r| out.push_str(&region(r)));
+    out
+}
+
+pub fn render_imhex(buf: &[u8], doc: &Doc, name: &str) -> String {
+    let mut out = format!(
+        "// {name}: record layout from `otl annotate-hex` (offsets fixed to this file)\n\
+         #pragma endian little\n\
+         import std.mem;\n\n\
+         struct Rec {{\n    \
+             char text[while(std::mem::read_unsigned($, 1) != 0xFF)];\n    \
+             u8 terminator;\n    \
+             u8 attr [[comment(\"N=0x80 C=0x20 S=0x08 K=0x04\")]];\n    \
+             u16 marker [[comment(\"FFFF expanded, FFFE collapsed\")]];\n    \
+             s16 delta [[comment(\"level change from the previous record\")]];\n    \
+             if (attr & 0x80) {{\n        \
+                 u16 note_len;\n        \
+                 u8 note[note_len];\n    \
+             }}\n\
+         }};\n\n",
+        name = escape_headline(name)
+    );
+    let (head, tail) = regions(buf, doc);
+    let region =
+        |(n, at, len, what): &Region| format!("u8 {n}[{len}] @ {at:#x} [[comment(\"{what}\")]];\n");
+    head.iter().for_each(|r| out.push_str(&region(r)));
+    for (i, r) in doc.recs.iter().enumerate() {
+        out.push_str(&format!(
+            "Rec rec_{i:03} @ {:#x} [[comment(\"{}\")]];\n",
+            r.off_text,
+            label(i, r)
+        ));
+    }
+    tail.iter().for_each(|r| out.push_str(&region(r)));
+    out
+}
+
+pub fn cmd_annotate_hex(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut format: Option<&str> = None;
+    let mut out = "-";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--format" => format = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "-o" => out = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let (Some(file), Some(format)) = (file, format) else {
+        usage(prog)
+    };
+    let buf = read_input(file)?;
+    let doc = parse_doc(&buf, "latin1")?;
+    let text = match format {
+        "010-template" => render_010(&buf, &doc, file),
+        "imhex" => render_imhex(&buf, &doc, file),
+        _ => usage(prog),
+    };
+    write_output(out, text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn overlays_place_every_part() {
+        let mut buf = b"\r\n".to_vec();
+        buf.extend(otl_file(vec![
+            rec_bytes("Top \"q\"", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("n", A_NOTE, M_EXPANDED, 0, Some(b"hi")),
+        ]));
+        buf.extend(b"\x1aIDX");
+        let doc = parse_doc(&buf, "latin1").unwrap();
+
+        let bt = render_010(&buf, &doc, "a.OTL");
+        assert!(bt.contains("FSeek(0x0); uchar prefix[2]"));
+        assert!(bt.contains("FSeek(0x2); uchar magic[3]"));
+        assert!(bt.contains("FSeek(0x5); uchar preamble[6]"));
+        assert!(bt.contains("FSeek(0xb); REC rec_000(7, 0) <comment=\"#000 Top \\\"q\\\"\">;"));
+        assert!(bt.contains("FSeek(0x18); REC rec_001(1, 1)"));
+        assert!(bt.contains("FSeek(0x23); uchar eof[1]"));
+        assert!(bt.contains("FSeek(0x24); uchar trailer[3]"));
+
+        let pat = render_imhex(&buf, &doc, "a.OTL");
+        assert!(pat.contains("Rec rec_001 @ 0x18 [[comment(\"#001 n\")]];"));
+        assert!(pat.contains("u8 trailer[3] @ 0x24"));
+    }
+}
@@ -0,0 +1,189 @@
+//! `history` subcommand: the change log of one outline across a directory
+//! of snapshots (old backups, floppy images copied out, ...).
+//!
+//! A snapshot is any .OTL file below the directory with the same file name
+//! (any case) as the outline, or with the same bytes as the outline or an
+//! already found snapshot (a renamed copy). Snapshots and the outline
+//! itself are ordered by modification time and each is diffed against the
+//! one before: counts, then the headings added, removed, renamed and moved
+//! (field-level detail is left to `--diff`). Identical copies are listed
+//! once as unchanged.
+
+use crate::diff::diff_report;
+use crate::gen::civil_from_days;
+use crate::ids::fnv1a64;
+use crate::{collect_otl_files, parse_otl, usage, Rec};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub mtime: SystemTime,
+    pub bytes: Vec<u8>,
+}
+
+fn read_snapshot(path: &Path) -> io::Result<Snapshot> {
+    let mtime = fs::metadata(path)?.modified()?;
+    Ok(Snapshot {
+        path: path.to_path_buf(),
+        mtime,
+        bytes: fs::read(path)?,
+    })
+}
+
+/// `file` and its snapshots under `dir`, oldest first.
+pub fn find_snapshots(file: &Path, dir: &Path) -> io::Result<Vec<Snapshot>> {
+    let me = read_snapshot(file)?;
+    let name = file.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    let own = fs::canonicalize(file)?;
+    let mut hashes: HashSet<u64> = HashSet::from([fnv1a64(&me.bytes)]);
+    let mut found = vec![me];
+    let mut by_content = Vec::new();
+    for p in collect_otl_files(dir)? {
+        if fs::canonicalize(&p)? == own {
+            continue;
+        }
+        let s = read_snapshot(&p)?;
+        let same_name = p.file_name().map(|n| n.to_string_lossy().to_lowercase()) == name;
+        if same_name {
+            hashes.insert(fnv1a64(&s.bytes));
+            found.push(s);
+        } else {
+            by_content.push(s);
+        }
+    }
+    found.extend(
+        by_content
+            .into_iter()
+            .filter(|s| hashes.contains(&fnv1a64(&s.bytes))),
+    );
+    found.sort_by(|a, b| a.mtime.cmp(&b.mtime).then_with(|| a.path.cmp(&b.path)));
+    Ok(found)
+}
+
+fn fmt_mtime(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{y:04}-{m:02}-{d:02} {:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+/// The change log of `snaps` (oldest first).
+pub fn history(snaps: &[Snapshot], summary: bool) -> String {
+    let mut out = String::new();
+    let mut prev: Option<(Vec<Rec>, &[u8])> = None;
+    for s in snaps {
+        let head = format!("{}  {}", fmt_mtime(s.mtime), s.path.display());
+        let recs = match parse_otl(&s.bytes, "latin1") {
+            Ok(recs) => recs,
+            Err(e) => {
+                out.push_str(&format!("{head}  parse error: {e}\n"));
+                continue;
+            }
+        };
+        match &prev {
+            None => out.push_str(&format!("{head}  first ({} headings)\n", recs.len())),
+            Some((_, bytes)) if *bytes == s.bytes.as_slice() => {
+                out.push_str(&format!("{head}  unchanged\n"));
+            }
+            Some((old, _)) => {
+                let (report, counts) = diff_report(old, &recs, false);
+                let what = if counts.is_empty() {
+                    "bytes differ; no heading changes".to_string()
+                } else {
+                    counts.to_string()
+                };
+                out.push_str(&format!("{head}  {what}\n"));
+                if !summary {
+                    // headline changes only; "  field: a -> b" lines are detail
+                    for line in report.lines().filter(|l| !l.starts_with("  ")) {
+                        out.push_str(&format!("  {line}\n"));
+                    }
+                }
+            }
+        }
+        prev = Some((recs, &s.bytes));
+    }
+    out
+}
+
+pub fn cmd_history(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut paths: Vec<&str> = Vec::new();
+    let mut summary = false;
+    for a in args {
+        match a.as_str() {
+            "--summary" => summary = true,
+            _ => paths.push(a),
+        }
+    }
+    let [file, dir] = paths[..] else { usage(prog) };
+    let snaps = find_snapshots(Path::new(file), Path::new(dir))?;
+    print!("{}", history(&snaps, summary));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_SIBFOLLOWS, M_EXPANDED};
+    use std::time::Duration;
+
+    fn outline(names: &[&str]) -> Vec<u8> {
+        let n = names.len();
+        otl_file(
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    let sib = if i + 1 < n { A_SIBFOLLOWS } else { 0 };
+                    rec_bytes(t, sib, M_EXPANDED, 0, None)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn snapshots_by_name_and_content_in_mtime_order() {
+        let dir = std::env::temp_dir().join(format!("otl-history-{}", std::process::id()));
+        let snaps = dir.join("snaps");
+        fs::create_dir_all(snaps.join("1991")).unwrap();
+        let write = |p: &Path, bytes: &[u8], secs: u64| {
+            fs::write(p, bytes).unwrap();
+            let f = fs::File::options().write(true).open(p).unwrap();
+            f.set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+        let v1 = outline(&["Plans", "Budget"]);
+        let v2 = outline(&["Plans", "Budget", "Travel"]);
+        write(&snaps.join("PLANS.OTL"), &v1, 600_000_000);
+        write(&snaps.join("1991").join("plans.otl"), &v2, 660_000_000);
+        write(&snaps.join("COPY.OTL"), &v2, 670_000_000); // renamed copy
+        write(&snaps.join("OTHER.OTL"), &outline(&["x"]), 650_000_000);
+        write(
+            &dir.join("PLANS.OTL"),
+            &outline(&["Plans", "Travel"]),
+            700_000_000,
+        );
+
+        let found = find_snapshots(&dir.join("PLANS.OTL"), &snaps).unwrap();
+        assert_eq!(found.len(), 4);
+        let log = history(&found, false);
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].starts_with("1989-01-05 ") && lines[0].ends_with("first (2 headings)"));
+        assert!(lines[1].ends_with("+1 -0 ~1 renamed 0 moved 0 notes 0"));
+        assert_eq!(lines[2..4], ["  ~ \"Budget\"", "  + \"Travel\""]);
+        assert!(lines[4].ends_with("COPY.OTL  unchanged"));
+        assert!(lines[5].ends_with("+0 -1 ~0 renamed 0 moved 0 notes 0"));
+        assert!(log.contains("  - \"Budget\"\n"));
+        assert!(!history(&found, true).contains("  + "));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Attr-bit hypotheses: `--hypothesis <bit>=<predicate>` pairs an attr bit
+//! with a structural predicate and counts, over every record checked, how
+//! often the two agree. `--assume-child-bit` is the hypothesis
+//! `0x04=has_child` turned into findings; this is the statistical version,
+//! for trying theories across a corpus without a new flag each.
+
+use crate::{rec_levels_capped, rec_parents_capped, Rec};
+use serde::Serialize;
+use std::io;
+
+/// Built-in predicates (prefix `!` to negate).
+pub const PREDICATES: &[&str] = &[
+    "has_child",
+    "has_next_sibling",
+    "is_last_child",
+    "is_first_child",
+    "is_top_level",
+    "is_collapsed",
+    "has_collapsed_ancestor",
+    "has_note",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hypothesis {
+    pub spec: String, // as given, for reports
+    pub mask: u8,
+    pub pred: &'static str,
+    pub negate: bool,
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+impl Hypothesis {
+    /// `0x04=has_child`, `128=has_note`, `0x08=!is_last_child`.
+    pub fn parse(s: &str) -> io::Result<Hypothesis> {
+        let (bit, pred) = s.split_once('=').ok_or_else(|| {
+            bad(format!(
+                "--hypothesis: expected <bit>=<predicate>, got {s:?}"
+            ))
+        })?;
+        let mask = match bit.strip_prefix("0x") {
+            Some(h) => u8::from_str_radix(h, 16).ok(),
+            None => bit.parse().ok(),
+        }
+        .filter(|&m| m != 0)
+        .ok_or_else(|| bad(format!("--hypothesis: bad bit {bit:?} (want e.g. 0x04)")))?;
+        let (negate, name) = match pred.strip_prefix('!') {
+            Some(p) => (true, p),
+            None => (false, pred),
+        };
+        let pred = PREDICATES.iter().find(|&&p| p == name).ok_or_else(|| {
+            bad(format!(
+                "--hypothesis: unknown predicate {name:?} (known: {})",
+                PREDICATES.join(", ")
+            ))
+        })?;
+        Ok(Hypothesis {
+            spec: s.to_string(),
+            mask,
+            pred,
+            negate,
+        })
+    }
+}
+
+/// Agreement counts for one hypothesis.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Tally {
+    pub hypothesis: String,
+    pub both: usize,      // bit set, predicate true
+    pub neither: usize,   // bit clear, predicate false
+    pub bit_only: usize,  // bit set, predicate false
+    pub pred_only: usize, // bit clear, predicate true
+}
+
+impl Tally {
+    pub fn add(&mut self, o: &Tally) {
+        self.both += o.both;
+        self.neither += o.neither;
+        self.bit_only += o.bit_only;
+        self.pred_only += o.pred_only;
+    }
+
+    pub fn agreement(&self) -> f64 {
+        let total = self.both + self.neither + self.bit_only + self.pred_only;
+        if total == 0 {
+            return 1.0;
+        }
+        (self.both + self.neither) as f64 / total as f64
+    }
+
+    pub fn line(&self) -> String {
+        format!(
+            "{}: {:.1}% agree (both {}, neither {}, bit only {}, predicate only {})",
+            self.hypothesis,
+            self.agreement() * 100.0,
+            self.both,
+            self.neither,
+            self.bit_only,
+            self.pred_only
+        )
+    }
+}
+
+// Value of each predicate for every record.
+fn predicate(recs: &[Rec], name: &str) -> Vec<bool> {
+    let levels = rec_levels_capped(recs);
+    let parents = rec_parents_capped(recs);
+    let n = recs.len();
+    let next_sib = |i: usize| {
+        levels[i + 1..]
+            .iter()
+            .find(|&&l| l <= levels[i])
+            .is_some_and(|&l| l == levels[i])
+    };
+    (0..n)
+        .map(|i| match name {
+            "has_child" => i + 1 < n && levels[i + 1] > levels[i],
+            "has_next_sibling" => next_sib(i),
+            "is_last_child" => !next_sib(i),
+            "is_first_child" => i == 0 || levels[i - 1] < levels[i],
+            "is_top_level" => levels[i] == 0,
+            "is_collapsed" => recs[i].collapsed,
+            "has_note" => recs[i].flags.has_note,
+            "has_collapsed_ancestor" => {
+                let mut p = parents[i];
+                while let Some(j) = p {
+                    if recs[j].collapsed {
+                        break;
+                    }
+                    p = parents[j];
+                }
+                p.is_some()
+            }
+            _ => unreachable!("predicate names are checked by Hypothesis::parse"),
+        })
+        .collect()
+}
+
+/// One tally per hypothesis over `recs`.
+pub fn evaluate(recs: &[Rec], hyps: &[Hypothesis]) -> Vec<Tally> {
+    hyps.iter()
+        .map(|h| {
+            let mut t = Tally {
+                hypothesis: h.spec.clone(),
+                ..Tally::default()
+            };
+            for (r, p) in recs.iter().zip(predicate(recs, h.pred)) {
+                match (r.attr & h.mask == h.mask, p != h.negate) {
+                    (true, true) => t.both += 1,
+                    (false, false) => t.neither += 1,
+                    (true, false) => t.bit_only += 1,
+                    (false, true) => t.pred_only += 1,
+                }
+            }
+            t
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_HASKIDS, A_SIBFOLLOWS, M_COLLAPSED, M_EXPANDED};
+
+    #[test]
+    fn tallies_agreement() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("A", A_HASKIDS | A_SIBFOLLOWS, M_COLLAPSED, 0, None),
+                rec_bytes("a1", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+                rec_bytes("a2", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("B", A_HASKIDS, M_EXPANDED, -1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let hyps: Vec<Hypothesis> = ["0x04=has_child", "0x08=!is_last_child", "8=is_collapsed"]
+            .iter()
+            .map(|s| Hypothesis::parse(s).unwrap())
+            .collect();
+        let t = evaluate(&recs, &hyps);
+        assert_eq!((t[0].both, t[0].neither, t[0].bit_only), (1, 2, 1));
+        assert_eq!(t[1].agreement(), 1.0);
+        assert_eq!((t[2].both, t[2].bit_only), (1, 1));
+        assert_eq!(
+            predicate(&recs, "has_collapsed_ancestor"),
+            [false, true, true, false]
+        );
+        assert!(Hypothesis::parse("0x04=has_kids").is_err());
+        assert!(Hypothesis::parse("0=has_child").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! Stable node identifiers.
+//!
+//! An id is the FNV-1a/64 hash (plus a murmur3 finalizer, so short prefixes
+//! are distinctive) of the node's key path, where each component
+//! is `text#ordinal` and the ordinal counts earlier same-named siblings under
+//! the same parent. Ids survive edits elsewhere in the outline and only change
+//! when the node (or an ancestor) is renamed or moved, or when a same-named
+//! sibling is inserted before it. Levels are clamped at DEFAULT_MAX_LEVEL
+//! whatever `--max-level` says, so ids do not depend on the flag.
+
+use crate::{rec_levels, Rec};
+use std::collections::HashMap;
+
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    // fmix64: FNV barely mixes the last byte, and ordinals differ only there
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    h
+}
+
+// Count one more child named `name` under a parent; returns its ordinal.
+fn bump(seen: &mut HashMap<String, usize>, name: &str) -> usize {
+    let n = seen.entry(name.to_string()).or_insert(0);
+    *n += 1;
+    *n - 1
+}
+
+/// Id of every record, in record order (16 lowercase hex digits).
+pub fn rec_ids(recs: &[Rec]) -> Vec<String> {
+    let levels = rec_levels(recs);
+    let mut keys: Vec<String> = Vec::new();
+    let mut seen: Vec<HashMap<String, usize>> = vec![HashMap::new()];
+    let mut out = Vec::with_capacity(recs.len());
+    for (r, &lvl) in recs.iter().zip(&levels) {
+        keys.truncate(lvl);
+        seen.truncate(lvl + 1);
+        // filler levels get an empty name, like build_tree's synthetic nodes
+        while keys.len() < lvl {
+            let ord = bump(&mut seen[keys.len()], "");
+            keys.push(format!("#{ord}"));
+            seen.push(HashMap::new());
+        }
+        let ord = bump(&mut seen[lvl], &r.text);
+        keys.push(format!("{}#{ord}", r.text));
+        seen.push(HashMap::new());
+        out.push(format!("{:016x}", fnv1a64(keys.join("\x1f").as_bytes())));
+    }
+    out
+}
+
+/// Fill in `Rec::id` for every record.
+pub fn assign_ids(recs: &mut [Rec]) {
+    let ids = rec_ids(recs);
+    for (r, id) in recs.iter_mut().zip(ids) {
+        r.id = id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids_of(names: &[(&str, i16)]) -> Vec<(String, String)> {
+        let mut recs: Vec<Rec> = names
+            .iter()
+            .map(|&(t, d)| Rec::new(t.to_string(), 0x00, 0xFFFF, d, None))
+            .collect();
+        assign_ids(&mut recs);
+        recs.into_iter()
+            .map(|r| (r.text.to_string(), r.id))
+            .collect()
+    }
+
+    #[test]
+    fn ids_survive_unrelated_edits() {
+        let before = ids_of(&[("A", 0), ("x", 1), ("B", -1), ("y", 1)]);
+        // insert a new heading under A; B and y keep their ids
+        let after = ids_of(&[("A", 0), ("new", 1), ("x", 0), ("B", -1), ("y", 1)]);
+        assert_eq!(before[1], after[2]);
+        assert_eq!(before[2], after[3]);
+        assert_eq!(before[3], after[4]);
+
+        // a same-named sibling inserted first takes over the ordinal
+        let dup = ids_of(&[("A", 0), ("x", 1), ("x", 0)]);
+        assert_eq!(dup[1].1, before[1].1);
+        assert_ne!(dup[2].1, before[1].1);
+    }
+}
@@ -0,0 +1,50 @@
+//! Plain-text import: indented text lines to records.
+
+use crate::{ids, Rec};
+
+/// One record per non-blank line; nesting follows indentation (any
+/// consistent width; a tab counts as 8 columns). Lines indented less than
+/// their predecessor close levels back to the matching indent.
+pub fn parse_indented(text: &str) -> Vec<Rec> {
+    let mut out = Vec::new();
+    let mut indents: Vec<usize> = Vec::new(); // indent width of each open level
+    let mut prev_level = 0usize;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let width = line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { 8 } else { 1 })
+            .sum::<usize>();
+        while indents.last().is_some_and(|&w| w > width) {
+            indents.pop();
+        }
+        if indents.last() != Some(&width) {
+            indents.push(width);
+        }
+        let level = indents.len() - 1;
+        let delta = level as i16 - prev_level as i16;
+        prev_level = level;
+        out.push(Rec::new(line.trim().to_string(), 0x00, 0xFFFF, delta, None));
+    }
+    let levels = crate::rec_levels_capped(&out);
+    crate::ops::restructure(&mut out, &levels);
+    ids::assign_ids(&mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rec_levels_capped;
+
+    #[test]
+    fn indentation_to_levels() {
+        let recs = parse_indented("A\n  B\n    C\n  D\n\tE\nF\n");
+        assert_eq!(rec_levels_capped(&recs), [0, 1, 2, 1, 2, 0]);
+        assert!(recs[1].flags.has_next_sibling);
+        assert!(!recs[2].flags.has_next_sibling);
+    }
+}
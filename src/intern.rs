@@ -0,0 +1,108 @@
+//! Shared heading text. Large outlines repeat the same headings thousands of
+//! times ("Notes", "TODO", dates), and batch runs over an archive repeat
+//! them across files, so the parser hands out one `Arc<str>` per distinct
+//! heading instead of a fresh String per record.
+//!
+//! The interner is per thread and lives for the whole run; `sweep` drops
+//! texts no record holds any more (ParseCache calls it on eviction). The
+//! texts themselves are `Arc`s, so parsed records may move between threads
+//! (Corpus loads files on worker threads).
+//! `--stats` prints the counters.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InternStats {
+    pub lookups: usize,  // headings interned
+    pub shared: usize,   // of which already present
+    pub distinct: usize, // texts held now
+    pub bytes_in: usize, // heading bytes looked up
+    pub bytes_held: usize,
+}
+
+impl fmt::Display for InternStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "interned headings: {} ({} shared, {} distinct held); text bytes {} -> {}",
+            self.lookups, self.shared, self.distinct, self.bytes_in, self.bytes_held
+        )
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    texts: HashSet<Arc<str>>,
+    stats: InternStats,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+/// The shared copy of `text`.
+pub fn intern(text: &str) -> Arc<str> {
+    INTERNER.with(|i| {
+        let mut i = i.borrow_mut();
+        i.stats.lookups += 1;
+        i.stats.bytes_in += text.len();
+        if let Some(t) = i.texts.get(text) {
+            let t = t.clone();
+            i.stats.shared += 1;
+            return t;
+        }
+        let t: Arc<str> = text.into();
+        i.texts.insert(t.clone());
+        i.stats.distinct += 1;
+        i.stats.bytes_held += text.len();
+        t
+    })
+}
+
+/// Forget texts held by nothing but the interner.
+pub fn sweep() {
+    INTERNER.with(|i| {
+        let mut i = i.borrow_mut();
+        let before = i.texts.len();
+        let mut freed = 0;
+        i.texts.retain(|t| {
+            let keep = Arc::strong_count(t) > 1;
+            if !keep {
+                freed += t.len();
+            }
+            keep
+        });
+        let dropped = before - i.texts.len();
+        i.stats.distinct -= dropped;
+        i.stats.bytes_held -= freed;
+    })
+}
+
+pub fn stats() -> InternStats {
+    INTERNER.with(|i| i.borrow().stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_texts_share_one_allocation() {
+        let before = stats();
+        let a = intern("intern-test TODO");
+        let b = intern("intern-test TODO");
+        assert!(Arc::ptr_eq(&a, &b));
+        let s = stats();
+        assert_eq!(
+            (s.lookups, s.shared),
+            (before.lookups + 2, before.shared + 1)
+        );
+        assert_eq!(s.distinct, before.distinct + 1);
+        drop((a, b));
+        sweep();
+        assert_eq!(stats().distinct, s.distinct - 1);
+    }
+}
@@ -0,0 +1,205 @@
+//! Undo journal for `--in-place` rewrites (`--journal`), and `otl undo`.
+//!
+//! The journal is a sidecar next to the file (`notes.OTL` ->
+//! `notes.OTL.undo`) holding one entry per rewrite: a header line
+//! `otl-undo <unix-time> <old-len> <new-hash> <command>` followed by the
+//! file's previous bytes and a newline. `undo` restores the newest entry and
+//! drops it, but only while the file still hashes to `<new-hash>` (that is,
+//! nobody changed it since), unless `--force`.
+
+use crate::ids::fnv1a64;
+use crate::usage;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TAG: &str = "otl-undo";
+
+pub fn sidecar(file: &str) -> String {
+    format!("{file}.undo")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: u64,
+    pub new_hash: u64,
+    pub command: String,
+    pub start: usize,                // offset of the header line
+    pub old: std::ops::Range<usize>, // previous file bytes
+}
+
+fn corrupt(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn entry_bytes(old: &[u8], new: &[u8], command: &str, time: u64) -> Vec<u8> {
+    let mut out = format!(
+        "{TAG} {time} {} {:016x} {}\n",
+        old.len(),
+        fnv1a64(new),
+        command.replace('\n', " ")
+    )
+    .into_bytes();
+    out.extend_from_slice(old);
+    out.push(b'\n');
+    out
+}
+
+/// Entries of a journal, oldest first.
+pub fn entries(j: &[u8]) -> io::Result<Vec<Entry>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < j.len() {
+        let eol = j[i..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|p| i + p)
+            .ok_or_else(|| corrupt(format!("journal: unterminated header at {i:#x}")))?;
+        let header = String::from_utf8_lossy(&j[i..eol]);
+        let mut f = header.splitn(5, ' ');
+        let (Some(TAG), Some(t), Some(n), Some(h)) = (f.next(), f.next(), f.next(), f.next())
+        else {
+            return Err(corrupt(format!("journal: bad header at {i:#x}")));
+        };
+        let parsed = (t.parse(), n.parse::<usize>(), u64::from_str_radix(h, 16));
+        let (Ok(time), Ok(len), Ok(new_hash)) = parsed else {
+            return Err(corrupt(format!("journal: bad header at {i:#x}")));
+        };
+        let truncated = || corrupt(format!("journal: truncated entry at {i:#x}"));
+        let end = eol.checked_add(1).and_then(|s| s.checked_add(len));
+        let old = eol + 1..end.ok_or_else(truncated)?;
+        if old.end >= j.len() || j[old.end] != b'\n' {
+            return Err(truncated());
+        }
+        out.push(Entry {
+            time,
+            new_hash,
+            command: f.next().unwrap_or("").to_string(),
+            start: i,
+            old: old.clone(),
+        });
+        i = old.end + 1;
+    }
+    Ok(out)
+}
+
+/// Write `new` over `file` (previously `old`), first appending `old` to the
+/// journal when `journal` is set.
+pub fn write_in_place(
+    file: &str,
+    old: &[u8],
+    new: &[u8],
+    journal: bool,
+    command: &str,
+) -> io::Result<()> {
+    if journal {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(sidecar(file))?
+            .write_all(&entry_bytes(old, new, command, time))?;
+    }
+    fs::write(file, new)
+}
+
+pub fn cmd_undo(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut list = false;
+    let mut force = false;
+    for a in args {
+        match a.as_str() {
+            "--list" => list = true,
+            "--force" => force = true,
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let side = sidecar(file);
+    let j = match fs::read(&side) {
+        Ok(j) => j,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let all = entries(&j)?;
+    if list {
+        for (n, e) in all.iter().enumerate().rev() {
+            println!("{} {} {} bytes  {}", n + 1, e.time, e.old.len(), e.command);
+        }
+        return Ok(());
+    }
+    let Some(last) = all.last() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("nothing to undo ({side} is empty or missing)"),
+        ));
+    };
+    if !force && fnv1a64(&fs::read(file)?) != last.new_hash {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{file} changed since `{}`; --force to restore anyway",
+                last.command
+            ),
+        ));
+    }
+    fs::write(file, &j[last.old.clone()])?;
+    fs::write(&side, &j[..last.start])?;
+    eprintln!("undid `{}` ({} left)", last.command, all.len() - 1);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_entries_round_trip() {
+        let mut j = entry_bytes(b"v1\n\x1a", b"v2", "sort --in-place", 100);
+        j.extend(entry_bytes(b"v2", b"v3 bytes", "edit x\ny", 200));
+        let all = entries(&j).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(&j[all[0].old.clone()], b"v1\n\x1a");
+        assert_eq!(all[1].command, "edit x y");
+        assert_eq!(all[1].new_hash, fnv1a64(b"v3 bytes"));
+        assert_eq!(all[1].start, all[0].old.end + 1);
+        assert!(entries(&j[..j.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn corrupt_headers_are_errors() {
+        for j in [
+            &b"otl-undo 1 18446744073709551615 0 x\n"[..],
+            b"otl-undo 1 99 0 x\nshort\n",
+            b"otl-undo one 0 0 x\n\n",
+            b"otl-undo 1 0 0 x",
+        ] {
+            let e = entries(j).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+        let e = entries(b"otl-undo 1 18446744073709551615 0 x\n").unwrap_err();
+        assert!(e.to_string().contains("truncated entry"));
+    }
+
+    #[test]
+    fn undo_restores_and_checks_for_later_changes() {
+        let dir = std::env::temp_dir().join(format!("otl-undo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let f = dir.join("t.OTL").to_string_lossy().into_owned();
+        fs::write(&f, b"one").unwrap();
+        write_in_place(&f, b"one", b"two", true, "a").unwrap();
+        write_in_place(&f, b"two", b"three", true, "b").unwrap();
+        let (plain, forced) = (vec![f.clone()], vec![f.clone(), "--force".into()]);
+        cmd_undo("otl", &plain).unwrap();
+        assert_eq!(fs::read(&f).unwrap(), b"two");
+        fs::write(&f, b"edited by hand").unwrap();
+        assert!(cmd_undo("otl", &plain).is_err());
+        cmd_undo("otl", &forced).unwrap();
+        assert_eq!(fs::read(&f).unwrap(), b"one");
+        assert!(cmd_undo("otl", &plain).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,686 @@
+//! The .OTL format core: records, parsing, the tree and the record writer.
+//!
+//! This is what the `otl` binary is built on, usable alone (an embedded
+//! parser in a recovery tool, say). With default features off it needs no
+//! dependencies at all; features add:
+//! - `serde`: `Serialize` for `Node` and `Flags` (the `--json` shape)
+//! - `memchr`: faster record scanning (same results)
+//! - `cli`: both of the above plus what the `otl` binary needs
+//!
+//! Exporters, subcommands and the server live in the binary; new ones that
+//! need a heavy dependency get a feature of their own (enabled by `cli`),
+//! so the library never pulls them in.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub mod corpus;
+pub mod enc;
+pub mod ids;
+pub mod intern;
+pub mod paths;
+pub mod search;
+pub mod write;
+
+/// Attribute bits we (currently) know
+pub const A_NOTE: u8 = 0x80; // has note bytes (then u16 noteLen + bytes)
+pub const A_CURSOR: u8 = 0x20; // caret on this heading (displayed only with --show-cursor)
+pub const A_SIBFOLLOWS: u8 = 0x08; // there exists a later sibling at same level
+pub const A_HASKIDS: u8 = 0x04; // semantics under study; shown as k/K; validation optional
+
+#[derive(Debug, Clone)]
+pub struct Rec {
+    pub text: Arc<str>,
+    pub delta: i16,                       // relative level change (i16 LE)
+    pub attr: u8,                         // raw attribute flags
+    pub marker_u16: u16,                  // raw marker word (FFFF/-1 expanded, FFFE/-2 collapsed)
+    pub collapsed: bool,                  // convenience (marker == FFFE)
+    pub note: Option<String>,             // hex digits when flags.note_binary
+    pub note_bin: Option<Vec<u8>>,        // exact note bytes of a binary note
+    pub note_guess: Option<&'static str>, // what `--enc mixed` read the note as
+    pub flags: Flags,
+
+    // Byte offsets (for --offsets)
+    pub off_text: usize,       // first heading byte
+    pub len_text: usize,       // heading byte count (before 0xFF terminator)
+    pub off_terminator: usize, // 0xFF that ends heading text
+    pub off_attr: usize,       // attr byte
+    pub off_marker: usize,     // first of the 2 marker bytes
+    pub off_delta: usize,      // first of the 2 delta bytes
+    pub off_note_len: Option<usize>,
+    pub off_note: Option<usize>,
+
+    // Raw note length from file (u16), independent of --enc decoding (0 if no note)
+    pub note_len: usize,
+
+    // Stable node id (see ids.rs), assigned once the whole file is parsed
+    pub id: String,
+}
+
+impl Rec {
+    /// A record built from field values rather than parsed bytes (offsets are
+    /// zero, lengths are the unencoded ones; `id` is assigned by the caller).
+    pub fn new(text: String, attr: u8, marker_u16: u16, delta: i16, note: Option<String>) -> Rec {
+        let len_text = text.chars().count();
+        let note_len = note.as_ref().map_or(0, |n| n.chars().count());
+        Rec {
+            text: text.into(),
+            delta,
+            attr,
+            marker_u16,
+            collapsed: marker_u16 == 0xFFFE,
+            note,
+            note_bin: None,
+            note_guess: None,
+            flags: Flags {
+                has_note: (attr & A_NOTE) != 0,
+                selected: (attr & A_CURSOR) != 0,
+                has_next_sibling: (attr & A_SIBFOLLOWS) != 0,
+                has_child: (attr & A_HASKIDS) != 0,
+                note_binary: false,
+            },
+            off_text: 0,
+            len_text,
+            off_terminator: 0,
+            off_attr: 0,
+            off_marker: 0,
+            off_delta: 0,
+            off_note_len: None,
+            off_note: None,
+            note_len,
+            id: String::new(),
+        }
+    }
+
+    /// The note as text: `note` if it was decoded, else (a record from
+    /// `parse_doc_lazy`) decoded now from `buf`, the bytes it was parsed
+    /// from. Binary notes come back as hex, as in `note`.
+    pub fn note_text<'a>(&'a self, buf: &[u8], note_enc: &str) -> Option<Cow<'a, str>> {
+        if let Some(n) = &self.note {
+            return Some(Cow::Borrowed(n));
+        }
+        let bytes = &buf[self.off_note?..self.off_note? + self.note_len];
+        Some(Cow::Owned(if self.flags.note_binary {
+            enc::to_hex(bytes)
+        } else {
+            decode_note(bytes, note_enc)
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Flags {
+    pub has_note: bool,         // attr & 0x80
+    pub selected: bool,         // attr & 0x20
+    pub has_next_sibling: bool, // attr & 0x08
+    pub has_child: bool,        // attr & 0x04 (shown only)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub note_binary: bool, // note is not text; `note` holds its bytes as hex
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Node {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>, // None for synthetic nodes
+    pub text: Arc<str>,
+    pub note: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub annotation: Option<String>, // from the --annotations sidecar
+    pub collapsed: bool,
+    pub flags: Flags,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub synthetic: bool, // true for root / filler nodes (renderers splice these)
+    // Stands for a level missing from the file (a level jump); stays set
+    // when shaping turns the filler into a visible heading.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "synthetic", skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub filler: bool,
+    pub children: Vec<Node>,
+}
+
+pub const MAGIC: [u8; 3] = [0x1a, 0x93, 0x1a];
+pub const PREAMBLE: [u8; 6] = [0xff, 0x00, 0xff, 0xff, 0xff, 0xff];
+pub const M_EXPANDED: u8 = 0xff;
+pub const M_COLLAPSED: u8 = 0xfe;
+
+// Guardrails (format is 16-bit; these just prevent runaway reads)
+const MAX_TEXTLEN: usize = 1 << 20; // 1 MiB heading (paranoid limit)
+const MAX_NOTELEN: usize = 0xFFFF; // format max (u16)
+pub const DEFAULT_MAX_LEVEL: usize = 255; // deepest level kept (--max-level)
+
+/// The level after `delta`: checked i64 math, clamped into 0..=`max` so
+/// crafted deltas can neither wrap nor build thousands of filler levels.
+/// validate_findings reports where clamping happened.
+pub fn next_level(level: usize, delta: i16, max: usize) -> usize {
+    let want = (level as i64).checked_add(i64::from(delta));
+    want.map_or(0, |l| l.clamp(0, max as i64) as usize)
+}
+
+pub fn decode_note(bytes: &[u8], enc: &str) -> String {
+    match enc {
+        "utf8" => String::from_utf8_lossy(bytes).to_string(),
+        "latin1" => bytes.iter().map(|&b| b as char).collect::<String>(),
+        "ascii" => bytes
+            .iter()
+            .map(|&b| (b & 0x7f) as char)
+            .collect::<String>(),
+        "cp1252" => enc::decode_cp1252(bytes),
+        "cp437" => enc::decode_cp437(bytes),
+        "cp850" => enc::decode_cp850(bytes),
+        "mixed" => decode_note(bytes, enc::guess(bytes)),
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+/// One heading byte: its 7-bit character and whether the high bit folded a
+/// space after it. A real space is a token of its own, so "a  b" stored as
+/// `a|0x80, ' ', b` can be told apart from the same text stored uncompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadingToken {
+    pub ch: char,
+    pub folded_space: bool,
+}
+
+/// Heading bytes as tokens, one per byte.
+pub fn decode_heading_raw(bytes: &[u8]) -> Vec<HeadingToken> {
+    bytes
+        .iter()
+        .map(|&b| HeadingToken {
+            ch: (b & 0x7f) as char,
+            folded_space: (b & 0x80) != 0,
+        })
+        .collect()
+}
+
+// Decode heading bytes: char = b & 0x7F; if high bit set, append a space.
+pub fn decode_heading(bytes: &[u8]) -> String {
+    if bytes.is_ascii() {
+        // no high bits: no folded spaces, and the bytes are the text
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let mut s = String::new();
+    for t in decode_heading_raw(bytes) {
+        s.push(t.ch);
+        if t.folded_space {
+            s.push(' ');
+        }
+    }
+    s
+}
+
+// Longest junk prefix (BOM, CR/LF from text tools, ...) skipped before MAGIC.
+const MAX_PREFIX: usize = 64;
+
+/// What surrounds the records of a document.
+#[derive(Debug, Clone, Default)]
+pub struct Framing {
+    pub start: usize,              // offset of the document (of its prefix, if any)
+    pub end: usize,                // offset just past its EOF sentinel (or end of input)
+    pub prefix: Vec<u8>,           // bytes skipped before MAGIC
+    pub preamble: Option<[u8; 6]>, // the words after MAGIC, when not PREAMBLE
+    pub trailer: Vec<u8>,          // bytes after the EOF sentinel (index/settings blobs)
+    pub notes: Vec<String>,        // parse-report notes (tolerated oddities)
+}
+
+/// A parsed document: records plus framing.
+#[derive(Debug, Clone)]
+pub struct Doc {
+    pub recs: Vec<Rec>,
+    pub framing: Framing,
+}
+
+fn describe_prefix(prefix: &[u8]) -> String {
+    let what = match prefix {
+        [0xef, 0xbb, 0xbf, rest @ ..] if rest.iter().all(u8::is_ascii_whitespace) => "UTF-8 BOM",
+        p if p.iter().all(|&b| b == b'\r' || b == b'\n') => "line breaks",
+        p if p.iter().all(u8::is_ascii_whitespace) => "whitespace",
+        _ => "stray bytes",
+    };
+    let hex: Vec<String> = prefix.iter().take(16).map(|b| format!("{b:02x}")).collect();
+    let more = if prefix.len() > 16 { " .." } else { "" };
+    format!(
+        "skipped {}-byte prefix before MAGIC ({what}: {}{more})",
+        prefix.len(),
+        hex.join(" ")
+    )
+}
+
+// Position of MAGIC at or shortly after the start of `buf`.
+pub fn find_magic(buf: &[u8]) -> Option<usize> {
+    let head = &buf[..buf.len().min(MAX_PREFIX + MAGIC.len())];
+    #[cfg(feature = "memchr")]
+    let at = memchr::memmem::find(head, &MAGIC);
+    #[cfg(not(feature = "memchr"))]
+    let at = head.windows(MAGIC.len()).position(|w| w == MAGIC);
+    at
+}
+
+// Position of the next 0xFF (heading terminator candidate) in `buf`.
+fn find_ff(buf: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    let at = memchr::memchr(0xff, buf);
+    #[cfg(not(feature = "memchr"))]
+    let at = buf.iter().position(|&b| b == 0xff);
+    at
+}
+
+pub fn parse_otl(buf: &[u8], note_enc: &str) -> io::Result<Vec<Rec>> {
+    parse_doc(buf, note_enc).map(|d| d.recs)
+}
+
+/// The first document in `buf`.
+pub fn parse_doc(buf: &[u8], note_enc: &str) -> io::Result<Doc> {
+    parse_doc_at(buf, 0, note_enc)
+}
+
+/// The first document in `buf`, notes left undecoded: `note` and
+/// `note_bin` stay None (`flags.has_note`, `note_len` and `off_note` are
+/// set) so that structural passes over files full of 64 KB notes do not
+/// hold them all in memory. Read a note with `Rec::note_text`.
+pub fn parse_doc_lazy(buf: &[u8], note_enc: &str) -> io::Result<Doc> {
+    parse_records(buf, 0, note_enc, true, true)
+}
+
+/// Every document in `buf`: recovered streams may hold several outlines
+/// back to back (MAGIC again right after an EOF sentinel). Offsets stay
+/// relative to `buf`.
+pub fn parse_stream(buf: &[u8], note_enc: &str) -> io::Result<Vec<Doc>> {
+    let mut docs = vec![parse_doc_at(buf, 0, note_enc)?];
+    loop {
+        let end = docs[docs.len() - 1].framing.end;
+        if end >= buf.len() || find_magic(&buf[end..]).is_none() {
+            return Ok(docs);
+        }
+        docs.push(parse_doc_at(buf, end, note_enc)?);
+    }
+}
+
+pub fn parse_doc_at(buf: &[u8], start: usize, note_enc: &str) -> io::Result<Doc> {
+    parse_records(buf, start, note_enc, false, true)
+}
+
+// `probe`: check whether a trailer parses as records (once; the probe's own
+// parse does not probe its leftovers again, so long ^Z padding stays cheap).
+fn parse_records(
+    buf: &[u8],
+    start: usize,
+    note_enc: &str,
+    lazy: bool,
+    probe: bool,
+) -> io::Result<Doc> {
+    let mut i = start;
+    let mut out = Vec::<Rec>::new();
+    let mut framing = Framing {
+        start,
+        end: buf.len(),
+        ..Framing::default()
+    };
+
+    if !buf[start..].starts_with(&MAGIC) {
+        if let Some(p) = find_magic(&buf[start..]) {
+            framing.prefix = buf[start..start + p].to_vec();
+            framing.notes.push(describe_prefix(&framing.prefix));
+            i = start + p;
+        }
+    }
+    let magic = buf.len() >= i + 3 && buf[i..i + 3] == MAGIC;
+    if magic {
+        i += 3;
+    }
+    if buf.len() >= i + 6 && buf[i..i + 6] == PREAMBLE {
+        i += 6;
+    } else if magic && buf.len() >= i + 6 && buf[i] == 0xff && buf[i + 3] == 0xff {
+        // other preamble words (the standard ones have this empty-record
+        // shape too); kept for the writer rather than read as a record
+        let words: [u8; 6] = buf[i..i + 6].try_into().expect("six bytes");
+        framing.notes.push(format!(
+            "nonstandard preamble {}",
+            words.map(|b| format!("{b:02x}")).join(" ")
+        ));
+        framing.preamble = Some(words);
+        i += 6;
+    }
+
+    while i < buf.len() {
+        // explicit EOF sentinels; anything after one is trailer (or the
+        // next document of a stream)
+        if buf[i] == 0x1a {
+            framing.end = i + 1;
+            break;
+        }
+        if i + 2 < buf.len() && buf[i] == 0xff && buf[i + 1] == 0xff && buf[i + 2] == 0x1a {
+            framing.end = i + 3;
+            break;
+        }
+
+        // Find next 0xFF; heading text may be zero-length.
+        let Some(k) = find_ff(&buf[i..]).map(|p| i + p) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unterminated heading text",
+            ));
+        };
+
+        // Must have 5 bytes after the terminator for attr+marker+delta.
+        if k + 5 >= buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated record header",
+            ));
+        }
+        let attr = buf[k + 1];
+        let (mark1, mark2) = (buf[k + 2], buf[k + 3]);
+
+        // Stray 0xFF? (marker must be FE/FF followed by FF). If not, skip this 0xFF and keep searching.
+        if !matches!([mark1, mark2], [M_EXPANDED | M_COLLAPSED, 0xff]) {
+            i = k + 1;
+            continue;
+        }
+
+        // Valid record
+        let text_bytes = &buf[i..k];
+        let text = match std::str::from_utf8(text_bytes) {
+            // 7-bit heading: the bytes are the text, no copy before interning
+            Ok(t) if t.is_ascii() => intern::intern(t),
+            _ => intern::intern(&decode_heading(text_bytes)),
+        };
+        let marker_u16 = u16::from_le_bytes([mark1, mark2]);
+        let collapsed = marker_u16 == 0xFFFE;
+        let delta = i16::from_le_bytes([buf[k + 4], buf[k + 5]]);
+
+        let off_text = i;
+        let len_text = k - i;
+        if len_text > MAX_TEXTLEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "heading too large",
+            ));
+        }
+        let off_terminator = k;
+        let off_attr = k + 1;
+        let off_marker = k + 2;
+        let off_delta = k + 4;
+
+        i = k + 6;
+
+        // Optional note
+        let mut note: Option<String> = None;
+        let mut note_bin: Option<Vec<u8>> = None;
+        let mut note_guess: Option<&'static str> = None;
+        let mut off_note_len: Option<usize> = None;
+        let mut off_note: Option<usize> = None;
+        let mut note_len: usize = 0;
+        let mut note_binary = false;
+
+        if (attr & A_NOTE) != 0 {
+            if i + 2 > buf.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated note length",
+                ));
+            }
+            off_note_len = Some(i);
+            let nlen = u16::from_le_bytes([buf[i], buf[i + 1]]) as usize;
+            if nlen > MAX_NOTELEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "note too large for u16 length",
+                ));
+            }
+            i += 2;
+            if i + nlen > buf.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated note bytes",
+                ));
+            }
+            off_note = Some(i);
+            note_len = nlen;
+            let bytes = &buf[i..i + nlen];
+            note_binary = enc::is_binary(bytes);
+            if lazy {
+                // decoded on demand by Rec::note_text
+            } else if note_binary {
+                note = Some(enc::to_hex(bytes));
+                note_bin = Some(bytes.to_vec());
+            } else if note_enc == "mixed" {
+                let g = enc::guess(bytes);
+                note = Some(decode_note(bytes, g));
+                note_guess = Some(g);
+            } else {
+                note = Some(decode_note(bytes, note_enc));
+            }
+            i += nlen;
+        }
+
+        let flags = Flags {
+            has_note: (attr & A_NOTE) != 0,
+            selected: (attr & A_CURSOR) != 0,
+            has_next_sibling: (attr & A_SIBFOLLOWS) != 0,
+            has_child: (attr & A_HASKIDS) != 0, // shown, not validated by default
+            note_binary,
+        };
+
+        out.push(Rec {
+            text,
+            delta,
+            attr,
+            marker_u16,
+            collapsed,
+            note,
+            note_bin,
+            note_guess,
+            flags,
+            off_text,
+            len_text,
+            off_terminator,
+            off_attr,
+            off_marker,
+            off_delta,
+            off_note_len,
+            off_note,
+            note_len,
+            id: String::new(),
+        });
+    }
+
+    ids::assign_ids(&mut out);
+    let rest = &buf[framing.end..];
+    if !rest.is_empty() && find_magic(rest).is_none() {
+        framing.trailer = rest.to_vec();
+        framing.notes.push(format!(
+            "{} byte(s) after the EOF sentinel kept as trailer",
+            rest.len()
+        ));
+        // a stray 0x1a at a record start ends the outline early
+        let probed = probe.then(|| parse_records(buf, framing.end, note_enc, true, false));
+        if let Some(Ok(d)) = probed {
+            if !d.recs.is_empty() {
+                framing.notes.push(format!(
+                    "trailer parses as {} record(s): the 0x1a at {:#06x} may be \
+                     heading text rather than the EOF sentinel",
+                    d.recs.len(),
+                    framing.end - 1
+                ));
+            }
+        }
+    }
+    Ok(Doc { recs: out, framing })
+}
+
+/// The tree of `recs`, levels clamped at DEFAULT_MAX_LEVEL.
+pub fn build_tree(recs: &[Rec]) -> Vec<Node> {
+    build_tree_with(recs, DEFAULT_MAX_LEVEL)
+}
+
+/// The tree of `recs`, levels clamped at `max_level`.
+pub fn build_tree_with(recs: &[Rec], max_level: usize) -> Vec<Node> {
+    let mut root = Node {
+        id: None,
+        text: "".into(),
+        note: None,
+        annotation: None,
+        collapsed: false,
+        flags: Flags {
+            has_note: false,
+            selected: false,
+            has_next_sibling: false,
+            has_child: false,
+            note_binary: false,
+        },
+        synthetic: true,
+        filler: false,
+        children: Vec::new(),
+    };
+
+    let mut path: Vec<usize> = Vec::new(); // indexes from root to current parent/node
+    let mut level = 0;
+
+    for r in recs {
+        level = next_level(level, r.delta, max_level);
+
+        // shrink to target level
+        while path.len() > level {
+            path.pop();
+        }
+        // if we jumped more than +1, create dummy intermediates
+        while path.len() < level {
+            let dummy = Node {
+                id: None,
+                text: "".into(),
+                note: None,
+                annotation: None,
+                collapsed: false,
+                flags: Flags {
+                    has_note: false,
+                    selected: false,
+                    has_next_sibling: false,
+                    has_child: false,
+                    note_binary: false,
+                },
+                synthetic: true,
+                filler: true,
+                children: Vec::new(),
+            };
+            push_child(&mut root, &mut path, dummy);
+        }
+
+        let node = Node {
+            id: Some(r.id.clone()),
+            text: r.text.clone(),
+            note: r.note.clone(),
+            annotation: None,
+            collapsed: r.collapsed,
+            flags: r.flags.clone(),
+            synthetic: false,
+            filler: false,
+            children: Vec::new(),
+        };
+        push_child(&mut root, &mut path, node);
+    }
+
+    root.children
+}
+
+fn push_child(root: &mut Node, path: &mut Vec<usize>, child: Node) {
+    // Walk the path safely to get a mutable reference to the parent.
+    let mut parent: &mut Node = root;
+    for &idx in path.iter() {
+        parent = parent
+            .children
+            .get_mut(idx)
+            .expect("path index out of bounds while building tree");
+    }
+    parent.children.push(child);
+    let new_idx = parent.children.len() - 1;
+    path.push(new_idx); // make the new node current
+}
+
+// Absolute level of each record (running sum of deltas, clamped like build_tree).
+pub fn rec_levels(recs: &[Rec]) -> Vec<usize> {
+    rec_levels_with(recs, DEFAULT_MAX_LEVEL)
+}
+
+pub fn rec_levels_with(recs: &[Rec], max_level: usize) -> Vec<usize> {
+    let mut level = 0;
+    let mut out = Vec::with_capacity(recs.len());
+    for r in recs {
+        level = next_level(level, r.delta, max_level);
+        out.push(level);
+    }
+    out
+}
+
+// Parent record index of each record (None for top-level records).
+pub fn rec_parents(recs: &[Rec]) -> Vec<Option<usize>> {
+    rec_parents_with(recs, DEFAULT_MAX_LEVEL)
+}
+
+pub fn rec_parents_with(recs: &[Rec], max_level: usize) -> Vec<Option<usize>> {
+    let levels = rec_levels_with(recs, max_level);
+    let mut stack: Vec<usize> = Vec::new();
+    let mut out = Vec::with_capacity(recs.len());
+    for (i, &lvl) in levels.iter().enumerate() {
+        while stack.last().is_some_and(|&p| levels[p] >= lvl) {
+            stack.pop();
+        }
+        out.push(stack.last().copied());
+        stack.push(i);
+    }
+    out
+}
+
+/// All .OTL files (any case) under `root`, recursively, in sorted order.
+pub fn collect_otl_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, out)?;
+            } else if path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case("otl"))
+            {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    walk(root, &mut out)?;
+    out.sort();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_the_writer_writes() {
+        let recs = vec![
+            Rec::new("Top level".into(), A_SIBFOLLOWS, 0xFFFF, 0, None),
+            Rec::new("kid".into(), A_NOTE, 0xFFFE, 1, Some("a\r\nb".into())),
+            Rec::new("Next".into(), 0x00, 0xFFFF, -1, None),
+        ];
+        let mut buf = b"\xef\xbb\xbf".to_vec();
+        buf.extend(write::serialize_recs(&recs, "latin1").unwrap());
+        let doc = parse_doc(&buf, "latin1").unwrap();
+        assert_eq!(doc.framing.prefix, b"\xef\xbb\xbf");
+        let texts: Vec<&str> = doc.recs.iter().map(|r| &*r.text).collect();
+        assert_eq!(texts, ["Top level", "kid", "Next"]);
+        assert_eq!(doc.recs[1].note.as_deref(), Some("a\r\nb"));
+        assert_eq!(rec_levels(&doc.recs), [0, 1, 0]);
+        let tree = build_tree(&doc.recs);
+        assert_eq!(tree.len(), 2);
+        assert!(tree[0].children[0].collapsed);
+    }
+}
@@ -0,0 +1,86 @@
+//! `paths` and `grep` subcommands.
+//!
+//! `paths` lists heading paths ("A/B/C"), optionally filtered by prefix for
+//! shell completion and fzf pickers; `--ids` prefixes each line with the
+//! record's stable id and a tab. `grep` prints matching records best first
+//! (see search.rs for the ranking).
+
+use crate::heading_paths_capped;
+use crate::paths::complete;
+use crate::search::search;
+use crate::{parse_otl, read_input, usage, EXIT_FINDINGS};
+use std::io;
+
+pub fn cmd_paths(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut prefix: Option<&str> = None;
+    let mut with_ids = false;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--complete" => match it.next() {
+                Some(v) => prefix = Some(v),
+                None => usage(prog),
+            },
+            "--ids" => with_ids = true,
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let buf = read_input(file)?;
+    let recs = parse_otl(&buf, "latin1")?;
+    let mut paths = heading_paths_capped(&recs);
+    if with_ids {
+        for (p, r) in paths.iter_mut().zip(&recs) {
+            *p = format!("{}\t{p}", r.id);
+        }
+    }
+    let lines = match prefix {
+        Some(p) if with_ids => paths
+            .into_iter()
+            .filter(|l| l.split_once('\t').is_some_and(|(_, q)| q.starts_with(p)))
+            .collect(),
+        Some(p) => complete(&paths, p),
+        None => paths,
+    };
+    for l in lines {
+        println!("{l}");
+    }
+    Ok(())
+}
+
+pub fn cmd_grep(prog: &str, args: &[String]) -> io::Result<u8> {
+    let mut words: Vec<&str> = Vec::new();
+    let mut notes = false;
+    let mut fuzzy = false;
+    let mut limit: Option<usize> = None;
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--notes" => notes = true,
+            "--fuzzy" => fuzzy = true,
+            "--limit" => {
+                limit = Some(
+                    it.next()
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_else(|| usage(prog)),
+                )
+            }
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            _ => words.push(a),
+        }
+    }
+    let [file, query] = words[..] else {
+        usage(prog)
+    };
+    let recs = parse_otl(&read_input(file)?, enc)?;
+    let paths = heading_paths_capped(&recs);
+    let hits = search(&recs, query, notes, fuzzy);
+    for h in hits.iter().take(limit.unwrap_or(usize::MAX)) {
+        let note = if h.in_note { "  (note)" } else { "" };
+        println!("#{:03} {:.2} {}{note}", h.rec, h.score, paths[h.rec]);
+    }
+    Ok(if hits.is_empty() { EXIT_FINDINGS } else { 0 })
+}
@@ -1,363 +1,288 @@
-use serde::Serialize;
+use otl::*;
 use std::env;
 use std::fs;
-use std::io::{self, Read};
-
-/// Attribute bits we (currently) know
-const A_NOTE: u8 = 0x80; // has note bytes (then u16 noteLen + bytes)
-const A_CURSOR: u8 = 0x20; // caret on this heading (displayed only with --show-cursor)
-const A_SIBFOLLOWS: u8 = 0x08; // there exists a later sibling at same level
-const A_HASKIDS: u8 = 0x04; // semantics under study; shown as k/K; validation optional
-
-#[derive(Debug, Clone)]
-struct Rec {
-    text: String,
-    delta: i16,      // relative level change (i16 LE)
-    attr: u8,        // raw attribute flags
-    marker_u16: u16, // raw marker word (FFFF/-1 expanded, FFFE/-2 collapsed)
-    collapsed: bool, // convenience (marker == FFFE)
-    note: Option<String>,
-    flags: Flags,
-
-    // Byte offsets (for --offsets)
-    off_text: usize,       // first heading byte
-    len_text: usize,       // heading byte count (before 0xFF terminator)
-    off_terminator: usize, // 0xFF that ends heading text
-    off_attr: usize,       // attr byte
-    off_marker: usize,     // first of the 2 marker bytes
-    off_delta: usize,      // first of the 2 delta bytes
-    off_note_len: Option<usize>,
-    off_note: Option<usize>,
-
-    // Raw note length from file (u16), independent of --enc decoding (0 if no note)
-    note_len: usize,
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use style::{Glyphs, TextStyle};
+
+mod addr;
+mod anki;
+mod annotate;
+mod asserts;
+mod cache;
+mod canon;
+mod check;
+mod collate;
+mod config;
+mod conform;
+mod diff;
+mod dirdiff;
+mod edit;
+mod encmap;
+mod encscan;
+mod enex;
+mod flatten;
+mod gen;
+mod git;
+mod hexmap;
+mod history;
+mod hypo;
+mod import;
+mod journal;
+mod lookup;
+mod markup;
+mod mbox;
+mod minimize;
+mod normalize;
+mod notes;
+mod ops;
+mod opts;
+mod preview;
+mod progress;
+mod roundtrip;
+mod rpc;
+mod scrub;
+mod select;
+mod serve;
+mod shape;
+mod slug;
+mod sort;
+mod stream;
+mod style;
+mod suspect;
+mod symbols;
+mod tagfilter;
+mod tags;
+mod template;
+mod transform;
+mod unique;
+mod wbs;
+mod wc;
+mod xml;
+mod xref;
+mod yaml;
+
+// Deepest level kept (`--max-level`). The library takes the limit as an
+// argument; the CLI sets it once per run, and the binary's modules use the
+// `*_capped` helpers below rather than the library's default-limit
+// `rec_levels`, `build_tree`, ... so that the flag reaches them.
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_LEVEL);
+
+fn max_level() -> usize {
+    MAX_LEVEL.load(Ordering::Relaxed)
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct Flags {
-    has_note: bool,         // attr & 0x80
-    selected: bool,         // attr & 0x20
-    has_next_sibling: bool, // attr & 0x08
-    has_child: bool,        // attr & 0x04 (shown only)
+fn next_level_capped(level: usize, delta: i16) -> usize {
+    next_level(level, delta, max_level())
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct Node {
-    text: String,
-    note: Option<String>,
-    collapsed: bool,
-    flags: Flags,
-    #[serde(skip)]
-    synthetic: bool, // true for root / filler nodes
-    children: Vec<Node>,
+fn rec_levels_capped(recs: &[Rec]) -> Vec<usize> {
+    rec_levels_with(recs, max_level())
 }
 
-const MAGIC: [u8; 3] = [0x1a, 0x93, 0x1a];
-const PREAMBLE: [u8; 6] = [0xff, 0x00, 0xff, 0xff, 0xff, 0xff];
-const M_EXPANDED: u8 = 0xff;
-const M_COLLAPSED: u8 = 0xfe;
-
-// Guardrails (format is 16-bit; these just prevent runaway reads)
-const MAX_TEXTLEN: usize = 1 << 20; // 1 MiB heading (paranoid limit)
-const MAX_NOTELEN: usize = 0xFFFF; // format max (u16)
-
-fn usage(prog: &str) -> ! {
-    eprintln!(
-        "Usage: {prog} <file | -> \
-         [--json] [--dump] [--offsets] [--validate] \
-         [--enc utf8|latin1|ascii] [--text] [--canon] \
-         [--show-cursor] [--assume-child-bit] \
-         [--diff <prev> <curr>]"
-    );
-    std::process::exit(2);
+fn rec_parents_capped(recs: &[Rec]) -> Vec<Option<usize>> {
+    rec_parents_with(recs, max_level())
 }
 
-fn decode_note(bytes: &[u8], enc: &str) -> String {
-    match enc {
-        "utf8" => String::from_utf8_lossy(bytes).to_string(),
-        "latin1" => bytes.iter().map(|&b| b as char).collect::<String>(),
-        "ascii" => bytes
-            .iter()
-            .map(|&b| (b & 0x7f) as char)
-            .collect::<String>(),
-        _ => String::from_utf8_lossy(bytes).to_string(),
-    }
+fn build_tree_capped(recs: &[Rec]) -> Vec<Node> {
+    build_tree_with(recs, max_level())
 }
 
-// Decode heading bytes: char = b & 0x7F; if high bit set, append a space.
-fn decode_heading(bytes: &[u8]) -> String {
-    let mut s = String::new();
-    for &b in bytes {
-        s.push((b & 0x7f) as char);
-        if (b & 0x80) != 0 {
-            s.push(' ');
-        }
-    }
-    s
+fn heading_paths_capped(recs: &[Rec]) -> Vec<String> {
+    paths::heading_paths_with(recs, max_level())
 }
 
-fn parse_otl(buf: &[u8], note_enc: &str) -> io::Result<Vec<Rec>> {
-    let mut i = 0usize;
-    let mut out = Vec::<Rec>::new();
-
-    if buf.len() >= 3 && buf[0..3] == MAGIC {
-        i += 3;
-    }
-    if buf.len() >= i + 6 && buf[i..i + 6] == PREAMBLE {
-        i += 6;
-    }
-
-    while i < buf.len() {
-        // explicit EOF sentinels
-        if i == buf.len() - 1 && buf[i] == 0x1a {
-            break;
-        }
-        if i + 2 < buf.len() && buf[i] == 0xff && buf[i + 1] == 0xff && buf[i + 2] == 0x1a {
-            break;
-        }
-
-        // Find next 0xFF; heading text may be zero-length.
-        let mut k = i;
-        while k < buf.len() && buf[k] != 0xff {
-            k += 1;
-        }
-        if k >= buf.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "unterminated heading text",
-            ));
-        }
-
-        // Must have at least 4 bytes after the terminator for attr+marker+delta.
-        if k + 4 >= buf.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "truncated record header",
-            ));
-        }
-        let attr = buf[k + 1];
-        let mark1 = buf[k + 2];
-        let mark2 = buf[k + 3];
-
-        // Stray 0xFF? (marker must be FE/FF followed by FF). If not, skip this 0xFF and keep searching.
-        if mark2 != 0xff || (mark1 != M_EXPANDED && mark1 != M_COLLAPSED) {
-            i = k + 1;
+// Takes the global `--max-level N` flag (any command) out of `args`.
+fn take_max_level_flag(args: Vec<String>) -> io::Result<Vec<String>> {
+    let mut rest = Vec::new();
+    let mut it = args.into_iter();
+    while let Some(a) = it.next() {
+        if a != "--max-level" {
+            rest.push(a);
             continue;
         }
-
-        // Valid record
-        let text_bytes = &buf[i..k];
-        let text = decode_heading(text_bytes);
-        let marker_u16 = u16::from_le_bytes([mark1, mark2]);
-        let collapsed = marker_u16 == 0xFFFE;
-        let delta = i16::from_le_bytes([buf[k + 4], buf[k + 5]]);
-
-        let off_text = i;
-        let len_text = k - i;
-        if len_text > MAX_TEXTLEN {
+        let v = it.next().unwrap_or_default();
+        let n: usize = v.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--max-level: not a number: {v:?}"),
+            )
+        })?;
+        if n == 0 || n > i16::MAX as usize {
             return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "heading too large",
+                io::ErrorKind::InvalidInput,
+                format!("--max-level: {n} is outside 1..={}", i16::MAX),
             ));
         }
-        let off_terminator = k;
-        let off_attr = k + 1;
-        let off_marker = k + 2;
-        let off_delta = k + 4;
-
-        i = k + 6;
-
-        // Optional note
-        let mut note: Option<String> = None;
-        let mut off_note_len: Option<usize> = None;
-        let mut off_note: Option<usize> = None;
-        let mut note_len: usize = 0;
-
-        if (attr & A_NOTE) != 0 {
-            if i + 2 > buf.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "truncated note length",
-                ));
-            }
-            off_note_len = Some(i);
-            let nlen = u16::from_le_bytes([buf[i], buf[i + 1]]) as usize;
-            if nlen > MAX_NOTELEN {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "note too large for u16 length",
-                ));
-            }
-            i += 2;
-            if i + nlen > buf.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "truncated note bytes",
-                ));
-            }
-            off_note = Some(i);
-            note_len = nlen;
-            note = Some(decode_note(&buf[i..i + nlen], note_enc));
-            i += nlen;
-        }
-
-        let flags = Flags {
-            has_note: (attr & A_NOTE) != 0,
-            selected: (attr & A_CURSOR) != 0,
-            has_next_sibling: (attr & A_SIBFOLLOWS) != 0,
-            has_child: (attr & A_HASKIDS) != 0, // shown, not validated by default
-        };
-
-        out.push(Rec {
-            text,
-            delta,
-            attr,
-            marker_u16,
-            collapsed,
-            note,
-            flags,
-            off_text,
-            len_text,
-            off_terminator,
-            off_attr,
-            off_marker,
-            off_delta,
-            off_note_len,
-            off_note,
-            note_len,
-        });
+        MAX_LEVEL.store(n, Ordering::Relaxed);
     }
-
-    Ok(out)
+    Ok(rest)
 }
 
-fn build_tree(recs: &[Rec]) -> Vec<Node> {
-    let mut root = Node {
-        text: String::new(),
-        note: None,
-        collapsed: false,
-        flags: Flags {
-            has_note: false,
-            selected: false,
-            has_next_sibling: false,
-            has_child: false,
-        },
-        synthetic: true,
-        children: Vec::new(),
-    };
-
-    let mut path: Vec<usize> = Vec::new(); // indexes from root to current parent/node
-    let mut level: i32 = 0;
-
-    for r in recs {
-        level += r.delta as i32;
-        if level < 0 {
-            level = 0;
-        }
-
-        // shrink to target level
-        while (path.len() as i32) > level {
-            path.pop();
-        }
-        // if we jumped more than +1, create dummy intermediates
-        while (path.len() as i32) < level {
-            let dummy = Node {
-                text: String::new(),
-                note: None,
-                collapsed: false,
-                flags: Flags {
-                    has_note: false,
-                    selected: false,
-                    has_next_sibling: false,
-                    has_child: false,
-                },
-                synthetic: true,
-                children: Vec::new(),
-            };
-            push_child(&mut root, &mut path, dummy);
-        }
+fn usage(prog: &str) -> ! {
+    eprintln!(
+        "Usage: {prog} <file | -> \
+         [--json|--yaml|--xml|--markdown|--html|--enex|--mbox|--anki-tsv|--plantuml-wbs] {slugs} [--annotations] {tags} [--dump] [--offsets] [--validate] [--stats] \
+         [--enc utf8|latin1|ascii|cp1252|cp437|cp850|mixed] [--text] [--canon] \
+         [--show-cursor] [--cursor-only] [--assume-child-bit] [--unique [--collate <locale>]] [--verify-roundtrip {preserve}] [--doc N] \
+         [--ctags|--etags [--tag-file <name>] [--tag-lines]] {} {} {} {} {} \
+         [--diff <prev> <curr> [--summary] [--color auto|always|never]]\n       \
+         {prog} check <files | dirs...> [--enc ..] [--enc-map <encodings.toml>] [--assume-child-bit] [--unique {slugs} [--collate <locale>]] [--report text|json] [--quiet] [--lazy-notes] [--stats] \
+         [--hypothesis <bit>=[!]<predicate>]...\n       \
+         {prog} diff-dir <oldDir> <newDir> [--enc ..] [--enc-map <encodings.toml>] [--summary] [--show-cursor] [--quiet] [--color auto|always|never]\n       \
+         {prog} history <file> <snapshotDir> [--summary]   (change log across snapshots)\n       \
+         {prog} serve <dir> [--port N] [--bind ADDR] [--enc ..]   (read-only HTTP: HTML/JSON views)\n       \
+         {prog} conformance <refDir>   (X.canon + SideKick-saved X.OTL pairs)\n       \
+         {prog} --xsd   (XML Schema for --xml output)\n       \
+         {prog} --serve-stdio   (JSON-RPC 2.0, one request per line: parse, render, apply_edits, check, shutdown)\n       \
+         {prog} textconv <file> [--show-cursor]\n       \
+         {prog} --git-diff <path> <old> <old-hex> <old-mode> <new> <new-hex> <new-mode>\n       \
+         {prog} clean [--enc E] [--enc-map FILE] [--path P] [file]   (.OTL -> canon; git filter)\n       \
+         {prog} smudge [--enc E] [--enc-map FILE] [--path P] [--fix-level-jumps ..] [file]  (canon -> .OTL; git filter)\n       \
+         {prog} sort <file | -> [{}] [--key text|numeric|date|note] [--collate <locale>] [--reverse] [--recursive] \
+         [--fix-level-jumps placeholder[=TEXT]|relevel] [--strip-prefix] [--compress-headings] [--enc ..] {preserve} \\
+         (-o <out> | --in-place [--journal])\n       \
+         {prog} edit <file> ({} | --script <file>) [--interactive] [--compress-headings] [--enc ..] {preserve} \\
+         (-o <out> | --in-place [--journal])\n       \
+         {prog} transform <file> {} [--compress-headings] [--enc ..] {preserve} (--dry-run | -o <out> | --in-place [--journal])\n       \
+         {prog} normalize <file | -> [--keep-folds] [--enc ..] {preserve} (-o <out> | --in-place [--journal])\n       \
+         {prog} undo <file> [--list] [--force]   (restore from <file>.undo)\n       \
+         {prog} symbols <file | -> [--format lsp-json]\n       \
+         {prog} flatten|unflatten <file | -> [--sep \" > \"] [--to otl|text] [--enc ..] [-o <out>]\n       \
+         {prog} gen [--nodes N] [--max-depth D] [--note-prob P] [--seed S] \
+         [--template meeting|project|journal] [-o <out>]\n       \
+         {prog} new --template <file.tmpl> [--var name=value ..] [--enc ..] [-o <out>]\n       \
+         {prog} scrub <file | -> [--words|--placeholder] [--salt S] [--keep-trailer] -o <out>\n       \
+         {prog} minimize <file | -> --check parse-error|validate-warning \
+         [--assume-child-bit] [-o <out>]\n       \
+         {prog} split-stream <file | -> [-o <prefix>] {slugs}\n       \
+         {prog} assert <file | -> [--max-depth N] [--max-records N] [--require-note <path|rec:N|id:HEX>].. \
+         [--forbid-unknown-bits] [--enc ..]\n       \
+         {prog} suspicious <file | ->   (corrupt/mis-encoded headings)\n       \
+         {prog} annotate-hex <file | -> --format 010-template|imhex [-o <out>]   (hex editor overlay)\n       \
+         {prog} encscan <file | -> [--write-enc utf8|latin1|cp1252|cp437|cp850|mixed|ascii]\n       \
+         {prog} notes <file | -> [--top N] [--enc ..]   (duplicate and largest notes)\n       \
+         {prog} wc <file | -> [--depth N] [--enc ..] [{}]\n       \
+         {prog} paths <file | -> [--complete <prefix>] [--ids]\n       \
+         {prog} grep <file | -> <text> [--notes] [--fuzzy] [--limit N] [--enc ..]   (ranked matches)\n       \
+         {prog} select <file | -> {} [--fuzzy] [--text|--json|--canon] [--show-cursor] [--enc ..] {} {} {}\n       \
+         --opt keys: {}\n       \
+         Any command: [--config <path>] (default ~/.config/otl/config.toml) [--no-<key>] (drop a config default) \
+         [--max-level N] (deeper levels are clamped; default 255)",
+        shape::SHAPE_USAGE,
+        style::STYLE_USAGE,
+        opts::OPT_USAGE,
+        preview::PREVIEW_USAGE,
+        transform::TRANSFORM_USAGE,
+        addr::ADDR_USAGE,
+        edit::EDIT_OPS,
+        transform::TRANSFORM_USAGE,
+        addr::ADDR_USAGE,
+        addr::ADDR_USAGE,
+        shape::SHAPE_USAGE,
+        style::STYLE_USAGE,
+        opts::OPT_USAGE,
+        opts::KNOWN
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join(", "),
+        slugs = slug::SLUG_USAGE,
+        tags = tagfilter::TAG_USAGE,
+        preserve = write::PRESERVE_USAGE,
+    );
+    std::process::exit(EXIT_USAGE.into());
+}
 
-        let node = Node {
-            text: r.text.clone(),
-            note: r.note.clone(),
-            collapsed: r.collapsed,
-            flags: r.flags.clone(),
-            synthetic: false,
-            children: Vec::new(),
-        };
-        push_child(&mut root, &mut path, node);
+// Read a whole input file; "-" means stdin.
+fn read_input(file: &str) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if file == "-" {
+        io::stdin().read_to_end(&mut buf)?;
+    } else {
+        buf = fs::read(file)?;
     }
-
-    root.children
+    Ok(buf)
 }
 
-fn push_child(root: &mut Node, path: &mut Vec<usize>, child: Node) {
-    // Walk the path safely to get a mutable reference to the parent.
-    let mut parent: &mut Node = root;
-    for &idx in path.iter() {
-        parent = parent
-            .children
-            .get_mut(idx)
-            .expect("path index out of bounds while building tree");
+// Write a whole output file; "-" means stdout.
+fn write_output(file: &str, bytes: &[u8]) -> io::Result<()> {
+    if file == "-" {
+        io::stdout().write_all(bytes)
+    } else {
+        fs::write(file, bytes)
     }
-    parent.children.push(child);
-    let new_idx = parent.children.len() - 1;
-    path.push(new_idx); // make the new node current
 }
 
-fn render_plain_all(nodes: &[Node], depth: usize) -> String {
+fn render_plain_all(nodes: &[Node], depth: usize, style: &TextStyle) -> String {
     let mut out = String::new();
     for n in nodes {
         if n.synthetic {
-            out.push_str(&render_plain_all(&n.children, depth));
+            out.push_str(&render_plain_all(&n.children, depth, style));
             continue;
         }
-        let indent = " ".repeat(depth * 2);
-        out.push_str(&format!("{indent}{}\n", n.text));
+        let indent = style.pad(depth, 2);
+        let g = style.glyphs.unwrap_or(Glyphs::None);
+        let sel = style.selected(g, n.flags.selected);
+        if g == Glyphs::None {
+            out.push_str(&format!("{indent}{sel}{}\n", n.text));
+        } else {
+            out.push_str(&format!(
+                "{indent}{}{sel} {}\n",
+                g.fold(n.collapsed),
+                n.text
+            ));
+        }
 
         if let Some(note) = &n.note {
-            let note_indent = " ".repeat((depth + 1) * 2);
+            let note_indent = style.pad(depth + 1, 2);
+            let mark = style.glyphs.map_or("", |g| g.note());
             for line in note.replace("\r\n", "\n").lines() {
-                out.push_str(&format!("{note_indent}{}\n", line));
+                out.push_str(&format!("{note_indent}{mark}{}\n", line));
             }
         }
         // Always descend (ignore collapsed)
-        out.push_str(&render_plain_all(&n.children, depth + 1));
+        out.push_str(&render_plain_all(&n.children, depth + 1, style));
     }
     out
 }
 
-fn render_indented(nodes: &[Node], prefix: &str) -> String {
+fn render_indented(nodes: &[Node], depth: usize, style: &TextStyle) -> String {
     let mut out = String::new();
+    let g = style.glyphs.unwrap_or(Glyphs::Ascii);
     for n in nodes {
         if n.synthetic {
-            out.push_str(&render_indented(&n.children, prefix));
+            out.push_str(&render_indented(&n.children, depth, style));
             continue;
         }
-        let fold = if n.collapsed { "[+]" } else { "[-]" };
-        let sel = if n.flags.selected { "*" } else { " " };
-        out.push_str(&format!("{prefix}{fold}{sel} {}\n", n.text));
+        let prefix = style.pad(depth, 4);
+        let fold = g.fold(n.collapsed);
+        let sel = style.selected(g, n.flags.selected);
+        if g == Glyphs::None {
+            out.push_str(&format!("{prefix}{sel}{}\n", n.text));
+        } else {
+            out.push_str(&format!("{prefix}{fold}{sel} {}\n", n.text));
+        }
 
         if let Some(note) = &n.note {
+            let note_prefix = style.pad(depth + 1, 4);
             for line in note.replace("\r\n", "\n").lines() {
-                out.push_str(&format!("{prefix}    > {}\n", line));
+                out.push_str(&format!("{note_prefix}{}{}\n", g.note(), line));
             }
         }
         if !n.collapsed && !n.children.is_empty() {
-            out.push_str(&render_indented(
-                &n.children,
-                &(prefix.to_string() + "    "),
-            ));
+            out.push_str(&render_indented(&n.children, depth + 1, style));
         }
     }
     out
 }
 
 fn dump_recs(recs: &[Rec]) -> String {
-    let mut lvl: i32 = 0;
+    let mut lvl = 0;
     let mut s = String::new();
     for (idx, r) in recs.iter().enumerate() {
-        lvl += r.delta as i32;
+        lvl = next_level_capped(lvl, r.delta);
         let c = if r.collapsed { 'C' } else { 'E' };
         let sel = if r.flags.selected { 'S' } else { ' ' };
         let nxt = if r.flags.has_next_sibling { 'S' } else { ' ' }; // 'S' to hint "sib follows"
@@ -370,6 +295,28 @@ fn dump_recs(recs: &[Rec]) -> String {
     s
 }
 
+fn dump_framing(f: &Framing) -> String {
+    let mut s = String::new();
+    if !f.prefix.is_empty() {
+        s.push_str(&format!(
+            "prefix[{:#06x}+{:>4}] (skipped)\n",
+            f.start,
+            f.prefix.len()
+        ));
+    }
+    if !f.trailer.is_empty() {
+        s.push_str(&format!(
+            "trailer[{:#06x}+{:>4}] (kept)\n",
+            f.end,
+            f.trailer.len()
+        ));
+    }
+    for n in &f.notes {
+        s.push_str(&format!("note: {n}\n"));
+    }
+    s
+}
+
 fn dump_offsets(recs: &[Rec]) -> String {
     let mut s = String::new();
     for (idx, r) in recs.iter().enumerate() {
@@ -400,13 +347,15 @@ mark[{:#06x}={:<5}] delta[{:#06x}]{}{}  {}\n",
     s
 }
 
-// Escape just backslash and quote for compact one-line headline printing
+// Escape backslash and quote for compact one-line headline printing;
+// control chars (rare, but they would break the line format) become \xNN.
 fn escape_headline(s: &str) -> String {
     let mut out = String::new();
     for ch in s.chars() {
         match ch {
             '\\' => out.push_str("\\\\"),
             '"' => out.push_str("\\\""),
+            c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
             _ => out.push(ch),
         }
     }
@@ -492,6 +441,8 @@ fn delta_field(d: i16) -> String {
 
 /// Offset-free, insertion-stable, bit-complete dump:
 /// <attrbits> mark=-1:+|-2:-|0xnnnn delta=+1|0|-1|0xnnnn textLen=%04x "text"
+/// (<attrbits> is followed by ` attr=0xnn` when the letters alone can't tell
+/// which unknown bit is set; see canon::parse_attr_bits.)
 /// If a note exists, emit on following lines:
 ///   noteLen=%04x
 ///   note
@@ -505,15 +456,24 @@ fn render_canon(recs: &[Rec], show_cursor: bool) -> String {
         let text_len_raw: u16 = r.len_text as u16;
 
         out.push_str(&format!(
-            "{} mark={} delta={} textLen={:04x} \"{}\"\n",
+            "{}{} mark={} delta={} textLen={:04x} \"{}\"\n",
             fmt_attr_bits(r.attr, show_cursor),
+            canon::attr_hex_field(r.attr, show_cursor),
             mark,
             delta_disp,
             text_len_raw,
             escape_headline(&r.text)
         ));
 
-        if r.flags.has_note {
+        if let Some(bytes) = &r.note_bin {
+            out.push_str(&format!("noteLen={:04x}\n", r.note_len as u16));
+            out.push_str("note hex\n");
+            for chunk in bytes.chunks(canon::HEX_PER_LINE) {
+                out.push_str(&enc::to_hex(chunk));
+                out.push('\n');
+            }
+            out.push_str("/note\n");
+        } else if r.flags.has_note {
             out.push_str(&format!("noteLen={:04x}\n", r.note_len as u16));
             out.push_str("note\n");
             let note_norm = r.note.as_deref().unwrap_or("").replace("\r\n", "\n");
@@ -527,31 +487,7 @@ fn render_canon(recs: &[Rec], show_cursor: bool) -> String {
     out
 }
 
-// Encode helpers to write .OTL from a Node tree
-#[cfg(test)]
-fn encode_heading_from_text(text: &str) -> Vec<u8> {
-    // Best-effort 7-bit mapping; non-ASCII becomes '?'. We do not use the high-bit space encoding.
-    let mut v = Vec::with_capacity(text.len());
-    for ch in text.chars() {
-        let b = if (ch as u32) < 0x80 { ch as u8 } else { b'?' } & 0x7f;
-        v.push(b);
-    }
-    v
-}
-
-#[cfg(test)]
-fn encode_note_bytes(note: &str, enc: &str) -> Vec<u8> {
-    match enc {
-        "utf8" => note.as_bytes().to_vec(),
-        "latin1" => note
-            .chars()
-            .map(|c| if (c as u32) <= 0xFF { c as u8 } else { b'?' })
-            .collect(),
-        "ascii" => note.bytes().map(|b| b & 0x7f).collect(),
-        _ => note.as_bytes().to_vec(),
-    }
-}
-
+// Encode a Node tree back to .OTL (tests only; see write.rs for the record writer)
 #[cfg(test)]
 fn serialize_tree_to_otl(nodes: &[Node], note_enc: &str) -> Vec<u8> {
     #[derive(Clone)]
@@ -582,8 +518,8 @@ fn serialize_tree_to_otl(nodes: &[Node], note_enc: &str) -> Vec<u8> {
             }
             // Intentionally do not set A_HASKIDS; semantics under study
             let marker_first = if n.collapsed { M_COLLAPSED } else { M_EXPANDED };
-            let text = encode_heading_from_text(&n.text);
-            let note = n.note.as_ref().map(|s| encode_note_bytes(s, note_enc));
+            let text = write::encode_heading(&n.text, usize::MAX);
+            let note = n.note.as_ref().map(|s| write::encode_note(s, note_enc));
             out.push(Flat {
                 level,
                 attr,
@@ -624,24 +560,44 @@ fn serialize_tree_to_otl(nodes: &[Node], note_enc: &str) -> Vec<u8> {
     buf
 }
 
-/// Validate derived invariants and print warnings to stderr.
-/// By default we only assert bits we're confident in (0x08 sibling follows).
-/// Use `assume_child_bit=true` to test the hypothesis that 0x04 == "has child".
-fn validate(recs: &[Rec], assume_child_bit: bool) {
-    // compute levels
-    let mut levels = Vec::with_capacity(recs.len());
-    let mut lvl = 0i32;
-    for r in recs {
-        lvl += r.delta as i32;
-        if lvl < 0 {
-            lvl = 0;
-        }
-        levels.push(lvl);
-    }
+/// One `--validate` finding; `kind` is stable, `detail` is for humans.
+struct Finding {
+    rec: usize,
+    kind: &'static str,
+    detail: String,
+}
+
+/// The `--validate` findings for `recs`, in record order (see `validate`).
+fn validate_findings(recs: &[Rec], assume_child_bit: bool) -> Vec<Finding> {
+    let levels = rec_levels_capped(recs);
+    let mut out = Vec::new();
 
     for i in 0..recs.len() {
         let my = levels[i];
 
+        // Level clamped (see next_level_capped): the delta asks for a level below
+        // 0 or deeper than --max-level
+        let prev = if i == 0 { 0 } else { levels[i - 1] };
+        let want = prev as i64 + i64::from(recs[i].delta);
+        if want != my as i64 {
+            let (kind, what) = if want < 0 {
+                ("level-underflow", "below 0".to_string())
+            } else {
+                (
+                    "level-overflow",
+                    format!("deeper than --max-level {}", max_level()),
+                )
+            };
+            out.push(Finding {
+                rec: i,
+                kind,
+                detail: format!(
+                    "delta {} takes level {prev} to {want}, {what}; clamped to {my} at delta[{:#06x}]",
+                    recs[i].delta, recs[i].off_delta
+                ),
+            });
+        }
+
         // 0x08 sibling-follows check -- solid
         let mut has_later_sibling = false;
         for &level in levels.iter().skip(i + 1) {
@@ -655,10 +611,14 @@ fn validate(recs: &[Rec], assume_child_bit: bool) {
         }
         let bit_sib = (recs[i].attr & A_SIBFOLLOWS) != 0;
         if has_later_sibling != bit_sib {
-            eprintln!(
-                "WARN: rec #{:03} sibling bit mismatch (attr={}, expected={}) at attr[{:#06x}]",
-                i, bit_sib, has_later_sibling, recs[i].off_attr
-            );
+            out.push(Finding {
+                rec: i,
+                kind: "sibling-bit",
+                detail: format!(
+                    "sibling bit mismatch (attr={}, expected={}) at attr[{:#06x}]",
+                    bit_sib, has_later_sibling, recs[i].off_attr
+                ),
+            });
         }
 
         // Optional hypothesis check for 0x04
@@ -666,10 +626,14 @@ fn validate(recs: &[Rec], assume_child_bit: bool) {
             let has_child_struct = i + 1 < recs.len() && levels[i + 1] > my;
             let bit_child = (recs[i].attr & A_HASKIDS) != 0;
             if has_child_struct != bit_child {
-                eprintln!(
-                    "WARN: rec #{:03} 0x04!=has_child (attr={}, expected={}) at attr[{:#06x}]",
-                    i, bit_child, has_child_struct, recs[i].off_attr
-                );
+                out.push(Finding {
+                    rec: i,
+                    kind: "child-bit",
+                    detail: format!(
+                        "0x04!=has_child (attr={}, expected={}) at attr[{:#06x}]",
+                        bit_child, has_child_struct, recs[i].off_attr
+                    ),
+                });
             }
         }
 
@@ -677,12 +641,468 @@ fn validate(recs: &[Rec], assume_child_bit: bool) {
         let known = A_NOTE | A_CURSOR | A_SIBFOLLOWS | A_HASKIDS;
         let unknown = recs[i].attr & !known;
         if unknown != 0 {
-            eprintln!(
-                "WARN: rec #{:03} unknown attr bits set: 0x{:02x} at attr[{:#06x}]",
-                i, unknown, recs[i].off_attr
+            out.push(Finding {
+                rec: i,
+                kind: "unknown-attr",
+                detail: format!(
+                    "unknown attr bits set: 0x{:02x} at attr[{:#06x}]",
+                    unknown, recs[i].off_attr
+                ),
+            });
+        }
+    }
+    out
+}
+
+/// Validate derived invariants and print warnings to stderr.
+/// By default we only assert bits we're confident in (0x08 sibling follows).
+/// Use `assume_child_bit=true` to test the hypothesis that 0x04 == "has child".
+/// Returns how many findings; `unique` adds the heading collision findings
+/// (see unique.rs).
+fn validate(recs: &[Rec], assume_child_bit: bool, unique: Option<&unique::Unique>) -> usize {
+    let mut findings = validate_findings(recs, assume_child_bit);
+    if let Some(u) = unique {
+        findings.extend(unique::collision_findings(recs, u));
+    }
+    for f in &findings {
+        eprintln!("WARN: rec #{:03} {}", f.rec, f.detail);
+    }
+    findings.len()
+}
+
+/// Exit statuses (documented in README "Exit status"): 0 ok, then these.
+const EXIT_FINDINGS: u8 = 1; // differences, validation findings
+const EXIT_USAGE: u8 = 2; // bad arguments (also from usage())
+const EXIT_PARSE: u8 = 3; // input is not a readable .OTL / canon dump
+const EXIT_IO: u8 = 4; // file system and other I/O errors
+
+fn exit_code(e: &io::Error) -> u8 {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => EXIT_PARSE,
+        io::ErrorKind::InvalidInput => EXIT_USAGE,
+        _ => EXIT_IO,
+    }
+}
+
+// Subcommands without findings always succeed with 0.
+fn no_findings(r: io::Result<()>) -> io::Result<u8> {
+    r.map(|()| 0)
+}
+
+fn main() -> std::process::ExitCode {
+    let raw_args: Vec<String> = env::args().skip(1).collect();
+    let prog = env::args().next().unwrap_or_else(|| "otl".into());
+    let code = match run(&prog, &raw_args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{prog}: {e}");
+            exit_code(&e)
+        }
+    };
+    std::process::ExitCode::from(code)
+}
+
+fn run(prog: &str, raw_args: &[String]) -> io::Result<u8> {
+    let prog = prog.to_string();
+    let (config_path, raw_args) = config::take_config_flag(raw_args)?;
+    let config = config::Config::load(config_path.as_deref())?;
+    // config guardrails apply to every command; its other defaults go
+    // before the arguments of the commands taking them (see keys_for)
+    let raw_args = take_max_level_flag(config.args_for(config::GLOBAL_KEYS, &raw_args))?;
+    let cmd = raw_args.first().map_or("", |s| s.as_str());
+    let args = config.args_for(config::keys_for(cmd), raw_args.get(1..).unwrap_or(&[]));
+
+    // Subcommands
+    match cmd {
+        "check" => return check::cmd_check(&prog, &args),
+        "conformance" => return conform::cmd_conformance(&prog, &args),
+        "diff-dir" => return dirdiff::cmd_diff_dir(&prog, &args),
+        "history" => return no_findings(history::cmd_history(&prog, &args)),
+        "serve" => return no_findings(serve::cmd_serve(&prog, &args)),
+        "textconv" => return no_findings(git::cmd_textconv(&prog, &args)),
+        "--git-diff" => return no_findings(git::cmd_git_diff(&prog, &args)),
+        "--serve-stdio" => return no_findings(rpc::serve(io::stdin().lock(), io::stdout().lock())),
+        "clean" => return no_findings(git::cmd_clean(&prog, &args)),
+        "smudge" => return no_findings(git::cmd_smudge(&prog, &args)),
+        "undo" => return no_findings(journal::cmd_undo(&prog, &args)),
+        "edit" => return no_findings(edit::cmd_edit(&prog, &args)),
+        "transform" => return no_findings(transform::cmd_transform(&prog, &args)),
+        "sort" => return no_findings(sort::cmd_sort(&prog, &args)),
+        "normalize" => return no_findings(normalize::cmd_normalize(&prog, &args)),
+        "symbols" => return no_findings(symbols::cmd_symbols(&prog, &args)),
+        "minimize" => return no_findings(minimize::cmd_minimize(&prog, &args)),
+        "scrub" => return no_findings(scrub::cmd_scrub(&prog, &args)),
+        "gen" => return no_findings(gen::cmd_gen(&prog, &args)),
+        "new" => return no_findings(template::cmd_new(&prog, &args)),
+        "flatten" => return no_findings(flatten::cmd_flatten(&prog, &args)),
+        "unflatten" => return no_findings(flatten::cmd_unflatten(&prog, &args)),
+        "split-stream" => return no_findings(stream::cmd_split_stream(&prog, &args)),
+        "suspicious" => return suspect::cmd_suspicious(&prog, &args),
+        "assert" => return asserts::cmd_assert(&prog, &args),
+        "notes" => return no_findings(notes::cmd_notes(&prog, &args)),
+        "encscan" => return no_findings(encscan::cmd_encscan(&prog, &args)),
+        "annotate-hex" => return no_findings(hexmap::cmd_annotate_hex(&prog, &args)),
+        "wc" => return no_findings(wc::cmd_wc(&prog, &args)),
+        "paths" => return no_findings(lookup::cmd_paths(&prog, &args)),
+        "grep" => return lookup::cmd_grep(&prog, &args),
+        "select" => return no_findings(select::cmd_select(&prog, &args)),
+        _ => {}
+    }
+
+    // Fast path: --diff <prev> <curr> [--show-cursor] [--summary] [--color ..]
+    if cmd == "--diff" {
+        let mut show_cursor = false;
+        let mut summary = false;
+        let mut color = diff::Color::default();
+        let mut paths: Vec<&str> = Vec::new();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            match a.as_str() {
+                "--show-cursor" => show_cursor = true,
+                "--summary" => summary = true,
+                "--color" => color = diff::Color::parse(it.next().unwrap_or_else(|| usage(&prog)))?,
+                _ => paths.push(a),
+            }
+        }
+        if paths.len() != 2 {
+            usage(&prog);
+        }
+        let prev_buf = fs::read(paths[0])?;
+        let curr_buf = fs::read(paths[1])?;
+        let prev_recs = parse_otl(&prev_buf, "latin1")?;
+        let curr_recs = parse_otl(&curr_buf, "latin1")?;
+        let (report, counts) = diff::diff_report(&prev_recs, &curr_recs, show_cursor);
+        if summary {
+            let bytes = curr_buf.len() as i64 - prev_buf.len() as i64;
+            println!(
+                "{counts} bytes {bytes:+} ({} -> {})",
+                prev_buf.len(),
+                curr_buf.len()
             );
+        } else if color.enabled() {
+            print!("{}", diff::paint(&report));
+        } else {
+            print!("{report}");
+        }
+        return Ok(if report.is_empty() { 0 } else { EXIT_FINDINGS });
+    }
+
+    // Normal modes
+    let view_args = config.args_for(config::VIEW_KEYS, &raw_args);
+    let mut args = view_args.iter();
+    let mut file: Option<String> = None;
+    let mut out_json = false;
+    let mut markdown = false;
+    let mut html = false;
+    let mut enex = false;
+    let mut mbox = false;
+    let mut anki = false;
+    let mut out_xml = false;
+    let mut out_yaml = false;
+    let mut wbs = false;
+    let mut do_dump = false;
+    let mut do_offsets = false;
+    let mut do_validate = false;
+    let mut stats = false;
+    let mut plain_text = false;
+    let mut canon = false;
+    let mut enc = String::from("latin1");
+    let mut assume_child_bit = false;
+    let mut unique = false;
+    let mut verify_roundtrip = false;
+    let mut keep = write::Preserve::default();
+    let mut show_cursor = false;
+    let mut ctags = false;
+    let mut etags = false;
+    let mut tag_file: Option<String> = None;
+    let mut tag_lines = false;
+    let mut cursor_only = false;
+    let mut doc_no: Option<usize> = None;
+    let mut shape = shape::Shape::default();
+    let mut style = TextStyle::default();
+    let mut opts = opts::Opts::default();
+    let mut preview = preview::Preview::default();
+    let mut transforms = transform::Transforms::default();
+    let mut tag_filter = tagfilter::TagFilter::default();
+    let mut slugs = slug::SlugStyle::default();
+    let mut collate: Option<collate::Collator> = None;
+    let mut annotations = false;
+
+    while let Some(a) = args.next() {
+        if let Some(r) = opts.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = preview.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = transforms.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = tag_filter.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = shape.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = style.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        if let Some(r) = keep.take_flag(a, &mut args) {
+            r?;
+            continue;
+        }
+        match a.as_str() {
+            "--json" => out_json = true,
+            "--markdown" => markdown = true,
+            "--html" => html = true,
+            "--enex" => enex = true,
+            "--mbox" => mbox = true,
+            "--anki-tsv" => anki = true,
+            "--xml" => out_xml = true,
+            "--slugs" => {
+                slugs = slug::SlugStyle::parse(args.next().unwrap_or_else(|| usage(&prog)))?
+            }
+            "--annotations" => annotations = true,
+            "--yaml" => out_yaml = true,
+            "--plantuml-wbs" => wbs = true,
+            "--xsd" => {
+                print!("{}", xml::XSD);
+                return Ok(0);
+            }
+            "--dump" => do_dump = true,
+            "--offsets" => do_offsets = true,
+            "--validate" => do_validate = true,
+            "--stats" => stats = true,
+            "--text" => plain_text = true,
+            "--canon" => canon = true,
+            "--assume-child-bit" => assume_child_bit = true,
+            "--unique" => unique = true,
+            "--collate" => {
+                collate = Some(collate::Collator::new(
+                    args.next().unwrap_or_else(|| usage(&prog)),
+                )?)
+            }
+            "--verify-roundtrip" => verify_roundtrip = true,
+            "--show-cursor" => show_cursor = true,
+            "--cursor-only" => cursor_only = true,
+            "--ctags" => ctags = true,
+            "--etags" => etags = true,
+            "--tag-lines" => tag_lines = true,
+            "--doc" => {
+                let v = args.next().unwrap_or_else(|| usage(&prog));
+                match v.parse::<usize>() {
+                    Ok(n) if n > 0 => doc_no = Some(n),
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("--doc wants a document number from 1, got {v:?}"),
+                        ))
+                    }
+                }
+            }
+            "--tag-file" => tag_file = Some(args.next().unwrap_or_else(|| usage(&prog)).clone()),
+            "--enc" => {
+                if let Some(v) = args.next() {
+                    enc = v.clone();
+                } else {
+                    usage(&env::args().next().unwrap_or_else(|| "otl".into()));
+                }
+            }
+            _ => {
+                if file.is_none() {
+                    file = Some(a.clone());
+                } else {
+                    usage(&env::args().next().unwrap_or_else(|| "otl".into()));
+                }
+            }
+        }
+    }
+
+    let file = file.unwrap_or_else(|| usage(&prog));
+    if collate.is_some() && !unique {
+        usage(&prog);
+    }
+    let unique = unique.then_some(unique::Unique { slugs, collate });
+
+    let buf = read_input(&file)?;
+
+    let mut docs = parse_stream(&buf, &enc)?;
+    let count = docs.len();
+    let doc_no = doc_no.unwrap_or(1);
+    if doc_no > count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("--doc {doc_no}: input holds {count} document(s)"),
+        ));
+    }
+    if count > 1 {
+        eprintln!(
+            "NOTE: input holds {count} documents; showing #{doc_no} (see --doc, split-stream)"
+        );
+    }
+    let Doc { recs, framing } = docs.swap_remove(doc_no - 1);
+    if stats {
+        eprintln!("{}", intern::stats());
+        eprintln!(
+            "trailer: {} byte(s) after the EOF sentinel",
+            framing.trailer.len()
+        );
+    }
+    let mut code = 0;
+    if verify_roundtrip {
+        let end = (framing.end + framing.trailer.len()).min(buf.len());
+        let input = &buf[framing.start..end];
+        let d = roundtrip::verify(input, &recs, &framing, &enc, &keep)?;
+        let (lines, bug) = roundtrip::report(&d, input.len());
+        for l in lines {
+            eprintln!("{l}");
+        }
+        if bug {
+            code = EXIT_FINDINGS;
+        }
+    }
+    // validate the file as read, not the filtered/previewed/transformed
+    // outline (those rebuild delta and sibling bits)
+    if do_validate {
+        for n in &framing.notes {
+            eprintln!("NOTE: {n}");
         }
+        if validate(&recs, assume_child_bit, unique.as_ref()) > 0 {
+            code = EXIT_FINDINGS;
+        }
+    }
+    let mut recs = preview.apply(tag_filter.apply(recs));
+    transforms.apply(&mut recs);
+    if do_dump {
+        print!("{}", dump_recs(&recs));
+        // fall through to also print offsets if requested
+    }
+    // any output format besides the default indented text
+    let export = out_json
+        || out_yaml
+        || out_xml
+        || markdown
+        || html
+        || enex
+        || mbox
+        || wbs
+        || anki
+        || plain_text
+        || canon
+        || ctags
+        || etags;
+    if do_offsets {
+        print!("{}", dump_framing(&framing));
+        print!("{}", dump_offsets(&recs));
+        if !export {
+            return Ok(code);
+        }
+    }
+
+    if cursor_only {
+        // heading path of the selected record(s); nothing if no cursor bit
+        let paths = heading_paths_capped(&recs);
+        for (r, p) in recs.iter().zip(&paths) {
+            if r.flags.selected {
+                println!("{p}");
+            }
+        }
+        return Ok(code);
+    }
+
+    if ctags || etags {
+        let tag_file = tag_file.as_deref().unwrap_or(&file);
+        if ctags {
+            let o = opts::TagOpts::from_opts(&opts)?;
+            print!("{}", tags::render_ctags(&recs, tag_file, tag_lines, &o));
+        } else {
+            print!("{}", tags::render_etags(&recs, tag_file));
+        }
+        return Ok(code);
+    }
+
+    let ann = if annotations {
+        if file == "-" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--annotations reads <file>.ann.toml; give a file",
+            ));
+        }
+        let (ann, warnings) = annotate::Annotations::load(&file, &recs)?;
+        for w in warnings {
+            eprintln!("NOTE: {w}");
+        }
+        ann
+    } else {
+        annotate::Annotations::default()
+    };
+
+    style.cursor = show_cursor;
+    let mut tree = build_tree_capped(&recs);
+    ann.apply(&mut tree);
+    let tree = shape.apply(tree);
+    if out_json {
+        println!("{}", opts::JsonOpts::from_opts(&opts)?.render(&tree));
+    } else if out_yaml {
+        print!("{}", yaml::render_yaml(&tree));
+    } else if out_xml {
+        print!("{}", xml::render_xml(&tree));
+    } else if markdown || html {
+        // anchors that move with document order
+        let anchors = unique::Unique {
+            slugs,
+            collate: None,
+        };
+        let clashes = unique::collision_findings(&recs, &anchors);
+        for f in clashes.iter().filter(|f| f.kind == "slug-collision") {
+            eprintln!("NOTE: rec #{:03} {}", f.rec, f.detail);
+        }
+        let idx = xref::Index::new(&recs, slugs);
+        let (page, unresolved) = if html {
+            markup::render_html(&tree, &idx, &file, &opts::HtmlOpts::from_opts(&opts)?)
+        } else {
+            markup::render_markdown(&tree, &idx, &opts::MarkdownOpts::from_opts(&opts)?)
+        };
+        print!("{page}");
+        for (from, target, why) in unresolved {
+            let why = match why {
+                xref::Resolved::Ambiguous(n) => format!("matches {n} headings"),
+                _ => "matches no heading".to_string(),
+            };
+            eprintln!("NOTE: {from:?}: reference {target:?} {why}; not linked");
+        }
+    } else if enex {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        print!("{}", enex::render_enex(&tree, now));
+    } else if mbox {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        print!("{}", mbox::render_mbox(&tree, now));
+    } else if wbs {
+        let root = Path::new(&file)
+            .file_name()
+            .map_or(file.clone(), |n| n.to_string_lossy().into_owned());
+        print!("{}", wbs::render_wbs(&tree, &root));
+    } else if anki {
+        print!("{}", anki::render_anki_tsv(&tree));
+    } else if plain_text {
+        print!("{}", render_plain_all(&tree, 0, &style));
+    } else if canon {
+        print!("{}", ann.render_canon(&recs, show_cursor));
+    } else {
+        print!("{}", render_indented(&tree, 0, &style));
     }
+
+    Ok(code)
 }
 
 /**************
@@ -700,7 +1120,7 @@ mod tests {
     }
 
     // Build a single record. Marker first byte is 0xFF (expanded) or 0xFE (collapsed).
-    fn rec_bytes(
+    pub(crate) fn rec_bytes(
         text: &str,
         attr: u8,
         marker_first: u8,
@@ -723,7 +1143,7 @@ mod tests {
     }
 
     // Build a minimal .OTL with MAGIC + PREAMBLE and provided records.
-    fn otl_file(records: Vec<Vec<u8>>) -> Vec<u8> {
+    pub(crate) fn otl_file(records: Vec<Vec<u8>>) -> Vec<u8> {
         let mut v = Vec::new();
         v.extend(MAGIC);
         v.extend(PREAMBLE);
@@ -747,15 +1167,15 @@ mod tests {
         assert!(recs[1].flags.has_note);
         assert_eq!(recs[1].note.as_deref().unwrap(), "Line1\r\nLine2");
 
-        let tree = build_tree(&recs);
+        let tree = build_tree_capped(&recs);
         assert_eq!(tree.len(), 1);
-        assert_eq!(tree[0].text, "Parent");
+        assert_eq!(&*tree[0].text, "Parent");
         assert_eq!(tree[0].children.len(), 2);
-        assert_eq!(tree[0].children[0].text, "Child1");
-        assert_eq!(tree[0].children[1].text, "Child2");
+        assert_eq!(&*tree[0].children[0].text, "Child1");
+        assert_eq!(&*tree[0].children[1].text, "Child2");
 
         // Plain render normalizes CRLF to LF inside notes
-        let plain = render_plain_all(&tree, 0);
+        let plain = render_plain_all(&tree, 0, &TextStyle::default());
         assert!(plain.contains("Child1"));
         assert!(plain.contains("Line1"));
         assert!(plain.contains("Line2"));
@@ -766,6 +1186,32 @@ mod tests {
         assert!(canon.contains("note\nLine1\nLine2\n/note"));
     }
 
+    #[test]
+    fn lazy_notes_decode_on_demand() {
+        let buf = otl_file(vec![
+            rec_bytes(
+                "Text",
+                A_NOTE | A_SIBFOLLOWS,
+                M_EXPANDED,
+                0,
+                Some(b"caf\xe9"),
+            ),
+            rec_bytes("Bin", A_NOTE, M_EXPANDED, 0, Some(b"\x00\x01")),
+        ]);
+        let eager = parse_doc(&buf, "latin1").unwrap().recs;
+        let lazy = parse_doc_lazy(&buf, "latin1").unwrap().recs;
+        assert!(lazy.iter().all(|r| r.note.is_none() && r.flags.has_note));
+        assert!(lazy[1].flags.note_binary);
+        for (e, l) in eager.iter().zip(&lazy) {
+            assert_eq!(e.id, l.id);
+            assert_eq!(e.note_text(&buf, "latin1"), l.note_text(&buf, "latin1"));
+        }
+        assert_eq!(
+            lazy[0].note_text(&buf, "latin1").as_deref(),
+            Some("caf\u{e9}")
+        );
+    }
+
     #[test]
     fn roundtrip_tree_to_otl_and_back() {
         // Build initial bytes via record helpers
@@ -776,16 +1222,16 @@ mod tests {
 
         // Parse and build tree
         let recs = parse_otl(&buf, "latin1").expect("parse otl");
-        let tree = build_tree(&recs);
+        let tree = build_tree_capped(&recs);
 
         // Serialize tree back to .OTL and parse again
         let buf2 = serialize_tree_to_otl(&tree, "latin1");
         let recs2 = parse_otl(&buf2, "latin1").expect("re-parse otl");
-        let tree2 = build_tree(&recs2);
+        let tree2 = build_tree_capped(&recs2);
 
         // Compare using plain text rendering (includes notes, normalized)
-        let plain1 = render_plain_all(&tree, 0);
-        let plain2 = render_plain_all(&tree2, 0);
+        let plain1 = render_plain_all(&tree, 0, &TextStyle::default());
+        let plain2 = render_plain_all(&tree2, 0, &TextStyle::default());
         assert_eq!(plain1, plain2);
     }
 
@@ -809,7 +1255,7 @@ mod tests {
         let item2 = rec_bytes("Item2", 0x00, M_EXPANDED, 0, None);
         let buf = otl_file(vec![a, intro, tasks, item1, item2]);
         let recs = parse_otl(&buf, "latin1").expect("parse otl");
-        let tree = build_tree(&recs);
+        let tree = build_tree_capped(&recs);
 
         let out_bytes = serialize_tree_to_otl(&tree, "latin1");
 
@@ -821,6 +1267,172 @@ mod tests {
         std::fs::write(&out, &out_bytes).expect("write sample .OTL");
         println!("Wrote sample .OTL to {}", out.display());
     }
+
+    // Parse throughput on a multi-megabyte recovered stream (long headings,
+    // a stray 0xFF before every record), and the 0xFF scan alone done byte
+    // by byte vs. with memchr.
+    // Run manually: cargo test --release bench_parse_stream -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_parse_stream() {
+        use std::hint::black_box;
+        use std::time::Instant;
+        let heading = "x".repeat(400);
+        let mut recs = Vec::new();
+        for i in 0..20_000 {
+            let mut r = b"junk\xff\x00\x00\x00\x00".to_vec();
+            let note = (i % 4 == 0).then_some(&b"a note line\r\n"[..]);
+            let attr = if note.is_some() { A_NOTE } else { 0 };
+            r.extend(rec_bytes(&heading, attr, M_EXPANDED, 0, note));
+            recs.push(r);
+        }
+        let buf = otl_file(recs);
+        let mb = buf.len() as f64 / 1e6;
+        let time = |what: &str, f: &dyn Fn() -> usize| {
+            let t = Instant::now();
+            let mut n = 0;
+            for _ in 0..10 {
+                n = black_box(f());
+            }
+            let s = t.elapsed().as_secs_f64() / 10.0;
+            println!("{what:<14} {n:>7}  {:8.2} ms  {:8.1} MB/s", s * 1e3, mb / s);
+        };
+        time("bytewise scan", &|| {
+            black_box(&buf).iter().filter(|&&b| b == 0xff).count()
+        });
+        time("memchr scan", &|| {
+            memchr::memchr_iter(0xff, black_box(&buf)).count()
+        });
+        time("parse_doc", &|| {
+            parse_doc(black_box(&buf), "latin1").unwrap().recs.len()
+        });
+    }
+    #[test]
+    fn skips_bom_and_line_breaks_before_magic() {
+        let plain = otl_file(vec![rec_bytes("A", 0x00, M_EXPANDED, 0, None)]);
+        for prefix in [&b"\xef\xbb\xbf"[..], b"\r\n", b"\r\n\r\n"] {
+            let buf = [prefix, &plain].concat();
+            let doc = parse_doc(&buf, "latin1").unwrap();
+            assert_eq!(doc.recs.len(), 1);
+            assert_eq!(&*doc.recs[0].text, "A");
+            assert_eq!(doc.recs[0].off_text, prefix.len() + 9);
+            assert_eq!(doc.framing.prefix, prefix);
+            assert_eq!(doc.framing.notes.len(), 1);
+        }
+        assert!(parse_doc(&plain, "latin1")
+            .unwrap()
+            .framing
+            .notes
+            .is_empty());
+        let bom = parse_doc(&[b"\xef\xbb\xbf", &plain[..]].concat(), "latin1").unwrap();
+        assert!(
+            bom.framing.notes[0].contains("UTF-8 BOM"),
+            "{:?}",
+            bom.framing.notes
+        );
+    }
+
+    #[test]
+    fn binary_notes_round_trip_exactly() {
+        let blob: &[u8] = b"\x00\x01\x02A1\x00\xffB2\x07";
+        let buf = otl_file(vec![
+            rec_bytes("Range", A_NOTE | A_SIBFOLLOWS, M_EXPANDED, 0, Some(blob)),
+            rec_bytes("Text", A_NOTE, M_EXPANDED, 0, Some(b"plain")),
+        ]);
+        let want = [&buf[..], b"\x1a"].concat(); // the writer adds the sentinel
+        for enc in ["utf8", "latin1", "cp437"] {
+            let recs = parse_otl(&buf, enc).unwrap();
+            assert!(recs[0].flags.note_binary && !recs[1].flags.note_binary);
+            assert_eq!(recs[0].note.as_deref(), Some("000102413100ff423207"));
+            assert_eq!(write::serialize_recs(&recs, enc).unwrap(), want);
+            let canon = render_canon(&recs, true);
+            assert!(canon.contains("note hex\n000102413100ff423207\n/note\n"));
+            let back = canon::parse_canon(&canon).unwrap();
+            assert_eq!(write::serialize_recs(&back, enc).unwrap(), want);
+        }
+        let json =
+            serde_json::to_string(&build_tree_capped(&parse_otl(&buf, "latin1").unwrap())).unwrap();
+        assert_eq!(json.matches("\"note_binary\":true").count(), 1);
+    }
+
+    #[test]
+    fn bytes_after_sentinel_are_trailer() {
+        let plain = otl_file(vec![rec_bytes("A", 0x00, M_EXPANDED, 0, None)]);
+        let blob = b"\x00\x01INDEX\xff";
+        let buf = [&plain[..], b"\x1a", blob].concat();
+        let doc = parse_doc(&buf, "latin1").unwrap();
+        assert_eq!(doc.recs.len(), 1);
+        assert_eq!(doc.framing.end, plain.len() + 1);
+        assert_eq!(doc.framing.trailer, blob);
+        assert_eq!(doc.framing.notes.len(), 1);
+        let out = write::serialize_framed(&doc.recs, &doc.framing, "latin1", false).unwrap();
+        assert!(out.ends_with(&[&b"\x1a"[..], blob].concat()));
+        assert_eq!(parse_doc(&out, "latin1").unwrap().framing.trailer, blob);
+
+        // records after a stray 0x1a: kept, but called out
+        let more = rec_bytes("B", 0x00, M_EXPANDED, 0, None);
+        let buf = [&plain[..], b"\x1a", &more].concat();
+        let doc = parse_doc(&buf, "latin1").unwrap();
+        assert_eq!(doc.framing.trailer, more);
+        assert!(doc.framing.notes[1].starts_with("trailer parses as 1 record(s)"));
+
+        // DOS ^Z padding: one probe, not one parse per padding byte
+        let padded = [&plain[..], &[0x1a; 200_000][..]].concat();
+        let doc = parse_doc(&padded, "latin1").unwrap();
+        assert_eq!(doc.recs.len(), 1);
+        assert_eq!(doc.framing.trailer.len(), 199_999);
+    }
+
+    #[test]
+    fn concatenated_documents() {
+        let one = otl_file(vec![rec_bytes("A", 0x00, M_EXPANDED, 0, None)]);
+        let two = otl_file(vec![
+            rec_bytes("B", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("C", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let buf = [&one[..], b"\x1a", &two, b"\x1a"].concat();
+        let docs = parse_stream(&buf, "latin1").unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].recs.len(), 1);
+        assert_eq!(docs[0].framing.end, one.len() + 1);
+        assert_eq!(docs[1].recs.len(), 2);
+        assert_eq!(docs[1].recs[0].off_text, one.len() + 1 + 9);
+        assert_eq!(docs[1].framing.end, buf.len());
+        // the first document alone is what parse_otl sees
+        assert_eq!(parse_otl(&buf, "latin1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn crafted_deltas_clamp_and_warn() {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, i16::MIN, None),
+            rec_bytes("B", 0x00, M_EXPANDED, i16::MAX, None),
+            rec_bytes("C", 0x00, M_EXPANDED, i16::MAX, None),
+            rec_bytes("D", 0x00, M_EXPANDED, -1, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let max = DEFAULT_MAX_LEVEL;
+        assert_eq!(rec_levels_capped(&recs), [0, max, max, max - 1]);
+        let kinds: Vec<&str> = validate_findings(&recs, false)
+            .iter()
+            .filter(|f| f.kind.starts_with("level-"))
+            .map(|f| f.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            ["level-underflow", "level-overflow", "level-overflow"]
+        );
+        let mut depth = 0;
+        let mut n = &build_tree_capped(&recs)[..];
+        while let Some(last) = n.last() {
+            depth += 1;
+            n = &last.children;
+        }
+        assert_eq!(depth, max); // down the last children to D, at level max - 1
+        let bad = |v: &str| take_max_level_flag(vec!["--max-level".into(), v.into()]).is_err();
+        assert!(bad("x") && bad("0") && bad("40000"));
+    }
+
     #[test]
     fn tree_with_level_jumps_and_neg_deltas() {
         // A (level 0)
@@ -835,20 +1447,39 @@ mod tests {
         let buf = otl_file(vec![a, b, c, d]);
 
         let recs = parse_otl(&buf, "latin1").expect("parse otl");
-        let tree = build_tree(&recs);
+        let tree = build_tree_capped(&recs);
 
         assert_eq!(tree.len(), 1);
         let a = &tree[0];
-        assert_eq!(a.text, "A");
+        assert_eq!(&*a.text, "A");
         assert_eq!(a.children.len(), 3, "A should have filler, C, D");
 
         let filler = &a.children[0];
         assert!(filler.synthetic, "first child is a synthetic filler node");
         assert_eq!(filler.children.len(), 1);
-        assert_eq!(filler.children[0].text, "B");
+        assert_eq!(&*filler.children[0].text, "B");
 
-        assert_eq!(a.children[1].text, "C");
-        assert_eq!(a.children[2].text, "D");
+        assert_eq!(&*a.children[1].text, "C");
+        assert_eq!(&*a.children[2].text, "D");
+    }
+
+    #[test]
+    fn validate_checks_the_file_before_previews_and_filters() {
+        // A lacks the sibling bit B calls for; --head 1 alone rebuilds it
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let path = std::env::temp_dir().join(format!("otl-validate-{}.OTL", std::process::id()));
+        std::fs::write(&path, &buf).unwrap();
+        let file = path.to_string_lossy().into_owned();
+        for extra in [&["--head", "1"][..], &["--transform", "title-case"]] {
+            let mut args: Vec<String> = vec!["--validate".into()];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            args.push(file.clone());
+            assert_eq!(run("otl", &args).unwrap(), EXIT_FINDINGS, "{extra:?}");
+        }
+        std::fs::remove_file(&path).ok();
     }
 
     #[test]
@@ -934,13 +1565,13 @@ mod tests {
             match std::fs::read(&path) {
                 Ok(buf) => match parse_otl(&buf, "latin1") {
                     Ok(recs) => {
-                        let tree = build_tree(&recs);
+                        let tree = build_tree_capped(&recs);
                         let buf2 = serialize_tree_to_otl(&tree, "latin1");
                         match parse_otl(&buf2, "latin1") {
                             Ok(recs2) => {
-                                let tree2 = build_tree(&recs2);
-                                let a = render_plain_all(&tree, 0);
-                                let b = render_plain_all(&tree2, 0);
+                                let tree2 = build_tree_capped(&recs2);
+                                let a = render_plain_all(&tree, 0, &TextStyle::default());
+                                let b = render_plain_all(&tree2, 0, &TextStyle::default());
                                 let mut outp = outdir.clone();
                                 outp.push(format!("{}.rt.OTL", fname));
                                 let _ = std::fs::write(&outp, &buf2);
@@ -977,199 +1608,3 @@ mod tests {
         }
     }
 }
-
-/**************
- * --diff mode
- **************/
-fn render_mark_for_diff(u: u16) -> String {
-    mark_field(u)
-}
-fn render_delta_for_diff(d: i16) -> String {
-    delta_field(d)
-}
-
-fn diff_two_recs(prev: &Rec, curr: &Rec, show_cursor: bool) -> Vec<String> {
-    let mut changes = Vec::new();
-    if prev.attr != curr.attr {
-        changes.push(format!(
-            "  attr: {} -> {}",
-            fmt_attr_bits(prev.attr, show_cursor),
-            fmt_attr_bits(curr.attr, show_cursor)
-        ));
-    }
-    if prev.marker_u16 != curr.marker_u16 {
-        changes.push(format!(
-            "  mark: {} -> {}",
-            render_mark_for_diff(prev.marker_u16),
-            render_mark_for_diff(curr.marker_u16)
-        ));
-    }
-    if prev.delta != curr.delta {
-        changes.push(format!(
-            "  delta: {} -> {}",
-            render_delta_for_diff(prev.delta),
-            render_delta_for_diff(curr.delta)
-        ));
-    }
-    if prev.len_text != curr.len_text {
-        changes.push(format!(
-            "  textLen: {:04x} -> {:04x}",
-            prev.len_text as u16, curr.len_text as u16
-        ));
-    }
-    if prev.note_len != curr.note_len {
-        changes.push(format!(
-            "  noteLen: {:04x} -> {:04x}",
-            prev.note_len as u16, curr.note_len as u16
-        ));
-    }
-    let prev_note = prev.note.as_deref().unwrap_or("");
-    let curr_note = curr.note.as_deref().unwrap_or("");
-    if prev_note != curr_note {
-        if prev.note_len == curr.note_len {
-            changes.push("  note: (text changed)".to_string());
-        } else {
-            changes.push("  note: (length and text changed)".to_string());
-        }
-    }
-    changes
-}
-
-fn diff_mode(prev: &[Rec], curr: &[Rec], show_cursor: bool) -> String {
-    // Greedy match by heading text (first unmatched occurrence)
-    let mut out = String::new();
-    let mut used_prev = vec![false; prev.len()];
-
-    for c in curr.iter() {
-        // find first unmatched prev with identical text
-        let mut match_idx: Option<usize> = None;
-        for (j, p) in prev.iter().enumerate() {
-            if !used_prev[j] && p.text == c.text {
-                match_idx = Some(j);
-                break;
-            }
-        }
-        if let Some(j) = match_idx {
-            used_prev[j] = true;
-            let changes = diff_two_recs(&prev[j], c, show_cursor);
-            if !changes.is_empty() {
-                out.push_str(&format!("~ \"{}\"\n", c.text));
-                for line in changes {
-                    out.push_str(&line);
-                    out.push('\n');
-                }
-            }
-        } else {
-            out.push_str(&format!("+ \"{}\"\n", c.text));
-        }
-    }
-    for (j, p) in prev.iter().enumerate() {
-        if !used_prev[j] {
-            out.push_str(&format!("- \"{}\"\n", p.text));
-        }
-    }
-    out
-}
-
-fn main() -> io::Result<()> {
-    // Fast path: --diff <prev> <curr> [--show-cursor]
-    let raw_args: Vec<String> = env::args().skip(1).collect();
-    if raw_args.first().map(|s| s.as_str()) == Some("--diff") {
-        // Accept optional --show-cursor as a trailing flag
-        let show_cursor = raw_args.iter().any(|s| s == "--show-cursor");
-        let paths: Vec<&str> = raw_args
-            .iter()
-            .skip(1)
-            .filter(|s| s.as_str() != "--show-cursor")
-            .map(|s| s.as_str())
-            .collect();
-        if paths.len() != 2 {
-            usage(&env::args().next().unwrap_or_else(|| "otl".into()));
-        }
-        let prev_buf = fs::read(paths[0])?;
-        let curr_buf = fs::read(paths[1])?;
-        let prev_recs = parse_otl(&prev_buf, "latin1")?;
-        let curr_recs = parse_otl(&curr_buf, "latin1")?;
-        let report = diff_mode(&prev_recs, &curr_recs, show_cursor);
-        print!("{report}");
-        return Ok(());
-    }
-
-    // Normal modes
-    let mut args = env::args().skip(1);
-    let mut file: Option<String> = None;
-    let mut out_json = false;
-    let mut do_dump = false;
-    let mut do_offsets = false;
-    let mut do_validate = false;
-    let mut plain_text = false;
-    let mut canon = false;
-    let mut enc = String::from("latin1");
-    let mut assume_child_bit = false;
-    let mut show_cursor = false;
-
-    while let Some(a) = args.next() {
-        match a.as_str() {
-            "--json" => out_json = true,
-            "--dump" => do_dump = true,
-            "--offsets" => do_offsets = true,
-            "--validate" => do_validate = true,
-            "--text" => plain_text = true,
-            "--canon" => canon = true,
-            "--assume-child-bit" => assume_child_bit = true,
-            "--show-cursor" => show_cursor = true,
-            "--enc" => {
-                if let Some(v) = args.next() {
-                    enc = v;
-                } else {
-                    usage(&env::args().next().unwrap_or_else(|| "otl".into()));
-                }
-            }
-            _ => {
-                if file.is_none() {
-                    file = Some(a);
-                } else {
-                    usage(&env::args().next().unwrap_or_else(|| "otl".into()));
-                }
-            }
-        }
-    }
-
-    let prog = env::args().next().unwrap_or_else(|| "otl".into());
-    let file = file.unwrap_or_else(|| usage(&prog));
-
-    let mut buf = Vec::new();
-    if file == "-" {
-        io::stdin().read_to_end(&mut buf)?;
-    } else {
-        buf = fs::read(&file)?;
-    }
-
-    let recs = parse_otl(&buf, &enc)?;
-    if do_validate {
-        validate(&recs, assume_child_bit);
-    }
-    if do_dump {
-        print!("{}", dump_recs(&recs));
-        // fall through to also print offsets if requested
-    }
-    if do_offsets {
-        print!("{}", dump_offsets(&recs));
-        if !out_json && !plain_text && !canon {
-            return Ok(());
-        }
-    }
-
-    let tree = build_tree(&recs);
-    if out_json {
-        println!("{}", serde_json::to_string_pretty(&tree).unwrap());
-    } else if plain_text {
-        print!("{}", render_plain_all(&tree, 0));
-    } else if canon {
-        print!("{}", render_canon(&recs, show_cursor));
-    } else {
-        print!("{}", render_indented(&tree, ""));
-    }
-
-    Ok(())
-}
@@ -0,0 +1,231 @@
+//! `--markdown` and `--html` exports, with cross-references (see xref.rs)
+//! turned into links to the referenced heading.
+//!
+//! Markdown is a nested bullet list (notes as indented paragraphs under
+//! their item), or with `--opt markdown.heading-base=N` `#` headings from
+//! level N down to 6 and bullets below that; HTML is a standalone page of
+//! nested `<ul>`s styled by `--opt html.theme`. Every heading carries an
+//! anchor named by its slug (see slug.rs, `--slugs`).
+
+use crate::opts::{HtmlOpts, MarkdownOpts};
+use crate::xref::{link_text, Index, Resolved};
+use crate::Node;
+
+/// Unlinked references as (referring heading, target, why).
+pub type Unresolved = Vec<(String, String, Resolved)>;
+
+// Links the references in one text, noting the unresolved ones against `from`.
+fn linked(
+    text: &str,
+    from: &str,
+    idx: &Index,
+    escape: &dyn Fn(&str) -> String,
+    link: &dyn Fn(&str, &str) -> String,
+    bad: &mut Unresolved,
+) -> String {
+    let mut missing = Vec::new();
+    let out = link_text(text, idx, escape, link, &mut missing);
+    bad.extend(
+        missing
+            .into_iter()
+            .map(|(t, why)| (from.to_string(), t, why)),
+    );
+    out
+}
+
+fn md_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>#".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// `head` is the # level for these nodes, 0 once they are bullets.
+fn md_nodes(
+    nodes: &[Node],
+    depth: usize,
+    head: usize,
+    idx: &Index,
+    out: &mut String,
+    bad: &mut Unresolved,
+) {
+    let link = |label: &str, a: &str| format!("[{}](#{a})", md_escape(label));
+    for n in nodes {
+        if n.synthetic {
+            md_nodes(&n.children, depth, head, idx, out, bad);
+            continue;
+        }
+        let anchor_tag = n.id.as_ref().map_or(String::new(), |id| {
+            format!("<a id=\"{}\"></a>", idx.anchor(id))
+        });
+        let text = linked(&n.text, &n.text, idx, &md_escape, &link, bad);
+        let note_lines = |pad: &str, out: &mut String, bad: &mut Unresolved| {
+            if let Some(note) = &n.note {
+                for line in note.replace("\r\n", "\n").lines() {
+                    let line = linked(line, &n.text, idx, &md_escape, &link, bad);
+                    out.push_str(&format!("{pad}{line}\n"));
+                }
+                out.push('\n');
+            }
+        };
+        if head > 0 {
+            out.push_str(&format!("{} {anchor_tag}{text}\n\n", "#".repeat(head)));
+            note_lines("", out, bad);
+            let next = if head < 6 { head + 1 } else { 0 };
+            md_nodes(&n.children, 0, next, idx, out, bad);
+            continue;
+        }
+        let pad = "  ".repeat(depth);
+        out.push_str(&format!("{pad}- {anchor_tag}{text}\n"));
+        if n.note.is_some() {
+            out.push('\n');
+            note_lines(&format!("{pad}  "), out, bad);
+        }
+        md_nodes(&n.children, depth + 1, 0, idx, out, bad);
+    }
+}
+
+pub fn render_markdown(nodes: &[Node], idx: &Index, o: &MarkdownOpts) -> (String, Unresolved) {
+    let (mut out, mut bad) = (String::new(), Vec::new());
+    md_nodes(nodes, 0, o.heading_base, idx, &mut out, &mut bad);
+    (out, bad)
+}
+
+fn html_nodes(nodes: &[Node], depth: usize, idx: &Index, out: &mut String, bad: &mut Unresolved) {
+    let link = |label: &str, a: &str| format!("<a href=\"#{a}\">{}</a>", html_escape(label));
+    let pad = "  ".repeat(depth + 1);
+    out.push_str(&format!("{pad}<ul>\n"));
+    for n in nodes {
+        if n.synthetic {
+            out.push_str(&format!("{pad}  <li>\n"));
+            html_nodes(&n.children, depth + 1, idx, out, bad);
+            out.push_str(&format!("{pad}  </li>\n"));
+            continue;
+        }
+        let id_attr =
+            n.id.as_ref()
+                .map_or(String::new(), |id| format!(" id=\"{}\"", idx.anchor(id)));
+        let text = linked(&n.text, &n.text, idx, &html_escape, &link, bad);
+        out.push_str(&format!("{pad}  <li{id_attr}>{text}"));
+        if let Some(a) = &n.annotation {
+            let a = html_escape(a).replace('\n', "<br>");
+            out.push_str(&format!("<div class=\"annotation\">{a}</div>"));
+        }
+        if let Some(note) = &n.note {
+            let lines: Vec<String> = note
+                .replace("\r\n", "\n")
+                .lines()
+                .map(|l| linked(l, &n.text, idx, &html_escape, &link, bad))
+                .collect();
+            out.push_str(&format!("<div class=\"note\">{}</div>", lines.join("<br>")));
+        }
+        if n.children.is_empty() {
+            out.push_str("</li>\n");
+        } else {
+            out.push('\n');
+            html_nodes(&n.children, depth + 1, idx, out, bad);
+            out.push_str(&format!("{pad}  </li>\n"));
+        }
+    }
+    out.push_str(&format!("{pad}</ul>\n"));
+}
+
+fn style_sheet(theme: &str) -> &'static str {
+    match theme {
+        "none" => "",
+        "dark" => {
+            "<style>body { background: #1e1e1e; color: #ddd; }\n\
+             a { color: #8ab4f8; }\n\
+             .note { color: #aaa; white-space: pre-wrap; }\n\
+             .annotation { color: #d7a84a; font-style: italic; }</style>\n"
+        }
+        _ => {
+            "<style>.note { color: #555; white-space: pre-wrap; }\n\
+             .annotation { color: #805b00; font-style: italic; }</style>\n"
+        }
+    }
+}
+
+pub fn render_html(nodes: &[Node], idx: &Index, title: &str, o: &HtmlOpts) -> (String, Unresolved) {
+    let (mut body, mut bad) = (String::new(), Vec::new());
+    html_nodes(nodes, 0, idx, &mut body, &mut bad);
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         {}</head>\n<body>\n{body}</body>\n</html>\n",
+        html_escape(title),
+        style_sheet(o.theme)
+    );
+    (page, bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slug::SlugStyle;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn markdown_and_html_link_references() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Budget", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes(
+                    "Plan *v2*",
+                    A_NOTE,
+                    M_EXPANDED,
+                    0,
+                    Some(b"see: Budget\r\n[[Gone]]"),
+                ),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let idx = Index::new(&recs, SlugStyle::Github);
+        let tree = build_tree_capped(&recs);
+        let a = "budget";
+        let (md, bad) = render_markdown(&tree, &idx, &MarkdownOpts::default());
+        assert!(
+            md.contains(&format!("- <a id=\"{a}\"></a>Budget\n")),
+            "{md}"
+        );
+        assert!(md.contains("Plan \\*v2\\*\n\n  see: [Budget](#"), "{md}");
+        assert_eq!(
+            bad,
+            [(
+                "Plan *v2*".to_string(),
+                "Gone".to_string(),
+                Resolved::Missing
+            )]
+        );
+        let (html, _) = render_html(&tree, &idx, "t & u", &HtmlOpts::default());
+        assert!(html.contains("<title>t &amp; u</title>"));
+        assert!(html.contains(&format!(
+            "<div class=\"note\">see: <a href=\"#{a}\">Budget</a><br>[[Gone]]</div>"
+        )));
+        assert!(html.contains(".note { color: #555;"));
+        let dark = HtmlOpts { theme: "dark" };
+        assert!(render_html(&tree, &idx, "t", &dark).0.contains("#1e1e1e"));
+        let bare = HtmlOpts { theme: "none" };
+        assert!(!render_html(&tree, &idx, "t", &bare).0.contains("<style>"));
+
+        let o = MarkdownOpts { heading_base: 2 };
+        let (md, _) = render_markdown(&tree, &idx, &o);
+        assert!(
+            md.starts_with(&format!("## <a id=\"{a}\"></a>Budget\n\n## ")),
+            "{md}"
+        );
+        assert!(md.contains("Plan \\*v2\\*\n\nsee: [Budget](#"), "{md}");
+    }
+}
@@ -0,0 +1,185 @@
+//! `--mbox` export: one mail message per noted heading, for reading a large
+//! pile of small dated notes in a mail client or indexing them with
+//! mail-based search tools.
+//!
+//! - Subject: the breadcrumb path (`Work > 1990 > Budget call`)
+//! - Date: the leading date (see sort::leading_date) of the heading, else of
+//!   the note's first line, else of the nearest dated heading above it; no
+//!   Date header when there is none
+//! - Message-ID: the record id, so re-imports deduplicate
+//! - body: the note as UTF-8 text
+//!
+//! The file is mboxrd: body lines starting with `From ` (after any `>`) get
+//! one more `>`. Undated messages carry the export time on the `From ` line.
+
+use crate::gen::{civil_from_days, days_from_civil};
+use crate::sort::leading_date;
+use crate::Node;
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// (weekday, day, month name, year, h, m, s) of seconds since 1970
+fn parts(secs: i64) -> (&'static str, u32, &'static str, i64, i64, i64, i64) {
+    let days = secs.div_euclid(86_400);
+    let t = secs.rem_euclid(86_400);
+    let (y, m, d) = civil_from_days(days);
+    let wd = DAYS[days.rem_euclid(7) as usize];
+    (
+        wd,
+        d,
+        MONTHS[m as usize - 1],
+        y,
+        t / 3600,
+        t / 60 % 60,
+        t % 60,
+    )
+}
+
+// RFC 5322 Date: Mon, 05 Mar 1990 00:00:00 +0000
+fn rfc_date(secs: i64) -> String {
+    let (wd, d, mon, y, h, mi, s) = parts(secs);
+    format!("{wd}, {d:02} {mon} {y:04} {h:02}:{mi:02}:{s:02} +0000")
+}
+
+// `From ` separator line date (asctime): Mon Mar  5 00:00:00 1990
+fn asctime(secs: i64) -> String {
+    let (wd, d, mon, y, h, mi, s) = parts(secs);
+    format!("{wd} {mon} {d:2} {h:02}:{mi:02}:{s:02} {y:04}")
+}
+
+// RFC 2047 Q-encoded words for a non-ASCII header value, each under 76
+// columns; plain ASCII is returned as is.
+fn header_text(s: &str) -> String {
+    if s.is_ascii() && !s.contains("=?") {
+        return s.to_string();
+    }
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        let mut enc = String::new();
+        match c {
+            ' ' => enc.push('_'),
+            _ if c.is_ascii_graphic() && !matches!(c, '=' | '?' | '_') => enc.push(c),
+            _ => {
+                let mut b = [0; 4];
+                for byte in c.encode_utf8(&mut b).bytes() {
+                    enc.push_str(&format!("={byte:02X}"));
+                }
+            }
+        }
+        if word.len() + enc.len() > 60 {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push_str(&enc);
+    }
+    words.push(word);
+    words
+        .iter()
+        .map(|w| format!("=?UTF-8?Q?{w}?="))
+        .collect::<Vec<_>>()
+        .join("\n ")
+}
+
+fn body(note: &str) -> String {
+    let mut out = String::new();
+    for line in note
+        .replace("\r\n", "\n")
+        .trim_end_matches('\n')
+        .split('\n')
+    {
+        if line.trim_start_matches('>').starts_with("From ") {
+            out.push('>');
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn day_secs((y, m, d): (u32, u32, u32)) -> i64 {
+    days_from_civil(i64::from(y), m, d) * 86_400
+}
+
+fn messages(
+    nodes: &[Node],
+    path: &mut Vec<String>,
+    dated: Option<i64>,
+    now: i64,
+    out: &mut String,
+) {
+    for n in nodes {
+        if n.synthetic {
+            messages(&n.children, path, dated, now, out);
+            continue;
+        }
+        path.push(n.text.trim().to_string());
+        let own = leading_date(&n.text).map(day_secs);
+        if let Some(note) = &n.note {
+            let date = own
+                .or_else(|| leading_date(note.lines().next().unwrap_or("")).map(day_secs))
+                .or(dated);
+            out.push_str(&format!("From otl {}\n", asctime(date.unwrap_or(now))));
+            out.push_str("From: otl <otl@localhost>\n");
+            out.push_str(&format!("Subject: {}\n", header_text(&path.join(" > "))));
+            if let Some(d) = date {
+                out.push_str(&format!("Date: {}\n", rfc_date(d)));
+            }
+            if let Some(id) = &n.id {
+                out.push_str(&format!("Message-ID: <{id}@otl>\n"));
+            }
+            out.push_str(
+                "MIME-Version: 1.0\n\
+                 Content-Type: text/plain; charset=UTF-8\n\
+                 Content-Transfer-Encoding: 8bit\n\n",
+            );
+            out.push_str(&body(note));
+            out.push('\n');
+        }
+        messages(&n.children, path, own.or(dated), now, out);
+        path.pop();
+    }
+}
+
+/// The whole mbox; `now` (seconds since 1970) stands in on the `From `
+/// line of undated messages.
+pub fn render_mbox(nodes: &[Node], now: u64) -> String {
+    let mut out = String::new();
+    messages(nodes, &mut Vec::new(), None, now as i64, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn noted_headings_become_dated_messages() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Journal", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("1990-03-05", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+                rec_bytes("Call", A_NOTE, M_EXPANDED, 1, Some(b"From Bob:\r\nok")),
+                rec_bytes("Gifts", A_NOTE, M_EXPANDED, -1, Some(b"12/24/91 gifts")),
+                rec_bytes("Loose", A_NOTE, M_EXPANDED, -1, Some(b"x")),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let out = render_mbox(&build_tree_capped(&recs), 1_792_150_200);
+        assert_eq!(out.matches("\nSubject: ").count(), 3);
+        assert!(
+            out.starts_with("From otl Mon Mar  5 00:00:00 1990\n"),
+            "{out}"
+        );
+        assert!(out.contains("Subject: Journal > 1990-03-05 > Call\nDate: Mon, 05 Mar 1990"));
+        assert!(out.contains("\n>From Bob:\nok\n\n"));
+        assert!(out.contains("Subject: Journal > Gifts\nDate: Tue, 24 Dec 1991 00:00:00 +0000"));
+        assert!(out.contains("From otl Fri Oct 16 11:30:00 2026\nFrom: otl <otl@localhost>\nSubject: Loose\nMessage-ID"));
+        assert_eq!(header_text("A > Caf\u{e9}"), "=?UTF-8?Q?A_>_Caf=C3=A9?=");
+    }
+}
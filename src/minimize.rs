@@ -0,0 +1,231 @@
+//! `minimize` subcommand: shrink a failing .OTL while the failure persists.
+//!
+//! `--check parse-error` keeps the file failing to parse with the same error
+//! message; the MAGIC/PREAMBLE header is kept and the rest is reduced byte
+//! chunk by byte chunk. `--check validate-warning` keeps the file parsing
+//! with at least one `--validate` finding of the first finding's kind; whole
+//! records are dropped, then notes removed or shortened.
+
+use crate::{
+    parse_otl, read_input, usage, validate_findings, write_output, A_NOTE, MAGIC, PREAMBLE,
+};
+use std::io;
+
+/// Drop chunks of `items` while `fails` holds, ddmin style: halve the
+/// chunk size down to single items, and start over from half the (now
+/// smaller) input whenever a single-item pass removed anything, so big
+/// inputs are not left to go one item per check.
+fn reduce<T: Clone>(mut items: Vec<T>, fails: &mut impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut chunk = (items.len() / 2).max(1);
+    loop {
+        let mut removed = false;
+        let mut i = 0;
+        while i < items.len() {
+            let end = (i + chunk).min(items.len());
+            let cand: Vec<T> = items[..i].iter().chain(&items[end..]).cloned().collect();
+            if fails(&cand) {
+                items = cand;
+                removed = true;
+            } else {
+                i = end;
+            }
+        }
+        if chunk > 1 {
+            chunk /= 2;
+        } else if removed {
+            chunk = (items.len() / 2).max(1);
+        } else {
+            return items;
+        }
+    }
+}
+
+fn header_len(buf: &[u8]) -> usize {
+    let mut n = 0;
+    if buf.starts_with(&MAGIC) {
+        n = 3;
+        if buf[3..].starts_with(&PREAMBLE) {
+            n += PREAMBLE.len();
+        }
+    }
+    n
+}
+
+fn parse_error(buf: &[u8]) -> Option<String> {
+    parse_otl(buf, "latin1").err().map(|e| e.to_string())
+}
+
+pub fn minimize_parse_error(buf: &[u8], checks: &mut usize) -> io::Result<Vec<u8>> {
+    let Some(want) = parse_error(buf) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "input parses cleanly; nothing to minimize",
+        ));
+    };
+    let head = &buf[..header_len(buf)];
+    let body = reduce(buf[head.len()..].to_vec(), &mut |b: &[u8]| {
+        *checks += 1;
+        parse_error(&[head, b].concat()).as_ref() == Some(&want)
+    });
+    Ok([head, &body].concat())
+}
+
+// One record's raw bytes: heading..delta, and the note bytes if any.
+#[derive(Clone)]
+struct Unit {
+    head: Vec<u8>,
+    note: Option<Vec<u8>>,
+}
+
+fn join(head: &[u8], units: &[Unit], tail: &[u8]) -> Vec<u8> {
+    let mut out = head.to_vec();
+    for u in units {
+        out.extend(&u.head);
+        if let Some(n) = &u.note {
+            out.extend((n.len() as u16).to_le_bytes());
+            out.extend(n);
+        }
+    }
+    out.extend(tail);
+    out
+}
+
+pub fn minimize_validate(
+    buf: &[u8],
+    assume_child_bit: bool,
+    checks: &mut usize,
+) -> io::Result<Vec<u8>> {
+    let recs = parse_otl(buf, "latin1")?;
+    let Some(kind) = validate_findings(&recs, assume_child_bit)
+        .first()
+        .map(|f| f.kind)
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "input validates cleanly; nothing to minimize",
+        ));
+    };
+    eprintln!("minimizing for validate finding: {kind}");
+    let head = &buf[..recs[0].off_text];
+    let units: Vec<Unit> = recs
+        .iter()
+        .map(|r| Unit {
+            head: buf[r.off_text..r.off_delta + 2].to_vec(),
+            note: r.off_note.map(|o| buf[o..o + r.note_len].to_vec()),
+        })
+        .collect();
+    let last = recs.last().expect("parsed at least one record");
+    let tail = &buf[last
+        .off_note
+        .map_or(last.off_delta + 2, |o| o + last.note_len)..];
+
+    let mut fails = |units: &[Unit]| {
+        *checks += 1;
+        parse_otl(&join(head, units, tail), "latin1").is_ok_and(|recs| {
+            validate_findings(&recs, assume_child_bit)
+                .iter()
+                .any(|f| f.kind == kind)
+        })
+    };
+    let mut units = reduce(units, &mut fails);
+    for i in 0..units.len() {
+        if units[i].note.is_none() {
+            continue;
+        }
+        let mut cand = units.clone();
+        let attr = cand[i].head.len() - 5;
+        cand[i].head[attr] &= !A_NOTE;
+        cand[i].note = None;
+        if fails(&cand) {
+            units = cand;
+            continue;
+        }
+        while let Some(n) = units[i].note.as_ref().filter(|n| !n.is_empty()) {
+            let mut cand = units.clone();
+            cand[i].note = Some(n[..n.len() / 2].to_vec());
+            if !fails(&cand) {
+                break;
+            }
+            units = cand;
+        }
+    }
+    Ok(join(head, &units, tail))
+}
+
+pub fn cmd_minimize(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut check: Option<&str> = None;
+    let mut out = "-";
+    let mut assume_child_bit = false;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--check" => check = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--assume-child-bit" => assume_child_bit = true,
+            "-o" => out = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let buf = read_input(file.unwrap_or_else(|| usage(prog)))?;
+    let mut checks = 0;
+    let small = match check {
+        Some("parse-error") => minimize_parse_error(&buf, &mut checks)?,
+        Some("validate-warning") => minimize_validate(&buf, assume_child_bit, &mut checks)?,
+        _ => usage(prog),
+    };
+    eprintln!(
+        "minimized {} -> {} bytes ({checks} checks)",
+        buf.len(),
+        small.len()
+    );
+    write_output(out, &small)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn keeps_parse_error() {
+        let mut buf = otl_file(vec![
+            rec_bytes("first heading", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("second", A_NOTE, M_EXPANDED, 0, Some(b"some note text")),
+        ]);
+        buf.truncate(buf.len() - 3); // cut into the note bytes
+        let want = parse_error(&buf).unwrap();
+        let small = minimize_parse_error(&buf, &mut 0).unwrap();
+        assert!(small.len() < buf.len() / 2, "{small:?}");
+        assert_eq!(parse_error(&small), Some(want));
+
+        let mut recs: Vec<_> = (0..300)
+            .map(|i| rec_bytes(&format!("heading {i}"), 0x00, M_EXPANDED, 0, None))
+            .collect();
+        recs.push(rec_bytes("n", A_NOTE, M_EXPANDED, 0, Some(b"note")));
+        let mut big = otl_file(recs);
+        big.truncate(big.len() - 2);
+        let mut checks = 0;
+        let small = minimize_parse_error(&big, &mut checks).unwrap();
+        assert!(small.len() < 40 && checks < 200, "{checks} checks");
+    }
+
+    #[test]
+    fn keeps_validate_finding() {
+        let buf = otl_file(vec![
+            rec_bytes("a", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("b", A_NOTE, M_EXPANDED, 0, Some(b"long note here")),
+            rec_bytes("c", A_SIBFOLLOWS, M_EXPANDED, 0, None), // bad: last sibling
+            rec_bytes("d", A_NOTE, M_EXPANDED, 1, Some(b"more")),
+        ]);
+        let mut buf = buf;
+        buf.push(0x1a);
+        let small = minimize_validate(&buf, false, &mut 0).unwrap();
+        let recs = parse_otl(&small, "latin1").unwrap();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(&*recs[0].text, "c");
+        assert_eq!(validate_findings(&recs, false)[0].kind, "sibling-bit");
+        assert_eq!(small.last(), Some(&0x1a));
+    }
+}
@@ -0,0 +1,162 @@
+//! `normalize` subcommand: rewrite an outline in one deterministic form, so
+//! archived copies of the same content become byte-comparable.
+//!
+//! - cursor bit (0x20) cleared
+//! - folds expanded (`--keep-folds`: collapsed stays collapsed); any other
+//!   marker value becomes expanded
+//! - deltas, sibling bit (0x08) and child bit (0x04) recomputed from levels
+//! - text notes with CRLF line ends (binary notes untouched)
+//! - headings folded as far as possible (see write::compress_headings)
+//! - no junk prefix or trailer: MAGIC, PREAMBLE, records, one 0x1a
+//!
+//! Unknown attr bits are kept; level jumps are kept (and warned about).
+//! `--preserve` / `--reset` (see write::Preserve) override the defaults for
+//! folds, cursor, unknown bits, trailer and prefix; `--keep-folds` is
+//! `--preserve folds`.
+
+use crate::journal::write_in_place;
+use crate::ops::{prepare_write, restructure};
+use crate::write::{compress_headings, encode_note, Preserve};
+use crate::{parse_doc, read_input, rec_levels_capped, usage, write_output, Doc, Rec, A_HASKIDS};
+use std::io;
+
+const EXPANDED: u16 = 0xFFFF;
+const COLLAPSED: u16 = 0xFFFE;
+
+/// What `normalize` keeps unless told otherwise.
+pub const NORMAL: Preserve = Preserve {
+    folds: false,
+    cursor: false,
+    unknown_bits: true,
+    trailer: false,
+    preamble: false,
+};
+
+/// `recs` in normal form (see the module docs), resetting what `keep`
+/// does not preserve; notes are measured as `enc` will write them.
+pub fn normalize(recs: &[Rec], keep: &Preserve, enc: &str) -> Vec<Rec> {
+    let levels = rec_levels_capped(recs);
+    let mut out = recs.to_vec();
+    restructure(&mut out, &levels);
+    for (i, r) in out.iter_mut().enumerate() {
+        let kids = levels.get(i + 1).is_some_and(|&l| l > levels[i]);
+        if kids {
+            r.attr |= A_HASKIDS;
+        } else {
+            r.attr &= !A_HASKIDS;
+        }
+        r.flags.has_child = kids;
+        r.marker_u16 = if r.collapsed { COLLAPSED } else { EXPANDED };
+        if r.note_bin.is_none() {
+            if let Some(note) = &mut r.note {
+                *note = note.replace("\r\n", "\n").replace('\n', "\r\n");
+                r.note_len = encode_note(note, enc).len();
+            }
+        }
+    }
+    compress_headings(&mut out);
+    keep.apply(&mut out);
+    out
+}
+
+pub fn cmd_normalize(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut out: Option<&str> = None;
+    let mut in_place = false;
+    let mut journal = false;
+    let mut keep = NORMAL;
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = keep.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        match a.as_str() {
+            "--keep-folds" => keep.folds = true,
+            "--in-place" => in_place = true,
+            "--journal" => journal = true,
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let Some(file) = file else { usage(prog) };
+    let out = match (out, in_place) {
+        (Some(o), false) => o,
+        (None, true) if file != "-" => file,
+        _ => usage(prog),
+    };
+    if journal && !in_place {
+        usage(prog);
+    }
+
+    let buf = read_input(file)?;
+    let Doc { recs, framing } = parse_doc(&buf, enc)?;
+    let recs = prepare_write(normalize(&recs, &keep, enc), None);
+    let bytes = keep.serialize(&recs, &framing, enc)?;
+    if in_place {
+        if bytes == buf {
+            eprintln!("already normal");
+            return Ok(());
+        }
+        let command = format!("normalize {}", args.join(" "));
+        return write_in_place(file, &buf, &bytes, journal, &command);
+    }
+    write_output(out, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::write::serialize_recs;
+    use crate::{
+        parse_otl, validate_findings, A_CURSOR, A_NOTE, A_SIBFOLLOWS, M_COLLAPSED, M_EXPANDED,
+    };
+
+    fn messy() -> Vec<u8> {
+        let mut buf = b"\r\n".to_vec();
+        buf.extend(otl_file(vec![
+            rec_bytes("Top  one", A_CURSOR, M_COLLAPSED, 0, None),
+            rec_bytes("kid", A_NOTE | 0x10, M_EXPANDED, 1, Some(b"a\nb\r\nc")),
+            rec_bytes("Next", 0x00, M_EXPANDED, -1, None),
+        ]));
+        buf.extend(b"\x1atrailer");
+        buf
+    }
+
+    #[test]
+    fn normal_form_is_canonical_and_stable() {
+        let recs = parse_doc(&messy(), "latin1").unwrap().recs;
+        let norm = normalize(&recs, &NORMAL, "latin1");
+        let attrs: Vec<u8> = norm.iter().map(|r| r.attr).collect();
+        assert_eq!(attrs, [A_SIBFOLLOWS | A_HASKIDS, A_NOTE | 0x10, 0x00]);
+        assert!(norm.iter().all(|r| r.marker_u16 == EXPANDED));
+        assert_eq!(norm[1].note.as_deref(), Some("a\r\nb\r\nc"));
+        assert_eq!(norm[0].len_text, 7); // "Top" + folded space, " one"
+
+        let bytes = serialize_recs(&norm, "latin1").unwrap();
+        assert!(bytes.starts_with(&crate::MAGIC) && bytes.ends_with(b"\x1a"));
+        let again = serialize_recs(
+            &normalize(&parse_otl(&bytes, "latin1").unwrap(), &NORMAL, "latin1"),
+            "latin1",
+        )
+        .unwrap();
+        assert_eq!(again, bytes);
+        assert!(
+            validate_findings(&parse_otl(&bytes, "latin1").unwrap(), true)
+                .iter()
+                .all(|f| f.kind == "unknown-attr")
+        );
+
+        let keep = Preserve {
+            folds: true,
+            cursor: true,
+            ..NORMAL
+        };
+        let kept = normalize(&recs, &keep, "latin1");
+        assert!(kept[0].collapsed && kept[0].attr & A_CURSOR != 0);
+    }
+}
@@ -0,0 +1,154 @@
+//! `notes` subcommand: duplicate notes and the largest notes, with paths,
+//! for deduplicating boilerplate before exporting.
+//!
+//! Notes count as duplicates when their text matches after CRLF -> LF and
+//! trimming trailing whitespace (pasted copies often differ only there).
+
+use crate::heading_paths_capped;
+use crate::{parse_otl, read_input, usage, Rec};
+use std::collections::HashMap;
+use std::io;
+
+const DEFAULT_TOP: usize = 10;
+const PREVIEW_CHARS: usize = 40;
+
+fn normalized(note: &str) -> String {
+    note.replace("\r\n", "\n").trim_end().to_string()
+}
+
+fn preview(note: &str) -> String {
+    let line = note.lines().next().unwrap_or("");
+    let mut p: String = line.chars().take(PREVIEW_CHARS).collect();
+    if p.len() < line.len() || note.lines().nth(1).is_some() {
+        p.push_str(" ...");
+    }
+    p
+}
+
+/// Groups of record indexes sharing a note (2+ each), most wasteful first.
+pub fn duplicate_notes(recs: &[Rec]) -> Vec<Vec<usize>> {
+    let mut by_text: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, r) in recs.iter().enumerate() {
+        if let Some(n) = &r.note {
+            let key = normalized(n);
+            if !key.is_empty() {
+                by_text.entry(key).or_default().push(i);
+            }
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = by_text.into_values().filter(|g| g.len() > 1).collect();
+    let waste = |g: &Vec<usize>| (g.len() - 1) * recs[g[0]].note_len;
+    groups.sort_by(|a, b| waste(b).cmp(&waste(a)).then(a[0].cmp(&b[0])));
+    groups
+}
+
+/// Indexes of the `top` largest notes (by stored bytes), largest first.
+pub fn largest_notes(recs: &[Rec], top: usize) -> Vec<usize> {
+    let mut idx: Vec<usize> = (0..recs.len())
+        .filter(|&i| recs[i].note.is_some())
+        .collect();
+    idx.sort_by(|&a, &b| recs[b].note_len.cmp(&recs[a].note_len).then(a.cmp(&b)));
+    idx.truncate(top);
+    idx
+}
+
+pub fn report(recs: &[Rec], top: usize) -> String {
+    let paths = heading_paths_capped(recs);
+    let mut out = String::new();
+    let dups = duplicate_notes(recs);
+    let saved: usize = dups
+        .iter()
+        .map(|g| (g.len() - 1) * recs[g[0]].note_len)
+        .sum();
+    out.push_str(&format!(
+        "duplicate notes: {} group(s), {saved} byte(s) in extra copies\n",
+        dups.len()
+    ));
+    for g in &dups {
+        let first = &recs[g[0]];
+        out.push_str(&format!(
+            "  {}x {} bytes: {}\n",
+            g.len(),
+            first.note_len,
+            preview(first.note.as_deref().unwrap_or(""))
+        ));
+        for &i in g {
+            out.push_str(&format!("      rec #{i:03} {}\n", paths[i]));
+        }
+    }
+    out.push_str("largest notes:\n");
+    for i in largest_notes(recs, top) {
+        out.push_str(&format!(
+            "  {:>6}  rec #{i:03} {}\n",
+            recs[i].note_len, paths[i]
+        ));
+    }
+    out
+}
+
+pub fn cmd_notes(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut enc = "latin1";
+    let mut top = DEFAULT_TOP;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "--top" => {
+                let v = it.next().unwrap_or_else(|| usage(prog));
+                top = v.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--top: not a number: {v}"),
+                    )
+                })?;
+            }
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let buf = read_input(file)?;
+    print!("{}", report(&parse_otl(&buf, enc)?, top));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn finds_duplicates_and_largest() {
+        let boiler: &[u8] = b"Status: open\r\nOwner: me\r\n";
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("A", A_NOTE, M_EXPANDED, 0, Some(boiler)),
+                rec_bytes(
+                    "B",
+                    A_NOTE,
+                    M_EXPANDED,
+                    0,
+                    Some(b"Status: open\r\nOwner: me"),
+                ),
+                rec_bytes(
+                    "C",
+                    A_NOTE,
+                    M_EXPANDED,
+                    0,
+                    Some(b"unique and rather long note"),
+                ),
+                rec_bytes("D", A_NOTE, M_EXPANDED, 0, Some(boiler)),
+                rec_bytes("E", 0x00, M_EXPANDED, 0, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        assert_eq!(duplicate_notes(&recs), [vec![0, 1, 3]]);
+        assert_eq!(largest_notes(&recs, 2), [2, 0]);
+        let text = report(&recs, 1);
+        assert!(text.contains("  3x 25 bytes: Status: open ...\n"), "{text}");
+        assert!(text.contains("      rec #003 D\n"));
+    }
+}
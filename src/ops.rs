@@ -0,0 +1,182 @@
+//! Structural operations on record lists.
+//!
+//! Edits work on records (not the Node tree) so raw attr bits, markers and
+//! notes survive untouched. After reordering or re-leveling, `restructure`
+//! recomputes what depends on position: deltas and the sibling-follows bit.
+
+use crate::addr::subtree_end_at;
+use crate::{ids, rec_levels_capped, Rec, A_SIBFOLLOWS};
+use std::io;
+
+/// Rewrite deltas to produce `levels`, and set/clear 0x08 (sibling follows)
+/// to match the resulting structure.
+pub fn restructure(recs: &mut [Rec], levels: &[usize]) {
+    let mut prev = 0i64;
+    for (r, &lvl) in recs.iter_mut().zip(levels) {
+        r.delta = (lvl as i64 - prev) as i16;
+        prev = lvl as i64;
+    }
+    for i in 0..recs.len() {
+        let mut sib = false;
+        for &l in &levels[i + 1..] {
+            if l <= levels[i] {
+                sib = l == levels[i];
+                break;
+            }
+        }
+        if sib {
+            recs[i].attr |= A_SIBFOLLOWS;
+        } else {
+            recs[i].attr &= !A_SIBFOLLOWS;
+        }
+        recs[i].flags.has_next_sibling = sib;
+    }
+}
+
+/// Record indexes of the direct children of `parent` (top level for None),
+/// from `levels` (see rec_levels_capped).
+pub fn children_at(levels: &[usize], parent: Option<usize>) -> Vec<usize> {
+    let (start, end, lvl) = match parent {
+        Some(p) => (p + 1, subtree_end_at(levels, p), levels[p] + 1),
+        None => (0, levels.len(), 0),
+    };
+    let mut out = Vec::new();
+    let mut i = start;
+    while i < end {
+        // the first record below a level jump sits deeper than `lvl`
+        if levels[i] <= lvl || out.is_empty() {
+            out.push(i);
+        }
+        i = subtree_end_at(levels, i);
+    }
+    out
+}
+
+/// How to repair level jumps (a record more than one level below its
+/// predecessor), which SideKick itself never writes and cannot display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JumpFix {
+    /// Insert plain headings with this text for the missing levels.
+    Placeholder(String),
+    /// Move the jumping record (and its subtree) up to the first free level.
+    Relevel,
+}
+
+impl JumpFix {
+    pub fn parse(s: &str) -> io::Result<JumpFix> {
+        match s {
+            "relevel" => Ok(JumpFix::Relevel),
+            "placeholder" => Ok(JumpFix::Placeholder(
+                crate::shape::DEFAULT_PLACEHOLDER.to_string(),
+            )),
+            _ => match s.strip_prefix("placeholder=") {
+                Some(t) => Ok(JumpFix::Placeholder(t.to_string())),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("--fix-level-jumps: expected placeholder[=TEXT] or relevel, got {s:?}"),
+                )),
+            },
+        }
+    }
+}
+
+/// Indexes of records sitting more than one level below their predecessor
+/// (or below level 0, for the first record).
+pub fn level_jumps(recs: &[Rec]) -> Vec<usize> {
+    let levels = rec_levels_capped(recs);
+    (0..recs.len())
+        .filter(|&i| levels[i] > if i == 0 { 0 } else { levels[i - 1] + 1 })
+        .collect()
+}
+
+/// A copy of `recs` without level jumps.
+pub fn fix_level_jumps(recs: &[Rec], fix: &JumpFix) -> Vec<Rec> {
+    let levels = rec_levels_capped(recs);
+    let mut out: Vec<Rec> = Vec::with_capacity(recs.len());
+    let mut new_levels: Vec<usize> = Vec::with_capacity(recs.len());
+    // (original level, new level) of the open ancestors
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for (r, &lvl) in recs.iter().zip(&levels) {
+        while stack.last().is_some_and(|&(o, _)| o >= lvl) {
+            stack.pop();
+        }
+        let mut next = stack.last().map_or(0, |&(_, n)| n + 1);
+        if let JumpFix::Placeholder(text) = fix {
+            let first = stack.last().map_or(0, |&(o, _)| o + 1);
+            for l in first..lvl {
+                out.push(Rec::new(text.clone(), 0x00, 0xFFFF, 0, None));
+                new_levels.push(next);
+                stack.push((l, next));
+                next += 1;
+            }
+        }
+        stack.push((lvl, next));
+        out.push(r.clone());
+        new_levels.push(next);
+    }
+    restructure(&mut out, &new_levels);
+    ids::assign_ids(&mut out);
+    out
+}
+
+/// Apply `fix` if given; otherwise warn (on stderr) about any level jumps
+/// that are about to be written.
+pub fn prepare_write(recs: Vec<Rec>, fix: Option<&JumpFix>) -> Vec<Rec> {
+    match fix {
+        Some(f) => fix_level_jumps(&recs, f),
+        None => {
+            let jumps = level_jumps(&recs);
+            if let Some(&first) = jumps.first() {
+                eprintln!(
+                    "warning: {} level jump(s) (first at record {first}); SideKick cannot \
+                     display these, see --fix-level-jumps placeholder|relevel",
+                    jumps.len()
+                );
+            }
+            recs
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, M_EXPANDED};
+
+    fn jumpy() -> Vec<Rec> {
+        // A, B two levels down, C beside B, D back at top
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", 0x00, M_EXPANDED, 2, None),
+            rec_bytes("C", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("D", 0x00, M_EXPANDED, -2, None),
+        ]);
+        parse_otl(&buf, "latin1").unwrap()
+    }
+
+    fn shape(recs: &[Rec]) -> Vec<(String, usize)> {
+        recs.iter()
+            .map(|r| r.text.to_string())
+            .zip(rec_levels_capped(recs))
+            .collect()
+    }
+
+    #[test]
+    fn placeholder_fills_missing_levels_once() {
+        let recs = jumpy();
+        assert_eq!(level_jumps(&recs), [1]);
+        let fixed = fix_level_jumps(&recs, &JumpFix::parse("placeholder=-").unwrap());
+        let want = [("A", 0), ("-", 1), ("B", 2), ("C", 2), ("D", 0)];
+        assert_eq!(shape(&fixed), want.map(|(t, l)| (t.to_string(), l)));
+        assert!(level_jumps(&fixed).is_empty());
+    }
+
+    #[test]
+    fn relevel_moves_subtree_up() {
+        let fixed = fix_level_jumps(&jumpy(), &JumpFix::Relevel);
+        let want = [("A", 0), ("B", 1), ("C", 1), ("D", 0)];
+        assert_eq!(shape(&fixed), want.map(|(t, l)| (t.to_string(), l)));
+        assert!(fixed[1].flags.has_next_sibling);
+    }
+}
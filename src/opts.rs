@@ -0,0 +1,187 @@
+//! `--opt key=value`: format-specific knobs, namespaced by exporter
+//! (`json.pretty=false`), collected once and read into each exporter's own
+//! option struct. Keys are checked against `KNOWN` when given, so a typo
+//! fails even when its exporter is not the one selected.
+
+use crate::Node;
+use std::collections::BTreeMap;
+use std::io;
+use std::str::FromStr;
+
+/// Every accepted key with its value syntax and default.
+pub const KNOWN: &[(&str, &str)] = &[
+    ("json.pretty", "true|false (default true)"),
+    ("json.ids", "true|false (default true)"),
+    ("ctags.kind", "one letter (default h)"),
+    (
+        "markdown.heading-base",
+        "0-6: top level as that # heading, deeper levels the next ones, \
+         bullets past 6 (default 0: all bullets)",
+    ),
+    ("html.theme", "light|dark|none (default light)"),
+];
+
+pub const OPT_USAGE: &str = "[--opt key=value]...";
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Opts(BTreeMap<String, String>);
+
+impl Opts {
+    /// Consume `a` (and its value from `it`) if it is `--opt`.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        if a != "--opt" {
+            return None;
+        }
+        Some(self.set(it.next().map(|s| s.as_str()).unwrap_or("")))
+    }
+
+    /// Record one `key=value`; later settings win.
+    pub fn set(&mut self, kv: &str) -> io::Result<()> {
+        let Some((k, v)) = kv.split_once('=') else {
+            return Err(bad(format!("--opt: expected key=value, got {kv:?}")));
+        };
+        if !KNOWN.iter().any(|(known, _)| *known == k) {
+            let keys: Vec<&str> = KNOWN.iter().map(|(k, _)| *k).collect();
+            return Err(bad(format!(
+                "--opt: unknown key {k:?} (known: {})",
+                keys.join(", ")
+            )));
+        }
+        self.0.insert(k.to_string(), v.to_string());
+        Ok(())
+    }
+
+    fn get<T: FromStr>(&self, key: &str, default: T) -> io::Result<T> {
+        match self.0.get(key) {
+            None => Ok(default),
+            Some(v) => v
+                .parse()
+                .map_err(|_| bad(format!("--opt {key}: unexpected value {v:?}"))),
+        }
+    }
+}
+
+/// `--json` output.
+#[derive(Debug, Clone)]
+pub struct JsonOpts {
+    pub pretty: bool,
+    pub ids: bool, // include stable node ids
+}
+
+impl JsonOpts {
+    pub fn from_opts(o: &Opts) -> io::Result<JsonOpts> {
+        Ok(JsonOpts {
+            pretty: o.get("json.pretty", true)?,
+            ids: o.get("json.ids", true)?,
+        })
+    }
+
+    pub fn render(&self, tree: &[Node]) -> String {
+        fn strip_ids(nodes: &mut [Node]) {
+            for n in nodes {
+                n.id = None;
+                strip_ids(&mut n.children);
+            }
+        }
+        let mut tree = tree.to_vec();
+        if !self.ids {
+            strip_ids(&mut tree);
+        }
+        if self.pretty {
+            serde_json::to_string_pretty(&tree).unwrap()
+        } else {
+            serde_json::to_string(&tree).unwrap()
+        }
+    }
+}
+
+/// `--ctags` output.
+#[derive(Debug, Clone)]
+pub struct TagOpts {
+    pub kind: char,
+}
+
+impl TagOpts {
+    pub fn from_opts(o: &Opts) -> io::Result<TagOpts> {
+        Ok(TagOpts {
+            kind: o.get("ctags.kind", 'h')?,
+        })
+    }
+}
+
+/// `--markdown` output.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOpts {
+    pub heading_base: usize, // 0: every level a bullet
+}
+
+impl MarkdownOpts {
+    pub fn from_opts(o: &Opts) -> io::Result<MarkdownOpts> {
+        let heading_base = o.get("markdown.heading-base", 0)?;
+        if heading_base > 6 {
+            return Err(bad(format!(
+                "--opt markdown.heading-base: expected 0-6, got {heading_base}"
+            )));
+        }
+        Ok(MarkdownOpts { heading_base })
+    }
+}
+
+/// `--html` output.
+#[derive(Debug, Clone)]
+pub struct HtmlOpts {
+    pub theme: &'static str, // light, dark or none (no style sheet)
+}
+
+impl Default for HtmlOpts {
+    fn default() -> Self {
+        HtmlOpts { theme: "light" }
+    }
+}
+
+impl HtmlOpts {
+    pub fn from_opts(o: &Opts) -> io::Result<HtmlOpts> {
+        let theme: String = o.get("html.theme", "light".to_string())?;
+        let theme = ["light", "dark", "none"]
+            .into_iter()
+            .find(|t| *t == theme)
+            .ok_or_else(|| bad(format!("--opt html.theme: unexpected value {theme:?}")))?;
+        Ok(HtmlOpts { theme })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opts_parse_and_reject_unknown_keys() {
+        let mut o = Opts::default();
+        o.set("json.pretty=false").unwrap();
+        o.set("ctags.kind=s").unwrap();
+        assert!(!JsonOpts::from_opts(&o).unwrap().pretty);
+        assert!(JsonOpts::from_opts(&o).unwrap().ids);
+        assert_eq!(TagOpts::from_opts(&o).unwrap().kind, 's');
+        assert!(o.set("json.prety=false").is_err());
+        assert!(o.set("json.pretty").is_err());
+        o.set("json.ids=maybe").unwrap();
+        assert!(JsonOpts::from_opts(&o).is_err());
+
+        assert_eq!(MarkdownOpts::from_opts(&o).unwrap().heading_base, 0);
+        assert_eq!(HtmlOpts::from_opts(&o).unwrap().theme, "light");
+        o.set("markdown.heading-base=7").unwrap();
+        o.set("html.theme=dark").unwrap();
+        assert!(MarkdownOpts::from_opts(&o).is_err());
+        assert_eq!(HtmlOpts::from_opts(&o).unwrap().theme, "dark");
+        o.set("html.theme=neon").unwrap();
+        assert!(HtmlOpts::from_opts(&o).is_err());
+    }
+}
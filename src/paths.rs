@@ -0,0 +1,73 @@
+//! Heading paths ("A/B/C"): how `paths`, `--path`, `grep` and the
+//! findings name a record.
+
+use crate::{rec_levels_with, Rec, DEFAULT_MAX_LEVEL};
+
+pub const PATH_SEP: char = '/';
+
+// Escape backslash and the path separator inside one heading component.
+pub fn escape_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        if ch == '\\' || ch == PATH_SEP {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// Full path of every record, in record order. Components come from the
+/// ancestor headings; filler levels (from +2 level jumps) are empty.
+pub fn heading_paths(recs: &[Rec]) -> Vec<String> {
+    heading_paths_with(recs, DEFAULT_MAX_LEVEL)
+}
+
+/// `heading_paths` with levels clamped at `max_level`.
+pub fn heading_paths_with(recs: &[Rec], max_level: usize) -> Vec<String> {
+    let levels = rec_levels_with(recs, max_level);
+    let mut stack: Vec<String> = Vec::new();
+    let mut out = Vec::with_capacity(recs.len());
+    for (r, &lvl) in recs.iter().zip(&levels) {
+        stack.truncate(lvl);
+        while stack.len() < lvl {
+            stack.push(String::new());
+        }
+        stack.push(escape_component(&r.text));
+        out.push(stack.join(&PATH_SEP.to_string()));
+    }
+    out
+}
+
+/// Paths starting with `prefix`, de-duplicated, in outline order.
+pub fn complete(paths: &[String], prefix: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for p in paths {
+        if p.starts_with(prefix) && !out.contains(p) {
+            out.push(p.clone());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_and_completion() {
+        let recs = vec![
+            Rec::new("Proj".into(), 0x00, 0xFFFF, 0, None),
+            Rec::new("a/b".into(), 0x00, 0xFFFF, 1, None),
+            Rec::new("Deep".into(), 0x00, 0xFFFF, 2, None),
+            Rec::new("Other".into(), 0x00, 0xFFFF, -3, None),
+        ];
+        let paths = heading_paths(&recs);
+        assert_eq!(paths, ["Proj", "Proj/a\\/b", "Proj/a\\/b//Deep", "Other"]);
+        assert_eq!(complete(&paths, "Pr").len(), 3);
+        assert_eq!(
+            complete(&paths, "Proj/a"),
+            ["Proj/a\\/b", "Proj/a\\/b//Deep"]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! `--head` / `--sample`: cut an outline down before export.
+//!
+//! Both keep records whole and in file order, then `restructure` so the
+//! result is a valid outline (deltas and sibling bits recomputed) in any
+//! output format, `--canon` included. Ids are those of the full file, so a
+//! preview can be traced back to its source.
+
+use crate::addr::subtree_end;
+use crate::gen::Rng;
+use crate::ops::restructure;
+use crate::{rec_levels_capped, rec_parents_capped, Rec};
+use std::io;
+
+pub const PREVIEW_USAGE: &str = "[--head N | --sample N [--seed S]]";
+
+#[derive(Debug, Clone, Default)]
+pub struct Preview {
+    head: Option<usize>,
+    sample: Option<usize>,
+    seed: u64,
+}
+
+fn number<'a>(flag: &str, it: &mut impl Iterator<Item = &'a String>) -> io::Result<u64> {
+    let v = it.next().map(|s| s.as_str()).unwrap_or("");
+    v.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{flag}: expected a number, got {v:?}"),
+        )
+    })
+}
+
+impl Preview {
+    /// Consume `a` (and its value from `it`) if it is a preview flag.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        let r = match a {
+            "--head" => number(a, it).map(|n| self.head = Some(n as usize)),
+            "--sample" => number(a, it).map(|n| self.sample = Some(n as usize)),
+            "--seed" => number(a, it).map(|n| self.seed = n),
+            _ => return None,
+        };
+        if r.is_ok() && self.head.is_some() && self.sample.is_some() {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--head and --sample are exclusive",
+            )));
+        }
+        Some(r)
+    }
+
+    pub fn apply(&self, recs: Vec<Rec>) -> Vec<Rec> {
+        let keep = match (self.head, self.sample) {
+            (Some(n), _) => (0..recs.len().min(n)).collect(),
+            (_, Some(n)) => sample(&recs, n, self.seed),
+            _ => return recs,
+        };
+        let levels = rec_levels_capped(&recs);
+        let mut out: Vec<Rec> = keep.iter().map(|&i| recs[i].clone()).collect();
+        let new_levels: Vec<usize> = keep.iter().map(|&i| levels[i]).collect();
+        restructure(&mut out, &new_levels);
+        out
+    }
+}
+
+/// Record indexes (ascending) of `n` random disjoint subtrees plus their
+/// ancestors, which keep the subtrees at their original paths.
+pub fn sample(recs: &[Rec], n: usize, seed: u64) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..recs.len()).collect();
+    let mut rng = Rng::new(seed);
+    for i in (1..order.len()).rev() {
+        order.swap(i, rng.below(i + 1));
+    }
+    let mut chosen: Vec<(usize, usize)> = Vec::new();
+    for i in order {
+        if chosen.len() == n {
+            break;
+        }
+        let end = subtree_end(recs, i);
+        if chosen.iter().all(|&(s, e)| end <= s || i >= e) {
+            chosen.push((i, end));
+        }
+    }
+    let parents = rec_parents_capped(recs);
+    let mut keep = vec![false; recs.len()];
+    for (s, e) in chosen {
+        keep[s..e].iter_mut().for_each(|k| *k = true);
+        let mut p = parents[s];
+        while let Some(a) = p {
+            keep[a] = true;
+            p = parents[a];
+        }
+    }
+    (0..recs.len()).filter(|&i| keep[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::level_jumps;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    fn outline() -> Vec<Rec> {
+        parse_otl(
+            &otl_file(vec![
+                rec_bytes("A", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("a1", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+                rec_bytes("a2", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("deep", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("B", A_SIBFOLLOWS, M_EXPANDED, -2, None),
+                rec_bytes("C", 0x00, M_EXPANDED, 0, None),
+            ]),
+            "latin1",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn head_keeps_a_valid_prefix() {
+        let p = Preview {
+            head: Some(2),
+            ..Preview::default()
+        };
+        let out = p.apply(outline());
+        assert_eq!(out.len(), 2);
+        assert_eq!(rec_levels_capped(&out), [0, 1]);
+        assert!(!out[1].flags.has_next_sibling);
+    }
+
+    #[test]
+    fn samples_are_whole_subtrees_under_their_ancestors() {
+        let recs = outline();
+        for seed in 0..20 {
+            let keep = sample(&recs, 2, seed);
+            let p = Preview {
+                sample: Some(2),
+                seed,
+                ..Preview::default()
+            };
+            let out = p.apply(recs.clone());
+            assert!(level_jumps(&out).is_empty());
+            assert_eq!(out.len(), keep.len());
+            // "deep" never appears without a2 and A
+            if keep.contains(&3) {
+                assert!(keep.contains(&2) && keep.contains(&0));
+            }
+        }
+        assert_eq!(sample(&recs, 100, 7), (0..recs.len()).collect::<Vec<_>>());
+    }
+}
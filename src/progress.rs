@@ -0,0 +1,108 @@
+//! Progress line on stderr for batch runs (`check`, `diff-dir`).
+//!
+//! Nothing is shown for the first second, so short runs stay silent. On a
+//! terminal the line is redrawn in place (at most 10 times a second);
+//! otherwise (logs, CI) a plain line is written every 10 seconds.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const QUIET_START: Duration = Duration::from_secs(1);
+const TTY_EVERY: Duration = Duration::from_millis(100);
+const LOG_EVERY: Duration = Duration::from_secs(10);
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    start: Instant,
+    last: Option<Instant>,
+    enabled: bool,
+    tty: bool,
+    drawn: bool, // a tty line is on screen
+}
+
+fn fmt_secs(d: Duration) -> String {
+    let s = d.as_secs();
+    if s >= 3600 {
+        format!("{}h{:02}m", s / 3600, s / 60 % 60)
+    } else {
+        format!("{}m{:02}s", s / 60, s % 60)
+    }
+}
+
+impl Progress {
+    pub fn new(total: usize, quiet: bool) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            start: Instant::now(),
+            last: None,
+            enabled: !quiet,
+            tty: io::stderr().is_terminal(),
+            drawn: false,
+        }
+    }
+
+    /// The progress text after `done` of `total` items.
+    pub fn line(done: usize, total: usize, elapsed: Duration, current: &str) -> String {
+        let eta = if done == 0 {
+            "?".to_string()
+        } else {
+            fmt_secs(elapsed.mul_f64((total - done) as f64 / done as f64))
+        };
+        format!("[{done}/{total}] ETA {eta} {current}")
+    }
+
+    /// Call before starting on `current`.
+    pub fn tick(&mut self, current: &str) {
+        let now = Instant::now();
+        let every = if self.tty { TTY_EVERY } else { LOG_EVERY };
+        let due = self.last.is_none_or(|t| now - t >= every);
+        if self.enabled && now - self.start >= QUIET_START && due {
+            let line = Progress::line(self.done, self.total, now - self.start, current);
+            if self.tty {
+                let width = 100;
+                let line: String = line.chars().take(width).collect();
+                eprint!("\r\x1b[K{line}");
+                io::stderr().flush().ok();
+                self.drawn = true;
+            } else {
+                eprintln!("{line}");
+            }
+            self.last = Some(now);
+        }
+        self.done += 1;
+    }
+
+    /// Print a message line on stderr without mangling the progress line.
+    pub fn eprintln(&mut self, msg: &str) {
+        self.clear();
+        eprintln!("{msg}");
+    }
+
+    pub fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            self.drawn = false;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_from_rate() {
+        let l = Progress::line(10, 40, Duration::from_secs(20), "a.OTL");
+        assert_eq!(l, "[10/40] ETA 1m00s a.OTL");
+        assert_eq!(Progress::line(0, 5, Duration::ZERO, "x"), "[0/5] ETA ? x");
+        assert_eq!(fmt_secs(Duration::from_secs(7322)), "2h02m");
+    }
+}
@@ -0,0 +1,110 @@
+//! `--verify-roundtrip`: write the parsed document back with the record
+//! writer (see write.rs; `--preserve` / `--reset` apply) and compare the
+//! result with the input byte for byte.
+//!
+//! Differences are classified as `conformance` does (see conform::compare)
+//! and each kind is marked as writer policy or a bug:
+//! - policy: what the writer deliberately writes its own way or was told
+//!   to reset (standard PREAMBLE words, one 0x1a EOF sentinel, spaces
+//!   refolded left to right, note line ends, 0x04 child bit, cursor, folds,
+//!   unknown attr bits, junk prefix, trailer)
+//! - bug: anything that changes what SideKick shows (heading text, note
+//!   bytes, note/sibling bits, deltas, record count) or that no kind covers
+//!
+//! The report goes to stderr; only bug kinds make the exit status 1.
+
+use crate::conform::{compare, Divergences};
+use crate::write::Preserve;
+use crate::{Framing, Rec};
+use std::io;
+
+/// Divergence kinds that are writer policy rather than bugs.
+pub const POLICY: &[&str] = &[
+    "preamble",
+    "prefix",
+    "eof-sentinel",
+    "trailer",
+    "heading-folding",
+    "note-line-ends",
+    "child-bit",
+    "cursor-bit",
+    "marker",
+    "unknown-attr-bits",
+];
+
+/// How rewriting `recs` (parsed from `input` with `framing`) differs from
+/// `input`.
+pub fn verify(
+    input: &[u8],
+    recs: &[Rec],
+    framing: &Framing,
+    enc: &str,
+    keep: &Preserve,
+) -> io::Result<Divergences> {
+    let mut recs = recs.to_vec();
+    keep.apply(&mut recs);
+    compare(&keep.serialize(&recs, framing, enc)?, input)
+}
+
+/// The report lines and whether any kind is a bug.
+pub fn report(d: &Divergences, len: usize) -> (Vec<String>, bool) {
+    if d.is_empty() {
+        return (vec![format!("roundtrip: identical ({len} bytes)")], false);
+    }
+    let total: usize = d.values().map(|&(n, _)| n).sum();
+    let bug = d.keys().any(|k| !POLICY.contains(k));
+    let mut lines = vec![format!(
+        "roundtrip: {total} difference(s) in {} kind(s){}",
+        d.len(),
+        if bug { "" } else { ", all writer policy" }
+    )];
+    for (kind, (n, off)) in d {
+        let what = if POLICY.contains(kind) {
+            "policy"
+        } else {
+            "BUG"
+        };
+        lines.push(format!("  {kind}: {n} (first at {off:#06x}) {what}"));
+    }
+    (lines, bug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_doc, A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn policy_differences_are_not_bugs() {
+        let mut input = otl_file(vec![
+            rec_bytes("a b", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("n", A_NOTE | 0x02, M_EXPANDED, 0, Some(b"x\ny")),
+        ]);
+        input.extend([0xff, 0xff, 0x1a]);
+        let doc = parse_doc(&input, "latin1").unwrap();
+        let keep = Preserve::default();
+        let d = verify(&input, &doc.recs, &doc.framing, "latin1", &keep).unwrap();
+        let kinds: Vec<&str> = d.keys().copied().collect();
+        assert_eq!(kinds, ["eof-sentinel"]);
+        assert!(!report(&d, input.len()).1);
+
+        let keep = Preserve {
+            unknown_bits: false,
+            ..Preserve::default()
+        };
+        let mut recs = doc.recs.clone();
+        recs[0].text = "a c".into();
+        let d = verify(&input, &recs, &doc.framing, "latin1", &keep).unwrap();
+        let (lines, bug) = report(&d, input.len());
+        assert!(bug);
+        assert_eq!(
+            lines[1..],
+            [
+                "  eof-sentinel: 1 (first at 0x0020) policy",
+                "  heading-text: 1 (first at 0x0009) BUG",
+                "  unknown-attr-bits: 1 (first at 0x0014) policy"
+            ]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! `--serve-stdio`: JSON-RPC 2.0 over stdin/stdout for GUI front ends.
+//!
+//! One request per line in, one response per line out (plus `progress`
+//! notifications during `check`). Methods:
+//! - `parse {path, enc?}`: record count, parse notes and validation findings
+//! - `render {path, format, enc?}`: the file in any export format
+//! - `apply_edits {path, ops, output?, enc?, preserve?, reset?}`: edit
+//!   operations (as in `otl edit`, one per string; `#` is literal, line
+//!   breaks are refused) applied all-or-nothing; writes `output` if given,
+//!   notes in `enc` and `preserve`/`reset` as the flags take them, and
+//!   returns the result as canon text
+//! - `check {paths, enc?}`: `check` reports, with `progress` notifications
+//! - `shutdown`
+//!
+//! Parsed files are kept for the session (see cache.rs). Errors carry the
+//! exit status the same failure would give on the command line in
+//! `error.data.exit`.
+
+use crate::cache::ParseCache;
+use crate::edit::{apply_all, parse_op, Strict};
+use crate::ops::prepare_write;
+use crate::opts::{HtmlOpts, MarkdownOpts};
+use crate::slug::SlugStyle;
+use crate::style::TextStyle;
+use crate::write::Preserve;
+use crate::{
+    exit_code, read_input, render_canon, render_indented, render_plain_all, validate_findings,
+    write_output, xref, Doc, Node, Rec,
+};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+pub const FORMATS: &[&str] = &[
+    "text",
+    "indented",
+    "json",
+    "canon",
+    "yaml",
+    "xml",
+    "markdown",
+    "html",
+    "anki-tsv",
+    "plantuml-wbs",
+];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+struct RpcError {
+    code: i64,
+    message: String,
+    exit: Option<u8>,
+}
+
+impl From<io::Error> for RpcError {
+    fn from(e: io::Error) -> RpcError {
+        RpcError {
+            code: SERVER_ERROR,
+            message: e.to_string(),
+            exit: Some(exit_code(&e)),
+        }
+    }
+}
+
+fn invalid_params(message: String) -> RpcError {
+    RpcError {
+        code: INVALID_PARAMS,
+        message,
+        exit: None,
+    }
+}
+
+fn str_param<'a>(params: &'a Value, key: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_params(format!("missing string parameter {key:?}")))
+}
+
+fn enc(params: &Value) -> &str {
+    params
+        .get("enc")
+        .and_then(Value::as_str)
+        .unwrap_or("latin1")
+}
+
+// The `preserve` / `reset` field lists, as `--preserve` / `--reset` take.
+fn preserve(params: &Value) -> Result<Preserve, RpcError> {
+    let mut keep = Preserve::default();
+    for flag in ["preserve", "reset"] {
+        if let Some(v) = params.get(flag).and_then(Value::as_str) {
+            let v = [v.to_string()];
+            if let Some(Err(e)) = keep.take_flag(&format!("--{flag}"), &mut v.iter()) {
+                return Err(invalid_params(e.to_string()));
+            }
+        }
+    }
+    Ok(keep)
+}
+
+fn load(params: &Value, cache: &mut ParseCache) -> Result<(Rc<Doc>, Rc<Vec<Node>>), RpcError> {
+    let path = str_param(params, "path")?;
+    Ok(cache.tree(&read_input(path)?, enc(params))?)
+}
+
+/// `recs` (with their `tree`) rendered as `format` (one of `FORMATS`).
+pub fn render(recs: &[Rec], tree: &[Node], format: &str, path: &str) -> Option<String> {
+    let style = TextStyle::default();
+    Some(match format {
+        "text" => render_plain_all(tree, 0, &style),
+        "indented" => render_indented(tree, 0, &style),
+        "json" => serde_json::to_string_pretty(tree).unwrap_or_default(),
+        "canon" => render_canon(recs, false),
+        "yaml" => crate::yaml::render_yaml(tree),
+        "xml" => crate::xml::render_xml(tree),
+        "markdown" | "html" => {
+            let idx = xref::Index::new(recs, SlugStyle::default());
+            if format == "html" {
+                crate::markup::render_html(tree, &idx, path, &HtmlOpts::default()).0
+            } else {
+                crate::markup::render_markdown(tree, &idx, &MarkdownOpts::default()).0
+            }
+        }
+        "anki-tsv" => crate::anki::render_anki_tsv(tree),
+        "plantuml-wbs" => crate::wbs::render_wbs(tree, path),
+        _ => return None,
+    })
+}
+
+fn call(
+    method: &str,
+    params: &Value,
+    cache: &mut ParseCache,
+    out: &mut dyn Write,
+) -> Result<Value, RpcError> {
+    match method {
+        "parse" => {
+            let (doc, _) = load(params, cache)?;
+            let Doc { recs, framing } = &*doc;
+            let findings: Vec<Value> = validate_findings(recs, false)
+                .iter()
+                .map(|f| json!({"rec": f.rec, "kind": f.kind, "detail": f.detail}))
+                .collect();
+            Ok(json!({"records": recs.len(), "notes": framing.notes, "findings": findings}))
+        }
+        "render" => {
+            let (doc, tree) = load(params, cache)?;
+            let format = str_param(params, "format")?;
+            let path = str_param(params, "path")?;
+            match render(&doc.recs, &tree, format, path) {
+                Some(output) => Ok(json!({ "output": output })),
+                None => Err(invalid_params(format!(
+                    "unknown format {format:?} (known: {})",
+                    FORMATS.join(", ")
+                ))),
+            }
+        }
+        "apply_edits" => {
+            let (doc, _) = load(params, cache)?;
+            let Doc { recs, framing } = &*doc;
+            let ops = params
+                .get("ops")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid_params("missing array parameter \"ops\"".into()))?
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let op = v.as_str().unwrap_or("");
+                    let op = parse_op(op).map_err(|e| invalid_params(format!("op:{}: {e}", i + 1)));
+                    Ok((i + 1, op?))
+                })
+                .collect::<Result<Vec<_>, RpcError>>()?;
+            let Some(recs) = apply_all(recs, &ops, "op", &mut Strict)? else {
+                return Ok(json!({"changed": false}));
+            };
+            let mut recs = prepare_write(recs, None);
+            if let Some(output) = params.get("output").and_then(Value::as_str) {
+                let keep = preserve(params)?;
+                keep.apply(&mut recs);
+                write_output(output, &keep.serialize(&recs, framing, enc(params))?)?;
+            }
+            Ok(json!({"changed": true, "records": recs.len(), "canon": render_canon(&recs, false)}))
+        }
+        "check" => {
+            let paths: Vec<&str> = params
+                .get("paths")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid_params("missing array parameter \"paths\"".into()))?
+                .iter()
+                .filter_map(Value::as_str)
+                .collect();
+            let enc = enc(params);
+            let mut reports = Vec::new();
+            for (done, p) in paths.iter().enumerate() {
+                let note = json!({"jsonrpc": "2.0", "method": "progress",
+                    "params": {"done": done, "total": paths.len(), "current": p}});
+                writeln!(out, "{note}")?;
+                out.flush()?;
+                reports.push(crate::check::check_file(
+                    Path::new(p),
+                    enc,
+                    false,
+                    None,
+                    &[],
+                    cache,
+                ));
+            }
+            Ok(serde_json::to_value(reports).unwrap_or(Value::Null))
+        }
+        "shutdown" => Ok(Value::Null),
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("unknown method {method:?}"),
+            exit: None,
+        }),
+    }
+}
+
+/// Handle one request line; None for notifications (no `id`).
+pub fn handle(line: &str, cache: &mut ParseCache, out: &mut dyn Write) -> Option<Value> {
+    let req: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => {
+            return Some(json!({"jsonrpc": "2.0", "id": null,
+                "error": {"code": PARSE_ERROR, "message": e.to_string()}}))
+        }
+    };
+    let id = req.get("id").cloned();
+    let Some(method) = req.get("method").and_then(Value::as_str) else {
+        return Some(json!({"jsonrpc": "2.0", "id": id,
+            "error": {"code": INVALID_REQUEST, "message": "no method"}}));
+    };
+    let params = req.get("params").cloned().unwrap_or(Value::Null);
+    let result = call(method, &params, cache, out);
+    let id = id?;
+    Some(match result {
+        Ok(v) => json!({"jsonrpc": "2.0", "id": id, "result": v}),
+        Err(e) => {
+            let mut err = json!({"code": e.code, "message": e.message});
+            if let Some(exit) = e.exit {
+                err["data"] = json!({ "exit": exit });
+            }
+            json!({"jsonrpc": "2.0", "id": id, "error": err})
+        }
+    })
+}
+
+pub fn serve(input: impl BufRead, mut out: impl Write) -> io::Result<()> {
+    let mut cache = ParseCache::default();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let shutdown = serde_json::from_str::<Value>(&line)
+            .is_ok_and(|v| v.get("method").and_then(Value::as_str) == Some("shutdown"));
+        if let Some(resp) = handle(&line, &mut cache, &mut out) {
+            writeln!(out, "{resp}")?;
+            out.flush()?;
+        }
+        if shutdown {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn parse_render_edit_and_errors() {
+        let dir = std::env::temp_dir().join(format!("otl-rpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.OTL");
+        std::fs::write(
+            &file,
+            otl_file(vec![rec_bytes("Alpha", 0x00, M_EXPANDED, 0, None)]),
+        )
+        .unwrap();
+        let f = file.to_string_lossy();
+        let reqs = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "parse", "params": {"path": f}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "render", "params": {"path": f, "format": "text"}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "apply_edits",
+                "params": {"path": f, "ops": ["rename rec:0 Beta"]}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "render", "params": {"path": "/nonexistent/x.OTL", "format": "text"}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "check", "params": {"paths": [f]}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "parse", "params": {"path": f}}),
+        ];
+        let input: String = reqs.iter().map(|r| format!("{r}\n")).collect();
+        let mut out = Vec::new();
+        serve(input.as_bytes(), &mut out).unwrap();
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["result"]["records"], 1);
+        assert_eq!(lines[1]["result"]["output"], "Alpha\n");
+        assert_eq!(lines[2]["result"]["changed"], true);
+        assert!(lines[2]["result"]["canon"]
+            .as_str()
+            .unwrap()
+            .contains("Beta"));
+        assert_eq!(lines[3]["error"]["data"]["exit"], 4);
+        assert_eq!(lines[4]["method"], "progress");
+        assert_eq!(lines[5]["result"][0]["status"], 0);
+        assert_eq!(lines[6]["id"], 6);
+        assert_eq!(lines.len(), 7); // nothing after shutdown
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn edits_keep_the_note_encoding_and_take_ops_whole() {
+        let dir = std::env::temp_dir().join(format!("otl-rpc-enc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (file, out) = (dir.join("a.OTL"), dir.join("b.OTL"));
+        let note: &[u8] = b"Gr\x81\xe1e"; // "Grüße" in cp437
+        std::fs::write(
+            &file,
+            otl_file(vec![rec_bytes("A", A_NOTE, M_EXPANDED, 0, Some(note))]),
+        )
+        .unwrap();
+        let (f, o) = (file.to_string_lossy(), out.to_string_lossy());
+        let reqs = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "apply_edits", "params": {"path": f,
+                "enc": "cp437", "output": o, "ops": ["rename rec:0 \"#1 item\""]}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "apply_edits",
+                "params": {"path": f, "ops": ["rename rec:0 x\ndelete rec:0"]}}),
+        ];
+        let input: String = reqs.iter().map(|r| format!("{r}\n")).collect();
+        let mut resp = Vec::new();
+        serve(input.as_bytes(), &mut resp).unwrap();
+        let lines: Vec<Value> = String::from_utf8(resp)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["result"]["changed"], true, "{}", lines[0]);
+        let back = parse_otl(&std::fs::read(&out).unwrap(), "latin1").unwrap();
+        assert_eq!(&*back[0].text, "#1 item");
+        assert_eq!(back[0].note_len, note.len());
+        assert!(std::fs::read(&out).unwrap().windows(5).any(|w| w == note));
+        assert_eq!(lines[1]["error"]["code"], INVALID_PARAMS);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
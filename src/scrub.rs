@@ -0,0 +1,330 @@
+//! `scrub` subcommand: anonymize an .OTL for public bug reports.
+//!
+//! Works on the raw file bytes of every document in the stream, so
+//! structure, attrs, markers, deltas, and heading and note lengths survive
+//! byte-for-byte. Bytes the parser does not interpret (junk before MAGIC
+//! other than a UTF-8 BOM, stray bytes it skipped, the trailer after the EOF
+//! sentinel) keep their length but not their content: NUL, ^Z, 0xFF and
+//! whitespace stay, every other byte becomes NUL. `--keep-trailer` leaves
+//! trailers as they are (when the bug is in one). Within heading and note
+//! bytes:
+//! - each word (run of ASCII letters) becomes a same-length dictionary word
+//!   (`--words`, the default) or `xxx` (`--placeholder`), keeping the case of
+//!   each position; the same word always maps to the same replacement;
+//! - digits become `0`;
+//! - in headings, the high bit (compressed following space) is kept;
+//! - non-ASCII note bytes keep their class: a valid UTF-8 sequence becomes a
+//!   fixed sequence of the same length, any other high byte becomes 0xE9;
+//! - spaces, punctuation and line breaks are kept.
+
+use crate::gen::Rng;
+use crate::ids::fnv1a64;
+use crate::{parse_stream, read_input, usage, write_output, Doc, MAGIC, PREAMBLE};
+use std::io;
+
+const DICT: &[&str] = &[
+    "apple",
+    "river",
+    "stone",
+    "cloud",
+    "maple",
+    "tiger",
+    "lemon",
+    "piano",
+    "orbit",
+    "candle",
+    "meadow",
+    "harbor",
+    "silver",
+    "window",
+    "garden",
+    "pencil",
+    "rocket",
+    "violet",
+    "anchor",
+    "blanket",
+    "compass",
+    "lantern",
+    "pelican",
+    "thunder",
+    "harvest",
+    "granite",
+    "sparrow",
+    "elephant",
+    "mountain",
+    "notebook",
+    "sunlight",
+    "umbrella",
+    "waterfall",
+    "telescope",
+];
+
+// Blank out uninterpreted bytes; those kept decide where records and
+// documents start, and none of MAGIC's 0x93 can appear.
+fn blank(bytes: &mut [u8]) {
+    for b in bytes {
+        if !matches!(*b, 0x00 | 0x1a | 0xff) && !b.is_ascii_whitespace() {
+            *b = 0;
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Words,
+    Placeholder,
+}
+
+struct Scrubber {
+    mode: Mode,
+    salt: String,
+    keep_trailer: bool,
+}
+
+impl Scrubber {
+    // Same-length lowercase replacement for a lowercase ASCII word.
+    fn replacement(&self, word: &str) -> String {
+        let n = word.len();
+        if self.mode == Mode::Placeholder {
+            return "x".repeat(n);
+        }
+        let mut rng = Rng::new(fnv1a64(format!("{}\x1f{word}", self.salt).as_bytes()));
+        let fits: Vec<&str> = DICT.iter().copied().filter(|w| w.len() == n).collect();
+        if !fits.is_empty() {
+            return fits[rng.below(fits.len())].to_string();
+        }
+        // no dictionary word of this length: glue words and cut
+        let mut out = String::new();
+        while out.len() < n {
+            out.push_str(DICT[rng.below(DICT.len())]);
+        }
+        out.truncate(n);
+        out
+    }
+
+    /// Scrub ASCII text (7-bit view), keeping every byte position.
+    fn scrub_ascii(&self, chars: &mut [u8]) {
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i].is_ascii_alphabetic() {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word = String::from_utf8_lossy(&chars[start..i]).to_ascii_lowercase();
+                for (c, r) in chars[start..i]
+                    .iter_mut()
+                    .zip(self.replacement(&word).bytes())
+                {
+                    *c = if c.is_ascii_uppercase() {
+                        r.to_ascii_uppercase()
+                    } else {
+                        r
+                    };
+                }
+                continue;
+            }
+            if chars[i].is_ascii_digit() {
+                chars[i] = b'0';
+            }
+            i += 1;
+        }
+    }
+
+    fn scrub_heading(&self, bytes: &mut [u8]) {
+        // a set high bit means "followed by a space": end the word there
+        let mut start = 0;
+        for i in 0..bytes.len() {
+            if bytes[i] & 0x80 != 0 || i + 1 == bytes.len() {
+                let mut chars: Vec<u8> = bytes[start..=i].iter().map(|b| b & 0x7f).collect();
+                self.scrub_ascii(&mut chars);
+                for (b, c) in bytes[start..=i].iter_mut().zip(chars) {
+                    *b = (*b & 0x80) | c;
+                }
+                start = i + 1;
+            }
+        }
+    }
+
+    fn scrub_note(&self, bytes: &mut [u8]) {
+        const UTF8_FILL: [&[u8]; 3] = [b"\xc3\xa9", b"\xe2\x82\xac", b"\xf0\x9f\x98\x80"];
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] < 0x80 {
+                let start = i;
+                while i < bytes.len() && bytes[i] < 0x80 {
+                    i += 1;
+                }
+                self.scrub_ascii(&mut bytes[start..i]);
+                continue;
+            }
+            let n = match bytes[i] {
+                0xc2..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf4 => 4,
+                _ => 1,
+            };
+            let valid =
+                n > 1 && i + n <= bytes.len() && std::str::from_utf8(&bytes[i..i + n]).is_ok();
+            if valid {
+                bytes[i..i + n].copy_from_slice(UTF8_FILL[n - 2]);
+                i += n;
+            } else {
+                bytes[i] = 0xe9;
+                i += 1;
+            }
+        }
+    }
+
+    fn scrub(&self, buf: &[u8], docs: &[Doc]) -> Vec<u8> {
+        let mut out = buf.to_vec();
+        for d in docs {
+            let f = &d.framing;
+            let bom = if f.prefix.starts_with(b"\xef\xbb\xbf") {
+                3
+            } else {
+                0
+            };
+            blank(&mut out[f.start + bom..f.start + f.prefix.len()]);
+            let mut at = f.start + f.prefix.len() + MAGIC.len() + PREAMBLE.len();
+            for r in &d.recs {
+                blank(&mut out[at.min(r.off_text)..r.off_text]);
+                self.scrub_heading(&mut out[r.off_text..r.off_text + r.len_text]);
+                at = r.off_delta + 2;
+                if let Some(off) = r.off_note {
+                    at = (off + r.note_len).min(out.len());
+                    self.scrub_note(&mut out[off..at]);
+                }
+            }
+            if !self.keep_trailer {
+                blank(&mut out[f.end..f.end + f.trailer.len()]);
+            }
+        }
+        out
+    }
+}
+
+pub fn cmd_scrub(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut out: Option<&str> = None;
+    let mut mode = Mode::Words;
+    let mut salt: Option<String> = None;
+    let mut keep_trailer = false;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--words" => mode = Mode::Words,
+            "--placeholder" => mode = Mode::Placeholder,
+            "--keep-trailer" => keep_trailer = true,
+            "--salt" => salt = Some(it.next().unwrap_or_else(|| usage(prog)).clone()),
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let (Some(file), Some(out)) = (file, out) else {
+        usage(prog)
+    };
+    // without --salt, word mapping differs on every run (no dictionary attack)
+    let salt = salt.unwrap_or_else(|| {
+        let t = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        format!("{t:x}")
+    });
+    let buf = read_input(file)?;
+    let docs = parse_stream(&buf, "latin1")?;
+    let s = Scrubber {
+        mode,
+        salt,
+        keep_trailer,
+    };
+    write_output(out, &s.scrub(&buf, &docs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, rec_levels_capped, A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn scrub_keeps_structure_and_lengths() {
+        let buf = otl_file(vec![
+            rec_bytes("Call Bob", 0x00, M_EXPANDED, 0, None),
+            rec_bytes(
+                "Notes",
+                A_NOTE,
+                M_EXPANDED,
+                1,
+                Some(b"Bob 555-1234\r\ncaf\xc3\xa9 \xe9t\xe9"),
+            ),
+        ]);
+        let mut buf = buf;
+        // fold "Call " into 'l'|0x80
+        let at = buf.iter().position(|&b| b == b' ').unwrap();
+        buf[at - 1] |= 0x80;
+        buf.remove(at);
+
+        let docs = parse_stream(&buf, "latin1").unwrap();
+        let recs = &docs[0].recs;
+        let s = Scrubber {
+            mode: Mode::Words,
+            salt: "t".into(),
+            keep_trailer: false,
+        };
+        let out = s.scrub(&buf, &docs);
+        assert_eq!(out.len(), buf.len());
+        let back = parse_otl(&out, "utf8").unwrap();
+        assert_eq!(rec_levels_capped(&back), rec_levels_capped(recs));
+        assert_eq!(back[0].len_text, recs[0].len_text);
+        assert_eq!(back[0].text.len(), "Call Bob".len());
+        assert!(back[0].text.chars().next().unwrap().is_ascii_uppercase());
+        assert!(!back[0].text.contains("Call") && !back[0].text.contains("Bob"));
+        let note = back[1].note.as_deref().unwrap();
+        assert!(note.starts_with(&back[0].text[5..])); // same word, same replacement
+        assert!(note.contains(" 000-0000\r\n"));
+        assert!(note.contains("é"));
+
+        let p = Scrubber {
+            mode: Mode::Placeholder,
+            salt: String::new(),
+            keep_trailer: false,
+        };
+        let back = parse_otl(&p.scrub(&buf, &docs), "latin1").unwrap();
+        assert_eq!(&*back[0].text, "Xxxx Xxx");
+    }
+
+    #[test]
+    fn scrub_covers_every_document_and_the_framing() {
+        let doc = |text: &str| {
+            let mut d = otl_file(vec![rec_bytes(text, 0x00, M_EXPANDED, 0, None)]);
+            d.push(0x1a);
+            d
+        };
+        let buf = [
+            &b"\xef\xbb\xbfJunk\r\n"[..],
+            &doc("Alice"),
+            &doc("Bob Smith"),
+            b"SECRET PASSWORD",
+        ]
+        .concat();
+        let docs = parse_stream(&buf, "latin1").unwrap();
+        assert_eq!(docs.len(), 2);
+        let mut s = Scrubber {
+            mode: Mode::Placeholder,
+            salt: String::new(),
+            keep_trailer: false,
+        };
+        let out = s.scrub(&buf, &docs);
+        assert_eq!(out.len(), buf.len());
+        assert!(out.starts_with(b"\xef\xbb\xbf\0\0\0\0\r\n"));
+        assert!(out.ends_with(b"\0\0\0\0\0\0 \0\0\0\0\0\0\0\0"));
+        let back = parse_stream(&out, "latin1").unwrap();
+        let texts: Vec<&str> = back.iter().map(|d| &*d.recs[0].text).collect();
+        assert_eq!(texts, ["Xxxxx", "Xxx Xxxxx"]);
+
+        s.keep_trailer = true;
+        assert!(s.scrub(&buf, &docs).ends_with(b"SECRET PASSWORD"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Ranked text search over headings (and notes): what `grep`, `--fuzzy`
+//! and `Corpus::grep` use.
+//!
+//! Matching ignores case. A plain substring scores 1. With `--fuzzy`, two
+//! approximate forms also count, for typos and damaged bytes in old files:
+//! - edit distance: the best-matching stretch of the text is at most
+//!   `query length / 4` (at least 1) edits away; scores 0.9 down to 0.7
+//! - subsequence: the query's letters appear in order ("kchrmdl" finds
+//!   "Kitchen remodel"); scores at most 0.5, less the more spread out
+//!
+//! `select --fuzzy` uses the same score for a `--path` that matches no
+//! heading path exactly.
+
+use crate::Rec;
+
+/// Fewest edits turning `q` into some substring of `t` (Sellers).
+pub fn substring_distance(q: &[char], t: &[char]) -> usize {
+    let mut col: Vec<usize> = (0..=q.len()).collect();
+    let mut best = col[q.len()];
+    for &c in t {
+        let mut diag = col[0]; // stays 0: a match may start anywhere
+        for i in 1..=q.len() {
+            let up = col[i];
+            col[i] = (diag + usize::from(q[i - 1] != c))
+                .min(up + 1)
+                .min(col[i - 1] + 1);
+            diag = up;
+        }
+        best = best.min(col[q.len()]);
+    }
+    best
+}
+
+/// Length of the shortest stretch of `t` holding `q` as a subsequence.
+fn subsequence_span(q: &[char], t: &[char]) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for start in (0..t.len()).filter(|&s| Some(&t[s]) == q.first()) {
+        let mut qi = 0;
+        for (j, &c) in t[start..].iter().enumerate() {
+            if c == q[qi] {
+                qi += 1;
+                if qi == q.len() {
+                    best = Some(best.map_or(j + 1, |b| b.min(j + 1)));
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
+/// How well `query` matches `text`, 0 < score <= 1, or None.
+pub fn score(query: &str, text: &str, fuzzy: bool) -> Option<f64> {
+    let (q, t) = (query.to_lowercase(), text.to_lowercase());
+    if q.is_empty() {
+        return None;
+    }
+    if t.contains(&q) {
+        return Some(1.0);
+    }
+    if !fuzzy {
+        return None;
+    }
+    let q: Vec<char> = q.chars().collect();
+    let t: Vec<char> = t.chars().collect();
+    let max_edits = (q.len() / 4).max(1);
+    let d = substring_distance(&q, &t);
+    let edit = (d <= max_edits && d < q.len()).then(|| 0.9 - 0.2 * d as f64 / max_edits as f64);
+    let letters: Vec<char> = q.iter().copied().filter(|c| !c.is_whitespace()).collect();
+    let subseq = subsequence_span(&letters, &t)
+        .filter(|_| letters.len() >= 3)
+        .map(|span| 0.5 * letters.len() as f64 / span as f64);
+    match (edit, subseq) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub rec: usize,
+    pub score: f64,
+    pub in_note: bool, // matched the note rather than the heading
+}
+
+/// Records matching `query`, best first (record order among equals).
+pub fn search(recs: &[Rec], query: &str, notes: bool, fuzzy: bool) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (i, r) in recs.iter().enumerate() {
+        let head = score(query, &r.text, fuzzy);
+        let note = match (&r.note, notes && r.note_bin.is_none()) {
+            (Some(n), true) => score(query, n, fuzzy),
+            _ => None,
+        };
+        let hit = match (head, note) {
+            (Some(h), Some(n)) if n > h => Some((n, true)),
+            (Some(h), _) => Some((h, false)),
+            (None, Some(n)) => Some((n, true)),
+            (None, None) => None,
+        };
+        if let Some((score, in_note)) = hit {
+            hits.push(Hit {
+                rec: i,
+                score,
+                in_note,
+            });
+        }
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.rec.cmp(&b.rec)));
+    hits
+}
+
+/// Records whose heading path (`paths`, from heading_paths) best matches
+/// `path` (all ties), for a path that matches nothing exactly.
+pub fn best_paths(paths: &[String], path: &str) -> Vec<usize> {
+    let scored: Vec<(usize, f64)> = paths
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| score(path, p, true).map(|s| (i, s)))
+        .collect();
+    let best = scored.iter().map(|&(_, s)| s).fold(0.0, f64::max);
+    scored
+        .into_iter()
+        .filter(|&(_, s)| s == best)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::heading_paths;
+    use crate::{A_NOTE, A_SIBFOLLOWS};
+
+    #[test]
+    fn scores_rank_exact_over_typo_over_subsequence() {
+        assert_eq!(score("REMODEL", "Kitchen remodel", false), Some(1.0));
+        assert_eq!(score("remodle", "Kitchen remodel", false), None);
+        let typo = score("remodle", "Kitchen remodel", true).unwrap();
+        let subseq = score("kchrmdl", "Kitchen remodel", true).unwrap();
+        assert!(1.0 > typo && typo > subseq && subseq > 0.0);
+        assert_eq!(score("budget", "Kitchen remodel", true), None);
+        assert_eq!(
+            substring_distance(&['a', 'b', 'c'], &['x', 'a', 'c', 'y']),
+            1
+        );
+    }
+
+    #[test]
+    fn search_covers_notes_and_paths() {
+        let recs = vec![
+            Rec::new("Projects".into(), A_SIBFOLLOWS, 0xFFFF, 0, None),
+            Rec::new("Kitchen remodel".into(), 0x00, 0xFFFF, 1, None),
+            Rec::new(
+                "Misc".into(),
+                A_NOTE,
+                0xFFFF,
+                -1,
+                Some("call about the kitchn tiles".into()),
+            ),
+        ];
+        let hits = search(&recs, "kitchen", true, true);
+        assert_eq!(hits.iter().map(|h| h.rec).collect::<Vec<_>>(), [1, 2]);
+        assert!(hits[1].in_note);
+        assert!(search(&recs, "kitchen", false, true).len() == 1);
+        let paths = heading_paths(&recs);
+        assert_eq!(best_paths(&paths, "Projcts/Kitchen remodel"), [1]);
+    }
+}
@@ -0,0 +1,123 @@
+//! `select` subcommand: print the subtree(s) at an address.
+//!
+//! With `--fuzzy`, a `--path` that matches no heading path exactly selects
+//! the closest one(s) instead (see search.rs), named on stderr.
+
+use crate::addr::{subtree_recs, Addr};
+use crate::heading_paths_capped;
+use crate::opts::{JsonOpts, Opts};
+use crate::search::best_paths;
+use crate::shape::Shape;
+use crate::style::TextStyle;
+use crate::usage;
+use crate::{
+    build_tree_capped, parse_otl, read_input, render_canon, render_indented, render_plain_all, Rec,
+};
+use std::io;
+
+// Records `addr` selects; with `fuzzy`, a path matching nothing exactly
+// falls back to the closest heading paths.
+fn select_hits(recs: &[Rec], addr: &Addr, fuzzy: bool) -> io::Result<Vec<usize>> {
+    match (addr.resolve(recs), addr) {
+        (Err(e), Addr::Path(p)) if fuzzy => {
+            let paths = heading_paths_capped(recs);
+            let hits = best_paths(&paths, p);
+            if hits.is_empty() {
+                return Err(e);
+            }
+            for &i in &hits {
+                eprintln!("NOTE: no exact match; using {:?} (#{i:03})", paths[i]);
+            }
+            Ok(hits)
+        }
+        (r, _) => r,
+    }
+}
+
+pub fn cmd_select(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut addr: Option<Addr> = None;
+    let mut enc = "latin1";
+    let mut mode = "";
+    let mut fuzzy = false;
+    let mut shape = Shape::default();
+    let mut style = TextStyle::default();
+    let mut opts = Opts::default();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = opts.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        if let Some(r) = Addr::from_flag(a, &mut it) {
+            addr = Some(r?);
+            continue;
+        }
+        if let Some(r) = shape.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        if let Some(r) = style.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        match a.as_str() {
+            "--json" | "--text" | "--canon" => mode = a,
+            "--show-cursor" => style.cursor = true,
+            "--fuzzy" => fuzzy = true,
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let (Some(file), Some(addr)) = (file, addr) else {
+        usage(prog)
+    };
+
+    let json = JsonOpts::from_opts(&opts)?;
+    let buf = read_input(file)?;
+    let recs = parse_otl(&buf, enc)?;
+    for i in select_hits(&recs, &addr, fuzzy)? {
+        let sub = subtree_recs(&recs, i);
+        let tree = || shape.apply(build_tree_capped(&sub));
+        match mode {
+            "--canon" => print!("{}", render_canon(&sub, style.cursor)),
+            "--json" => println!("{}", json.render(&tree())),
+            "--text" => print!("{}", render_plain_all(&tree(), 0, &style)),
+            _ => print!("{}", render_indented(&tree(), 0, &style)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn paths_select_exactly_then_fuzzily() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Kitchen", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Tiles", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Garden", 0x00, M_EXPANDED, -1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let path = |p: &str| Addr::Path(p.to_string());
+        assert_eq!(
+            select_hits(&recs, &path("Kitchen/Tiles"), false).unwrap(),
+            [1]
+        );
+        assert!(select_hits(&recs, &path("Kitchn/Tiles"), false).is_err());
+        assert_eq!(
+            select_hits(&recs, &path("Kitchn/Tiles"), true).unwrap(),
+            [1]
+        );
+        assert!(select_hits(&recs, &path("zzzz/qqqq"), true).is_err());
+        assert_eq!(subtree_recs(&recs, 0).len(), 2);
+    }
+}
@@ -0,0 +1,329 @@
+//! `serve` subcommand: a read-only HTTP view of a directory of outlines.
+//!
+//! `GET /` lists the .OTL files below the directory; `/view/<path>` serves
+//! one as HTML (see markup.rs) and `/json/<path>` as the `--json` tree.
+//! Files are read on every request but parsed only when their content
+//! changed (see cache.rs), so edits show up on reload. One request at a
+//! time; only GET/HEAD; paths with `..` or outside the directory are
+//! refused.
+
+use crate::cache::ParseCache;
+use crate::markup::{html_escape, render_html};
+use crate::opts::HtmlOpts;
+use crate::slug::SlugStyle;
+use crate::{collect_otl_files, read_input, usage, xref};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const MAX_HEAD: usize = 8192;
+
+/// How long a connection may sit idle (browsers open spare ones) before it
+/// is dropped; requests are served one at a time, so it holds up the rest.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Server {
+    root: PathBuf,
+    enc: String,
+    cache: ParseCache,
+    timeout: Duration,
+}
+
+/// (status, content type, body)
+pub type Response = (u16, &'static str, Vec<u8>);
+
+fn text(status: u16, msg: &str) -> Response {
+    (
+        status,
+        "text/plain; charset=utf-8",
+        format!("{msg}\n").into_bytes(),
+    )
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'%' {
+            let hex = std::str::from_utf8(b.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(b[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+impl Server {
+    pub fn new(root: PathBuf, enc: &str) -> Server {
+        Server {
+            root,
+            enc: enc.to_string(),
+            cache: ParseCache::default(),
+            timeout: IO_TIMEOUT,
+        }
+    }
+
+    // Relative '/'-separated paths of the outlines, sorted.
+    fn listing(&self) -> io::Result<Vec<String>> {
+        Ok(collect_otl_files(&self.root)?
+            .iter()
+            .map(|p| {
+                let rel = p.strip_prefix(&self.root).unwrap_or(p);
+                rel.components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect())
+    }
+
+    // The file a request path names, if it is an outline below the root.
+    fn resolve(&self, rel: &str) -> Option<PathBuf> {
+        let rel = percent_decode(rel)?;
+        let mut path = self.root.clone();
+        for part in rel.split('/') {
+            if part.is_empty() || part == "." || part == ".." || part.contains('\\') {
+                return None;
+            }
+            path.push(part);
+        }
+        let is_otl = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("otl"));
+        (is_otl && path.is_file()).then_some(path)
+    }
+
+    fn index(&self) -> io::Result<Response> {
+        let mut body = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+             </head>\n<body>\n<h1>{0}</h1>\n<ul>\n",
+            html_escape(&self.root.display().to_string())
+        );
+        for rel in self.listing()? {
+            let url = percent_encode(&rel);
+            body.push_str(&format!(
+                "  <li><a href=\"/view/{url}\">{}</a> <a href=\"/json/{url}\">[json]</a></li>\n",
+                html_escape(&rel)
+            ));
+        }
+        body.push_str("</ul>\n</body>\n</html>\n");
+        Ok((200, "text/html; charset=utf-8", body.into_bytes()))
+    }
+
+    /// The response to `GET target`.
+    pub fn respond(&mut self, target: &str) -> Response {
+        let target = target.split(['?', '#']).next().unwrap_or("");
+        let result = if target == "/" {
+            self.index()
+        } else if let Some((kind, rel)) = target
+            .strip_prefix("/view/")
+            .map(|r| ("view", r))
+            .or_else(|| target.strip_prefix("/json/").map(|r| ("json", r)))
+        {
+            let Some(path) = self.resolve(rel) else {
+                return text(404, "no such outline");
+            };
+            let title = rel.to_string();
+            let enc = self.enc.clone();
+            read_input(&path.to_string_lossy())
+                .and_then(|buf| self.cache.tree(&buf, &enc))
+                .map(|(doc, tree)| {
+                    if kind == "json" {
+                        let json = serde_json::to_string_pretty(&*tree).unwrap_or_default();
+                        (200, "application/json", json.into_bytes())
+                    } else {
+                        let idx = xref::Index::new(&doc.recs, SlugStyle::default());
+                        let page = render_html(
+                            &tree,
+                            &idx,
+                            &percent_decode(&title).unwrap_or(title),
+                            &HtmlOpts::default(),
+                        )
+                        .0;
+                        (200, "text/html; charset=utf-8", page.into_bytes())
+                    }
+                })
+        } else {
+            return text(404, "not found");
+        };
+        result.unwrap_or_else(|e| text(500, &e.to_string()))
+    }
+
+    fn handle(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // skip the headers
+        let mut head = request_line.len();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            head += line.len();
+            if head > MAX_HEAD {
+                break;
+            }
+        }
+        let parts: Vec<&str> = request_line.split_whitespace().collect();
+        let (status, ctype, body) = match parts.as_slice() {
+            [m @ ("GET" | "HEAD"), target, ..] => {
+                let (s, c, b) = self.respond(target);
+                (s, c, if *m == "HEAD" { Vec::new() } else { b })
+            }
+            [_, _, ..] => text(405, "read-only: GET and HEAD only"),
+            _ => text(400, "bad request"),
+        };
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: {ctype}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        stream.flush()
+    }
+}
+
+pub fn cmd_serve(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut dir: Option<&str> = None;
+    let mut port: u16 = 8080;
+    let mut bind = "127.0.0.1";
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--port" => {
+                let v = it.next().unwrap_or_else(|| usage(prog));
+                port = v.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--port: expected a port number, got {v:?}"),
+                    )
+                })?;
+            }
+            "--bind" => bind = it.next().unwrap_or_else(|| usage(prog)),
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            _ if dir.is_none() => dir = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let Some(dir) = dir else { usage(prog) };
+    let root = PathBuf::from(dir);
+    if !root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{dir}: not a directory"),
+        ));
+    }
+    let listener = TcpListener::bind((bind, port))?;
+    eprintln!("serving {dir} on http://{}/", listener.local_addr()?);
+    let mut server = Server::new(root, enc);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => {
+                match server.handle(s) {
+                    // an idle connection, dropped
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) => {}
+                    Err(e) => eprintln!("request failed: {e}"),
+                    Ok(()) => {}
+                }
+            }
+            Err(e) => eprintln!("accept failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::M_EXPANDED;
+
+    #[test]
+    fn lists_renders_reparses_and_refuses_escapes() {
+        let dir = std::env::temp_dir().join(format!("otl-serve-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub dir")).unwrap();
+        let file = dir.join("sub dir").join("a.OTL");
+        std::fs::write(
+            &file,
+            otl_file(vec![rec_bytes("Alpha", 0x00, M_EXPANDED, 0, None)]),
+        )
+        .unwrap();
+        let mut s = Server::new(dir.clone(), "latin1");
+
+        let (status, _, body) = s.respond("/");
+        assert_eq!(status, 200);
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .contains("href=\"/view/sub%20dir/a.OTL\""));
+        let (status, ctype, body) = s.respond("/view/sub%20dir/a.OTL");
+        assert_eq!((status, ctype), (200, "text/html; charset=utf-8"));
+        assert!(String::from_utf8(body).unwrap().contains(">Alpha</li>"));
+
+        // new content is parsed afresh
+        std::fs::write(
+            &file,
+            otl_file(vec![rec_bytes("Alphabet", 0x00, M_EXPANDED, 0, None)]),
+        )
+        .unwrap();
+        let (_, ctype, body) = s.respond("/json/sub%20dir/a.OTL");
+        assert_eq!(ctype, "application/json");
+        assert!(String::from_utf8(body).unwrap().contains("\"Alphabet\""));
+
+        assert_eq!(s.respond("/view/../etc/passwd").0, 404);
+        assert_eq!(s.respond("/view/sub%20dir/%2e%2e/x.OTL").0, 404);
+        assert_eq!(s.respond("/nope").0, 404);
+
+        // an idle connection times out instead of blocking the next one
+        s.timeout = Duration::from_millis(50);
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _idle = TcpStream::connect(addr).unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        let e = s.handle(conn).unwrap_err();
+        assert!(matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /nope HTTP/1.1\r\n\r\n").unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        s.handle(conn).unwrap();
+        let mut reply = String::new();
+        io::Read::read_to_string(&mut client, &mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 404"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
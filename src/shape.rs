@@ -0,0 +1,246 @@
+//! Export-time tree shaping: `--prune-empty`, `--fillers` and `--notes`.
+//!
+//! Filler nodes are the synthetic parents `build_tree_capped` inserts for level
+//! jumps (delta > +1). Without shaping, the text renderers splice them away
+//! while `--json` shows them as blank headings; `--fillers` picks one
+//! treatment for every export. `--show-fillers` is `--fillers placeholder`
+//! with a placeholder that cannot be mistaken for a heading. JSON marks
+//! fillers (kept or placeholder) with `"synthetic": true`.
+
+use crate::Node;
+use std::io;
+
+pub const DEFAULT_PLACEHOLDER: &str = "...";
+pub const FILLER_MARK: &str = "\u{27e8}missing level\u{27e9}";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fillers {
+    /// Splice the filler's children into its parent (they move up a level).
+    Merge,
+    /// Turn the filler into a heading with this text.
+    Placeholder(String),
+    /// Keep the filler as a blank heading.
+    Keep,
+}
+
+impl Fillers {
+    pub fn parse(s: &str) -> Option<Fillers> {
+        match s {
+            "merge" => Some(Fillers::Merge),
+            "keep" => Some(Fillers::Keep),
+            "placeholder" => Some(Fillers::Placeholder(DEFAULT_PLACEHOLDER.to_string())),
+            _ => s
+                .strip_prefix("placeholder=")
+                .map(|t| Fillers::Placeholder(t.to_string())),
+        }
+    }
+}
+
+/// How much of each note to export. Cut notes end in " ...".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notes {
+    Full,
+    Hide,
+    FirstLine,
+    Truncate(usize), // chars
+}
+
+impl Notes {
+    pub fn parse(s: &str) -> Option<Notes> {
+        match s {
+            "full" => Some(Notes::Full),
+            "hide" => Some(Notes::Hide),
+            "first-line" => Some(Notes::FirstLine),
+            _ => s
+                .strip_prefix("truncate=")?
+                .parse()
+                .ok()
+                .map(Notes::Truncate),
+        }
+    }
+
+    pub fn apply(self, note: &str) -> Option<String> {
+        let cut = |s: &str| format!("{} ...", s.trim_end());
+        match self {
+            Notes::Full => Some(note.to_string()),
+            Notes::Hide => None,
+            Notes::FirstLine => {
+                let first = note.lines().next().unwrap_or("");
+                if first.len() < note.trim_end().len() {
+                    Some(cut(first))
+                } else {
+                    Some(first.to_string())
+                }
+            }
+            Notes::Truncate(n) => match note.char_indices().nth(n) {
+                Some((i, _)) => Some(cut(&note[..i])),
+                None => Some(note.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub prune_empty: bool,
+    pub fillers: Fillers,
+    pub notes: Notes,
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape {
+            prune_empty: false,
+            fillers: Fillers::Merge,
+            notes: Notes::Full,
+        }
+    }
+}
+
+pub const SHAPE_USAGE: &str =
+    "[--prune-empty] [--fillers merge|keep|placeholder[=TEXT] | --show-fillers] \
+     [--notes full|hide|first-line|truncate=N]";
+
+impl Shape {
+    /// Consume `a` (and its value from `it`) if it is a shaping flag.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        match a {
+            "--prune-empty" => self.prune_empty = true,
+            "--show-fillers" => self.fillers = Fillers::Placeholder(FILLER_MARK.to_string()),
+            "--fillers" => {
+                let v = it.next().map(|s| s.as_str()).unwrap_or("");
+                match Fillers::parse(v) {
+                    Some(f) => self.fillers = f,
+                    None => {
+                        return Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "--fillers: expected merge, keep or placeholder[=TEXT], got {v:?}"
+                            ),
+                        )))
+                    }
+                }
+            }
+            "--notes" => {
+                let v = it.next().map(|s| s.as_str()).unwrap_or("");
+                match Notes::parse(v) {
+                    Some(n) => self.notes = n,
+                    None => {
+                        return Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "--notes: expected full, hide, first-line or truncate=N, got {v:?}"
+                            ),
+                        )))
+                    }
+                }
+            }
+            _ => return None,
+        }
+        Some(Ok(()))
+    }
+
+    pub fn apply(&self, nodes: Vec<Node>) -> Vec<Node> {
+        let mut out = Vec::with_capacity(nodes.len());
+        for mut n in nodes {
+            n.children = self.apply(std::mem::take(&mut n.children));
+            // a heading is empty when it has no text, no note and (after
+            // pruning below it) no children
+            if self.prune_empty
+                && n.text.trim().is_empty()
+                && n.note.as_deref().unwrap_or("").is_empty()
+                && n.children.is_empty()
+            {
+                continue;
+            }
+            n.note = n.note.and_then(|t| self.notes.apply(&t));
+            if n.synthetic {
+                match &self.fillers {
+                    Fillers::Merge => {
+                        out.extend(n.children);
+                        continue;
+                    }
+                    Fillers::Placeholder(t) => n.text = t.as_str().into(),
+                    Fillers::Keep => {}
+                }
+                n.synthetic = false;
+            }
+            out.push(n);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, render_plain_all, M_EXPANDED};
+
+    fn tree() -> Vec<Node> {
+        // A, then B two levels down (filler between), then an empty heading
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", 0x00, M_EXPANDED, 2, None),
+            rec_bytes("", 0x00, M_EXPANDED, -2, None),
+        ]);
+        build_tree_capped(&parse_otl(&buf, "latin1").unwrap())
+    }
+
+    fn render(shape: Shape) -> String {
+        render_plain_all(&shape.apply(tree()), 0, &Default::default())
+    }
+
+    #[test]
+    fn filler_modes() {
+        assert_eq!(render(Shape::default()), "A\n  B\n\n");
+        let keep = Shape {
+            fillers: Fillers::Keep,
+            ..Shape::default()
+        };
+        assert_eq!(render(keep), "A\n  \n    B\n\n");
+        let ph = Shape {
+            fillers: Fillers::parse("placeholder=(gap)").unwrap(),
+            ..Shape::default()
+        };
+        assert_eq!(render(ph), "A\n  (gap)\n    B\n\n");
+
+        let mut show = Shape::default();
+        let args = [String::new()];
+        show.take_flag("--show-fillers", &mut args.iter())
+            .unwrap()
+            .unwrap();
+        let shown = show.apply(tree());
+        assert_eq!(&*shown[0].children[0].text, FILLER_MARK);
+        let json = serde_json::to_value(&shown).unwrap();
+        assert_eq!(json[0]["children"][0]["synthetic"], true);
+        assert!(json[0].get("synthetic").is_none());
+    }
+
+    #[test]
+    fn prune_empty_drops_blank_leaves() {
+        let prune = Shape {
+            prune_empty: true,
+            fillers: Fillers::Keep,
+            ..Shape::default()
+        };
+        assert_eq!(render(prune), "A\n  \n    B\n");
+    }
+
+    #[test]
+    fn note_modes() {
+        let note = "first line\r\nsecond line\r\n";
+        let get = |m: &str| Notes::parse(m).unwrap().apply(note);
+        assert_eq!(get("full").as_deref(), Some(note));
+        assert_eq!(get("hide"), None);
+        assert_eq!(get("first-line").as_deref(), Some("first line ..."));
+        assert_eq!(get("truncate=5").as_deref(), Some("first ..."));
+        assert_eq!(get("truncate=500").as_deref(), Some(note));
+        assert_eq!(Notes::FirstLine.apply("only\r\n").as_deref(), Some("only"));
+        assert!(Notes::parse("truncate=x").is_none());
+    }
+}
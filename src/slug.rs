@@ -0,0 +1,151 @@
+//! Heading slugs: the one place anchors and slug-based file names come from,
+//! so links agree across export formats.
+//!
+//! Slugs are assigned in document order and made unique by suffixing `-1`,
+//! `-2`, ... to repeats (GitHub's rule), so the same file always yields the
+//! same slugs.
+
+use crate::{rec_levels_capped, Rec};
+use std::collections::HashSet;
+use std::io;
+
+pub const SLUG_USAGE: &str = "[--slugs github|kebab|numeric]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SlugStyle {
+    /// GitHub heading ids: lowercase, punctuation dropped, spaces to `-`.
+    #[default]
+    Github,
+    /// Lowercase alphanumeric runs joined by single `-`.
+    Kebab,
+    /// Outline number, e.g. `2-1-3` for the third child of 2.1.
+    Numeric,
+}
+
+impl SlugStyle {
+    pub fn parse(s: &str) -> io::Result<SlugStyle> {
+        match s {
+            "github" => Ok(SlugStyle::Github),
+            "kebab" => Ok(SlugStyle::Kebab),
+            "numeric" => Ok(SlugStyle::Numeric),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--slugs: expected github, kebab or numeric, got {s:?}"),
+            )),
+        }
+    }
+}
+
+/// The slug of one heading text (not unique; see `Slugger`). Numeric
+/// slugs depend on position, so here they fall back to kebab.
+pub fn slug(style: SlugStyle, text: &str) -> String {
+    let s: String = match style {
+        SlugStyle::Github => text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+                _ => None,
+            })
+            .collect(),
+        SlugStyle::Kebab | SlugStyle::Numeric => text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>()
+            .join("-"),
+    };
+    if s.is_empty() {
+        "section".to_string()
+    } else {
+        s
+    }
+}
+
+/// Hands out unique slugs.
+#[derive(Debug, Default)]
+pub struct Slugger {
+    used: HashSet<String>,
+}
+
+impl Slugger {
+    pub fn unique(&mut self, base: String) -> String {
+        let mut s = base.clone();
+        let mut n = 0;
+        while !self.used.insert(s.clone()) {
+            n += 1;
+            s = format!("{base}-{n}");
+        }
+        s
+    }
+}
+
+/// One unique slug per record, in document order.
+pub fn rec_slugs(recs: &[Rec], style: SlugStyle) -> Vec<String> {
+    let mut slugger = Slugger::default();
+    let mut numbers: Vec<usize> = Vec::new();
+    let levels = rec_levels_capped(recs);
+    recs.iter()
+        .zip(levels)
+        .map(|(r, lvl)| {
+            let base = match style {
+                SlugStyle::Numeric => {
+                    numbers.resize(lvl + 1, 0);
+                    numbers[lvl] += 1;
+                    numbers
+                        .iter()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<_>>()
+                        .join("-")
+                }
+                _ => slug(style, &r.text),
+            };
+            slugger.unique(base)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn styles() {
+        assert_eq!(
+            slug(SlugStyle::Github, "Q3: Plan & Budget!"),
+            "q3-plan--budget"
+        );
+        assert_eq!(
+            slug(SlugStyle::Kebab, "Q3: Plan & Budget!"),
+            "q3-plan-budget"
+        );
+        assert_eq!(slug(SlugStyle::Kebab, "Café Müller"), "café-müller");
+        assert_eq!(slug(SlugStyle::Github, "***"), "section");
+    }
+
+    #[test]
+    fn record_slugs_are_unique_and_numbered_by_position() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Notes", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Notes", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Notes", 0x00, M_EXPANDED, -1, None),
+                rec_bytes("x", 0x00, M_EXPANDED, 2, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        assert_eq!(
+            rec_slugs(&recs, SlugStyle::Github),
+            ["notes", "notes-1", "notes-2", "x"]
+        );
+        assert_eq!(
+            rec_slugs(&recs, SlugStyle::Numeric),
+            ["1", "1-1", "2", "2-0-1"]
+        );
+    }
+}
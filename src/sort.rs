@@ -0,0 +1,403 @@
+//! `sort` subcommand: reorder sibling headings by a key.
+//!
+//! `--key text` compares headings case-insensitively; `--key numeric` and
+//! `--key date` use a number or date at the start of the heading, so journal
+//! style outlines come out chronological. `--key note` compares the first
+//! non-blank line of each note (decoded per `--enc`), for contact outlines
+//! that keep the full name there. Records without a key sort after those
+//! with one; ties keep their original order. `--collate <locale>` makes
+//! `--key text` and `--key note` language-aware (see collate.rs).
+
+use crate::addr::{subtree_end_at, Addr};
+use crate::collate::Collator;
+use crate::journal::write_in_place;
+use crate::ops::{children_at, prepare_write, restructure, JumpFix};
+use crate::write::{compress_headings, Preserve};
+use crate::{parse_doc, read_input, rec_levels_capped, usage, write_output, Doc, Rec};
+use std::cmp::Ordering;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Text,
+    Collated(Collator),
+    Note,
+    CollatedNote(Collator),
+    Numeric,
+    Date,
+}
+
+// First non-blank line of the note, trimmed.
+fn note_line(r: &Rec) -> Option<&str> {
+    let note = r.note.as_deref()?;
+    Some(
+        note.lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .unwrap_or(""),
+    )
+}
+
+/// Leading number: optional sign, digits, optional fraction.
+pub fn leading_number(s: &str) -> Option<f64> {
+    let s = s.trim_start();
+    let mut end = 0;
+    for (i, ch) in s.char_indices() {
+        let ok = ch.is_ascii_digit()
+            || (ch == '.' && !s[..i].contains('.'))
+            || (i == 0 && (ch == '-' || ch == '+'));
+        if !ok {
+            break;
+        }
+        end = i + ch.len_utf8();
+    }
+    s[..end].trim_end_matches('.').parse().ok()
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+fn month_from_name(w: &str) -> Option<u32> {
+    let w = w.to_ascii_lowercase();
+    if w.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|m| w.starts_with(m))
+        .map(|i| i as u32 + 1)
+}
+
+fn valid(y: u32, m: u32, d: u32) -> Option<(u32, u32, u32)> {
+    ((1..=12).contains(&m) && (1..=31).contains(&d)).then_some((y, m, d))
+}
+
+fn full_year(y: u32, digits: usize) -> u32 {
+    match digits {
+        2 if y < 70 => 2000 + y,
+        2 => 1900 + y,
+        _ => y,
+    }
+}
+
+/// Leading date as (year, month, day). Accepts YYYY-MM-DD (also with '/' or
+/// '.'), YYYYMMDD, MM/DD/YY[YY], DD.MM.YY[YY], "Jan 5, 1990", "5 Jan 1990".
+pub fn leading_date(s: &str) -> Option<(u32, u32, u32)> {
+    let s = s.trim_start();
+    let words: Vec<&str> = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .take(3)
+        .collect();
+    let first = *words.first()?;
+
+    // numeric forms in the first word
+    let digits: String = first.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() == 8 {
+        let n: u32 = digits.parse().ok()?;
+        return valid(n / 10000, n / 100 % 100, n % 100);
+    }
+    for sep in ['-', '/', '.'] {
+        let parts: Vec<&str> = first.split(sep).collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        let num = |p: &str| -> Option<(u32, usize)> {
+            let d: String = p.chars().take_while(|c| c.is_ascii_digit()).collect();
+            Some((d.parse().ok()?, d.len()))
+        };
+        let ((a, al), (b, _), (c, cl)) = (num(parts[0])?, num(parts[1])?, num(parts[2])?);
+        return match (al, sep) {
+            (4, _) => valid(a, b, c),
+            (_, '.') => valid(full_year(c, cl), b, a),
+            _ => valid(full_year(c, cl), a, b),
+        };
+    }
+
+    // month-name forms
+    let num = |w: &str| w.trim_end_matches(['.', ',']).parse::<u32>().ok();
+    if words.len() == 3 {
+        if let (Some(m), Some(d), Some(y)) =
+            (month_from_name(words[0]), num(words[1]), num(words[2]))
+        {
+            return valid(full_year(y, words[2].len()), m, d);
+        }
+        if let (Some(d), Some(m), Some(y)) =
+            (num(words[0]), month_from_name(words[1]), num(words[2]))
+        {
+            return valid(full_year(y, words[2].len()), m, d);
+        }
+    }
+    None
+}
+
+fn compare(a: &Rec, b: &Rec, key: &Key) -> Ordering {
+    // None sorts after Some
+    fn opt<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    match key {
+        Key::Text => a.text.to_lowercase().cmp(&b.text.to_lowercase()),
+        Key::Collated(c) => c.compare(&a.text, &b.text),
+        Key::Note => opt(
+            note_line(a).map(str::to_lowercase),
+            note_line(b).map(str::to_lowercase),
+        ),
+        Key::CollatedNote(c) => match (note_line(a), note_line(b)) {
+            (Some(x), Some(y)) => c.compare(x, y),
+            (x, y) => opt(x.map(|_| ()), y.map(|_| ())),
+        },
+        Key::Numeric => opt(leading_number(&a.text), leading_number(&b.text)),
+        Key::Date => opt(leading_date(&a.text), leading_date(&b.text)),
+    }
+}
+
+/// Sort the children of `parent` (top level for None); with `recursive`,
+/// every sibling group below it as well.
+pub fn sort_children(
+    recs: &mut Vec<Rec>,
+    parent: Option<usize>,
+    key: &Key,
+    reverse: bool,
+    recursive: bool,
+) {
+    let levels = rec_levels_capped(recs);
+    let end = parent.map_or(recs.len(), |p| subtree_end_at(&levels, p));
+    let start = parent.map_or(0, |p| p + 1);
+    // the new order of record indexes; levels travel with their records
+    let mut order: Vec<usize> = (0..start).collect();
+    sorted_block(recs, &levels, parent, key, reverse, recursive, &mut order);
+    order.extend(end..recs.len());
+    let new_levels: Vec<usize> = order.iter().map(|&i| levels[i]).collect();
+    let mut sorted: Vec<Rec> = order.iter().map(|&i| recs[i].clone()).collect();
+    restructure(&mut sorted, &new_levels);
+    *recs = sorted;
+}
+
+// Append the records below `parent` to `out`, child subtrees in sorted
+// order (and, with `recursive`, their children too).
+fn sorted_block(
+    recs: &[Rec],
+    levels: &[usize],
+    parent: Option<usize>,
+    key: &Key,
+    reverse: bool,
+    recursive: bool,
+    out: &mut Vec<usize>,
+) {
+    let kids = children_at(levels, parent);
+    let end = parent.map_or(recs.len(), |p| subtree_end_at(levels, p));
+    let mut order: Vec<usize> = (0..kids.len()).collect();
+    order.sort_by(|&x, &y| {
+        let o = compare(&recs[kids[x]], &recs[kids[y]], key);
+        if reverse {
+            o.reverse()
+        } else {
+            o
+        }
+    });
+    for k in order {
+        // a child's block runs to the next child (records below a level
+        // jump ride along with the child before them)
+        let (kid, next) = (kids[k], kids.get(k + 1).copied().unwrap_or(end));
+        if recursive {
+            out.push(kid);
+            sorted_block(recs, levels, Some(kid), key, reverse, true, out);
+            out.extend(subtree_end_at(levels, kid)..next);
+        } else {
+            out.extend(kid..next);
+        }
+    }
+}
+
+pub fn cmd_sort(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut addr: Option<Addr> = None;
+    let mut key = Key::Text;
+    let mut collate: Option<Collator> = None;
+    let mut reverse = false;
+    let mut enc = "latin1";
+    let mut recursive = false;
+    let mut out: Option<&str> = None;
+    let mut in_place = false;
+    let mut journal = false;
+    let mut compress = false;
+    let mut keep = Preserve::default();
+    let mut fix: Option<JumpFix> = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = keep.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        if let Some(r) = Addr::from_flag(a, &mut it) {
+            addr = Some(r?);
+            continue;
+        }
+        match a.as_str() {
+            "--key" => {
+                key = match it.next().map(|s| s.as_str()) {
+                    Some("text") => Key::Text,
+                    Some("numeric") => Key::Numeric,
+                    Some("date") => Key::Date,
+                    Some("note") => Key::Note,
+                    _ => usage(prog),
+                }
+            }
+            "--collate" => collate = Some(Collator::new(it.next().unwrap_or_else(|| usage(prog)))?),
+            "--reverse" => reverse = true,
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "--recursive" => recursive = true,
+            "--in-place" => in_place = true,
+            "--journal" => journal = true,
+            "--compress-headings" => compress = true,
+            "--strip-prefix" => keep.preamble = false,
+            "--fix-level-jumps" => {
+                fix = Some(JumpFix::parse(it.next().unwrap_or_else(|| usage(prog)))?)
+            }
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let out = match (out, in_place) {
+        (Some(o), false) => o,
+        (None, true) if file != "-" => file,
+        _ => usage(prog),
+    };
+    if journal && !in_place {
+        usage(prog);
+    }
+    if let Some(c) = collate {
+        key = match key {
+            Key::Text => Key::Collated(c),
+            Key::Note => Key::CollatedNote(c),
+            _ => usage(prog),
+        };
+    }
+
+    let buf = read_input(file)?;
+    let Doc { mut recs, framing } = parse_doc(&buf, enc)?;
+    let parent = match &addr {
+        Some(a) => {
+            let hits = a.resolve(&recs)?;
+            if hits.len() > 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{a} matches {} records; use --rec or --id", hits.len()),
+                ));
+            }
+            Some(hits[0])
+        }
+        None => None,
+    };
+    sort_children(&mut recs, parent, &key, reverse, recursive);
+    let mut recs = prepare_write(recs, fix.as_ref());
+    if compress {
+        compress_headings(&mut recs);
+    }
+    keep.apply(&mut recs);
+    let bytes = keep.serialize(&recs, &framing, enc)?;
+    if in_place {
+        let command = format!("sort {}", args.join(" "));
+        return write_in_place(file, &buf, &bytes, journal, &command);
+    }
+    write_output(out, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, rec_levels_capped, A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn leading_keys() {
+        assert_eq!(leading_date("1990-03-05 standup"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("19900305"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("3/5/90 call"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("05.03.1990"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("Mar 5, 1990"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("5 March 1990"), Some((1990, 3, 5)));
+        assert_eq!(leading_date("Notes"), None);
+        assert_eq!(leading_number("10. Ten"), Some(10.0));
+        assert_eq!(leading_number("-2.5x"), Some(-2.5));
+        assert_eq!(leading_number("x1"), None);
+    }
+
+    #[test]
+    fn sorts_siblings_with_subtrees() {
+        let buf = otl_file(vec![
+            rec_bytes("10 ten", 0x08, M_EXPANDED, 0, None),
+            rec_bytes("child of ten", 0x00, M_EXPANDED, 1, None),
+            rec_bytes("9 nine", 0x08, M_EXPANDED, -1, None),
+            rec_bytes("misc", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let mut recs = parse_otl(&buf, "latin1").unwrap();
+        sort_children(&mut recs, None, &Key::Numeric, false, false);
+        let texts: Vec<&str> = recs.iter().map(|r| &*r.text).collect();
+        assert_eq!(texts, ["9 nine", "10 ten", "child of ten", "misc"]);
+        assert_eq!(rec_levels_capped(&recs), [0, 0, 1, 0]);
+        let sib: Vec<bool> = recs.iter().map(|r| r.flags.has_next_sibling).collect();
+        assert_eq!(sib, [true, true, false, false]);
+    }
+
+    #[test]
+    fn note_key_collates_decoded_notes() {
+        let buf = otl_file(vec![
+            rec_bytes(
+                "c1",
+                A_NOTE | 0x08,
+                M_EXPANDED,
+                0,
+                Some(b"\r\n\xd6rn, Eva\r\nphone"),
+            ),
+            rec_bytes("c2", 0x08, M_EXPANDED, 0, None),
+            rec_bytes("c3", A_NOTE | 0x08, M_EXPANDED, 0, Some(b"Olle")),
+            rec_bytes("c4", A_NOTE, M_EXPANDED, 0, Some(b"\xc5sa")),
+        ]);
+        let texts = |key: Key| {
+            let mut recs = parse_otl(&buf, "latin1").unwrap();
+            sort_children(&mut recs, None, &key, false, false);
+            recs.iter().map(|r| r.text.to_string()).collect::<Vec<_>>()
+        };
+        // by code point Å (0xc5) < Ö (0xd6), both after ASCII
+        assert_eq!(texts(Key::Note), ["c3", "c4", "c1", "c2"]);
+        let sv = Collator::new("sv").unwrap();
+        assert_eq!(texts(Key::CollatedNote(sv)), ["c3", "c4", "c1", "c2"]);
+        let de = Collator::new("de").unwrap();
+        assert_eq!(texts(Key::CollatedNote(de)), ["c4", "c3", "c1", "c2"]);
+    }
+
+    #[test]
+    fn recursive_sort_orders_every_level() {
+        let buf = otl_file(vec![
+            rec_bytes("b", 0x08, M_EXPANDED, 0, None),
+            rec_bytes("y", 0x08, M_EXPANDED, 1, None),
+            rec_bytes("x", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("a", 0x08, M_EXPANDED, -1, None),
+            rec_bytes("d", 0x08, M_EXPANDED, 1, None),
+            rec_bytes("c", 0x00, M_EXPANDED, 0, None),
+        ]);
+        let mut recs = parse_otl(&buf, "latin1").unwrap();
+        sort_children(&mut recs, None, &Key::Text, false, true);
+        let texts: Vec<&str> = recs.iter().map(|r| &*r.text).collect();
+        assert_eq!(texts, ["a", "c", "d", "b", "x", "y"]);
+        assert_eq!(rec_levels_capped(&recs), [0, 1, 1, 0, 1, 1]);
+
+        // one pass over the levels, not one per child: big flat lists are quick
+        let flat: Vec<Vec<u8>> = (0..20_000)
+            .map(|i| rec_bytes(&format!("{}", 20_000 - i), 0x00, M_EXPANDED, 0, None))
+            .collect();
+        let mut recs = parse_otl(&otl_file(flat), "latin1").unwrap();
+        sort_children(&mut recs, None, &Key::Numeric, false, true);
+        assert_eq!(&*recs[0].text, "1");
+    }
+}
@@ -0,0 +1,86 @@
+//! `split-stream`: cut a buffer holding several concatenated .OTL documents
+//! (as recovered from backups or tape dumps) into one file per document.
+
+use crate::slug::{slug, SlugStyle, Slugger};
+use crate::{parse_stream, read_input, usage, write_output};
+use std::io;
+use std::path::Path;
+
+/// Byte ranges of each document in `buf`, without any prefix skipped before
+/// its MAGIC but with its EOF sentinel.
+pub fn split_stream(buf: &[u8]) -> io::Result<Vec<std::ops::Range<usize>>> {
+    Ok(parse_stream(buf, "latin1")?
+        .iter()
+        .map(|d| d.framing.start + d.framing.prefix.len()..d.framing.end)
+        .collect())
+}
+
+pub fn cmd_split_stream(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut out: Option<&str> = None;
+    let mut slugs: Option<SlugStyle> = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--slugs" => slugs = Some(SlugStyle::parse(it.next().unwrap_or_else(|| usage(prog)))?),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let prefix = match out {
+        Some(o) => o.to_string(),
+        None if file == "-" => "stream".to_string(),
+        None => Path::new(file)
+            .with_extension("")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let buf = read_input(file)?;
+    let docs = parse_stream(&buf, "latin1")?;
+    let mut slugger = Slugger::default();
+    for (n, range) in split_stream(&buf)?.into_iter().enumerate() {
+        // named by the document's first heading, if asked
+        let name = match (slugs, docs[n].recs.first()) {
+            (Some(s), Some(r)) if s != SlugStyle::Numeric => {
+                let base = slug(s, &r.text);
+                let name = slugger.unique(base.clone());
+                if name != base {
+                    eprintln!(
+                        "NOTE: document {} slug {base:?} already used; named {name:?}",
+                        n + 1
+                    );
+                }
+                name
+            }
+            _ => (n + 1).to_string(),
+        };
+        let path = format!("{prefix}.{name}.OTL");
+        println!("{path}: bytes {:#x}..{:#x}", range.start, range.end);
+        write_output(&path, &buf[range])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, M_EXPANDED};
+
+    #[test]
+    fn split_yields_standalone_documents() {
+        let one = otl_file(vec![rec_bytes("A", 0x00, M_EXPANDED, 0, None)]);
+        let two = otl_file(vec![rec_bytes("B", 0x00, M_EXPANDED, 0, None)]);
+        let buf = [&one[..], b"\x1a\xef\xbb\xbf", &two].concat();
+        let parts = split_stream(&buf).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(&buf[parts[0].clone()], &[&one[..], b"\x1a"].concat()[..]);
+        assert_eq!(&buf[parts[1].clone()], &two[..]);
+        assert_eq!(
+            &*parse_otl(&buf[parts[1].clone()], "latin1").unwrap()[0].text,
+            "B"
+        );
+    }
+}
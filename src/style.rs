@@ -0,0 +1,159 @@
+//! Indentation and glyph options shared by the text renderers
+//! (`--text` and the default indented view).
+
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyphs {
+    None,
+    Ascii,   // [+] [-] * >
+    Unicode, // ▸ ▾ • │
+}
+
+impl Glyphs {
+    pub fn fold(self, collapsed: bool) -> &'static str {
+        match (self, collapsed) {
+            (Glyphs::None, _) => "",
+            (Glyphs::Ascii, true) => "[+]",
+            (Glyphs::Ascii, false) => "[-]",
+            (Glyphs::Unicode, true) => "▸",
+            (Glyphs::Unicode, false) => "▾",
+        }
+    }
+
+    pub fn selected(self, selected: bool) -> &'static str {
+        match (self, selected) {
+            (Glyphs::None, _) => "",
+            (_, false) => " ",
+            (Glyphs::Ascii, true) => "*",
+            (Glyphs::Unicode, true) => "•",
+        }
+    }
+
+    /// Prefix of each note line.
+    pub fn note(self) -> &'static str {
+        match self {
+            Glyphs::None => "",
+            Glyphs::Ascii => "> ",
+            Glyphs::Unicode => "│ ",
+        }
+    }
+}
+
+/// Unset fields fall back to each renderer's own default
+/// (`--text`: 2 spaces, no glyphs; default view: 4 spaces, ASCII glyphs).
+#[derive(Debug, Clone, Default)]
+pub struct TextStyle {
+    pub indent: Option<usize>, // columns per level (tabs per level with `tab`)
+    pub tab: bool,
+    pub glyphs: Option<Glyphs>,
+    pub cursor: bool, // mark the selected heading (set from --show-cursor)
+}
+
+pub const STYLE_USAGE: &str =
+    "[--indent N] [--indent-char space|tab] [--glyphs none|ascii|unicode]";
+
+fn bad(flag: &str, v: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{flag}: unexpected value {v:?}"),
+    )
+}
+
+impl TextStyle {
+    /// Consume `a` (and its value from `it`) if it is a style flag.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        if !matches!(a, "--indent" | "--indent-char" | "--glyphs") {
+            return None;
+        }
+        let v = it.next().map(|s| s.as_str()).unwrap_or("");
+        match (a, v) {
+            ("--indent", _) => match v.parse() {
+                Ok(n) => self.indent = Some(n),
+                Err(_) => return Some(Err(bad(a, v))),
+            },
+            ("--indent-char", "space") => self.tab = false,
+            ("--indent-char", "tab") => self.tab = true,
+            ("--glyphs", "none") => self.glyphs = Some(Glyphs::None),
+            ("--glyphs", "ascii") => self.glyphs = Some(Glyphs::Ascii),
+            ("--glyphs", "unicode") => self.glyphs = Some(Glyphs::Unicode),
+            _ => return Some(Err(bad(a, v))),
+        }
+        Some(Ok(()))
+    }
+
+    /// Selection mark for a heading line: the glyph set's own mark, or
+    /// "* " where the glyph set has none.
+    pub fn selected(&self, g: Glyphs, selected: bool) -> &'static str {
+        match (g, self.cursor && selected) {
+            (Glyphs::None, true) => "* ",
+            (g, sel) => g.selected(sel),
+        }
+    }
+
+    /// Leading whitespace for `depth`; `default` is the renderer's width.
+    pub fn pad(&self, depth: usize, default: usize) -> String {
+        if self.tab {
+            "\t".repeat(depth * self.indent.unwrap_or(1))
+        } else {
+            " ".repeat(depth * self.indent.unwrap_or(default))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{
+        build_tree_capped, parse_otl, render_indented, render_plain_all, A_NOTE, M_COLLAPSED,
+        M_EXPANDED,
+    };
+
+    fn style(args: &[&str]) -> TextStyle {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut st = TextStyle::default();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            st.take_flag(a, &mut it).unwrap().unwrap();
+        }
+        st
+    }
+
+    #[test]
+    fn shared_indent_and_glyph_options() {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", A_NOTE, M_COLLAPSED, 1, Some(b"n")),
+        ]);
+        let tree = build_tree_capped(&parse_otl(&buf, "latin1").unwrap());
+        let tab = style(&["--indent-char", "tab", "--glyphs", "none"]);
+        assert_eq!(render_indented(&tree, 0, &tab), "A\n\tB\n\t\tn\n");
+        assert_eq!(render_plain_all(&tree, 0, &tab), "A\n\tB\n\t\tn\n");
+        let uni = style(&["--indent", "1", "--glyphs", "unicode"]);
+        assert_eq!(render_plain_all(&tree, 0, &uni), "▾  A\n ▸  B\n  │ n\n");
+        assert_eq!(
+            render_indented(&tree, 0, &TextStyle::default()),
+            "[-]  A\n    [+]  B\n        > n\n"
+        );
+    }
+
+    #[test]
+    fn cursor_shown_only_with_show_cursor() {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", crate::A_CURSOR, M_EXPANDED, 1, None),
+        ]);
+        let tree = build_tree_capped(&parse_otl(&buf, "latin1").unwrap());
+        let mut st = TextStyle::default();
+        assert_eq!(render_indented(&tree, 0, &st), "[-]  A\n    [-]  B\n");
+        assert_eq!(render_plain_all(&tree, 0, &st), "A\n  B\n");
+        st.cursor = true;
+        assert_eq!(render_indented(&tree, 0, &st), "[-]  A\n    [-]* B\n");
+        assert_eq!(render_plain_all(&tree, 0, &st), "A\n  * B\n");
+    }
+}
@@ -0,0 +1,112 @@
+//! `suspicious` subcommand: headings whose bytes look like disk corruption
+//! or a wrong-encoding round trip, for targeted manual repair.
+//!
+//! Heading bytes keep only 7 bits of text (the high bit folds a following
+//! space, see `decode_heading`), so 8-bit text written by another tool comes
+//! back as runs of "folded" bytes and control characters. Checks:
+//! - `control`: a byte that decodes to a control character
+//! - `high-bit-run`: three or more folded bytes in a row (one-letter words
+//!   are rare; multi-byte UTF-8 or cp437 text produces exactly this)
+//! - `repeat`: a character repeated 6+ times (rules like `-----` excepted)
+//! - `symbols`: over half of the non-space characters neither letters nor
+//!   digits (headings of 4+ such characters)
+
+use crate::{decode_heading_raw, read_input, usage, Rec, EXIT_FINDINGS};
+use std::io;
+
+const MIN_REPEAT: usize = 6;
+const MIN_HIGH_RUN: usize = 3;
+const RULE_CHARS: &str = "-=_*~.#+/\\";
+
+/// (kind, detail) for each check a heading fails.
+pub fn suspicions(raw: &[u8], text: &str) -> Vec<(&'static str, String)> {
+    let mut out = Vec::new();
+    if let Some(b) = raw
+        .iter()
+        .map(|b| b & 0x7f)
+        .find(|&b| b < 0x20 || b == 0x7f)
+    {
+        out.push(("control", format!("control byte 0x{b:02x}")));
+    }
+    let mut run = 0;
+    let mut longest = 0;
+    for t in decode_heading_raw(raw) {
+        run = if t.folded_space { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    if longest >= MIN_HIGH_RUN {
+        out.push(("high-bit-run", format!("{longest} folded bytes in a row")));
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let n = chars[i..].iter().take_while(|&&c| c == chars[i]).count();
+        if n >= MIN_REPEAT && !chars[i].is_whitespace() && !RULE_CHARS.contains(chars[i]) {
+            out.push(("repeat", format!("{:?} x{n}", chars[i])));
+            break;
+        }
+        i += n;
+    }
+    let visible: Vec<char> = chars.into_iter().filter(|c| !c.is_whitespace()).collect();
+    let odd = visible.iter().filter(|c| !c.is_alphanumeric()).count();
+    if visible.len() >= 4
+        && odd * 2 > visible.len()
+        && !visible.iter().all(|c| RULE_CHARS.contains(*c))
+    {
+        out.push((
+            "symbols",
+            format!("{odd}/{} non-alphanumeric", visible.len()),
+        ));
+    }
+    out
+}
+
+/// One line per suspicious heading.
+pub fn report(buf: &[u8], recs: &[Rec]) -> Vec<String> {
+    let mut out = Vec::new();
+    for (i, r) in recs.iter().enumerate() {
+        let raw = &buf[r.off_text..r.off_text + r.len_text];
+        for (kind, detail) in suspicions(raw, &r.text) {
+            out.push(format!(
+                "rec #{i:03} text[{:#06x}+{:>4}] {kind}: {detail}: {:?}",
+                r.off_text, r.len_text, r.text
+            ));
+        }
+    }
+    out
+}
+
+pub fn cmd_suspicious(prog: &str, args: &[String]) -> io::Result<u8> {
+    let [file] = args else { usage(prog) };
+    let buf = read_input(file)?;
+    let recs = crate::parse_otl(&buf, "latin1")?;
+    let lines = report(&buf, &recs);
+    for l in &lines {
+        println!("{l}");
+    }
+    Ok(if lines.is_empty() { 0 } else { EXIT_FINDINGS })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write::encode_heading;
+
+    fn kinds(raw: &[u8]) -> Vec<&'static str> {
+        let text = crate::decode_heading(raw);
+        suspicions(raw, &text).into_iter().map(|(k, _)| k).collect()
+    }
+
+    #[test]
+    fn flags_corruption_not_ordinary_headings() {
+        assert!(kinds(&encode_heading("Call Bob about Q3 budget (draft)", 0)).is_empty());
+        assert!(kinds(&encode_heading("----------------", 0)).is_empty());
+        assert!(kinds(&encode_heading("a b c", 0)).is_empty());
+        // "Café" stored as UTF-8 by another tool
+        assert!(kinds(b"Caf\xc3\xa9 menu").is_empty()); // two are not enough
+        assert_eq!(kinds(b"Caf\xc3\xa9\xc3\xa9 menu"), ["high-bit-run"]);
+        assert_eq!(kinds(b"Bad\x81byte"), ["control"]);
+        assert_eq!(kinds(b"zzzzzzzz"), ["repeat"]);
+        assert_eq!(kinds(b"#@!%&(a)"), ["symbols"]);
+    }
+}
@@ -0,0 +1,173 @@
+//! `symbols` subcommand: a DocumentSymbol-like outline for editor plugins.
+//!
+//! `--format lsp-json` emits nested symbols shaped like LSP's DocumentSymbol
+//! (`name`, `detail`, `kind`, `range`, `selectionRange`, `children`), where
+//! `range`s are line positions in the canon dump (`otl --canon`, cursor
+//! hidden), plus `byteRange`/`selectionByteRange` offsets into the .OTL file
+//! itself for hex views. A symbol's range covers its whole subtree.
+
+use crate::addr::subtree_end;
+use crate::canon::canon_line_starts;
+use crate::{parse_otl, read_input, rec_levels_capped, rec_parents_capped, usage, Rec};
+use serde::Serialize;
+use std::io;
+
+// LSP SymbolKind.String, as used for Markdown headings
+const KIND_HEADING: u8 = 15;
+
+#[derive(Serialize)]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+#[derive(Serialize)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+#[derive(Serialize)]
+struct ByteRange {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Symbol {
+    name: String,
+    detail: String,
+    kind: u8,
+    range: Range,
+    selection_range: Range,
+    byte_range: ByteRange,
+    selection_byte_range: ByteRange,
+    depth: usize,
+    id: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    selected: bool, // cursor (A_CURSOR) record
+    children: Vec<Symbol>,
+}
+
+fn lines(start: usize, end: usize) -> Range {
+    Range {
+        start: Position {
+            line: start,
+            character: 0,
+        },
+        end: Position {
+            line: end,
+            character: 0,
+        },
+    }
+}
+
+// One past the record's last byte (heading, header, note).
+fn rec_end(r: &Rec) -> usize {
+    match r.off_note {
+        Some(o) => o + r.note_len,
+        None => r.off_delta + 2,
+    }
+}
+
+fn symbols(recs: &[Rec]) -> Vec<Symbol> {
+    let levels = rec_levels_capped(recs);
+    let parents = rec_parents_capped(recs);
+    let starts = canon_line_starts(recs);
+    let mut kids: Vec<Vec<usize>> = vec![Vec::new(); recs.len()];
+    let mut tops = Vec::new();
+    for (i, p) in parents.iter().enumerate() {
+        match p {
+            Some(p) => kids[*p].push(i),
+            None => tops.push(i),
+        }
+    }
+
+    fn build(
+        i: usize,
+        recs: &[Rec],
+        levels: &[usize],
+        starts: &[usize],
+        kids: &[Vec<usize>],
+    ) -> Symbol {
+        let r = &recs[i];
+        let end = subtree_end(recs, i);
+        let detail = match (r.flags.has_note, r.collapsed) {
+            (true, true) => "note, collapsed",
+            (true, false) => "note",
+            (false, true) => "collapsed",
+            (false, false) => "",
+        };
+        Symbol {
+            name: r.text.to_string(),
+            detail: format!("#{i}{}{detail}", if detail.is_empty() { "" } else { " " }),
+            kind: KIND_HEADING,
+            range: lines(starts[i], starts[end]),
+            selection_range: lines(starts[i], starts[i] + 1),
+            byte_range: ByteRange {
+                start: r.off_text,
+                end: rec_end(&recs[end - 1]),
+            },
+            selection_byte_range: ByteRange {
+                start: r.off_text,
+                end: r.off_delta + 2,
+            },
+            depth: levels[i],
+            id: r.id.clone(),
+            selected: r.flags.selected,
+            children: kids[i]
+                .iter()
+                .map(|&k| build(k, recs, levels, starts, kids))
+                .collect(),
+        }
+    }
+    tops.iter()
+        .map(|&i| build(i, recs, &levels, &starts, &kids))
+        .collect()
+}
+
+pub fn cmd_symbols(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut format = "lsp-json";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--format" => format = it.next().map(|s| s.as_str()).unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    if format != "lsp-json" {
+        usage(prog);
+    }
+    let buf = read_input(file.unwrap_or_else(|| usage(prog)))?;
+    let recs = parse_otl(&buf, "latin1")?;
+    println!("{}", serde_json::to_string_pretty(&symbols(&recs)).unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, M_EXPANDED};
+
+    #[test]
+    fn nested_symbols_cover_subtrees() {
+        let buf = otl_file(vec![
+            rec_bytes("A", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("B", A_NOTE, M_EXPANDED, 1, Some(b"n1\r\nn2")),
+            rec_bytes("C", 0x00, M_EXPANDED, -1, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let syms = symbols(&recs);
+        assert_eq!(syms.len(), 2);
+        let a = &syms[0];
+        assert_eq!((a.range.start.line, a.range.end.line), (0, 7));
+        assert_eq!(a.children[0].name, "B");
+        assert_eq!(a.children[0].depth, 1);
+        assert_eq!(a.byte_range.end, recs[2].off_text);
+        assert_eq!(syms[1].byte_range.end, buf.len());
+    }
+}
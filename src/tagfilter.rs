@@ -0,0 +1,196 @@
+//! `--include-tag` / `--exclude-tag`: select or redact subtrees by `@tag`.
+//!
+//! A tag is `@` plus letters, digits, `_` or `-`, not preceded by a letter
+//! or digit (so `bob@example.com` is no tag), in a heading or its note;
+//! case is ignored. A tagged record stands for its whole subtree. With
+//! `--include-tag`, only tagged subtrees and their ancestors are kept;
+//! `--exclude-tag` drops tagged subtrees and wins over inclusion. Like
+//! `--head`, the result is restructured and keeps the full file's ids.
+
+use crate::addr::subtree_end;
+use crate::ops::restructure;
+use crate::{rec_levels_capped, rec_parents_capped, Rec};
+use std::io;
+
+pub const TAG_USAGE: &str = "[--include-tag <tag>] [--exclude-tag <tag>]";
+
+/// The tags in `text`, lowercased and without the `@`, in order.
+pub fn find_tags(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '@' && (i == 0 || !chars[i - 1].is_alphanumeric()) {
+            let n = chars[i + 1..].iter().take_while(|&&c| word(c)).count();
+            if n > 0 {
+                out.push(
+                    chars[i + 1..i + 1 + n]
+                        .iter()
+                        .collect::<String>()
+                        .to_lowercase(),
+                );
+            }
+            i += n;
+        }
+        i += 1;
+    }
+    out
+}
+
+fn has_tag(r: &Rec, wanted: &[String]) -> bool {
+    let note = r.note_bin.is_none().then_some(r.note.as_deref()).flatten();
+    [Some(&*r.text), note]
+        .into_iter()
+        .flatten()
+        .flat_map(find_tags)
+        .any(|t| wanted.contains(&t))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Consume `a` (and its value from `it`) if it is a tag filter flag.
+    /// Values may be comma lists, with or without the `@`.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        let list = match a {
+            "--include-tag" => &mut self.include,
+            "--exclude-tag" => &mut self.exclude,
+            _ => return None,
+        };
+        let Some(v) = it.next() else {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{a} needs a tag"),
+            )));
+        };
+        for t in v.split(',') {
+            let t = t.trim().trim_start_matches('@');
+            if find_tags(&format!("@{t}")) != [t.to_lowercase()] {
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{a}: {t:?} is not a tag"),
+                )));
+            }
+            list.push(t.to_lowercase());
+        }
+        Some(Ok(()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Record indexes (ascending) that pass the filter.
+    pub fn keep(&self, recs: &[Rec]) -> Vec<usize> {
+        let mut keep = vec![self.include.is_empty(); recs.len()];
+        if !self.include.is_empty() {
+            let parents = rec_parents_capped(recs);
+            for i in (0..recs.len()).filter(|&i| has_tag(&recs[i], &self.include)) {
+                keep[i..subtree_end(recs, i)]
+                    .iter_mut()
+                    .for_each(|k| *k = true);
+                let mut p = parents[i];
+                while let Some(a) = p {
+                    keep[a] = true;
+                    p = parents[a];
+                }
+            }
+        }
+        if !self.exclude.is_empty() {
+            for i in (0..recs.len()).filter(|&i| has_tag(&recs[i], &self.exclude)) {
+                keep[i..subtree_end(recs, i)]
+                    .iter_mut()
+                    .for_each(|k| *k = false);
+            }
+        }
+        (0..recs.len()).filter(|&i| keep[i]).collect()
+    }
+
+    pub fn apply(&self, recs: Vec<Rec>) -> Vec<Rec> {
+        if self.is_empty() {
+            return recs;
+        }
+        let keep = self.keep(&recs);
+        let levels = rec_levels_capped(&recs);
+        let mut out: Vec<Rec> = keep.iter().map(|&i| recs[i].clone()).collect();
+        let new_levels: Vec<usize> = keep.iter().map(|&i| levels[i]).collect();
+        restructure(&mut out, &new_levels);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    fn texts(recs: &[Rec]) -> Vec<&str> {
+        recs.iter().map(|r| &*r.text).collect()
+    }
+
+    fn filter(flags: &[&str]) -> TagFilter {
+        let args: Vec<String> = flags.iter().map(|s| s.to_string()).collect();
+        let mut f = TagFilter::default();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            f.take_flag(a, &mut it).unwrap().unwrap();
+        }
+        f
+    }
+
+    #[test]
+    fn tags_are_words_after_an_at_sign() {
+        assert_eq!(find_tags("Call @Bob re @q3-budget."), ["bob", "q3-budget"]);
+        assert!(find_tags("mail bob@example.com, @ alone").is_empty());
+    }
+
+    #[test]
+    fn exclusion_drops_subtrees_and_wins() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Work @share", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes(
+                    "Salary",
+                    A_NOTE | A_SIBFOLLOWS,
+                    M_EXPANDED,
+                    1,
+                    Some(b"@Private"),
+                ),
+                rec_bytes("figures", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Roadmap", 0x00, M_EXPANDED, -1, None),
+                rec_bytes("Home", A_SIBFOLLOWS, M_EXPANDED, -1, None),
+                rec_bytes("Trip @share", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Diary", 0x00, M_EXPANDED, -1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let out = filter(&["--exclude-tag", "private"]).apply(recs.clone());
+        assert_eq!(
+            texts(&out),
+            ["Work @share", "Roadmap", "Home", "Trip @share", "Diary"]
+        );
+        assert_eq!(rec_levels_capped(&out), [0, 1, 0, 1, 0]);
+        assert_eq!(out[1].id, recs[3].id);
+
+        let out = filter(&["--include-tag", "@SHARE", "--exclude-tag", "private"]).apply(recs);
+        assert_eq!(
+            texts(&out),
+            ["Work @share", "Roadmap", "Home", "Trip @share"]
+        );
+        assert!(TagFilter::default()
+            .take_flag("--include-tag", &mut [String::from("a b")].iter())
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! `--ctags` / `--etags`: tags files mapping headings to locations.
+//!
+//! ctags addresses are `goto N` (1-based byte offset of the heading in the
+//! .OTL, usable from vim) or, with `--tag-lines`, canon line numbers for a
+//! companion canon/text export named with `--tag-file`. etags entries carry
+//! both the canon line and the byte offset.
+
+use crate::canon::canon_line_starts;
+use crate::heading_paths_capped;
+use crate::opts::TagOpts;
+use crate::{rec_levels_capped, Rec};
+
+// Tabs would break the tags line format; they never matter in a tag name.
+fn tag_name(s: &str) -> String {
+    s.replace('\t', " ")
+}
+
+pub fn render_ctags(recs: &[Rec], file: &str, canon_lines: bool, o: &TagOpts) -> String {
+    let levels = rec_levels_capped(recs);
+    let paths = heading_paths_capped(recs);
+    let starts = canon_line_starts(recs);
+    let mut lines: Vec<String> = Vec::new();
+    for (i, r) in recs.iter().enumerate() {
+        if r.text.trim().is_empty() {
+            continue;
+        }
+        let addr = if canon_lines {
+            format!("{}", starts[i] + 1)
+        } else {
+            format!("goto {}", r.off_text + 1)
+        };
+        lines.push(format!(
+            "{}\t{file}\t{addr};\"\tkind:{}\tdepth:{}\tpath:{}",
+            tag_name(&r.text),
+            o.kind,
+            levels[i],
+            tag_name(&paths[i])
+        ));
+    }
+    lines.sort();
+    let mut out = String::from("!_TAG_FILE_FORMAT\t2\t/extended format/\n");
+    out.push_str("!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\n");
+    for l in lines {
+        out.push_str(&l);
+        out.push('\n');
+    }
+    out
+}
+
+pub fn render_etags(recs: &[Rec], file: &str) -> String {
+    let starts = canon_line_starts(recs);
+    let mut body = String::new();
+    for (i, r) in recs.iter().enumerate() {
+        if r.text.trim().is_empty() {
+            continue;
+        }
+        // pattern \x7f name \x01 line,offset
+        body.push_str(&format!(
+            "{}\u{7f}{}\u{1}{},{}\n",
+            r.text,
+            r.text,
+            starts[i] + 1,
+            r.off_text
+        ));
+    }
+    format!("\u{c}\n{file},{}\n{body}", body.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, M_EXPANDED};
+
+    #[test]
+    fn ctags_sorted_with_addresses() {
+        let buf = otl_file(vec![
+            rec_bytes("Zeta", 0x00, M_EXPANDED, 0, None),
+            rec_bytes("Alpha", 0x00, M_EXPANDED, 1, None),
+        ]);
+        let recs = parse_otl(&buf, "latin1").unwrap();
+        let o = TagOpts { kind: 'h' };
+        let tags = render_ctags(&recs, "f.OTL", false, &o);
+        let lines: Vec<&str> = tags.lines().skip(2).collect();
+        assert_eq!(
+            lines,
+            [
+                "Alpha\tf.OTL\tgoto 20;\"\tkind:h\tdepth:1\tpath:Zeta/Alpha",
+                "Zeta\tf.OTL\tgoto 10;\"\tkind:h\tdepth:0\tpath:Zeta",
+            ]
+        );
+        assert!(render_ctags(&recs, "f.txt", true, &o).contains("Alpha\tf.txt\t2;\""));
+        assert!(render_etags(&recs, "f.OTL").starts_with("\u{c}\nf.OTL,"));
+    }
+}
@@ -0,0 +1,169 @@
+//! `new` subcommand: a fresh .OTL from a template with `{{var}}`
+//! placeholders (a weekly planning outline stamped with its date, say).
+//!
+//! The template is a canon dump (as `--canon` prints it) or indented text
+//! (as `gen --template` uses). Placeholders in headings and notes are
+//! replaced by `--var name=value` values after the template is parsed, so a
+//! value may hold quotes or newlines without breaking the canon syntax;
+//! `{{today}}` is the current date unless given. A placeholder with no
+//! value is an error naming every missing variable. Braces not forming
+//! `{{name}}` are kept as written.
+
+use crate::canon::parse_canon;
+use crate::gen::today;
+use crate::import::parse_indented;
+use crate::ops::prepare_write;
+use crate::write::serialize_recs;
+use crate::{ids, read_input, usage, write_output, Rec, A_CURSOR};
+use std::collections::BTreeSet;
+use std::io;
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// `text` with each `{{name}}` (spaces inside the braces allowed) replaced
+/// by `lookup(name)`; names without a value go to `missing`.
+pub fn expand<'a>(
+    text: &str,
+    lookup: &impl Fn(&str) -> Option<&'a str>,
+    missing: &mut BTreeSet<String>,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let name = after.find("}}").map(|close| (after[..close].trim(), close));
+        match name {
+            Some((name, close)) if is_name(name) => {
+                match lookup(name) {
+                    Some(v) => out.push_str(v),
+                    None => {
+                        missing.insert(name.to_string());
+                    }
+                }
+                rest = &after[close + 2..];
+            }
+            _ => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// A canon dump starts (after blanks and comments) with a record line.
+fn looks_canon(text: &str) -> bool {
+    text.lines()
+        .map(str::trim_end)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .is_some_and(|l| l.ends_with('"') && l.contains(" mark=") && l.contains(" delta="))
+}
+
+/// Records of the template `text` with `vars` filled in.
+pub fn instantiate(text: &str, vars: &[(String, String)]) -> io::Result<Vec<Rec>> {
+    let mut recs = if looks_canon(text) {
+        parse_canon(text)?
+    } else {
+        let mut recs = parse_indented(text);
+        if let Some(r) = recs.first_mut() {
+            r.attr |= A_CURSOR;
+            r.flags.selected = true;
+        }
+        recs
+    };
+    let today = today();
+    let lookup = |name: &str| {
+        vars.iter()
+            .rev()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .or((name == "today").then_some(today.as_str()))
+    };
+    let mut missing = BTreeSet::new();
+    for r in &mut recs {
+        if r.text.contains("{{") {
+            let text = expand(&r.text, &lookup, &mut missing);
+            // keep as many folded spaces as the template heading had
+            let folded = r.text.chars().count().saturating_sub(r.len_text);
+            r.len_text = text.chars().count().saturating_sub(folded);
+            r.text = text.into();
+        }
+        if r.note_bin.is_none() {
+            if let Some(note) = r.note.as_mut().filter(|n| n.contains("{{")) {
+                *note = expand(note, &lookup, &mut missing);
+                r.note_len = note.chars().count();
+            }
+        }
+    }
+    if !missing.is_empty() {
+        let names: Vec<String> = missing.into_iter().collect();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("template needs --var for: {}", names.join(", ")),
+        ));
+    }
+    ids::assign_ids(&mut recs);
+    Ok(recs)
+}
+
+pub fn cmd_new(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut tmpl: Option<&str> = None;
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut enc = "latin1";
+    let mut out = "-";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--template" => tmpl = Some(it.next().unwrap_or_else(|| usage(prog))),
+            "--var" => {
+                let kv = it.next().unwrap_or_else(|| usage(prog));
+                let (k, v) = kv.split_once('=').unwrap_or_else(|| usage(prog));
+                if !is_name(k) {
+                    usage(prog)
+                }
+                vars.push((k.to_string(), v.to_string()));
+            }
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "-o" => out = it.next().unwrap_or_else(|| usage(prog)),
+            _ => usage(prog),
+        }
+    }
+    let buf = read_input(tmpl.unwrap_or_else(|| usage(prog)))?;
+    let recs = prepare_write(instantiate(&String::from_utf8_lossy(&buf), &vars)?, None);
+    write_output(out, &serialize_recs(&recs, enc)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_otl;
+
+    #[test]
+    fn canon_template_fills_headings_and_notes() {
+        let tmpl = "# weekly\n\
+                    NsK mark=-1:+ delta=+0 textLen=000f \"Week of {{date}}\"\n\
+                    noteLen=0000\nnote\nplan \"{{ who }}\" {{x\n/note\n\
+                    nsk mark=-1:+ delta=+1 textLen=0009 \"{{today}} \"\n";
+        let vars = [
+            ("date".to_string(), "2024-06-01".to_string()),
+            ("who".to_string(), "Ann \"A\"".to_string()),
+        ];
+        let recs = instantiate(tmpl, &vars).unwrap();
+        let back = parse_otl(&serialize_recs(&recs, "latin1").unwrap(), "latin1").unwrap();
+        assert_eq!(&*back[0].text, "Week of 2024-06-01");
+        assert_eq!(back[0].note.as_deref(), Some("plan \"Ann \"A\"\" {{x"));
+        assert_eq!(&*back[1].text, format!("{} ", today()));
+
+        let err = instantiate("Week {{date}}\n  {{owner}}\n", &[]).unwrap_err();
+        assert!(err.to_string().ends_with("date, owner"), "{err}");
+        let recs = instantiate("Week {{date}}\n  Mon\n", &vars).unwrap();
+        assert_eq!(&*recs[0].text, "Week 2024-06-01");
+        assert!(recs[0].flags.selected);
+    }
+}
@@ -0,0 +1,267 @@
+//! `--transform`: heading cleanups, on export or as a rewrite.
+//!
+//! A transform list (`--transform caps-to-sentence,title-case`, repeatable)
+//! runs left to right on every heading. Notes are left alone. The
+//! `transform` subcommand writes the result back to .OTL, or with
+//! `--dry-run` only lists the headings it would change.
+
+use crate::journal::write_in_place;
+use crate::ops::prepare_write;
+use crate::write::{compress_headings, Preserve};
+use crate::{ids, parse_doc, read_input, usage, write_output, Doc, Rec};
+use std::io;
+
+pub const TRANSFORMS: &[&str] = &[
+    "title-case",
+    "collapse-space",
+    "strip-dots",
+    "caps-to-sentence",
+];
+
+pub const TRANSFORM_USAGE: &str =
+    "[--transform title-case|collapse-space|strip-dots|caps-to-sentence[,..]]";
+
+// Lowercase inside titles unless first.
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "of", "on", "or", "the", "to",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    /// Capitalize each word (small words after the first excepted); other
+    /// letters are kept, so acronyms survive.
+    TitleCase,
+    /// Trim, and turn each run of whitespace into one space.
+    CollapseSpace,
+    /// Drop trailing periods (an ellipsis is kept).
+    StripDots,
+    /// A heading with no lowercase letters becomes "Sentence case".
+    CapsToSentence,
+}
+
+fn upper_first(w: &str) -> String {
+    let mut c = w.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().chain(c).collect(),
+        None => String::new(),
+    }
+}
+
+impl Transform {
+    pub fn parse(s: &str) -> Option<Transform> {
+        match s {
+            "title-case" => Some(Transform::TitleCase),
+            "collapse-space" => Some(Transform::CollapseSpace),
+            "strip-dots" => Some(Transform::StripDots),
+            "caps-to-sentence" => Some(Transform::CapsToSentence),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Transform::TitleCase => text
+                .split(' ')
+                .enumerate()
+                .map(|(i, w)| {
+                    if i > 0 && SMALL_WORDS.contains(&w.to_lowercase().as_str()) {
+                        w.to_lowercase()
+                    } else {
+                        upper_first(w)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Transform::CollapseSpace => text.split_whitespace().collect::<Vec<_>>().join(" "),
+            Transform::StripDots => {
+                let t = text.trim_end();
+                if t.ends_with("...") {
+                    text.to_string()
+                } else {
+                    t.trim_end_matches('.').trim_end().to_string()
+                }
+            }
+            Transform::CapsToSentence => {
+                let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+                if letters < 2 || text.chars().any(|c| c.is_lowercase()) {
+                    text.to_string()
+                } else {
+                    upper_first(&text.to_lowercase())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Transforms(Vec<Transform>);
+
+impl Transforms {
+    /// Consume `--transform LIST` if `a` is that flag.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        if a != "--transform" {
+            return None;
+        }
+        let v = it.next().map(|s| s.as_str()).unwrap_or("");
+        for name in v.split(',') {
+            match Transform::parse(name.trim()) {
+                Some(t) => self.0.push(t),
+                None => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "--transform: expected {}, got {name:?}",
+                            TRANSFORMS.join(", ")
+                        ),
+                    )))
+                }
+            }
+        }
+        Some(Ok(()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn text(&self, text: &str) -> String {
+        self.0.iter().fold(text.to_string(), |t, tr| tr.apply(&t))
+    }
+
+    /// Rewrite headings in place; returns (record, old text) per change.
+    pub fn apply(&self, recs: &mut [Rec]) -> Vec<(usize, String)> {
+        let mut changed = Vec::new();
+        for (i, r) in recs.iter_mut().enumerate() {
+            let new = self.text(&r.text);
+            if *new != *r.text {
+                r.len_text = new.chars().count();
+                changed.push((i, std::mem::replace(&mut r.text, new.into()).to_string()));
+            }
+        }
+        if !changed.is_empty() {
+            ids::assign_ids(recs);
+        }
+        changed
+    }
+}
+
+pub fn cmd_transform(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut out: Option<&str> = None;
+    let mut in_place = false;
+    let mut journal = false;
+    let mut compress = false;
+    let mut keep = Preserve::default();
+    let mut dry_run = false;
+    let mut tr = Transforms::default();
+    let mut enc = "latin1";
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = keep.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        if let Some(r) = tr.take_flag(a, &mut it) {
+            r?;
+            continue;
+        }
+        match a.as_str() {
+            "--dry-run" => dry_run = true,
+            "--in-place" => in_place = true,
+            "--journal" => journal = true,
+            "--compress-headings" => compress = true,
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            "-o" => out = Some(it.next().unwrap_or_else(|| usage(prog))),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let Some(file) = file else { usage(prog) };
+    if tr.is_empty() || (journal && !in_place) {
+        usage(prog);
+    }
+    let out = match (out, in_place, dry_run) {
+        (_, _, true) => "",
+        (Some(o), false, _) => o,
+        (None, true, _) if file != "-" => file,
+        _ => usage(prog),
+    };
+
+    let buf = read_input(file)?;
+    let Doc { mut recs, framing } = parse_doc(&buf, enc)?;
+    let changed = tr.apply(&mut recs);
+    if dry_run {
+        for (i, old) in &changed {
+            println!("#{i:03} \"{old}\" -> \"{}\"", recs[*i].text);
+        }
+        eprintln!("{} heading(s) would change", changed.len());
+        return Ok(());
+    }
+    let mut recs = prepare_write(recs, None);
+    if compress {
+        compress_headings(&mut recs);
+    }
+    keep.apply(&mut recs);
+    let bytes = keep.serialize(&recs, &framing, enc)?;
+    if in_place {
+        if changed.is_empty() {
+            eprintln!("nothing changed");
+            return Ok(());
+        }
+        let command = format!("transform {}", args.join(" "));
+        return write_in_place(file, &buf, &bytes, journal, &command);
+    }
+    write_output(out, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, M_EXPANDED};
+
+    #[test]
+    fn each_transform() {
+        use Transform::*;
+        assert_eq!(
+            TitleCase.apply("plan of the NASA trip"),
+            "Plan of the NASA Trip"
+        );
+        assert_eq!(CollapseSpace.apply("  a \t b  c "), "a b c");
+        assert_eq!(StripDots.apply("Done.. "), "Done");
+        assert_eq!(StripDots.apply("Wait..."), "Wait...");
+        assert_eq!(CapsToSentence.apply("CALL THE BANK!"), "Call the bank!");
+        assert_eq!(CapsToSentence.apply("Call IBM"), "Call IBM");
+    }
+
+    #[test]
+    fn pipeline_runs_in_order_and_reports_changes() {
+        let mut tr = Transforms::default();
+        let flag = ["caps-to-sentence,collapse-space".to_string()];
+        tr.take_flag("--transform", &mut flag.iter())
+            .unwrap()
+            .unwrap();
+        let mut recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("MEETING  NOTES", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("fine", 0x00, M_EXPANDED, 1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let old_id = recs[0].id.clone();
+        let changed = tr.apply(&mut recs);
+        assert_eq!(changed, [(0, "MEETING  NOTES".to_string())]);
+        assert_eq!(&*recs[0].text, "Meeting notes");
+        assert_ne!(recs[0].id, old_id);
+        let bad = ["shout".to_string()];
+        assert!(tr
+            .take_flag("--transform", &mut bad.iter())
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Heading collisions: `--validate --unique` and `check --unique` findings,
+//! also noted on stderr by the anchor-writing exports.
+//!
+//! - `duplicate-sibling`: siblings with the same (trimmed) text. A path
+//!   then addresses more than one node, and `--path` picks the first.
+//! - `slug-collision`: different headings with the same slug, or a heading
+//!   whose slug is the `-N` suffix handed to a repeat of another heading.
+//!   The Slugger (see slug.rs) still keeps anchors and split-file names
+//!   unique, but which heading gets the plain slug depends on document
+//!   order, so links and file names silently move when the outline changes.
+//!
+//! Numeric slugs never collide. Each finding lists every heading involved
+//! as `#NNN path`. With `--collate <locale>`, siblings equal under that
+//! collation's primary strength (case and accents aside) are duplicates too.
+
+use crate::collate::Collator;
+use crate::heading_paths_capped;
+use crate::slug::{rec_slugs, slug, SlugStyle};
+use crate::{rec_levels_capped, Finding, Rec};
+use std::collections::BTreeMap;
+
+fn listed(members: &[usize], paths: &[String]) -> String {
+    members
+        .iter()
+        .map(|&i| format!("#{i:03} {}", paths[i]))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What `--unique` checks: slugs as `--slugs` makes them, and siblings
+/// compared exactly or under `--collate`.
+#[derive(Debug, Clone, Default)]
+pub struct Unique {
+    pub slugs: SlugStyle,
+    pub collate: Option<Collator>,
+}
+
+/// Sibling groups sharing a text, each in record order.
+fn duplicate_siblings(recs: &[Rec], collate: Option<&Collator>) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = Vec::new(); // open ancestors, by level
+    let mut groups: BTreeMap<(Option<usize>, usize, Vec<u32>), Vec<usize>> = BTreeMap::new();
+    for (i, lvl) in rec_levels_capped(recs).into_iter().enumerate() {
+        parents.truncate(lvl);
+        // a level jump: the nearest real ancestor stands in for the gap
+        let filler = parents.last().copied().unwrap_or(usize::MAX);
+        parents.resize(lvl, filler);
+        let text = recs[i].text.trim();
+        let key = match collate {
+            Some(c) => c.primary(text),
+            None => text.chars().map(u32::from).collect(),
+        };
+        groups
+            .entry((parents.last().copied(), lvl, key))
+            .or_default()
+            .push(i);
+        parents.push(i);
+    }
+    let mut out: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    out.sort();
+    out
+}
+
+pub fn collision_findings(recs: &[Rec], unique: &Unique) -> Vec<Finding> {
+    let style = unique.slugs;
+    let paths = heading_paths_capped(recs);
+    let mut out = Vec::new();
+    for g in duplicate_siblings(recs, unique.collate.as_ref()) {
+        let text = recs[g[0]].text.trim();
+        out.push(Finding {
+            rec: g[0],
+            kind: "duplicate-sibling",
+            detail: format!(
+                "{text:?} appears {} times among siblings: {}",
+                g.len(),
+                listed(&g, &paths)
+            ),
+        });
+    }
+    if style == SlugStyle::Numeric {
+        return out;
+    }
+    let base: Vec<String> = recs.iter().map(|r| slug(style, &r.text)).collect();
+    let mut by_slug: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, s) in base.iter().enumerate() {
+        by_slug.entry(s).or_default().push(i);
+    }
+    for (s, g) in &by_slug {
+        let first = recs[g[0]].text.trim();
+        if g.iter().any(|&i| recs[i].text.trim() != first) {
+            out.push(Finding {
+                rec: g[0],
+                kind: "slug-collision",
+                detail: format!("slug {s:?} shared by {}", listed(g, &paths)),
+            });
+        }
+    }
+    let anchors = rec_slugs(recs, style);
+    for (i, a) in anchors.iter().enumerate() {
+        if *a == base[i] {
+            continue;
+        }
+        // who holds our plain slug, and did it get there by suffixing?
+        if let Some(k) = anchors.iter().position(|x| *x == base[i]) {
+            if base[k] != base[i] {
+                out.push(Finding {
+                    rec: i,
+                    kind: "slug-collision",
+                    detail: format!(
+                        "slug {:?} of {} went to repeated {:?} at {}; this heading gets {a:?}",
+                        base[i],
+                        listed(&[i], &paths),
+                        base[k],
+                        listed(&[k], &paths)
+                    ),
+                });
+            }
+        }
+    }
+    out.sort_by_key(|f| f.rec);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn reports_duplicate_siblings_and_slug_clashes() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Work", 0x00, M_EXPANDED, 0, None),
+                rec_bytes("Notes", A_SIBFOLLOWS, M_EXPANDED, 1, None),
+                rec_bytes("Notes", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Notes 1", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Q3: Plan", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Q3 Plan", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Home", 0x00, M_EXPANDED, -1, None),
+                rec_bytes("Notes", 0x00, M_EXPANDED, 1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let kebab = Unique {
+            slugs: SlugStyle::Kebab,
+            collate: None,
+        };
+        let f = collision_findings(&recs, &kebab);
+        let got: Vec<(usize, &str)> = f.iter().map(|f| (f.rec, f.kind)).collect();
+        assert_eq!(
+            got,
+            [
+                (1, "duplicate-sibling"),
+                (3, "slug-collision"),
+                (4, "slug-collision")
+            ]
+        );
+        assert_eq!(
+            f[0].detail,
+            "\"Notes\" appears 2 times among siblings: #001 Work/Notes, #002 Work/Notes"
+        );
+        assert!(f[1]
+            .detail
+            .starts_with("slug \"notes-1\" of #003 Work/Notes 1 went to"));
+        assert_eq!(
+            f[2].detail,
+            "slug \"q3-plan\" shared by #004 Work/Q3: Plan, #005 Work/Q3 Plan"
+        );
+        let numeric = Unique {
+            slugs: SlugStyle::Numeric,
+            collate: None,
+        };
+        assert_eq!(collision_findings(&recs, &numeric).len(), 1);
+    }
+
+    #[test]
+    fn collated_siblings_ignore_case() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Smith, Ann", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("SMITH, ANN", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("Smith Ann", 0x00, M_EXPANDED, 0, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let mut u = Unique {
+            slugs: SlugStyle::Numeric,
+            collate: None,
+        };
+        assert!(collision_findings(&recs, &u).is_empty());
+        u.collate = Some(Collator::new("de").unwrap());
+        let f = collision_findings(&recs, &u);
+        assert_eq!(f.len(), 1);
+        assert!(f[0].detail.contains("#000 Smith, Ann, #001 SMITH, ANN"));
+    }
+}
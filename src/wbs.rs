@@ -0,0 +1,71 @@
+//! `--plantuml-wbs` export: a PlantUML work breakdown structure.
+//!
+//! One `*` per level; a WBS has a single root, so several top-level headings
+//! are hung under a root named after the input. Notes are not shown. Filler
+//! nodes and blank headings become boxless `...` nodes.
+
+use crate::Node;
+
+fn label(text: &str) -> String {
+    let t = text.trim();
+    // `<`/`>` set direction and `_` removes the box when they follow the stars
+    if t.starts_with(['<', '>', '_', '[', '~']) {
+        format!("~{t}")
+    } else {
+        t.to_string()
+    }
+}
+
+fn nodes_wbs(nodes: &[Node], depth: usize, out: &mut String) {
+    for n in nodes {
+        let stars = "*".repeat(depth);
+        let text = label(&n.text);
+        if n.synthetic || text.is_empty() {
+            out.push_str(&format!("{stars}_ ...\n"));
+        } else {
+            out.push_str(&format!("{stars} {text}\n"));
+        }
+        nodes_wbs(&n.children, depth + 1, out);
+    }
+}
+
+pub fn render_wbs(nodes: &[Node], root: &str) -> String {
+    let mut out = String::from("@startwbs\n");
+    if nodes.len() == 1 {
+        nodes_wbs(nodes, 1, &mut out);
+    } else {
+        out.push_str(&format!("* {}\n", label(root)));
+        nodes_wbs(nodes, 2, &mut out);
+    }
+    out.push_str("@endwbs\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn levels_become_stars_under_one_root() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Design", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("<UI>", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Build", 0x00, M_EXPANDED, -1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let tree = build_tree_capped(&recs);
+        assert_eq!(
+            render_wbs(&tree, "plan.OTL"),
+            "@startwbs\n* plan.OTL\n** Design\n*** ~<UI>\n** Build\n@endwbs\n"
+        );
+        assert_eq!(
+            render_wbs(&tree[..1], "x"),
+            "@startwbs\n* Design\n** ~<UI>\n@endwbs\n"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! `wc` subcommand: heading, note, word and character counts per subtree.
+//!
+//! Words and characters cover heading and note text as decoded (so the
+//! binary framing and folded heading spaces never count); binary notes
+//! count as notes but add no words or characters. Notes are decoded one at
+//! a time (see parse_doc_lazy), so counting a file of large notes does not
+//! hold them all.
+
+use crate::addr::{subtree_end, subtree_recs, Addr};
+use crate::{parse_doc_lazy, read_input, rec_levels_capped, usage, Rec};
+use std::io;
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counts {
+    pub headings: usize,
+    pub notes: usize,
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, o: Counts) {
+        self.headings += o.headings;
+        self.notes += o.notes;
+        self.words += o.words;
+        self.chars += o.chars;
+    }
+}
+
+impl Counts {
+    /// Counts of `r`, parsed from `buf` with note encoding `enc`.
+    pub fn of(r: &Rec, buf: &[u8], enc: &str) -> Counts {
+        let mut c = Counts {
+            headings: 1,
+            notes: 0,
+            words: r.text.split_whitespace().count(),
+            chars: r.text.chars().count(),
+        };
+        if let Some(note) = r.note_text(buf, enc) {
+            c.notes = 1;
+            if !r.flags.note_binary {
+                c.words += note.split_whitespace().count();
+                c.chars += note.chars().filter(|&ch| ch != '\r').count();
+            }
+        }
+        c
+    }
+
+    fn line(&self, label: &str) -> String {
+        format!(
+            "{:>8} {:>6} {:>7} {:>8}  {label}\n",
+            self.headings, self.notes, self.words, self.chars
+        )
+    }
+}
+
+/// One line per subtree rooted above `depth` (indented by level), then the
+/// total. `depth` 0 gives the total only. `recs` were parsed from `buf`.
+pub fn report(recs: &[Rec], buf: &[u8], enc: &str, depth: usize) -> String {
+    let levels = rec_levels_capped(recs);
+    let each: Vec<Counts> = recs.iter().map(|r| Counts::of(r, buf, enc)).collect();
+    let mut out = format!(
+        "{:>8} {:>6} {:>7} {:>8}\n",
+        "headings", "notes", "words", "chars"
+    );
+    let mut total = Counts::default();
+    for (i, r) in recs.iter().enumerate() {
+        total += each[i];
+        if levels[i] >= depth {
+            continue;
+        }
+        let mut sub = Counts::default();
+        for c in &each[i..subtree_end(recs, i)] {
+            sub += *c;
+        }
+        out.push_str(&sub.line(&format!("{}{}", "  ".repeat(levels[i]), r.text)));
+    }
+    out.push_str(&total.line("total"));
+    out
+}
+
+pub fn cmd_wc(prog: &str, args: &[String]) -> io::Result<()> {
+    let mut file: Option<&str> = None;
+    let mut addr: Option<Addr> = None;
+    let mut enc = "latin1";
+    let mut depth = 1;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(r) = Addr::from_flag(a, &mut it) {
+            addr = Some(r?);
+            continue;
+        }
+        match a.as_str() {
+            "--depth" => {
+                let v = it.next().unwrap_or_else(|| usage(prog));
+                depth = v.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--depth: not a number: {v}"),
+                    )
+                })?;
+            }
+            "--enc" => enc = it.next().unwrap_or_else(|| usage(prog)),
+            _ if file.is_none() => file = Some(a),
+            _ => usage(prog),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(prog));
+    let buf = read_input(file)?;
+    let recs = parse_doc_lazy(&buf, enc)?.recs;
+    match addr {
+        None => print!("{}", report(&recs, &buf, enc, depth)),
+        Some(addr) => {
+            for i in addr.resolve(&recs)? {
+                print!("{}", report(&subtree_recs(&recs, i), &buf, enc, depth));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{A_NOTE, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn counts_per_subtree_and_total() {
+        let buf = otl_file(vec![
+            rec_bytes("Call Bob", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+            rec_bytes("about  it", A_NOTE, M_EXPANDED, 1, Some(b"two words\r\n")),
+            rec_bytes("Z", A_NOTE, M_EXPANDED, -1, Some(b"\x00\x01")),
+        ]);
+        let recs = parse_doc_lazy(&buf, "latin1").unwrap().recs;
+        let lines: Vec<String> = report(&recs, &buf, "latin1", 2)
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "headings notes words chars",
+                "2 1 6 27 Call Bob",
+                "1 1 4 19 about it",
+                "1 1 1 1 Z",
+                "3 2 7 28 total",
+            ]
+        );
+        assert_eq!(report(&recs, &buf, "latin1", 0).lines().count(), 2);
+    }
+}
@@ -0,0 +1,345 @@
+//! Record writer: `Rec`s back to .OTL bytes.
+//!
+//! Raw fields (attr, marker, delta) are written as-is. Headings are
+//! recompressed (char + following space -> char|0x80) just enough to reach
+//! the record's `len_text`, so a record from `parse_otl` or `parse_canon`
+//! re-encodes to the same heading length. The file is framed with MAGIC,
+//! PREAMBLE and a single 0x1a EOF sentinel. A note that encodes to more than
+//! the 65,535 bytes its u16 length holds is an error, not a truncation.
+//!
+//! `Preserve` (`--preserve` / `--reset`) picks, per field, between keeping
+//! what was read and writing it clean.
+
+use crate::{Framing, Rec, A_CURSOR, A_HASKIDS, A_NOTE, A_SIBFOLLOWS, MAGIC, PREAMBLE};
+use std::io;
+
+pub const PRESERVE_USAGE: &str =
+    "[--preserve|--reset folds,cursor,unknown-bits,trailer,preamble|all]";
+
+const KNOWN_ATTR: u8 = A_NOTE | A_CURSOR | A_SIBFOLLOWS | A_HASKIDS;
+
+/// Encode heading text as 7-bit bytes (non-ASCII becomes '?'), folding spaces
+/// into the preceding byte's high bit, left to right, until the encoding is
+/// `target_len` bytes long (or nothing more can be folded).
+pub fn encode_heading(text: &str, target_len: usize) -> Vec<u8> {
+    let bytes: Vec<u8> = text
+        .chars()
+        .map(|ch| if (ch as u32) < 0x80 { ch as u8 } else { b'?' })
+        .collect();
+    let mut need = bytes.len().saturating_sub(target_len);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        i += 1;
+        // 0x7f|0x80 would be the 0xFF terminator
+        if need > 0 && b != 0x7f && bytes.get(i) == Some(&b' ') {
+            out.push(b | 0x80);
+            i += 1;
+            need -= 1;
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Set every record's `len_text` to its shortest encoding, so the writer
+/// folds every space it can (`--compress-headings`: edited headings of files
+/// saved fully folded then keep the original sizes).
+pub fn compress_headings(recs: &mut [Rec]) {
+    for r in recs {
+        r.len_text = encode_heading(&r.text, 0).len();
+    }
+}
+
+pub fn encode_note(note: &str, enc: &str) -> Vec<u8> {
+    match enc {
+        "utf8" => note.as_bytes().to_vec(),
+        "latin1" => note
+            .chars()
+            .map(|c| if (c as u32) <= 0xFF { c as u8 } else { b'?' })
+            .collect(),
+        "ascii" => note.bytes().map(|b| b & 0x7f).collect(),
+        "cp1252" => crate::enc::encode_cp1252(note),
+        "cp437" => crate::enc::encode_cp437(note),
+        "cp850" => crate::enc::encode_cp850(note),
+        "mixed" => crate::enc::encode_mixed(note, None),
+        _ => note.as_bytes().to_vec(),
+    }
+}
+
+/// Serialize records to a complete .OTL file.
+pub fn serialize_recs(recs: &[Rec], note_enc: &str) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::<u8>::new();
+    buf.extend(MAGIC);
+    buf.extend(PREAMBLE);
+    for (i, r) in recs.iter().enumerate() {
+        buf.extend(encode_heading(&r.text, r.len_text));
+        buf.push(0xFF);
+        buf.push(r.attr);
+        buf.extend_from_slice(&r.marker_u16.to_le_bytes());
+        buf.extend_from_slice(&r.delta.to_le_bytes());
+        if (r.attr & A_NOTE) != 0 {
+            let note = match &r.note_bin {
+                Some(bytes) => bytes.clone(),
+                None if note_enc == "mixed" => {
+                    crate::enc::encode_mixed(r.note.as_deref().unwrap_or(""), r.note_guess)
+                }
+                None => encode_note(r.note.as_deref().unwrap_or(""), note_enc),
+            };
+            let nlen = u16::try_from(note.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "rec #{i:03} {:?}: note is {} bytes as {note_enc}, over the 65535 a record holds",
+                        &*r.text,
+                        note.len()
+                    ),
+                )
+            })?;
+            buf.extend_from_slice(&nlen.to_le_bytes());
+            buf.extend(&note);
+        }
+    }
+    buf.push(0x1a);
+    Ok(buf)
+}
+
+/// Serialize records inside the framing they were parsed with (any junk
+/// prefix before MAGIC and nonstandard preamble words are kept unless
+/// `standard_head`; a trailer after the EOF sentinel is always kept).
+pub fn serialize_framed(
+    recs: &[Rec],
+    framing: &Framing,
+    note_enc: &str,
+    standard_head: bool,
+) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if !standard_head {
+        buf.extend(&framing.prefix);
+    }
+    let at = buf.len() + MAGIC.len();
+    buf.extend(serialize_recs(recs, note_enc)?);
+    if let (false, Some(words)) = (standard_head, framing.preamble) {
+        buf[at..at + PREAMBLE.len()].copy_from_slice(&words);
+    }
+    buf.extend(&framing.trailer);
+    Ok(buf)
+}
+
+/// Which read fields a rewrite keeps. Reset, each is written clean:
+/// - `folds`: every heading expanded
+/// - `cursor`: no cursor bit (0x20)
+/// - `unknown-bits`: attr bits other than 0x80/0x20/0x08/0x04 cleared
+/// - `trailer`: bytes after the EOF sentinel dropped
+/// - `preamble`: junk before MAGIC (BOM, CR/LF) dropped and the 6 words
+///   after MAGIC written as the standard PREAMBLE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preserve {
+    pub folds: bool,
+    pub cursor: bool,
+    pub unknown_bits: bool,
+    pub trailer: bool,
+    pub preamble: bool,
+}
+
+impl Default for Preserve {
+    /// Byte-faithful: everything kept.
+    fn default() -> Self {
+        Preserve {
+            folds: true,
+            cursor: true,
+            unknown_bits: true,
+            trailer: true,
+            preamble: true,
+        }
+    }
+}
+
+impl Preserve {
+    /// Consume `a` (and its value from `it`) if it is `--preserve` or
+    /// `--reset`.
+    pub fn take_flag<'a>(
+        &mut self,
+        a: &str,
+        it: &mut impl Iterator<Item = &'a String>,
+    ) -> Option<io::Result<()>> {
+        let keep = match a {
+            "--preserve" => true,
+            "--reset" => false,
+            _ => return None,
+        };
+        let v = it.next().map_or("", |s| s.as_str());
+        for name in v.split(',') {
+            match name.trim() {
+                "folds" => self.folds = keep,
+                "cursor" => self.cursor = keep,
+                "unknown-bits" => self.unknown_bits = keep,
+                "trailer" => self.trailer = keep,
+                "preamble" => self.preamble = keep,
+                "all" => {
+                    *self = Preserve {
+                        folds: keep,
+                        cursor: keep,
+                        unknown_bits: keep,
+                        trailer: keep,
+                        preamble: keep,
+                    }
+                }
+                other => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{a}: unknown field {other:?} \
+                             (folds, cursor, unknown-bits, trailer, preamble, all)"
+                        ),
+                    )))
+                }
+            }
+        }
+        Some(Ok(()))
+    }
+
+    /// Reset the record fields that are not preserved.
+    pub fn apply(&self, recs: &mut [Rec]) {
+        for r in recs {
+            if !self.folds {
+                r.collapsed = false;
+                r.marker_u16 = 0xFFFF;
+            }
+            if !self.cursor {
+                r.attr &= !A_CURSOR;
+                r.flags.selected = false;
+            }
+            if !self.unknown_bits {
+                r.attr &= KNOWN_ATTR;
+            }
+        }
+    }
+
+    /// `serialize_framed`, keeping the trailer and the prefix and preamble
+    /// words only if preserved.
+    pub fn serialize(
+        &self,
+        recs: &[Rec],
+        framing: &Framing,
+        note_enc: &str,
+    ) -> io::Result<Vec<u8>> {
+        let mut framing = framing.clone();
+        if !self.trailer {
+            framing.trailer.clear();
+        }
+        serialize_framed(recs, &framing, note_enc, !self.preamble)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_heading, decode_heading_raw};
+
+    #[test]
+    fn heading_compression_hits_target_len() {
+        let text = "a  b c";
+        assert_eq!(encode_heading(text, usize::MAX), b"a  b c");
+        let full = encode_heading(text, 0);
+        assert_eq!(full, [b'a' | 0x80, b' ', b'b' | 0x80, b'c']);
+        assert_eq!(decode_heading(&full), text);
+        let partial = encode_heading(text, 5);
+        assert_eq!(partial.len(), 5);
+        assert_eq!(decode_heading(&partial), text);
+    }
+
+    #[test]
+    fn tokens_keep_real_spaces_apart_from_folded_ones() {
+        let mut recs = vec![Rec::new("a  b".into(), 0, 0xFFFF, 0, None)];
+        assert_eq!(recs[0].len_text, 4);
+        compress_headings(&mut recs);
+        let bytes = encode_heading(&recs[0].text, recs[0].len_text);
+        let tokens: Vec<(char, bool)> = decode_heading_raw(&bytes)
+            .iter()
+            .map(|t| (t.ch, t.folded_space))
+            .collect();
+        assert_eq!(tokens, [('a', true), (' ', false), ('b', false)]);
+    }
+
+    #[test]
+    fn oversized_notes_are_an_error() {
+        let fits = "x".repeat(0xFFFF);
+        let mut recs = vec![
+            Rec::new("a".into(), 0, 0xFFFF, 0, None),
+            Rec::new("b".into(), A_NOTE, 0xFFFF, 0, Some(fits.clone())),
+        ];
+        assert!(serialize_recs(&recs, "latin1").is_ok());
+        // fits as latin1, not as UTF-8
+        recs[1].note = Some("é".repeat(0xFFFF / 2 + 1));
+        assert!(serialize_recs(&recs, "latin1").is_ok());
+        let e = serialize_recs(&recs, "utf8").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(e
+            .to_string()
+            .starts_with("rec #001 \"b\": note is 65536 bytes"));
+        recs[1].note = Some(fits + "y");
+        assert!(serialize_recs(&recs, "latin1").is_err());
+    }
+
+    #[test]
+    fn mixed_rewrite_keeps_each_note_encoding() {
+        let raw: [&[u8]; 2] = ["café ─".as_bytes(), b"caf\x82 \xc4\xc4"]; // UTF-8, cp437
+        let recs: Vec<Rec> = raw
+            .iter()
+            .map(|&b| {
+                let mut r = Rec::new("n".into(), A_NOTE, 0xFFFF, 0, Some(String::new()));
+                r.note_bin = Some(b.to_vec());
+                r
+            })
+            .collect();
+        let buf = serialize_recs(&recs, "latin1").unwrap();
+        let doc = crate::parse_doc(&buf, "mixed").unwrap();
+        assert_eq!(doc.recs[1].note.as_deref(), Some("café ──"));
+        assert_eq!(serialize_recs(&doc.recs, "mixed").unwrap(), buf);
+    }
+
+    #[test]
+    fn reset_fields_are_written_clean() {
+        let mut recs = vec![Rec::new("A".into(), A_CURSOR | 0x02, 0xFFFE, 0, None)];
+        recs[0].collapsed = true;
+        let framing = Framing {
+            prefix: b"\r\n".to_vec(),
+            preamble: Some([0xff, 0x01, 0xff, 0xff, 0xff, 0xff]),
+            trailer: b"idx".to_vec(),
+            ..Framing::default()
+        };
+        let args: Vec<String> = ["--reset", "all", "--preserve", "folds,trailer"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let keep_all = Preserve::default();
+        let mut keep = Preserve::default();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            keep.take_flag(a, &mut it).unwrap().unwrap();
+        }
+        keep.apply(&mut recs);
+        assert_eq!((recs[0].attr, recs[0].marker_u16), (0x00, 0xFFFE));
+        let bytes = keep.serialize(&recs, &framing, "latin1").unwrap();
+        assert!(bytes.starts_with(&[&MAGIC[..], &PREAMBLE].concat()));
+        assert!(bytes.ends_with(b"\x1aidx"));
+
+        // kept, odd preamble words go back as read
+        let buf = keep_all.serialize(&recs, &framing, "latin1").unwrap();
+        assert_eq!(buf[..11], *b"\r\n\x1a\x93\x1a\xff\x01\xff\xff\xff\xff");
+        let doc = crate::parse_doc(&buf, "latin1").unwrap();
+        assert_eq!(doc.framing.preamble, framing.preamble);
+        assert_eq!(doc.recs.len(), 1);
+        assert_eq!(
+            keep_all
+                .serialize(&doc.recs, &doc.framing, "latin1")
+                .unwrap(),
+            buf
+        );
+        let bad = [String::from("colour")];
+        assert!(keep.take_flag("--reset", &mut bad.iter()).unwrap().is_err());
+    }
+}
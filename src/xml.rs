@@ -0,0 +1,151 @@
+//! `--xml` export and the `--xsd` schema it validates against.
+//!
+//! The document shape is fixed and versioned (`version="1"`): an `<outline>`
+//! of nested `<node>`s, each with its heading in `text`, an optional
+//! `<note>` first, then child nodes. Filler nodes (blank parents implied by
+//! level jumps, see shape.rs) carry `filler="true"` and no id.
+
+use crate::Node;
+
+pub const NAMESPACE: &str = "urn:otl:outline:1";
+
+pub const XSD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema"
+           xmlns:o="urn:otl:outline:1"
+           targetNamespace="urn:otl:outline:1"
+           elementFormDefault="qualified">
+  <xs:element name="outline">
+    <xs:complexType>
+      <xs:sequence>
+        <xs:element name="node" type="o:node" minOccurs="0" maxOccurs="unbounded"/>
+      </xs:sequence>
+      <xs:attribute name="version" type="xs:positiveInteger" use="required"/>
+    </xs:complexType>
+  </xs:element>
+  <xs:complexType name="note">
+    <xs:simpleContent>
+      <xs:extension base="xs:string">
+        <xs:attribute name="encoding" default="text">
+          <xs:simpleType>
+            <xs:restriction base="xs:string">
+              <xs:enumeration value="text"/>
+              <xs:enumeration value="hex"/>
+            </xs:restriction>
+          </xs:simpleType>
+        </xs:attribute>
+      </xs:extension>
+    </xs:simpleContent>
+  </xs:complexType>
+  <xs:complexType name="node">
+    <xs:sequence>
+      <xs:element name="note" type="o:note" minOccurs="0"/>
+      <xs:element name="node" type="o:node" minOccurs="0" maxOccurs="unbounded"/>
+    </xs:sequence>
+    <xs:attribute name="id" type="xs:hexBinary"/>
+    <xs:attribute name="text" type="xs:string" use="required"/>
+    <xs:attribute name="collapsed" type="xs:boolean" default="false"/>
+    <xs:attribute name="selected" type="xs:boolean" default="false"/>
+    <xs:attribute name="filler" type="xs:boolean" default="false"/>
+  </xs:complexType>
+</xs:schema>
+"#;
+
+// XML 1.0 cannot carry most C0 controls even as references.
+fn xml_escape(s: &str, attr: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            '\n' | '\t' if attr => out.push_str(&format!("&#{};", c as u32)),
+            '\r' => out.push_str("&#13;"),
+            '\t' | '\n' => out.push(c),
+            c if (c as u32) < 0x20 => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn nodes_xml(nodes: &[Node], depth: usize, out: &mut String) {
+    let pad = "  ".repeat(depth + 1);
+    for n in nodes {
+        let mut attrs = String::new();
+        if let Some(id) = &n.id {
+            attrs.push_str(&format!(" id=\"{id}\""));
+        }
+        attrs.push_str(&format!(" text=\"{}\"", xml_escape(&n.text, true)));
+        if n.collapsed {
+            attrs.push_str(" collapsed=\"true\"");
+        }
+        if n.flags.selected {
+            attrs.push_str(" selected=\"true\"");
+        }
+        if n.filler {
+            attrs.push_str(" filler=\"true\"");
+        }
+        if n.note.is_none() && n.children.is_empty() {
+            out.push_str(&format!("{pad}<node{attrs}/>\n"));
+            continue;
+        }
+        out.push_str(&format!("{pad}<node{attrs}>\n"));
+        if let Some(note) = &n.note {
+            let enc = if n.flags.note_binary {
+                " encoding=\"hex\""
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "{pad}  <note{enc}>{}</note>\n",
+                xml_escape(&note.replace("\r\n", "\n"), false)
+            ));
+        }
+        nodes_xml(&n.children, depth + 1, out);
+        out.push_str(&format!("{pad}</node>\n"));
+    }
+}
+
+pub fn render_xml(nodes: &[Node]) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<outline xmlns=\"{NAMESPACE}\" version=\"1\">\n"
+    );
+    nodes_xml(nodes, 0, &mut out);
+    out.push_str("</outline>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, M_COLLAPSED, M_EXPANDED};
+
+    #[test]
+    fn nested_nodes_with_escaped_text_and_notes() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("A \"&\" B", 0x00, M_COLLAPSED, 0, None),
+                rec_bytes("<c>", A_NOTE, M_EXPANDED, 1, Some(b"l1\r\nl2")),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let out = render_xml(&build_tree_capped(&recs));
+        let want = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<outline xmlns=\"{NAMESPACE}\" version=\"1\">\n  \
+             <node id=\"{}\" text=\"A &quot;&amp;&quot; B\" collapsed=\"true\">\n    \
+             <node id=\"{}\" text=\"&lt;c&gt;\">\n      <note>l1\nl2</note>\n    </node>\n  \
+             </node>\n</outline>\n",
+            recs[0].id, recs[1].id
+        );
+        assert_eq!(out, want);
+    }
+
+    #[test]
+    fn control_characters_never_reach_the_document() {
+        assert_eq!(xml_escape("a\u{1}b\tc\n", false), "a\u{fffd}b\tc\n");
+        assert_eq!(xml_escape("a\tb\r", true), "a&#9;b&#13;");
+    }
+}
@@ -0,0 +1,195 @@
+//! Cross-references between headings, for linking exports.
+//!
+//! Two forms are recognized in heading and note text: `[[Target]]`, and
+//! `see: Target` (up to the end of the line or the next `.`, `;`, `,` or
+//! `)`). A target is a heading path (`A/B`) or a heading text (any case);
+//! it resolves when exactly one record matches.
+
+use crate::heading_paths_capped;
+use crate::slug::{rec_slugs, SlugStyle};
+use crate::Rec;
+use std::collections::HashMap;
+
+const SEE: &str = "see:";
+
+/// One reference found in a text; `range` is what a link replaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ref {
+    pub range: std::ops::Range<usize>,
+    pub target: String,
+}
+
+pub fn find_refs(text: &str) -> Vec<Ref> {
+    let mut out = Vec::new();
+    let lower = text.to_ascii_lowercase();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if let Some(body) = rest.strip_prefix("[[") {
+            if let Some(end) = body.find("]]") {
+                if end > 0 && !body[..end].contains('\n') {
+                    out.push(Ref {
+                        range: i..i + 2 + end + 2,
+                        target: body[..end].trim().to_string(),
+                    });
+                    i += 2 + end + 2;
+                    continue;
+                }
+            }
+        }
+        let at_word = i == 0 || !text[..i].ends_with(|c: char| c.is_alphanumeric());
+        if at_word && lower[i..].starts_with(SEE) {
+            let start = i + SEE.len();
+            let skip = text[start..].len() - text[start..].trim_start_matches([' ', '\t']).len();
+            let body = &text[start + skip..];
+            let len = body
+                .find(['\n', '\r', '.', ';', ',', ')'])
+                .unwrap_or(body.len());
+            let target = body[..len].trim_end();
+            if !target.is_empty() {
+                out.push(Ref {
+                    range: start + skip..start + skip + target.len(),
+                    target: target.to_string(),
+                });
+                i = start + skip + target.len();
+                continue;
+            }
+        }
+        i += rest.chars().next().map_or(1, |c| c.len_utf8());
+    }
+    out
+}
+
+/// What a reference target names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolved {
+    Unique(String), // the record's id
+    Ambiguous(usize),
+    Missing,
+}
+
+/// Headings by path and by (lowercased) text, and each one's anchor.
+pub struct Index {
+    by_path: HashMap<String, Vec<String>>,
+    by_text: HashMap<String, Vec<String>>,
+    anchors: HashMap<String, String>,
+}
+
+impl Index {
+    pub fn new(recs: &[Rec], style: SlugStyle) -> Index {
+        let mut by_path: HashMap<String, Vec<String>> = HashMap::new();
+        let mut by_text: HashMap<String, Vec<String>> = HashMap::new();
+        let mut anchors = HashMap::new();
+        let slugs = rec_slugs(recs, style);
+        for ((r, p), s) in recs.iter().zip(heading_paths_capped(recs)).zip(slugs) {
+            by_path.entry(p).or_default().push(r.id.clone());
+            by_text
+                .entry(r.text.trim().to_lowercase())
+                .or_default()
+                .push(r.id.clone());
+            anchors.insert(r.id.clone(), s);
+        }
+        Index {
+            by_path,
+            by_text,
+            anchors,
+        }
+    }
+
+    /// Anchor name (slug) for a record id.
+    pub fn anchor(&self, id: &str) -> String {
+        self.anchors
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    pub fn resolve(&self, target: &str) -> Resolved {
+        let hits = self
+            .by_path
+            .get(target)
+            .or_else(|| self.by_text.get(&target.to_lowercase()));
+        match hits.map(|h| h.as_slice()) {
+            Some([id]) => Resolved::Unique(id.clone()),
+            Some(h) if !h.is_empty() => Resolved::Ambiguous(h.len()),
+            _ => Resolved::Missing,
+        }
+    }
+}
+
+/// `text` with references turned into links: `escape` applies to plain
+/// text, `link(label, anchor)` builds a link. References that do not
+/// resolve stay plain text and are appended to `unresolved`.
+pub fn link_text(
+    text: &str,
+    idx: &Index,
+    escape: &dyn Fn(&str) -> String,
+    link: &dyn Fn(&str, &str) -> String,
+    unresolved: &mut Vec<(String, Resolved)>,
+) -> String {
+    let mut out = String::new();
+    let mut at = 0;
+    for r in find_refs(text) {
+        out.push_str(&escape(&text[at..r.range.start]));
+        match idx.resolve(&r.target) {
+            Resolved::Unique(id) => out.push_str(&link(&r.target, &idx.anchor(&id))),
+            other => {
+                out.push_str(&escape(&text[r.range.clone()]));
+                unresolved.push((r.target.clone(), other));
+            }
+        }
+        at = r.range.end;
+    }
+    out.push_str(&escape(&text[at..]));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{parse_otl, A_SIBFOLLOWS, M_EXPANDED};
+
+    #[test]
+    fn finds_both_reference_forms() {
+        let refs = find_refs("Call Bob (see: Budget 2024). Also [[Projects/Kitchen]]; oversee: x");
+        let targets: Vec<&str> = refs.iter().map(|r| r.target.as_str()).collect();
+        assert_eq!(targets, ["Budget 2024", "Projects/Kitchen"]);
+        assert_eq!(refs[0].range, 15..26);
+        assert!(find_refs("[[]] see:   \nnothing").is_empty());
+    }
+
+    #[test]
+    fn links_resolved_references_only() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Budget", A_SIBFOLLOWS, M_EXPANDED, 0, None),
+                rec_bytes("x", 0x00, M_EXPANDED, 1, None),
+                rec_bytes("Plan", 0x00, M_EXPANDED, -1, None),
+                rec_bytes("x", 0x00, M_EXPANDED, 1, None),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        let idx = Index::new(&recs, SlugStyle::Github);
+        let mut missing = Vec::new();
+        let out = link_text(
+            "see: budget; [[x]] [[Plan/x]] [[Nope]] <b>",
+            &idx,
+            &|s| s.replace('<', "&lt;").replace('>', "&gt;"),
+            &|label, a| format!("<a href=\"#{a}\">{label}</a>"),
+            &mut missing,
+        );
+        assert_eq!(
+            out,
+            "see: <a href=\"#budget\">budget</a>; [[x]] <a href=\"#x-1\">Plan/x</a> [[Nope]] &lt;b&gt;"
+        );
+        assert_eq!(
+            missing,
+            [
+                ("x".to_string(), Resolved::Ambiguous(2)),
+                ("Nope".to_string(), Resolved::Missing)
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! `--yaml` export: the tree as nested block sequences/mappings.
+//!
+//! Each node is a mapping with `text`, `collapsed`, then `note` (only when
+//! the heading has one) and `children` (only when it has some). Multi-line
+//! notes are literal blocks with LF line ends; anything a plain scalar could
+//! misread is double-quoted (JSON escaping, which YAML accepts).
+
+use crate::Node;
+
+const KEYWORDS: &[&str] = &[
+    "true", "false", "null", "yes", "no", "on", "off", "y", "n", "~",
+];
+
+fn plain_ok(s: &str) -> bool {
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    first.is_alphabetic()
+        && !s.ends_with(' ')
+        && !KEYWORDS.contains(&s.to_ascii_lowercase().as_str())
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || " _-.,()/'&+?!".contains(c))
+}
+
+fn scalar(s: &str) -> String {
+    if plain_ok(s) {
+        s.to_string()
+    } else {
+        serde_json::to_string(s).unwrap_or_default()
+    }
+}
+
+// `key: value` for a note, as a literal block when that reads better.
+fn note_value(note: &str, pad: &str) -> String {
+    let text = note.replace("\r\n", "\n");
+    let blockable = text.contains('\n')
+        && !text.starts_with([' ', '\n'])
+        && text.chars().all(|c| c == '\n' || !c.is_control());
+    if !blockable {
+        return format!(" {}\n", scalar(note));
+    }
+    let body = text.trim_end_matches('\n');
+    let chomp = match text.len() - body.len() {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let mut out = format!(" |{chomp}\n");
+    for line in text.split_inclusive('\n').map(|l| l.trim_end_matches('\n')) {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{pad}  {line}\n"));
+        }
+    }
+    out
+}
+
+fn nodes_yaml(nodes: &[Node], depth: usize, out: &mut String) {
+    let pad = "  ".repeat(depth);
+    for n in nodes {
+        out.push_str(&format!("{pad}- text: {}\n", scalar(&n.text)));
+        out.push_str(&format!("{pad}  collapsed: {}\n", n.collapsed));
+        if let Some(note) = &n.note {
+            out.push_str(&format!(
+                "{pad}  note:{}",
+                note_value(note, &format!("{pad}  "))
+            ));
+        }
+        if !n.children.is_empty() {
+            out.push_str(&format!("{pad}  children:\n"));
+            nodes_yaml(&n.children, depth + 1, out);
+        }
+    }
+}
+
+pub fn render_yaml(nodes: &[Node]) -> String {
+    if nodes.is_empty() {
+        return "[]\n".to_string();
+    }
+    let mut out = String::new();
+    nodes_yaml(nodes, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{otl_file, rec_bytes};
+    use crate::{build_tree_capped, parse_otl, A_NOTE, A_SIBFOLLOWS, M_COLLAPSED, M_EXPANDED};
+
+    #[test]
+    fn nested_nodes_with_block_notes_and_quoting() {
+        let recs = parse_otl(
+            &otl_file(vec![
+                rec_bytes("Plan: Q3", A_SIBFOLLOWS, M_COLLAPSED, 0, None),
+                rec_bytes("Step one", A_NOTE, M_EXPANDED, 1, Some(b"a\r\n\r\nb\r\n")),
+                rec_bytes("yes", A_NOTE, M_EXPANDED, -1, Some(b"one line")),
+            ]),
+            "latin1",
+        )
+        .unwrap();
+        assert_eq!(
+            render_yaml(&build_tree_capped(&recs)),
+            "- text: \"Plan: Q3\"\n  collapsed: true\n  children:\n  \
+             - text: Step one\n    collapsed: false\n    note: |\n      a\n\n      b\n\
+             - text: \"yes\"\n  collapsed: false\n  note: one line\n"
+        );
+    }
+}